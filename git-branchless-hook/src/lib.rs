@@ -30,12 +30,14 @@ use tracing::{error, instrument, warn};
 use lib::core::eventlog::{should_ignore_ref_updates, Event, EventLogDb};
 use lib::core::formatting::{Glyphs, Pluralize};
 use lib::core::gc::{gc, mark_commit_reachable};
+use lib::core::obsmarkers::{import_obsmarkers, is_remote_obsmarkers_ref};
+use lib::core::rewrite::IN_MEMORY_COMMIT_ENV_VAR;
 use lib::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
 
 use lib::core::effects::Effects;
 pub use lib::core::rewrite::rewrite_hooks::{
-    hook_drop_commit_if_empty, hook_post_rewrite, hook_register_extra_post_rewrite_hook,
-    hook_skip_upstream_applied_commit,
+    hook_drop_commit, hook_drop_commit_if_empty, hook_post_rewrite,
+    hook_register_extra_post_rewrite_hook, hook_skip_upstream_applied_commit,
 };
 
 /// Handle Git's `post-checkout` hook.
@@ -133,6 +135,11 @@ fn hook_post_commit_common(effects: &Effects, hook_name: &str) -> eyre::Result<(
 /// See the man-page for `githooks(5)`.
 #[instrument]
 fn hook_post_commit(effects: &Effects) -> eyre::Result<()> {
+    // If the commit was created by an in-memory rebase, then `HEAD` doesn't
+    // point to it, and the rebase will record the rewritten commits itself.
+    if std::env::var_os(IN_MEMORY_COMMIT_ENV_VAR).is_some() {
+        return Ok(());
+    }
     hook_post_commit_common(effects, "post-commit")
 }
 
@@ -421,6 +428,39 @@ fn hook_reference_transaction(effects: &Effects, transaction_state: &str) -> eyr
                 }
             }
         })
+        .collect();
+
+    let obsmarkers_ref_names: Vec<ReferenceName> = parsed_lines
+        .iter()
+        .filter(
+            |ParsedReferenceTransactionLine {
+                 ref_name,
+                 old_oid: _,
+                 new_oid,
+             }| {
+                is_remote_obsmarkers_ref(ref_name) && matches!(new_oid, MaybeZeroOid::NonZero(_))
+            },
+        )
+        .map(|parsed_line| parsed_line.ref_name.clone())
+        .collect();
+    for ref_name in obsmarkers_ref_names {
+        let num_imported = import_obsmarkers(&repo, &event_log_db, now, &ref_name)?;
+        if num_imported > 0 {
+            writeln!(
+                effects.get_output_stream(),
+                "branchless: imported {} from {}",
+                Pluralize {
+                    determiner: None,
+                    amount: num_imported,
+                    unit: ("obsolescence marker", "obsolescence markers"),
+                },
+                ref_name.as_str(),
+            )?;
+        }
+    }
+
+    let parsed_lines: Vec<ParsedReferenceTransactionLine> = parsed_lines
+        .into_iter()
         .filter(
             |ParsedReferenceTransactionLine {
                  ref_name,
@@ -501,6 +541,11 @@ pub fn command_main(ctx: CommandContext, args: HookArgs) -> eyre::Result<ExitCod
             hook_drop_commit_if_empty(&effects, old_commit_oid)?;
         }
 
+        HookSubcommand::DropCommit { commit_oid } => {
+            let commit_oid: NonZeroOid = commit_oid.parse()?;
+            hook_drop_commit(&effects, commit_oid)?;
+        }
+
         HookSubcommand::PreAutoGc => {
             gc(&effects)?;
         }
@@ -28,6 +28,7 @@ use lib::core::dag::Dag;
 use lib::core::effects::Effects;
 use lib::core::eventlog::{EventLogDb, EventReplayer};
 use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::{UPDATE_MARKER_END, UPDATE_MARKER_START};
 use lib::git::{BranchType, Config, ConfigRead, ConfigWrite, GitRunInfo, GitVersion, Repo};
 
 /// The contents of all Git hooks to install.
@@ -90,6 +91,7 @@ const ALL_ALIASES: &[(&str, &str)] = &[
     ("prev", "prev"),
     ("query", "query"),
     ("record", "record"),
+    ("reorder", "reorder"),
     ("restack", "restack"),
     ("reword", "reword"),
     ("sl", "smartlog"),
@@ -135,8 +137,6 @@ pub fn determine_hook_path(repo: &Repo, hooks_dir: &Path, hook_type: &str) -> ey
 }
 
 const SHEBANG: &str = "#!/bin/sh";
-const UPDATE_MARKER_START: &str = "## START BRANCHLESS CONFIG";
-const UPDATE_MARKER_END: &str = "## END BRANCHLESS CONFIG";
 
 fn append_hook(new_lines: &mut String, hook_contents: &str) {
     new_lines.push_str(UPDATE_MARKER_START);
@@ -1,16 +1,21 @@
 use std::collections::HashSet;
 use std::path::PathBuf;
+use std::time::SystemTime;
 
-use branchless::core::dag::{CommitSet, Dag};
+use branchless::core::check_out::CheckOutCommitOptions;
+use branchless::core::dag::{commit_set_to_vec, CommitSet, Dag};
 use branchless::core::effects::Effects;
 use branchless::core::eventlog::{EventLogDb, EventReplayer};
 use branchless::core::formatting::Glyphs;
 use branchless::core::repo_ext::RepoExt;
 use branchless::core::rewrite::{
-    BuildRebasePlanOptions, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+    execute_rebase_plan, execute_rebase_plans, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
+    RebasePlan, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
 };
 use branchless::git::{CherryPickFastOptions, Commit, Diff, Repo};
+use branchless::testing::{make_git, GitInitOptions, GitWrapper};
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use eden_dag::DagAlgorithm;
 use rayon::ThreadPoolBuilder;
 
 fn get_repo() -> Repo {
@@ -163,6 +168,131 @@ fn bench_get_paths_touched_by_commits(c: &mut Criterion) {
     });
 }
 
+/// Set up a repository with many independent stacks which are all behind the
+/// main branch, and build the rebase plans to move each of them on top of the
+/// main branch, as `git sync` would.
+fn make_sync_plans(
+    num_stacks: usize,
+    stack_size: usize,
+) -> eyre::Result<(GitWrapper, Vec<RebasePlan>)> {
+    let git = make_git()?;
+    git.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: true,
+        run_branchless_init: false,
+    })?;
+    let mut time = 1;
+    for stack_num in 0..num_stacks {
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        for commit_num in 0..stack_size {
+            git.commit_file(&format!("stack{stack_num}-{commit_num}"), time)?;
+            time += 1;
+        }
+        // Without `git branchless init`, the commits are only visible if a
+        // branch points to them.
+        git.run(&["branch", &format!("stack{stack_num}")])?;
+    }
+    git.run(&["checkout", "master"])?;
+    let main_branch_oid = git.commit_file("main", time)?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let dag = Dag::open_and_sync(
+        &effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    let pool = ThreadPoolBuilder::new().build()?;
+    let repo_pool = RepoResource::new_pool(&repo)?;
+
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits: false,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: true,
+    };
+    let draft_commits = dag.query_draft_commits()?;
+    let draft_roots = dag.query().roots(draft_commits.clone())?;
+    let permissions =
+        RebasePlanPermissions::verify_rewrite_set(&dag, build_options, &draft_roots)?.unwrap();
+    let builder = RebasePlanBuilder::new(&dag, permissions);
+    let mut rebase_plans = Vec::new();
+    for root_oid in commit_set_to_vec(&draft_roots)? {
+        let mut builder = builder.clone();
+        builder.move_subtree(root_oid, vec![main_branch_oid])?;
+        let rebase_plan = builder
+            .build(&effects, &pool, &repo_pool)?
+            .unwrap()
+            .unwrap();
+        rebase_plans.push(rebase_plan);
+    }
+    Ok((git, rebase_plans))
+}
+
+fn bench_sync_many_stacks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sync");
+    group.sample_size(10);
+    let (git, rebase_plans) = make_sync_plans(32, 5).unwrap();
+    assert_eq!(rebase_plans.len(), 32);
+    let git_run_info = git.get_git_run_info();
+    let repo = git.get_repo().unwrap();
+    let conn = repo.get_db_conn().unwrap();
+    let event_log_db = EventLogDb::new(&conn).unwrap();
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let now = SystemTime::now();
+    let execute_options = ExecuteRebasePlanOptions {
+        now,
+        event_tx_id: event_log_db.make_transaction_id(now, "sync").unwrap(),
+        preserve_timestamps: true,
+        force_in_memory: true,
+        force_on_disk: false,
+        resolve_merge_conflicts: false,
+        create_conflict_commits: false,
+        check_out_commit_options: CheckOutCommitOptions::default(),
+    };
+
+    // Re-executing the same plans creates the same commits, so the repository
+    // can be reused across iterations.
+    group.bench_function("execute_rebase_plan (sequential)", |b| {
+        b.iter(|| {
+            rebase_plans
+                .iter()
+                .map(|rebase_plan| {
+                    execute_rebase_plan(
+                        &effects,
+                        &git_run_info,
+                        &repo,
+                        &event_log_db,
+                        rebase_plan,
+                        &execute_options,
+                    )
+                    .unwrap()
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("execute_rebase_plans (parallel)", |b| {
+        b.iter(|| {
+            execute_rebase_plans(
+                &effects,
+                &git_run_info,
+                &repo,
+                &event_log_db,
+                &rebase_plans.iter().collect::<Vec<_>>(),
+                &execute_options,
+            )
+            .unwrap()
+        })
+    });
+}
+
 criterion_group!(
     name = benches;
     config = Criterion::default().sample_size(10);
@@ -171,5 +301,6 @@ criterion_group!(
         bench_diff_fast,
         bench_get_paths_touched_by_commits,
         bench_rebase_plan,
+        bench_sync_many_stacks,
 );
 criterion_main!(benches);
@@ -110,6 +110,7 @@ fn main() -> eyre::Result<()> {
                 &message,
                 &actual_tree,
                 parents.iter().collect(),
+                None,
             )?;
             repo.find_commit_or_fail(actual_oid)?
         };
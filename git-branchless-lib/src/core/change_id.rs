@@ -0,0 +1,172 @@
+//! Manage the `Change-Id` trailers which Gerrit uses to identify a change under
+//! review. As long as a rewritten commit keeps its `Change-Id`, pushing it
+//! updates the existing change rather than creating a new one.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use tracing::instrument;
+
+use crate::core::eventlog::Event;
+use crate::git::{MaybeZeroOid, NonZeroOid};
+
+/// The key of the trailer which holds the change ID.
+pub const CHANGE_ID_TRAILER: &str = "Change-Id";
+
+fn is_trailer_line(line: &str) -> bool {
+    match line.split_once(':') {
+        Some((key, _value)) => {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }
+        None => false,
+    }
+}
+
+/// Get the last paragraph of the message if it consists only of trailers.
+fn get_trailer_block(message: &str) -> Option<&str> {
+    let (_body, last_paragraph) = message.trim_end().rsplit_once("\n\n")?;
+    if last_paragraph.lines().all(is_trailer_line) {
+        Some(last_paragraph)
+    } else {
+        None
+    }
+}
+
+/// Find the value of the `Change-Id` trailer in the given commit message, if
+/// it has one.
+pub fn find_change_id(message: &str) -> Option<&str> {
+    get_trailer_block(message)?.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key == CHANGE_ID_TRAILER {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+/// Make a change ID from the given seed, such as the OID of the commit which
+/// needs one. The result is in the format that Gerrit expects, which is `I`
+/// followed by 40 hex digits, and is always the same for the same seed.
+pub fn make_change_id(seed: &str) -> eyre::Result<String> {
+    let oid = git2::Oid::hash_object(
+        git2::ObjectType::Blob,
+        format!("{CHANGE_ID_TRAILER} {seed}").as_bytes(),
+    )?;
+    Ok(format!("I{oid}"))
+}
+
+/// Add a `Change-Id` trailer with the given value to the message, as part of
+/// its existing trailers if it has any.
+pub fn add_change_id(message: &str, change_id: &str) -> String {
+    let message = message.trim_end();
+    if get_trailer_block(message).is_some() {
+        format!("{message}\n{CHANGE_ID_TRAILER}: {change_id}\n")
+    } else {
+        format!("{message}\n\n{CHANGE_ID_TRAILER}: {change_id}\n")
+    }
+}
+
+/// Add a `Change-Id` trailer to the message of the commit with the given OID,
+/// unless it already has one.
+#[instrument]
+pub fn ensure_change_id(message: &str, commit_oid: NonZeroOid) -> eyre::Result<Cow<'_, str>> {
+    match find_change_id(message) {
+        Some(_) => Ok(Cow::Borrowed(message)),
+        None => {
+            let change_id = make_change_id(&commit_oid.to_string())?;
+            Ok(Cow::Owned(add_change_id(message, &change_id)))
+        }
+    }
+}
+
+/// The commits which each commit was rewritten from, according to the event
+/// log.
+#[derive(Debug, Default)]
+pub struct CommitPredecessors {
+    predecessors: HashMap<NonZeroOid, NonZeroOid>,
+}
+
+impl CommitPredecessors {
+    /// Collect the predecessors recorded by the rewrite events in `events`.
+    pub fn from_events(events: &[Event]) -> Self {
+        let predecessors = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::RewriteEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                    new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                } => Some((*new_commit_oid, *old_commit_oid)),
+                _ => None,
+            })
+            .collect();
+        Self { predecessors }
+    }
+
+    /// Iterate over the commits that the given commit was rewritten from,
+    /// starting with the most recent.
+    pub fn iter_predecessors(
+        &self,
+        commit_oid: NonZeroOid,
+    ) -> impl Iterator<Item = NonZeroOid> + '_ {
+        let mut current_oid = commit_oid;
+        let mut num_visited = 0;
+        std::iter::from_fn(move || {
+            // Guard against cycles, such as from undoing a rewrite.
+            if num_visited > self.predecessors.len() {
+                return None;
+            }
+            num_visited += 1;
+            let predecessor_oid = *self.predecessors.get(&current_oid)?;
+            current_oid = predecessor_oid;
+            Some(predecessor_oid)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_change_id() {
+        assert_eq!(find_change_id("foo"), None);
+        assert_eq!(find_change_id("Change-Id: I1234"), None);
+        assert_eq!(find_change_id("foo\n\nChange-Id: I1234\n"), Some("I1234"));
+        assert_eq!(
+            find_change_id("foo\n\nbar\n\nSigned-off-by: Foo <foo@example.com>\nChange-Id: I1234"),
+            Some("I1234")
+        );
+        assert_eq!(find_change_id("foo\n\nChange-Id: I1234\n\nbar"), None);
+    }
+
+    #[test]
+    fn test_add_change_id() {
+        insta::assert_snapshot!(add_change_id("foo\n", "I1234"), @r###"
+        foo
+
+        Change-Id: I1234
+        "###);
+        insta::assert_snapshot!(
+            add_change_id("foo\n\nSigned-off-by: Foo <foo@example.com>\n", "I1234"),
+            @r###"
+            foo
+
+            Signed-off-by: Foo <foo@example.com>
+            Change-Id: I1234
+            "###
+        );
+        insta::assert_snapshot!(
+            add_change_id("foo\n\nThis is: not a trailer\n", "I1234"),
+            @r###"
+            foo
+
+            This is: not a trailer
+
+            Change-Id: I1234
+            "###
+        );
+    }
+}
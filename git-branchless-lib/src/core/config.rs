@@ -7,6 +7,8 @@ use std::path::PathBuf;
 use cursive::theme::{BaseColor, Effect, Style};
 use cursive::utils::markup::StyledString;
 use eyre::Context;
+use itertools::Itertools;
+use regex::Regex;
 use tracing::{instrument, warn};
 
 use crate::core::formatting::StyledStringBuilder;
@@ -76,6 +78,35 @@ pub fn get_main_branch_name(repo: &Repo) -> eyre::Result<String> {
     Ok("master".to_string())
 }
 
+/// The config key for the patterns of the branches, besides the main branch,
+/// whose commits are public. It may be given more than once.
+pub const PUBLIC_BRANCH_CONFIG_KEY: &str = "branchless.core.publicBranch";
+
+/// Get the patterns of the branches, besides the main branch, whose commits
+/// are considered public, such as release branches, from the multi-valued
+/// `branchless.core.publicBranch` config option.
+///
+/// Each pattern is matched against the whole branch name (without the
+/// `refs/heads/` prefix), and may contain `*` to match any sequence of
+/// characters, such as `release/*`.
+#[instrument]
+pub fn get_public_branch_patterns(repo: &Repo) -> eyre::Result<Vec<Regex>> {
+    let config = repo.get_readonly_config()?;
+    // Config keys are reported in lowercase.
+    let key_pattern = format!(
+        "^{}$",
+        regex::escape(&PUBLIC_BRANCH_CONFIG_KEY.to_lowercase())
+    );
+    let mut result = Vec::new();
+    for (_key, pattern) in config.list(key_pattern)? {
+        let pattern = pattern.split('*').map(regex::escape).join(".*");
+        let pattern = Regex::new(&format!("^{pattern}$"))
+            .wrap_err_with(|| format!("Parsing {PUBLIC_BRANCH_CONFIG_KEY} pattern"))?;
+        result.push(pattern);
+    }
+    Ok(result)
+}
+
 /// If `true`, switch to the branch associated with a target commit instead of
 /// the commit directly.
 ///
@@ -190,6 +221,25 @@ pub fn get_restack_preserve_timestamps(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.restack.preserveTimestamps", false)
 }
 
+/// If `true`, run the `pre-commit`, `commit-msg`, and `post-commit` hooks for
+/// each commit created during an in-memory rebase, as `git commit` would.
+#[instrument]
+pub fn get_restack_run_commit_hooks(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.restack.runCommitHooks", false)
+}
+
+/// If `true`, add a `Change-Id` trailer to commits which don't have one when
+/// they're created by `git record` or rewritten by git-branchless. Defaults to
+/// `true` if the configured forge for `git submit` is Gerrit.
+#[instrument]
+pub fn get_change_ids_enabled(repo: &Repo) -> eyre::Result<bool> {
+    let config = repo.get_readonly_config()?;
+    let forge: Option<String> = config.get("branchless.submit.forge")?;
+    let default = forge.map_or(false, |forge| forge.eq_ignore_ascii_case("gerrit"));
+    config.get_or("branchless.changeIds", default)
+}
+
 /// If `true`, when advancing to a "next" commit, prompt interactively to
 /// if there is ambiguity in which commit to advance to.
 #[instrument]
@@ -212,6 +262,14 @@ pub fn get_commit_descriptors_differential_revision(repo: &Repo) -> eyre::Result
         .get_or("branchless.commitDescriptors.differentialRevision", true)
 }
 
+/// If `true`, show whether each branch in the smartlog is ahead of, behind,
+/// or diverged from its remote branch.
+#[instrument]
+pub fn get_commit_descriptors_remote_status(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.remoteStatus", false)
+}
+
 /// If `true`, show the age of each commit in the smartlog.
 #[instrument]
 pub fn get_commit_descriptors_relative_time(repo: &Repo) -> eyre::Result<bool> {
@@ -219,6 +277,32 @@ pub fn get_commit_descriptors_relative_time(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.commitDescriptors.relativeTime", true)
 }
 
+/// If set, `git branchless gc` compacts events in the event log which are older
+/// than this many days into a checkpoint.
+#[instrument]
+pub fn get_event_log_max_age_days(repo: &Repo) -> eyre::Result<Option<u32>> {
+    let max_age_days: Option<i32> = repo
+        .get_readonly_config()?
+        .get("branchless.eventLog.maxAgeDays")?;
+    max_age_days
+        .map(u32::try_from)
+        .transpose()
+        .wrap_err("Parsing `branchless.eventLog.maxAgeDays`")
+}
+
+/// If set, `git branchless gc` compacts all but this many of the most recent
+/// events in the event log into a checkpoint.
+#[instrument]
+pub fn get_event_log_max_events(repo: &Repo) -> eyre::Result<Option<usize>> {
+    let max_events: Option<i32> = repo
+        .get_readonly_config()?
+        .get("branchless.eventLog.maxEvents")?;
+    max_events
+        .map(usize::try_from)
+        .transpose()
+        .wrap_err("Parsing `branchless.eventLog.maxEvents`")
+}
+
 /// Config key for `get_restack_warn_abandoned`.
 pub const RESTACK_WARN_ABANDONED_CONFIG_KEY: &str = "branchless.restack.warnAbandoned";
 
@@ -0,0 +1,206 @@
+//! Record merge conflicts as commits, so that they can be resolved later.
+//!
+//! When an in-memory rebase encounters a merge conflict, it can commit the
+//! conflicted result instead of aborting, so that the rest of the stack can
+//! still be rebased. Such a "conflict commit" records the three sides of the
+//! conflicted merge (the base, "ours", and "theirs" trees), so that the
+//! conflict can later be materialized into the working copy and resolved.
+//!
+//! The tree of a conflict commit takes the contents of each conflicting path
+//! from the "ours" side, i.e. from its parent. The sides of the conflict are
+//! stored as subtrees of a separate tree, whose OID is written to the
+//! [`CONFLICT_HEADER_NAME`] header field of the conflict commit. Git doesn't
+//! consider objects referred to by header fields to be reachable, so the sides
+//! tree is also kept alive by a reference under [`CONFLICT_REF_PREFIX`], which
+//! is cleaned up by `git branchless gc` once the conflict commit is obsolete.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bstr::ByteSlice;
+use eyre::Context;
+use tracing::instrument;
+
+use crate::git::{
+    hydrate_tree, Commit, CommitSigner, FileMode, NonZeroOid, ReferenceName, Repo, Signature, Tree,
+};
+
+/// The name of the commit header field which stores the OID of the tree
+/// containing the sides of the conflict.
+pub const CONFLICT_HEADER_NAME: &str = "branchless-conflict";
+
+/// The prefix of references which keep the sides of conflict commits alive.
+/// The full reference name is suffixed with the OID of the conflict commit.
+pub const CONFLICT_REF_PREFIX: &str = "refs/branchless/conflicts/";
+
+const BASE_TREE_NAME: &str = "base";
+const OUR_TREE_NAME: &str = "ours";
+const THEIR_TREE_NAME: &str = "theirs";
+
+/// The sides of a three-way merge which resulted in a conflict.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommitConflict {
+    /// The tree of the common ancestor, or `None` if there was no common
+    /// ancestor (such as when cherry-picking a root commit).
+    pub base_tree_oid: Option<NonZeroOid>,
+
+    /// The tree which the changes were being applied onto.
+    pub our_tree_oid: NonZeroOid,
+
+    /// The tree containing the changes which were being applied.
+    pub their_tree_oid: NonZeroOid,
+}
+
+impl CommitConflict {
+    /// Get the sides of the merge performed when cherry-picking `patch_commit`
+    /// onto `target_commit`.
+    #[instrument]
+    pub fn from_cherry_pick(patch_commit: &Commit, target_commit: &Commit) -> eyre::Result<Self> {
+        let base_tree_oid = match patch_commit.get_only_parent() {
+            Some(parent_commit) => Some(parent_commit.get_tree()?.get_oid()),
+            None => None,
+        };
+        Ok(Self {
+            base_tree_oid,
+            our_tree_oid: target_commit.get_tree()?.get_oid(),
+            their_tree_oid: patch_commit.get_tree()?.get_oid(),
+        })
+    }
+
+    /// Perform the three-way merge for this conflict. Returns the merged tree,
+    /// in which conflicting paths take their contents from the "ours" side, and
+    /// the paths which were in conflict.
+    ///
+    /// The `commit` and `onto` OIDs are used for error reporting.
+    #[instrument]
+    pub fn merge<'repo>(
+        &self,
+        repo: &'repo Repo,
+        commit: NonZeroOid,
+        onto: NonZeroOid,
+    ) -> eyre::Result<(Tree<'repo>, HashSet<PathBuf>)> {
+        let base_tree_oid = match self.base_tree_oid {
+            Some(base_tree_oid) => base_tree_oid,
+            None => hydrate_tree(repo, None, HashMap::new())?,
+        };
+        let base_tree = repo.find_tree_or_fail(base_tree_oid)?;
+        let our_tree = repo.find_tree_or_fail(self.our_tree_oid)?;
+        let their_tree = repo.find_tree_or_fail(self.their_tree_oid)?;
+        let result =
+            repo.merge_trees_with_conflicts(&base_tree, &our_tree, &their_tree, commit, onto)?;
+        Ok(result)
+    }
+}
+
+/// Get the name of the reference which keeps the sides of the given conflict
+/// commit alive.
+pub fn get_conflict_ref_name(commit_oid: NonZeroOid) -> ReferenceName {
+    ReferenceName::from(format!("{CONFLICT_REF_PREFIX}{commit_oid}"))
+}
+
+/// If the given reference keeps the sides of a conflict commit alive, get the
+/// OID of that conflict commit.
+pub fn get_conflict_ref_commit_oid(reference_name: &ReferenceName) -> Option<NonZeroOid> {
+    let commit_oid = reference_name.as_str().strip_prefix(CONFLICT_REF_PREFIX)?;
+    NonZeroOid::from_str(commit_oid).ok()
+}
+
+/// Determine whether the given commit is a conflict commit.
+pub fn is_conflict_commit(commit: &Commit) -> eyre::Result<bool> {
+    Ok(commit.get_header_field(CONFLICT_HEADER_NAME)?.is_some())
+}
+
+/// Get the sides of the conflict recorded in the given commit, or `None` if it
+/// isn't a conflict commit.
+#[instrument]
+pub fn get_commit_conflict(repo: &Repo, commit: &Commit) -> eyre::Result<Option<CommitConflict>> {
+    let sides_tree_oid = match commit.get_header_field(CONFLICT_HEADER_NAME)? {
+        Some(sides_tree_oid) => sides_tree_oid,
+        None => return Ok(None),
+    };
+    let sides_tree_oid = sides_tree_oid
+        .to_str()
+        .ok()
+        .and_then(|sides_tree_oid| NonZeroOid::from_str(sides_tree_oid.trim()).ok())
+        .ok_or_else(|| {
+            eyre::eyre!(
+                "Invalid {CONFLICT_HEADER_NAME} header for commit {:?}: {:?}",
+                commit.get_oid(),
+                sides_tree_oid,
+            )
+        })?;
+    let sides_tree = repo
+        .find_tree(sides_tree_oid)?
+        .ok_or_else(|| eyre::eyre!("Could not find conflict sides tree: {sides_tree_oid:?}"))?;
+
+    let get_side = |name: &str| -> eyre::Result<Option<NonZeroOid>> {
+        let entry = sides_tree.get_path(Path::new(name))?;
+        Ok(entry.map(|entry| entry.get_oid()))
+    };
+    let our_tree_oid = get_side(OUR_TREE_NAME)?
+        .ok_or_else(|| eyre::eyre!("Conflict sides tree has no {OUR_TREE_NAME:?} entry"))?;
+    let their_tree_oid = get_side(THEIR_TREE_NAME)?
+        .ok_or_else(|| eyre::eyre!("Conflict sides tree has no {THEIR_TREE_NAME:?} entry"))?;
+    Ok(Some(CommitConflict {
+        base_tree_oid: get_side(BASE_TREE_NAME)?,
+        our_tree_oid,
+        their_tree_oid,
+    }))
+}
+
+/// Create a conflict commit recording the provided conflict. The `tree` should
+/// be the result of [`CommitConflict::merge`].
+#[instrument]
+pub fn create_conflict_commit(
+    repo: &Repo,
+    author: &Signature,
+    committer: &Signature,
+    message: &str,
+    tree: &Tree,
+    parents: Vec<&Commit>,
+    conflict: &CommitConflict,
+    signer: Option<&CommitSigner>,
+) -> eyre::Result<NonZeroOid> {
+    let CommitConflict {
+        base_tree_oid,
+        our_tree_oid,
+        their_tree_oid,
+    } = conflict;
+    let sides_tree_oid = {
+        let mut entries = HashMap::new();
+        if let Some(base_tree_oid) = base_tree_oid {
+            entries.insert(
+                PathBuf::from(BASE_TREE_NAME),
+                Some((*base_tree_oid, FileMode::Tree)),
+            );
+        }
+        entries.insert(
+            PathBuf::from(OUR_TREE_NAME),
+            Some((*our_tree_oid, FileMode::Tree)),
+        );
+        entries.insert(
+            PathBuf::from(THEIR_TREE_NAME),
+            Some((*their_tree_oid, FileMode::Tree)),
+        );
+        hydrate_tree(repo, None, entries).wrap_err("Writing conflict sides tree")?
+    };
+
+    let commit_oid = repo.create_commit_with_extra_headers(
+        author,
+        committer,
+        message,
+        tree,
+        parents,
+        &[(CONFLICT_HEADER_NAME, sides_tree_oid.to_string())],
+        signer,
+    )?;
+    repo.create_reference(
+        &get_conflict_ref_name(commit_oid),
+        sides_tree_oid,
+        true,
+        "branchless: recording conflict sides",
+    )
+    .wrap_err("Creating conflict reference")?;
+    Ok(commit_oid)
+}
@@ -16,8 +16,9 @@ use tracing::{instrument, trace, warn};
 
 use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::{CommitActivityStatus, EventCursor, EventReplayer};
-use crate::git::{Commit, MaybeZeroOid, NonZeroOid, Repo, Time};
+use crate::git::{CategorizedReferenceName, Commit, MaybeZeroOid, NonZeroOid, Repo, Time};
 
+use super::config::get_public_branch_patterns;
 use super::repo_ext::RepoReferencesSnapshot;
 
 impl From<NonZeroOid> for eden_dag::VertexName {
@@ -102,6 +103,11 @@ pub struct Dag {
     /// A set containing the commit that the main branch currently points to.
     pub main_branch_commit: CommitSet,
 
+    /// A set containing the commits that the main branch and the other public
+    /// branches (as configured by `branchless.core.publicBranch`) currently
+    /// point to.
+    pub public_branch_commits: CommitSet,
+
     /// A set containing all commits currently pointed to by local branches.
     pub branch_commits: CommitSet,
 
@@ -182,11 +188,33 @@ impl Dag {
         };
         let main_branch_commit = CommitSet::from(*main_branch_oid);
         let branch_commits: CommitSet = branch_oid_to_names.keys().copied().collect();
+        let public_branch_patterns = get_public_branch_patterns(repo)?;
+        let public_branch_commits: CommitSet = branch_oid_to_names
+            .iter()
+            .filter(|(_oid, names)| {
+                names.iter().any(|name| {
+                    let name = CategorizedReferenceName::new(name);
+                    match name {
+                        CategorizedReferenceName::LocalBranch { .. } => {
+                            let branch_name = name.render_suffix();
+                            public_branch_patterns
+                                .iter()
+                                .any(|pattern| pattern.is_match(&branch_name))
+                        }
+                        CategorizedReferenceName::RemoteBranch { .. }
+                        | CategorizedReferenceName::OtherRef { .. } => false,
+                    }
+                })
+            })
+            .map(|(oid, _names)| *oid)
+            .collect();
+        let public_branch_commits = public_branch_commits.union(&main_branch_commit);
 
         Ok(Self {
             inner: dag,
             head_commit,
             main_branch_commit,
+            public_branch_commits,
             branch_commits,
             observed_commits,
             obsolete_commits,
@@ -209,7 +237,7 @@ impl Dag {
     /// This function's code adapted from `GitDag`, licensed under GPL-2.
     #[instrument]
     fn sync(&mut self, effects: &Effects, repo: &Repo) -> eyre::Result<()> {
-        let master_heads = self.main_branch_commit.clone();
+        let master_heads = self.public_branch_commits.clone();
         let non_master_heads = self
             .observed_commits
             .union(&self.head_commit)
@@ -311,6 +339,7 @@ impl Dag {
             head_commit: self.head_commit.clone(),
             branch_commits: self.branch_commits.clone(),
             main_branch_commit: self.main_branch_commit.clone(),
+            public_branch_commits: self.public_branch_commits.clone(),
             observed_commits: self.observed_commits.clone(),
             obsolete_commits: CommitSet::empty(),
             draft_commits: Default::default(),
@@ -374,9 +403,26 @@ impl Dag {
     }
 
     /// Determine whether or not the given commit is a public commit (i.e. is an
-    /// ancestor of the main branch).
+    /// ancestor of the main branch or of one of the other public branches).
     #[instrument]
     pub fn is_public_commit(&self, commit_oid: NonZeroOid) -> eyre::Result<bool> {
+        let public_branch_commits = commit_set_to_vec(&self.public_branch_commits)?;
+        for public_branch_commit in public_branch_commits {
+            if self
+                .inner
+                .is_ancestor(commit_oid.into(), public_branch_commit.into())?
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Determine whether or not the given commit is an ancestor of the main
+    /// branch. Unlike `is_public_commit`, this excludes commits which are only
+    /// on the other public branches.
+    #[instrument]
+    pub fn is_main_branch_ancestor(&self, commit_oid: NonZeroOid) -> eyre::Result<bool> {
         let main_branch_commits = commit_set_to_vec(&self.main_branch_commit)?;
         for main_branch_commit in main_branch_commits {
             if self
@@ -395,7 +441,7 @@ impl Dag {
     #[instrument]
     pub fn query_public_commits_slow(&self) -> eyre::Result<&CommitSet> {
         self.public_commits.get_or_try_init(|| {
-            let public_commits = self.query().ancestors(self.main_branch_commit.clone())?;
+            let public_commits = self.query().ancestors(self.public_branch_commits.clone())?;
             Ok(public_commits)
         })
     }
@@ -409,7 +455,7 @@ impl Dag {
             let visible_heads = CommitSet::empty()
                 .union(&self.observed_commits.difference(&self.obsolete_commits))
                 .union(&self.head_commit)
-                .union(&self.main_branch_commit)
+                .union(&self.public_branch_commits)
                 .union(&self.branch_commits);
             let visible_heads = self.query().heads(visible_heads)?;
             Ok(visible_heads)
@@ -451,7 +497,7 @@ impl Dag {
             let visible_heads = self.query_visible_heads()?;
             let draft_commits = self
                 .query()
-                .only(visible_heads.clone(), self.main_branch_commit.clone())?;
+                .only(visible_heads.clone(), self.public_branch_commits.clone())?;
             Ok(draft_commits)
         })
     }
@@ -36,6 +36,7 @@ pub enum OperationType {
     MakeGraph,
     ProcessEvents,
     PushBranches,
+    PushCommits,
     QueryWorkingCopy,
     ReadingFromCache,
     RebaseCommits,
@@ -43,6 +44,7 @@ pub enum OperationType {
     RepairCommits,
     RunGitCommand(Arc<String>),
     RunTests(Arc<String>),
+    SubmitRevisions,
     RunTestOnCommit(Arc<String>),
     SortCommits,
     SyncCommits,
@@ -71,6 +73,7 @@ impl ToString for OperationType {
             OperationType::InitializeRebase => "Initializing rebase",
             OperationType::MakeGraph => "Examining local history",
             OperationType::PushBranches => "Pushing branches",
+            OperationType::PushCommits => "Pushing commits",
             OperationType::ProcessEvents => "Processing events",
             OperationType::QueryWorkingCopy => "Querying the working copy",
             OperationType::ReadingFromCache => "Reading from cache",
@@ -83,6 +86,7 @@ impl ToString for OperationType {
             OperationType::RunTests(command) => return format!("Running tests: {command}"),
             OperationType::RunTestOnCommit(commit) => return format!("Waiting to test {commit}"),
             OperationType::SortCommits => "Sorting commits",
+            OperationType::SubmitRevisions => "Submitting revisions",
             OperationType::SyncCommits => "Syncing commit stacks",
             OperationType::UpdateCommitGraph => "Updating commit graph",
             OperationType::WalkCommits => "Walking commits",
@@ -6,16 +6,19 @@
 //! they're still working on.
 
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
+use std::fmt::Display;
 
 use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
 use eyre::Context;
+use itertools::Itertools;
 use tracing::{error, instrument};
 
 use crate::core::effects::{Effects, OperationType};
+use crate::core::migrations::apply_migrations;
 use crate::core::repo_ext::RepoExt;
 use crate::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
 
@@ -56,10 +59,10 @@ struct Row {
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct EventTransactionId(isize);
 
-impl ToString for EventTransactionId {
-    fn to_string(&self) -> String {
+impl Display for EventTransactionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let EventTransactionId(event_id) = self;
-        event_id.to_string()
+        write!(f, "{event_id}")
     }
 }
 
@@ -213,6 +216,19 @@ impl Event {
             Event::WorkingCopySnapshot { event_tx_id, .. } => *event_tx_id,
         }
     }
+
+    /// Move this event into the given event transaction.
+    pub fn with_event_tx_id(mut self, new_event_tx_id: EventTransactionId) -> Self {
+        match &mut self {
+            Event::RewriteEvent { event_tx_id, .. } => *event_tx_id = new_event_tx_id,
+            Event::RefUpdateEvent { event_tx_id, .. } => *event_tx_id = new_event_tx_id,
+            Event::CommitEvent { event_tx_id, .. } => *event_tx_id = new_event_tx_id,
+            Event::ObsoleteEvent { event_tx_id, .. } => *event_tx_id = new_event_tx_id,
+            Event::UnobsoleteEvent { event_tx_id, .. } => *event_tx_id = new_event_tx_id,
+            Event::WorkingCopySnapshot { event_tx_id, .. } => *event_tx_id = new_event_tx_id,
+        }
+        self
+    }
 }
 
 impl From<Event> for Row {
@@ -435,50 +451,104 @@ impl std::fmt::Debug for EventLogDb<'_> {
     }
 }
 
-#[instrument]
-fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+fn insert_events(conn: &rusqlite::Connection, events: Vec<Event>) -> eyre::Result<()> {
+    for event in events {
+        insert_event(conn, None, event)?;
+    }
+    Ok(())
+}
+
+/// Insert a new event transaction and return its ID. This should be called in
+/// a transaction, in case there's another thread in this process making queries
+/// with the same SQLite connection, since it queries `last_insert_rowid`.
+fn insert_transaction(
+    conn: &rusqlite::Connection,
+    now: SystemTime,
+    message: &str,
+) -> eyre::Result<EventTransactionId> {
+    let timestamp = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .wrap_err("Calculating event transaction timestamp")?
+        .as_secs_f64();
     conn.execute(
         "
-CREATE TABLE IF NOT EXISTS event_log (
-    timestamp REAL NOT NULL,
-    type TEXT NOT NULL,
-    event_tx_id INTEGER NOT NULL,
-    old_ref TEXT,
-    new_ref TEXT,
-    ref_name TEXT,
-    message TEXT
-)
-",
-        rusqlite::params![],
+            INSERT INTO event_transactions
+            (timestamp, message)
+            VALUES
+            (:timestamp, :message)
+        ",
+        rusqlite::named_params! {
+            ":timestamp": timestamp,
+            ":message": message,
+        },
     )
-    .wrap_err("Creating `event_log` table")?;
+    .wrap_err("Creating event transaction")?;
+    let event_tx_id: isize = conn.last_insert_rowid().try_into()?;
+    Ok(EventTransactionId(event_tx_id))
+}
 
-    conn.execute(
-        "
-CREATE TABLE IF NOT EXISTS event_transactions (
-    timestamp REAL NOT NULL,
+/// Insert an event with the given `rowid`, or with the next available `rowid`
+/// if not provided.
+fn insert_event(conn: &rusqlite::Connection, rowid: Option<i64>, event: Event) -> eyre::Result<()> {
+    let Row {
+        timestamp,
+        type_,
+        event_tx_id,
+        ref1,
+        ref2,
+        ref_name,
+        message,
+    } = Row::from(event);
 
-    -- Set as `PRIMARY KEY` to have SQLite select a value automatically. Set as
-    -- `AUTOINCREMENT` to ensure that SQLite doesn't reuse the value later if a
-    -- row is deleted. (We don't plan to delete rows right now, but maybe
-    -- later?)
-    event_tx_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    let ref1 = ref1.as_ref().map(|x| x.as_str());
+    let ref2 = ref2.as_ref().map(|x| x.as_str());
+    let ref_name = ref_name.as_ref().map(|x| x.as_str());
+    let message = message.as_ref().map(|x| x.as_str());
 
-    message TEXT
+    conn.execute(
+        "
+INSERT INTO event_log
+(rowid, timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message)
+VALUES (
+    :rowid,
+    :timestamp,
+    :type,
+    :event_tx_id,
+    :old_ref,
+    :new_ref,
+    :ref_name,
+    :message
 )
-",
-        rusqlite::params![],
-    )
-    .wrap_err("Creating `event_transactions` table")?;
-
+        ",
+        rusqlite::named_params! {
+            ":rowid": rowid,
+            ":timestamp": timestamp,
+            ":type": &type_,
+            ":event_tx_id": event_tx_id,
+            ":old_ref": &ref1,
+            ":new_ref": &ref2,
+            ":ref_name": &ref_name,
+            ":message": &message,
+        },
+    )?;
     Ok(())
 }
 
+/// Group consecutive events with the same transaction ID together.
+fn group_events_by_transaction(events: Vec<Event>) -> Vec<Vec<Event>> {
+    events
+        .into_iter()
+        .group_by(|event| event.get_event_tx_id())
+        .into_iter()
+        .map(|(_event_tx_id, events)| events.collect())
+        .collect()
+}
+
 impl<'conn> EventLogDb<'conn> {
     /// Constructor.
     #[instrument]
     pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
-        init_tables(conn)?;
+        apply_migrations(conn)?;
         Ok(EventLogDb { conn })
     }
 
@@ -489,45 +559,7 @@ impl<'conn> EventLogDb<'conn> {
     #[instrument]
     pub fn add_events(&self, events: Vec<Event>) -> eyre::Result<()> {
         let tx = self.conn.unchecked_transaction()?;
-        for event in events {
-            let Row {
-                timestamp,
-                type_,
-                event_tx_id,
-                ref1,
-                ref2,
-                ref_name,
-                message,
-            } = Row::from(event);
-
-            let ref1 = ref1.as_ref().map(|x| x.as_str());
-            let ref2 = ref2.as_ref().map(|x| x.as_str());
-            let ref_name = ref_name.as_ref().map(|x| x.as_str());
-            let message = message.as_ref().map(|x| x.as_str());
-
-            tx.execute(
-                "
-INSERT INTO event_log VALUES (
-    :timestamp,
-    :type,
-    :event_tx_id,
-    :old_ref,
-    :new_ref,
-    :ref_name,
-    :message
-)
-            ",
-                rusqlite::named_params! {
-                    ":timestamp": timestamp,
-                    ":type": &type_,
-                    ":event_tx_id": event_tx_id,
-                    ":old_ref": &ref1,
-                    ":new_ref": &ref2,
-                    ":ref_name": &ref_name,
-                    ":message": &message,
-                },
-            )?;
-        }
+        insert_events(&tx, events)?;
         tx.commit()?;
         Ok(())
     }
@@ -570,6 +602,58 @@ ORDER BY rowid ASC
         rows.into_iter().map(Event::try_from).collect()
     }
 
+    /// Merge the given events into the database, in a transaction. Unlike
+    /// `add_events`, the events may be older than some of the events already
+    /// in the database (such as when importing another event log). Each
+    /// transaction of the given events is placed before the first existing
+    /// transaction with a later latest timestamp, so that replaying the events in
+    /// order produces the same result as if they had been recorded live.
+    ///
+    /// Only the existing events after the first merged event are rewritten.
+    #[instrument]
+    pub fn merge_events(&self, events: Vec<Event>) -> eyre::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let existing_rowids = self.get_rowids()?;
+        let existing_transactions = group_events_by_transaction(self.get_events()?);
+        let new_transactions = group_events_by_transaction(events);
+
+        // Some events (such as commit events) carry the timestamp of the
+        // underlying object rather than the time they were recorded, so use
+        // the latest timestamp in each transaction.
+        let get_transaction_timestamp = |events: &[Event]| -> Option<SystemTime> {
+            events.iter().map(|event| event.get_timestamp()).max()
+        };
+        let mut existing_transactions = existing_transactions.into_iter().peekable();
+        let mut merged_events = Vec::new();
+        let mut num_unchanged_events = None;
+        for new_transaction in new_transactions {
+            let new_timestamp = get_transaction_timestamp(&new_transaction);
+            while let Some(existing_transaction) = existing_transactions
+                .next_if(|transaction| get_transaction_timestamp(transaction) <= new_timestamp)
+            {
+                merged_events.extend(existing_transaction);
+            }
+            num_unchanged_events.get_or_insert(merged_events.len());
+            merged_events.extend(new_transaction);
+        }
+        merged_events.extend(existing_transactions.flatten());
+
+        let num_unchanged_events = match num_unchanged_events {
+            Some(num_unchanged_events) => num_unchanged_events,
+            None => return Ok(()),
+        };
+        if let Some(first_changed_rowid) = existing_rowids.get(num_unchanged_events) {
+            tx.execute(
+                "DELETE FROM event_log WHERE rowid >= :rowid",
+                rusqlite::named_params! {":rowid": first_changed_rowid},
+            )
+            .wrap_err("Deleting events to be merged")?;
+        }
+        insert_events(&tx, merged_events.split_off(num_unchanged_events))?;
+        tx.commit()?;
+        Ok(())
+    }
+
     #[instrument]
     fn make_transaction_id_inner(
         &self,
@@ -583,32 +667,9 @@ ORDER BY rowid ASC
         }
 
         let tx = self.conn.unchecked_transaction()?;
-
-        let timestamp = now
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .wrap_err("Calculating event transaction timestamp")?
-            .as_secs_f64();
-        self.conn
-            .execute(
-                "
-            INSERT INTO event_transactions
-            (timestamp, message)
-            VALUES
-            (:timestamp, :message)
-        ",
-                rusqlite::named_params! {
-                    ":timestamp": timestamp,
-                    ":message": message,
-                },
-            )
-            .wrap_err("Creating event transaction")?;
-
-        // Ensure that we query `last_insert_rowid` in a transaction, in case
-        // there's another thread in this process making queries with the same
-        // SQLite connection.
-        let event_tx_id: isize = self.conn.last_insert_rowid().try_into()?;
+        let event_tx_id = insert_transaction(&tx, now, message)?;
         tx.commit()?;
-        Ok(EventTransactionId(event_tx_id))
+        Ok(event_tx_id)
     }
 
     /// Create a new event transaction ID to be used to insert subsequent
@@ -621,6 +682,117 @@ ORDER BY rowid ASC
         self.make_transaction_id_inner(now, message.as_ref())
     }
 
+    /// Get the `rowid` of each event in the database, ordered from oldest to
+    /// newest event.
+    fn get_rowids(&self) -> eyre::Result<Vec<i64>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT rowid FROM event_log ORDER BY rowid ASC")?;
+        let rowids: rusqlite::Result<Vec<i64>> = stmt
+            .query_map(rusqlite::params![], |row| row.get(0))?
+            .collect();
+        Ok(rowids?)
+    }
+
+    /// Replace the oldest events in the database with checkpoint events, in a
+    /// transaction. The checkpoint events are assigned a new event transaction
+    /// with the given message and the timestamp of the last replaced event.
+    /// Transactions which no longer have any associated events are deleted.
+    ///
+    /// `compute_checkpoint` is called with all of the events in the database
+    /// and returns the number of events to replace and the checkpoint events
+    /// to replace them with, or `None` if no events should be replaced. The
+    /// events are read and replaced in a single write transaction, so that
+    /// events added concurrently are never compacted without being included
+    /// in the checkpoint.
+    ///
+    /// The checkpoint events are ordered before all of the remaining events.
+    /// The remaining events are left in place.
+    ///
+    /// Returns: the number of events which were replaced and the number of
+    /// checkpoint events, or `None` if no events were replaced.
+    #[instrument(skip(compute_checkpoint))]
+    pub fn compact_events(
+        &self,
+        message: &str,
+        compute_checkpoint: impl FnOnce(&[Event]) -> eyre::Result<Option<(usize, Vec<Event>)>>,
+    ) -> eyre::Result<Option<(usize, usize)>> {
+        let tx = rusqlite::Transaction::new_unchecked(
+            self.conn,
+            rusqlite::TransactionBehavior::Immediate,
+        )?;
+        let events = self.get_events()?;
+        let (num_events, checkpoint_events) = match compute_checkpoint(&events)? {
+            Some((num_events, checkpoint_events)) if num_events > 0 => {
+                (num_events.min(events.len()), checkpoint_events)
+            }
+            Some(_) | None => return Ok(None),
+        };
+        let num_checkpoint_events = checkpoint_events.len();
+        let event_tx_id = insert_transaction(&tx, events[num_events - 1].get_timestamp(), message)?;
+        let rowids = self.get_rowids()?;
+        let last_compacted_rowid = rowids[num_events - 1];
+        let compacted_event_tx_ids: HashSet<isize> = events[..num_events]
+            .iter()
+            .map(|event| {
+                let EventTransactionId(event_tx_id) = event.get_event_tx_id();
+                event_tx_id
+            })
+            .collect();
+
+        tx.execute(
+            "DELETE FROM event_log WHERE rowid <= :rowid",
+            rusqlite::named_params! {":rowid": last_compacted_rowid},
+        )
+        .wrap_err("Deleting compacted events")?;
+
+        // Number the checkpoint events so that they immediately precede the
+        // remaining events. (SQLite permits negative rowids, if necessary.)
+        let first_remaining_rowid = last_compacted_rowid + 1;
+        let first_checkpoint_rowid = first_remaining_rowid - i64::try_from(num_checkpoint_events)?;
+        for (rowid, event) in (first_checkpoint_rowid..).zip(checkpoint_events) {
+            insert_event(&tx, Some(rowid), event.with_event_tx_id(event_tx_id))?;
+        }
+
+        for event_tx_id in compacted_event_tx_ids {
+            tx.execute(
+                "
+DELETE FROM event_transactions
+WHERE event_tx_id = :event_tx_id
+AND NOT EXISTS (
+    SELECT 1 FROM event_log WHERE event_log.event_tx_id = :event_tx_id
+)
+",
+                rusqlite::named_params! {":event_tx_id": event_tx_id},
+            )
+            .wrap_err("Deleting compacted transactions")?;
+        }
+        tx.commit()?;
+        Ok(Some((num_events, num_checkpoint_events)))
+    }
+
+    /// Get the size of the database on disk, in bytes.
+    #[instrument]
+    pub fn get_size_in_bytes(&self) -> eyre::Result<u64> {
+        let page_count: i64 =
+            self.conn
+                .query_row("PRAGMA page_count", rusqlite::params![], |row| row.get(0))?;
+        let page_size: i64 =
+            self.conn
+                .query_row("PRAGMA page_size", rusqlite::params![], |row| row.get(0))?;
+        let size: u64 = (page_count * page_size).try_into()?;
+        Ok(size)
+    }
+
+    /// Rebuild the database file to release the space used by deleted rows.
+    #[instrument]
+    pub fn vacuum(&self) -> eyre::Result<()> {
+        self.conn
+            .execute("VACUUM", rusqlite::params![])
+            .wrap_err("Vacuuming database")?;
+        Ok(())
+    }
+
     /// Get the message associated with the given transaction.
     pub fn get_transaction_message(&self, event_tx_id: EventTransactionId) -> eyre::Result<String> {
         let EventTransactionId(event_tx_id) = event_tx_id;
@@ -779,6 +951,88 @@ impl EventReplayer {
         Ok(result)
     }
 
+    /// Construct the replayer from only the provided events, which should be
+    /// ordered from oldest to newest.
+    #[instrument]
+    pub fn from_events(repo: &Repo, events: &[Event]) -> eyre::Result<Self> {
+        let main_branch_reference_name = repo.get_main_branch()?.get_reference_name()?;
+        let mut result = EventReplayer::new(main_branch_reference_name);
+        for event in events {
+            result.process_event(event);
+        }
+        Ok(result)
+    }
+
+    /// Produce a minimal list of events which, when replayed, reproduces the
+    /// state of all observed commits and references after the last processed
+    /// event. In particular, the latest rewrite event for each obsolete commit
+    /// is preserved, so that it's still possible to determine what it was
+    /// rewritten into.
+    ///
+    /// The produced events are assigned to the provided transaction.
+    pub fn get_checkpoint_events(&self, event_tx_id: EventTransactionId) -> Vec<Event> {
+        let timestamp = match self.events.last() {
+            Some(event) => event
+                .get_timestamp()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            None => return Vec::new(),
+        };
+
+        let ref_events = self
+            .ref_locations
+            .iter()
+            .sorted_by(|(lhs, _), (rhs, _)| lhs.as_str().cmp(rhs.as_str()))
+            .map(|(ref_name, oid)| Event::RefUpdateEvent {
+                timestamp,
+                event_tx_id,
+                ref_name: ref_name.clone(),
+                old_oid: MaybeZeroOid::Zero,
+                new_oid: (*oid).into(),
+                message: None,
+            });
+
+        let latest_commit_events: BTreeMap<isize, &Event> = self
+            .commit_history
+            .values()
+            .filter_map(|history| history.last())
+            .map(|event_info| (event_info.id, &event_info.event))
+            .collect();
+        let commit_events = latest_commit_events.into_values().map(|event| {
+            let mut event = event.clone();
+            match &mut event {
+                Event::RewriteEvent {
+                    event_tx_id: old_event_tx_id,
+                    ..
+                }
+                | Event::RefUpdateEvent {
+                    event_tx_id: old_event_tx_id,
+                    ..
+                }
+                | Event::CommitEvent {
+                    event_tx_id: old_event_tx_id,
+                    ..
+                }
+                | Event::ObsoleteEvent {
+                    event_tx_id: old_event_tx_id,
+                    ..
+                }
+                | Event::UnobsoleteEvent {
+                    event_tx_id: old_event_tx_id,
+                    ..
+                }
+                | Event::WorkingCopySnapshot {
+                    event_tx_id: old_event_tx_id,
+                    ..
+                } => *old_event_tx_id = event_tx_id,
+            }
+            event
+        });
+
+        ref_events.chain(commit_events).collect()
+    }
+
     /// Process the given event.
     ///
     /// This also sets the event cursor to point to immediately after the event
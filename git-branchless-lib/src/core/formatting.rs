@@ -133,6 +133,14 @@ pub struct Glyphs {
     /// Bullet-point character for a list of newline-separated items.
     pub bullet_point: &'static str,
 
+    /// Character preceding the number of commits that a branch is ahead of
+    /// its remote branch.
+    pub remote_ahead: &'static str,
+
+    /// Character preceding the number of commits that a branch is behind its
+    /// remote branch.
+    pub remote_behind: &'static str,
+
     /// Arrow character used when printing a commit cycle.
     pub cycle_arrow: &'static str,
 
@@ -181,6 +189,8 @@ impl Glyphs {
             commit_merge: "&",
             branch_arrow: ">",
             bullet_point: "-",
+            remote_ahead: "+",
+            remote_behind: "-",
             cycle_arrow: ">",
             cycle_horizontal_line: "-",
             cycle_vertical_line: "|",
@@ -210,6 +220,8 @@ impl Glyphs {
             commit_main_obsolete_head: "❖",
             branch_arrow: "ᐅ",
             bullet_point: "•",
+            remote_ahead: "↑",
+            remote_behind: "↓",
             cycle_arrow: "ᐅ",
             cycle_horizontal_line: "─",
             cycle_vertical_line: "│",
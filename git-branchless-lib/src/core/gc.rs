@@ -9,10 +9,13 @@
 //! active.
 
 use std::fmt::Write;
+use std::time::{Duration, SystemTime};
 
 use eyre::Context;
 use tracing::instrument;
 
+use crate::core::config::{get_event_log_max_age_days, get_event_log_max_events};
+use crate::core::conflicts::get_conflict_ref_commit_oid;
 use crate::core::effects::Effects;
 use crate::core::eventlog::{
     is_gc_ref, CommitActivityStatus, EventCursor, EventLogDb, EventReplayer,
@@ -34,6 +37,16 @@ pub fn find_dangling_references<'repo>(
             continue;
         }
 
+        // Conflict references point to trees rather than commits, so check the
+        // status of the conflict commit named by the reference instead.
+        if let Some(commit_oid) = get_conflict_ref_commit_oid(&reference_name) {
+            match event_replayer.get_cursor_commit_activity_status(event_cursor, commit_oid) {
+                CommitActivityStatus::Active | CommitActivityStatus::Inactive => {}
+                CommitActivityStatus::Obsolete => result.push(reference),
+            }
+            continue;
+        }
+
         // The graph only contains commits, so we don't need to handle the
         // case of the reference not peeling to a valid commit. (It might be
         // a reference to a different kind of object.)
@@ -94,6 +107,124 @@ pub fn mark_commit_reachable(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Resul
     Ok(())
 }
 
+/// Determines which events are retained when compacting the event log. Events
+/// which violate either limit are compacted.
+#[derive(Clone, Debug, Default)]
+pub struct EventLogRetentionOptions {
+    /// Compact events older than this.
+    pub max_age: Option<Duration>,
+
+    /// Compact all but this many of the most recent events.
+    pub max_events: Option<usize>,
+}
+
+impl EventLogRetentionOptions {
+    /// Read the retention options from the repository configuration.
+    #[instrument]
+    pub fn from_config(repo: &Repo) -> eyre::Result<Self> {
+        let max_age = get_event_log_max_age_days(repo)?
+            .map(|days| Duration::from_secs(u64::from(days) * 24 * 60 * 60));
+        let max_events = get_event_log_max_events(repo)?;
+        Ok(Self {
+            max_age,
+            max_events,
+        })
+    }
+}
+
+/// The result of compacting the event log.
+#[derive(Clone, Debug)]
+pub struct CompactEventLogResult {
+    /// The number of events which were replaced by the checkpoint.
+    pub num_compacted_events: usize,
+
+    /// The number of events in the checkpoint.
+    pub num_checkpoint_events: usize,
+
+    /// The reduction in size of the database, in bytes.
+    pub num_bytes_reclaimed: u64,
+}
+
+/// Compact the events which fall outside of the retention limits into a
+/// checkpoint, which preserves the state of all observed commits (including
+/// which commits were rewritten into which) and references as of the last
+/// compacted event. Transactions are never split, so slightly more events than
+/// requested may be retained.
+///
+/// Undo history before the checkpoint is lost.
+///
+/// Returns: `None` if no events needed to be compacted.
+#[instrument]
+pub fn compact_event_log(
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    now: SystemTime,
+    options: &EventLogRetentionOptions,
+) -> eyre::Result<Option<CompactEventLogResult>> {
+    let EventLogRetentionOptions {
+        max_age,
+        max_events,
+    } = options;
+
+    let size_before = event_log_db.get_size_in_bytes()?;
+    let compact_result = event_log_db.compact_events("gc: event log checkpoint", |events| {
+        let num_events_by_count = match max_events {
+            Some(max_events) => events.len().saturating_sub(*max_events),
+            None => 0,
+        };
+        let num_events_by_age = match max_age {
+            Some(max_age) => {
+                let cutoff = now.checked_sub(*max_age).unwrap_or(SystemTime::UNIX_EPOCH);
+                events
+                    .iter()
+                    .position(|event| event.get_timestamp() >= cutoff)
+                    .unwrap_or(events.len())
+            }
+            None => 0,
+        };
+
+        // Don't split a transaction between the checkpoint and the retained
+        // events.
+        let mut num_events_to_compact = num_events_by_count.max(num_events_by_age);
+        while num_events_to_compact > 0
+            && num_events_to_compact < events.len()
+            && events[num_events_to_compact].get_event_tx_id()
+                == events[num_events_to_compact - 1].get_event_tx_id()
+        {
+            num_events_to_compact -= 1;
+        }
+        if num_events_to_compact == 0 {
+            return Ok(None);
+        }
+
+        let compacted_events = &events[..num_events_to_compact];
+        let last_compacted_event = &compacted_events[num_events_to_compact - 1];
+        let event_replayer = EventReplayer::from_events(repo, compacted_events)?;
+
+        // Avoid repeatedly replacing a previous checkpoint with an equivalent
+        // one. The checkpoint is only assigned its own transaction once we
+        // know that it will be used.
+        let checkpoint_events =
+            event_replayer.get_checkpoint_events(last_compacted_event.get_event_tx_id());
+        if checkpoint_events.len() >= num_events_to_compact {
+            return Ok(None);
+        }
+        Ok(Some((num_events_to_compact, checkpoint_events)))
+    })?;
+    let (num_compacted_events, num_checkpoint_events) = match compact_result {
+        Some(compact_result) => compact_result,
+        None => return Ok(None),
+    };
+    event_log_db.vacuum()?;
+    let size_after = event_log_db.get_size_in_bytes()?;
+
+    Ok(Some(CompactEventLogResult {
+        num_compacted_events,
+        num_checkpoint_events,
+        num_bytes_reclaimed: size_before.saturating_sub(size_after),
+    }))
+}
+
 /// Run branchless's garbage collection.
 ///
 /// Frees any references to commits which are no longer visible in the smartlog.
@@ -124,5 +255,37 @@ pub fn gc(effects: &Effects) -> eyre::Result<()> {
         effects.get_output_stream(),
         "branchless: {num_dangling_references} deleted",
     )?;
+
+    let retention_options = EventLogRetentionOptions::from_config(&repo)?;
+    if let Some(CompactEventLogResult {
+        num_compacted_events,
+        num_checkpoint_events,
+        num_bytes_reclaimed,
+    }) = compact_event_log(&repo, &event_log_db, SystemTime::now(), &retention_options)?
+    {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: compacted {} into {}",
+            Pluralize {
+                determiner: None,
+                amount: num_compacted_events,
+                unit: ("event", "events"),
+            },
+            Pluralize {
+                determiner: None,
+                amount: num_checkpoint_events,
+                unit: ("checkpoint event", "checkpoint events"),
+            },
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: reclaimed {} from the event log",
+            Pluralize {
+                determiner: None,
+                amount: usize::try_from(num_bytes_reclaimed)?,
+                unit: ("byte", "bytes"),
+            },
+        )?;
+    }
     Ok(())
 }
@@ -0,0 +1,235 @@
+//! Versioned schema migrations for the branchless SQLite database.
+//!
+//! Each migration is applied exactly once, in order, and recorded in the
+//! `schema_version` table. Databases created before the `schema_version` table
+//! existed are treated as being at version 0; the first migration only creates
+//! tables which don't already exist, so such databases are upgraded in place.
+//!
+//! To change the schema, append a new entry to `MIGRATIONS`. Never edit or
+//! reorder an existing entry, since it may already have been applied to users'
+//! databases.
+
+use thiserror::Error;
+use tracing::instrument;
+
+/// A single forward migration of the database schema.
+#[derive(Debug)]
+struct Migration {
+    /// The schema version after this migration has been applied.
+    version: i64,
+
+    /// A short description of the migration, recorded alongside the version.
+    description: &'static str,
+
+    /// The SQL statements which perform the migration.
+    sql: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create event log tables",
+        sql: "
+CREATE TABLE IF NOT EXISTS event_log (
+    timestamp REAL NOT NULL,
+    type TEXT NOT NULL,
+    event_tx_id INTEGER NOT NULL,
+    old_ref TEXT,
+    new_ref TEXT,
+    ref_name TEXT,
+    message TEXT
+);
+
+CREATE TABLE IF NOT EXISTS event_transactions (
+    timestamp REAL NOT NULL,
+
+    -- Set as `PRIMARY KEY` to have SQLite select a value automatically. Set as
+    -- `AUTOINCREMENT` to ensure that SQLite doesn't reuse the value later if a
+    -- row is deleted (such as when the event log is compacted).
+    event_tx_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+
+    message TEXT
+);
+",
+    },
+    Migration {
+        version: 2,
+        description: "index events by transaction",
+        sql: "
+CREATE INDEX IF NOT EXISTS event_log_event_tx_id ON event_log (event_tx_id);
+",
+    },
+];
+
+/// The latest schema version known to this version of git-branchless.
+pub const LATEST_SCHEMA_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+#[allow(missing_docs)]
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error(
+        "the database has schema version {version}, but this version of git-branchless only supports up to schema version {supported_version}; please upgrade git-branchless"
+    )]
+    UnsupportedSchemaVersion {
+        version: i64,
+        supported_version: i64,
+    },
+
+    #[error("could not apply migration to schema version {version} ({description}): {source}")]
+    ApplyMigration {
+        source: rusqlite::Error,
+        version: i64,
+        description: &'static str,
+    },
+
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+fn get_schema_version(conn: &rusqlite::Connection) -> Result<i64, MigrationError> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER NOT NULL PRIMARY KEY,
+    description TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )?;
+    let version: Option<i64> = conn.query_row(
+        "SELECT MAX(version) FROM schema_version",
+        rusqlite::params![],
+        |row| row.get(0),
+    )?;
+    Ok(version.unwrap_or(0))
+}
+
+/// Bring the database schema up to `LATEST_SCHEMA_VERSION` by applying any
+/// migrations which haven't been applied yet.
+///
+/// Returns an error if the database was created by a newer version of
+/// git-branchless, since its schema may not be understood by this version.
+#[instrument]
+pub fn apply_migrations(conn: &rusqlite::Connection) -> Result<(), MigrationError> {
+    let version = get_schema_version(conn)?;
+    if version > LATEST_SCHEMA_VERSION {
+        return Err(MigrationError::UnsupportedSchemaVersion {
+            version,
+            supported_version: LATEST_SCHEMA_VERSION,
+        });
+    } else if version == LATEST_SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    // Take the write lock before re-reading the version, in case another
+    // process is migrating the database concurrently.
+    let tx = rusqlite::Transaction::new_unchecked(conn, rusqlite::TransactionBehavior::Immediate)?;
+    let version = get_schema_version(&tx)?;
+    for Migration {
+        version: migration_version,
+        description,
+        sql,
+    } in MIGRATIONS
+    {
+        if *migration_version <= version {
+            continue;
+        }
+        tx.execute_batch(sql)
+            .and_then(|()| {
+                tx.execute(
+                    "INSERT INTO schema_version (version, description) VALUES (?1, ?2)",
+                    rusqlite::params![migration_version, description],
+                )
+            })
+            .map_err(|source| MigrationError::ApplyMigration {
+                source,
+                version: *migration_version,
+                description,
+            })?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_table_names(conn: &rusqlite::Connection) -> eyre::Result<Vec<String>> {
+        let mut stmt = conn.prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )?;
+        let names = stmt
+            .query_map(rusqlite::params![], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i64::try_from(i).unwrap() + 1);
+        }
+    }
+
+    #[test]
+    fn test_apply_migrations_new_database() -> eyre::Result<()> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        apply_migrations(&conn)?;
+        assert_eq!(get_schema_version(&conn)?, LATEST_SCHEMA_VERSION);
+        insta::assert_debug_snapshot!(get_table_names(&conn)?, @r###"
+        [
+            "event_log",
+            "event_transactions",
+            "schema_version",
+        ]
+        "###);
+
+        // Applying migrations again should be a no-op.
+        apply_migrations(&conn)?;
+        assert_eq!(get_schema_version(&conn)?, LATEST_SCHEMA_VERSION);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_migrations_unversioned_database() -> eyre::Result<()> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        conn.execute_batch(MIGRATIONS[0].sql)?;
+        conn.execute(
+            "INSERT INTO event_transactions (timestamp, message) VALUES (0.0, 'foo')",
+            rusqlite::params![],
+        )?;
+
+        apply_migrations(&conn)?;
+        assert_eq!(get_schema_version(&conn)?, LATEST_SCHEMA_VERSION);
+        let num_transactions: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM event_transactions",
+            rusqlite::params![],
+            |row| row.get(0),
+        )?;
+        assert_eq!(num_transactions, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_migrations_newer_database() -> eyre::Result<()> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        apply_migrations(&conn)?;
+        conn.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?1, 'from the future')",
+            rusqlite::params![LATEST_SCHEMA_VERSION + 1],
+        )?;
+
+        match apply_migrations(&conn) {
+            Err(MigrationError::UnsupportedSchemaVersion {
+                version,
+                supported_version,
+            }) => {
+                assert_eq!(version, LATEST_SCHEMA_VERSION + 1);
+                assert_eq!(supported_version, LATEST_SCHEMA_VERSION);
+            }
+            result => panic!("Expected unsupported schema version error, got: {result:?}"),
+        }
+        Ok(())
+    }
+}
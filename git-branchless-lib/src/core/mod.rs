@@ -1,13 +1,17 @@
 //! Core algorithms and data structures.
 
+pub mod change_id;
 pub mod check_out;
 pub mod config;
+pub mod conflicts;
 pub mod dag;
 pub mod effects;
 pub mod eventlog;
 pub mod formatting;
 pub mod gc;
+pub mod migrations;
 pub mod node_descriptors;
+pub mod obsmarkers;
 pub mod repo_ext;
 pub mod rewrite;
 pub mod task;
@@ -17,12 +17,14 @@ use tracing::instrument;
 
 use crate::core::config::{
     get_commit_descriptors_branches, get_commit_descriptors_differential_revision,
-    get_commit_descriptors_relative_time,
+    get_commit_descriptors_relative_time, get_commit_descriptors_remote_status,
 };
 use crate::git::{
-    CategorizedReferenceName, Commit, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo,
+    BranchType, CategorizedReferenceName, Commit, NonZeroOid, ReferenceName, Repo,
+    ResolvedReferenceInfo,
 };
 
+use super::conflicts::is_conflict_commit;
 use super::eventlog::{Event, EventCursor, EventReplayer};
 use super::formatting::{Glyphs, StyledStringBuilder};
 use super::repo_ext::RepoReferencesSnapshot;
@@ -291,6 +293,40 @@ impl<'a> NodeDescriptor for ObsolescenceExplanationDescriptor<'a> {
     }
 }
 
+/// For conflict commits, indicate that the commit has unresolved merge
+/// conflicts.
+#[derive(Debug)]
+pub struct ConflictDescriptor;
+
+impl ConflictDescriptor {
+    /// Constructor.
+    pub fn new() -> eyre::Result<Self> {
+        Ok(ConflictDescriptor)
+    }
+}
+
+impl NodeDescriptor for ConflictDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let is_conflict_commit = match object {
+            NodeObject::Commit { commit } => is_conflict_commit(commit)?,
+            NodeObject::GarbageCollected { oid: _ } => false,
+        };
+        if is_conflict_commit {
+            Ok(Some(StyledString::styled(
+                "(conflicted)",
+                BaseColor::Red.light(),
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 /// Display branches that point to a given commit.
 #[derive(Debug)]
 pub struct BranchesDescriptor<'a> {
@@ -378,6 +414,168 @@ impl<'a> NodeDescriptor for BranchesDescriptor<'a> {
     }
 }
 
+/// How a local branch compares to its remote branch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RemoteStatus {
+    /// There's no remote branch for the branch.
+    Unpushed,
+
+    /// The remote branch points to the same commit as the branch.
+    UpToDate,
+
+    /// The branch has commits which the remote branch doesn't, and vice-versa.
+    Diverged { ahead: usize, behind: usize },
+
+    /// The branch has commits which the remote branch doesn't.
+    Ahead(usize),
+
+    /// The remote branch has commits which the branch doesn't.
+    Behind(usize),
+}
+
+/// Display whether the branches that point to a given commit are pushed to,
+/// ahead of, behind, or diverged from their remote branches.
+#[derive(Debug)]
+pub struct RemoteStatusDescriptor<'a> {
+    is_enabled: bool,
+    repo: &'a Repo,
+    references_snapshot: &'a RepoReferencesSnapshot,
+    redactor: &'a Redactor,
+}
+
+impl<'a> RemoteStatusDescriptor<'a> {
+    /// Constructor.
+    pub fn new(
+        repo: &'a Repo,
+        references_snapshot: &'a RepoReferencesSnapshot,
+        redactor: &'a Redactor,
+    ) -> eyre::Result<Self> {
+        let is_enabled = get_commit_descriptors_remote_status(repo)?;
+        Ok(RemoteStatusDescriptor {
+            is_enabled,
+            repo,
+            references_snapshot,
+            redactor,
+        })
+    }
+
+    /// Compare the branch to the branch of the same name on its push remote,
+    /// or to its upstream branch if there isn't one.
+    fn get_remote_status(
+        &self,
+        branch_name: &str,
+        local_oid: NonZeroOid,
+    ) -> eyre::Result<RemoteStatus> {
+        let branch = match self.repo.find_branch(branch_name, BranchType::Local)? {
+            Some(branch) => branch,
+            None => return Ok(RemoteStatus::Unpushed),
+        };
+        let push_branch_oid = match branch.get_push_remote_name()? {
+            Some(remote_name) => match self
+                .repo
+                .find_branch(&format!("{remote_name}/{branch_name}"), BranchType::Remote)?
+            {
+                Some(remote_branch) => remote_branch.get_oid()?,
+                None => None,
+            },
+            None => None,
+        };
+        let remote_oid = match push_branch_oid {
+            Some(remote_oid) => remote_oid,
+            None => match branch.get_upstream_branch_target()? {
+                Some(remote_oid) => remote_oid,
+                None => return Ok(RemoteStatus::Unpushed),
+            },
+        };
+        if remote_oid == local_oid {
+            return Ok(RemoteStatus::UpToDate);
+        }
+
+        let status = match self.repo.get_ahead_behind(local_oid, remote_oid)? {
+            (ahead, 0) => RemoteStatus::Ahead(ahead),
+            (0, behind) => RemoteStatus::Behind(behind),
+            (ahead, behind) => RemoteStatus::Diverged { ahead, behind },
+        };
+        Ok(status)
+    }
+}
+
+impl<'a> NodeDescriptor for RemoteStatusDescriptor<'a> {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+
+        let local_oid = object.get_oid();
+        let mut branch_names: Vec<&ReferenceName> =
+            match self.references_snapshot.branch_oid_to_names.get(&local_oid) {
+                Some(branch_names) => branch_names
+                    .iter()
+                    .filter(|branch_name| {
+                        matches!(
+                            CategorizedReferenceName::new(branch_name),
+                            CategorizedReferenceName::LocalBranch { .. }
+                        )
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+        if branch_names.is_empty() {
+            return Ok(None);
+        }
+        branch_names.sort_unstable();
+
+        let mut result = StyledStringBuilder::new();
+        let num_branches = branch_names.len();
+        for (index, branch_name) in branch_names.into_iter().enumerate() {
+            if index > 0 {
+                result = result.append_plain(", ");
+            }
+            // Only name the branch if it's ambiguous which branch the status
+            // is for.
+            if num_branches > 1 {
+                let redacted_branch_name = self.redactor.redact_ref_name(branch_name.clone());
+                result = result.append_plain(format!(
+                    "{} ",
+                    CategorizedReferenceName::new(&redacted_branch_name).render_suffix()
+                ));
+            }
+
+            let status = self.get_remote_status(
+                &CategorizedReferenceName::new(branch_name).render_suffix(),
+                local_oid,
+            )?;
+            result = match status {
+                RemoteStatus::Unpushed => {
+                    result.append_styled("unpushed", BaseColor::Yellow.dark())
+                }
+                RemoteStatus::UpToDate => result.append_styled("pushed", BaseColor::Green.dark()),
+                RemoteStatus::Ahead(ahead) => result.append_styled(
+                    format!("{}{ahead}", glyphs.remote_ahead),
+                    BaseColor::Yellow.dark(),
+                ),
+                RemoteStatus::Behind(behind) => result.append_styled(
+                    format!("{}{behind}", glyphs.remote_behind),
+                    BaseColor::Yellow.dark(),
+                ),
+                RemoteStatus::Diverged { ahead, behind } => result.append_styled(
+                    format!(
+                        "diverged {}{ahead} {}{behind}",
+                        glyphs.remote_ahead, glyphs.remote_behind
+                    ),
+                    BaseColor::Red.light(),
+                ),
+            };
+        }
+        Ok(Some(result.build()))
+    }
+}
+
 /// Display the associated Phabricator revision for a given commit.
 #[derive(Debug)]
 pub struct DifferentialRevisionDescriptor<'a> {
@@ -396,7 +594,9 @@ impl<'a> DifferentialRevisionDescriptor<'a> {
     }
 }
 
-fn extract_diff_number(message: &str) -> Option<String> {
+/// Extract the revision number, such as `D123`, from the `Differential
+/// Revision:` line of a commit message, if it has one.
+pub fn extract_diff_number(message: &str) -> Option<String> {
     lazy_static! {
         static ref RE: Regex = Regex::new(
             r"(?mx)
@@ -0,0 +1,553 @@
+//! Share obsolescence information between clones of a repository.
+//!
+//! The event log is local to each clone. When a collaborator rewrites a stack
+//! of commits and pushes the result, other clones would otherwise continue to
+//! show the old versions of the commits as active drafts. To address this,
+//! rewrite, hide, and unhide events can be exported as "obsolescence markers"
+//! into Git objects stored under [`OBSMARKERS_REF_NAME`]. That reference can
+//! be pushed and fetched like any other reference, and the markers can then be
+//! imported into the event log of another clone.
+//!
+//! The markers are stored as a single blob in the tree of the commit pointed to
+//! by the reference, one marker per line. Each export creates a new commit
+//! whose parents are the previously-exported commit and any fetched remote
+//! marker commits, so that pushing the markers is always a fast-forward.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use eyre::Context;
+use itertools::Itertools;
+use tracing::instrument;
+
+use crate::core::eventlog::{Event, EventLogDb, EventTransactionId};
+use crate::git::{
+    hydrate_tree, FileMode, MaybeZeroOid, NonZeroOid, ReferenceName, Repo, Signature,
+};
+
+/// The reference under which the local obsolescence markers are exported.
+pub const OBSMARKERS_REF_NAME: &str = "refs/branchless/obsmarkers";
+
+/// The prefix of references under which obsolescence markers fetched from
+/// remotes are stored.
+pub const REMOTE_OBSMARKERS_REF_PREFIX: &str = "refs/branchless/remotes/";
+
+/// The name of the file in the obsolescence marker commit's tree containing the
+/// serialized markers.
+const OBSMARKERS_FILE_NAME: &str = "obsmarkers";
+
+/// Get the name of the reference under which the obsolescence markers fetched
+/// from the given remote are stored.
+///
+/// To fetch the markers automatically, configure a refspec for the remote such
+/// as `+refs/branchless/obsmarkers:refs/branchless/remotes/origin/obsmarkers`.
+pub fn get_remote_obsmarkers_ref_name(remote_name: &str) -> ReferenceName {
+    ReferenceName::from(format!(
+        "{REMOTE_OBSMARKERS_REF_PREFIX}{remote_name}/obsmarkers"
+    ))
+}
+
+/// Determine whether the given reference stores obsolescence markers fetched
+/// from a remote.
+pub fn is_remote_obsmarkers_ref(reference_name: &ReferenceName) -> bool {
+    let reference_name = reference_name.as_str();
+    reference_name.starts_with(REMOTE_OBSMARKERS_REF_PREFIX)
+        && reference_name.ends_with("/obsmarkers")
+}
+
+/// A record of a commit having been rewritten, hidden, or unhidden, suitable
+/// for sharing with other clones of the repository.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Obsmarker {
+    /// Corresponds to [`Event::RewriteEvent`].
+    Rewrite {
+        /// The timestamp of the original event.
+        timestamp: f64,
+
+        /// The OID of the commit before the rewrite.
+        old_commit_oid: NonZeroOid,
+
+        /// The OID of the commit after the rewrite, or zero if the commit was
+        /// deleted.
+        new_commit_oid: MaybeZeroOid,
+    },
+
+    /// Corresponds to [`Event::ObsoleteEvent`].
+    Obsolete {
+        /// The timestamp of the original event.
+        timestamp: f64,
+
+        /// The OID of the commit that was obsoleted.
+        commit_oid: NonZeroOid,
+    },
+
+    /// Corresponds to [`Event::UnobsoleteEvent`].
+    Unobsolete {
+        /// The timestamp of the original event.
+        timestamp: f64,
+
+        /// The OID of the commit that was unobsoleted.
+        commit_oid: NonZeroOid,
+    },
+}
+
+impl Obsmarker {
+    /// Convert the given event into an obsolescence marker, if it conveys
+    /// obsolescence information.
+    pub fn from_event(event: &Event) -> Option<Self> {
+        match event {
+            Event::RewriteEvent {
+                timestamp,
+                event_tx_id: _,
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                new_commit_oid,
+            } => Some(Obsmarker::Rewrite {
+                timestamp: *timestamp,
+                old_commit_oid: *old_commit_oid,
+                new_commit_oid: *new_commit_oid,
+            }),
+            Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id: _,
+                commit_oid,
+            } => Some(Obsmarker::Obsolete {
+                timestamp: *timestamp,
+                commit_oid: *commit_oid,
+            }),
+            Event::UnobsoleteEvent {
+                timestamp,
+                event_tx_id: _,
+                commit_oid,
+            } => Some(Obsmarker::Unobsolete {
+                timestamp: *timestamp,
+                commit_oid: *commit_oid,
+            }),
+            Event::RewriteEvent {
+                old_commit_oid: MaybeZeroOid::Zero,
+                ..
+            }
+            | Event::RefUpdateEvent { .. }
+            | Event::CommitEvent { .. }
+            | Event::WorkingCopySnapshot { .. } => None,
+        }
+    }
+
+    /// Convert this obsolescence marker into an event belonging to the given
+    /// transaction.
+    pub fn into_event(self, event_tx_id: EventTransactionId) -> Event {
+        match self {
+            Obsmarker::Rewrite {
+                timestamp,
+                old_commit_oid,
+                new_commit_oid,
+            } => Event::RewriteEvent {
+                timestamp,
+                event_tx_id,
+                old_commit_oid: old_commit_oid.into(),
+                new_commit_oid,
+            },
+            Obsmarker::Obsolete {
+                timestamp,
+                commit_oid,
+            } => Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+            },
+            Obsmarker::Unobsolete {
+                timestamp,
+                commit_oid,
+            } => Event::UnobsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+            },
+        }
+    }
+
+    fn get_timestamp(&self) -> f64 {
+        match self {
+            Obsmarker::Rewrite { timestamp, .. }
+            | Obsmarker::Obsolete { timestamp, .. }
+            | Obsmarker::Unobsolete { timestamp, .. } => *timestamp,
+        }
+    }
+
+    /// Get the OIDs of the commits which must be present in the repository for
+    /// this marker to be meaningful.
+    fn get_commit_oids(&self) -> Vec<NonZeroOid> {
+        match self {
+            Obsmarker::Rewrite {
+                timestamp: _,
+                old_commit_oid,
+                new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+            } => vec![*old_commit_oid, *new_commit_oid],
+            Obsmarker::Rewrite {
+                timestamp: _,
+                old_commit_oid,
+                new_commit_oid: MaybeZeroOid::Zero,
+            } => vec![*old_commit_oid],
+            Obsmarker::Obsolete {
+                timestamp: _,
+                commit_oid,
+            }
+            | Obsmarker::Unobsolete {
+                timestamp: _,
+                commit_oid,
+            } => vec![*commit_oid],
+        }
+    }
+}
+
+impl Display for Obsmarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Obsmarker::Rewrite {
+                timestamp,
+                old_commit_oid,
+                new_commit_oid,
+            } => write!(f, "rewrite {timestamp} {old_commit_oid} {new_commit_oid}"),
+            Obsmarker::Obsolete {
+                timestamp,
+                commit_oid,
+            } => write!(f, "obsolete {timestamp} {commit_oid}"),
+            Obsmarker::Unobsolete {
+                timestamp,
+                commit_oid,
+            } => write!(f, "unobsolete {timestamp} {commit_oid}"),
+        }
+    }
+}
+
+impl FromStr for Obsmarker {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect_vec();
+        let obsmarker = match fields.as_slice() {
+            ["rewrite", timestamp, old_commit_oid, new_commit_oid] => Obsmarker::Rewrite {
+                timestamp: timestamp.parse()?,
+                old_commit_oid: old_commit_oid.parse()?,
+                new_commit_oid: new_commit_oid.parse()?,
+            },
+            ["obsolete", timestamp, commit_oid] => Obsmarker::Obsolete {
+                timestamp: timestamp.parse()?,
+                commit_oid: commit_oid.parse()?,
+            },
+            ["unobsolete", timestamp, commit_oid] => Obsmarker::Unobsolete {
+                timestamp: timestamp.parse()?,
+                commit_oid: commit_oid.parse()?,
+            },
+            _ => eyre::bail!("Unrecognized obsolescence marker: {s:?}"),
+        };
+        Ok(obsmarker)
+    }
+}
+
+/// Read the obsolescence markers stored at the given reference. Returns an
+/// empty list if the reference does not exist.
+#[instrument]
+pub fn read_obsmarkers(repo: &Repo, ref_name: &ReferenceName) -> eyre::Result<Vec<Obsmarker>> {
+    let commit = match repo.find_reference(ref_name)? {
+        Some(reference) => match reference.peel_to_commit()? {
+            Some(commit) => commit,
+            None => eyre::bail!("Reference {ref_name:?} does not point to a commit"),
+        },
+        None => return Ok(Default::default()),
+    };
+    let tree = commit.get_tree()?;
+    let blob_oid = match tree.get_oid_for_path(Path::new(OBSMARKERS_FILE_NAME))? {
+        Some(MaybeZeroOid::NonZero(blob_oid)) => blob_oid,
+        Some(MaybeZeroOid::Zero) | None => return Ok(Default::default()),
+    };
+    let blob = repo.find_blob_or_fail(blob_oid)?;
+    let contents = std::str::from_utf8(blob.get_content())
+        .wrap_err_with(|| format!("Decoding obsolescence markers at {ref_name:?}"))?;
+    contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(Obsmarker::from_str)
+        .collect()
+}
+
+/// Combine the given lists of markers, removing duplicates and ordering the
+/// result by timestamp, so that the same set of markers always serializes to
+/// the same contents.
+fn merge_obsmarkers(obsmarkers: impl IntoIterator<Item = Obsmarker>) -> Vec<Obsmarker> {
+    let mut seen = HashSet::new();
+    let mut result = obsmarkers
+        .into_iter()
+        .filter(|obsmarker| seen.insert(obsmarker.to_string()))
+        .collect_vec();
+    result.sort_by(|lhs, rhs| {
+        lhs.get_timestamp()
+            .partial_cmp(&rhs.get_timestamp())
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| lhs.to_string().cmp(&rhs.to_string()))
+    });
+    result
+}
+
+/// The result of exporting obsolescence markers.
+#[derive(Clone, Debug)]
+pub struct ExportObsmarkersResult {
+    /// The commit now pointed to by [`OBSMARKERS_REF_NAME`], if any markers
+    /// have been exported.
+    pub commit_oid: Option<NonZeroOid>,
+
+    /// The total number of markers stored at [`OBSMARKERS_REF_NAME`].
+    pub num_obsmarkers: usize,
+
+    /// The number of markers which were not previously stored at
+    /// [`OBSMARKERS_REF_NAME`].
+    pub num_new_obsmarkers: usize,
+}
+
+/// Export the obsolescence markers from the local event log into
+/// [`OBSMARKERS_REF_NAME`], merging them with any previously-exported markers
+/// and any markers fetched from remotes.
+#[instrument]
+pub fn export_obsmarkers(
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+) -> eyre::Result<ExportObsmarkersResult> {
+    let local_ref_name = ReferenceName::from(OBSMARKERS_REF_NAME);
+    let existing_commit = match repo.find_reference(&local_ref_name)? {
+        Some(reference) => reference.peel_to_commit()?,
+        None => None,
+    };
+    let existing_obsmarkers = read_obsmarkers(repo, &local_ref_name)?;
+
+    let mut remote_obsmarkers = Vec::new();
+    let mut remote_commits = Vec::new();
+    for reference in repo.get_all_references()? {
+        let ref_name = reference.get_name()?;
+        if !is_remote_obsmarkers_ref(&ref_name) {
+            continue;
+        }
+        if let Some(commit) = reference.peel_to_commit()? {
+            let is_already_merged = match &existing_commit {
+                Some(existing_commit) => {
+                    repo.find_merge_base(commit.get_oid(), existing_commit.get_oid())?
+                        == Some(commit.get_oid())
+                }
+                None => false,
+            };
+            if !is_already_merged {
+                remote_commits.push(commit);
+            }
+        }
+        remote_obsmarkers.extend(read_obsmarkers(repo, &ref_name)?);
+    }
+
+    let local_obsmarkers = event_log_db
+        .get_events()?
+        .iter()
+        .filter_map(Obsmarker::from_event)
+        .collect_vec();
+
+    let existing_obsmarker_strs: HashSet<String> = existing_obsmarkers
+        .iter()
+        .map(|obsmarker| obsmarker.to_string())
+        .collect();
+    let obsmarkers = merge_obsmarkers(
+        existing_obsmarkers
+            .into_iter()
+            .chain(remote_obsmarkers)
+            .chain(local_obsmarkers),
+    );
+    let num_obsmarkers = obsmarkers.len();
+    let num_new_obsmarkers = obsmarkers
+        .iter()
+        .filter(|obsmarker| !existing_obsmarker_strs.contains(&obsmarker.to_string()))
+        .count();
+    if num_new_obsmarkers == 0 && remote_commits.is_empty() {
+        return Ok(ExportObsmarkersResult {
+            commit_oid: existing_commit.map(|commit| commit.get_oid()),
+            num_obsmarkers,
+            num_new_obsmarkers,
+        });
+    }
+
+    let contents = obsmarkers
+        .iter()
+        .map(|obsmarker| format!("{obsmarker}\n"))
+        .join("");
+    let blob_oid = repo.create_blob_from_contents(contents.as_bytes())?;
+    let tree_oid = hydrate_tree(
+        repo,
+        None,
+        [(
+            OBSMARKERS_FILE_NAME.into(),
+            Some((blob_oid, FileMode::Blob)),
+        )]
+        .into_iter()
+        .collect(),
+    )?;
+    let tree = repo.find_tree_or_fail(tree_oid)?;
+    let signature = Signature::automated()?;
+    let parents = existing_commit
+        .iter()
+        .chain(remote_commits.iter())
+        .collect_vec();
+    let commit_oid = repo.create_commit(
+        None,
+        &signature,
+        &signature,
+        "branchless: obsolescence markers",
+        &tree,
+        parents,
+        None,
+    )?;
+    repo.create_reference(
+        &local_ref_name,
+        commit_oid,
+        true,
+        "branchless: exporting obsolescence markers",
+    )?;
+
+    Ok(ExportObsmarkersResult {
+        commit_oid: Some(commit_oid),
+        num_obsmarkers,
+        num_new_obsmarkers,
+    })
+}
+
+/// Import the obsolescence markers stored at the given reference into the
+/// event log.
+///
+/// Markers which are already present in the event log are skipped, as are
+/// markers which refer to commits which don't exist in this repository (yet).
+/// The latter will be imported by a later call once the commits have been
+/// fetched.
+///
+/// Imported markers are added after all existing events, so a marker which is
+/// older than the newest local marker for any of its commits is also skipped.
+/// Otherwise, a stale marker could override a more recent local one, such as
+/// hiding a commit which was unhidden locally afterwards.
+///
+/// Returns: The number of markers imported.
+#[instrument]
+pub fn import_obsmarkers(
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    now: SystemTime,
+    ref_name: &ReferenceName,
+) -> eyre::Result<usize> {
+    let local_obsmarkers = event_log_db
+        .get_events()?
+        .iter()
+        .filter_map(Obsmarker::from_event)
+        .collect_vec();
+    let known_obsmarkers: HashSet<String> = local_obsmarkers
+        .iter()
+        .map(|obsmarker| obsmarker.to_string())
+        .collect();
+    let mut latest_local_timestamps: HashMap<NonZeroOid, f64> = HashMap::new();
+    for obsmarker in local_obsmarkers.iter() {
+        let timestamp = obsmarker.get_timestamp();
+        for commit_oid in obsmarker.get_commit_oids() {
+            let latest_timestamp = latest_local_timestamps
+                .entry(commit_oid)
+                .or_insert(timestamp);
+            *latest_timestamp = latest_timestamp.max(timestamp);
+        }
+    }
+
+    let mut obsmarkers_to_import = Vec::new();
+    for obsmarker in read_obsmarkers(repo, ref_name)? {
+        if known_obsmarkers.contains(&obsmarker.to_string()) {
+            continue;
+        }
+        let is_stale =
+            obsmarker
+                .get_commit_oids()
+                .into_iter()
+                .any(
+                    |commit_oid| match latest_local_timestamps.get(&commit_oid) {
+                        Some(latest_timestamp) => obsmarker.get_timestamp() < *latest_timestamp,
+                        None => false,
+                    },
+                );
+        if is_stale {
+            continue;
+        }
+        let mut all_commits_exist = true;
+        for commit_oid in obsmarker.get_commit_oids() {
+            if repo.find_commit(commit_oid)?.is_none() {
+                all_commits_exist = false;
+                break;
+            }
+        }
+        if all_commits_exist {
+            obsmarkers_to_import.push(obsmarker);
+        }
+    }
+
+    let obsmarkers_to_import = merge_obsmarkers(obsmarkers_to_import);
+    let num_imported = obsmarkers_to_import.len();
+    if num_imported > 0 {
+        let event_tx_id = event_log_db
+            .make_transaction_id(now, format!("import obsmarkers from {}", ref_name.as_str()))?;
+        event_log_db.add_events(
+            obsmarkers_to_import
+                .into_iter()
+                .map(|obsmarker| obsmarker.into_event(event_tx_id))
+                .collect(),
+        )?;
+    }
+    Ok(num_imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_obsmarker_round_trip() -> eyre::Result<()> {
+        let obsmarkers = vec![
+            Obsmarker::Rewrite {
+                timestamp: 1.5,
+                old_commit_oid: "62fc20d2a290daea0d52bdc2ed2ad4be6491010e".parse()?,
+                new_commit_oid: "96d1c37a3d4363611c49f7e52186e189a04c531f".parse()?,
+            },
+            Obsmarker::Rewrite {
+                timestamp: 2.0,
+                old_commit_oid: "62fc20d2a290daea0d52bdc2ed2ad4be6491010e".parse()?,
+                new_commit_oid: MaybeZeroOid::Zero,
+            },
+            Obsmarker::Obsolete {
+                timestamp: 1643579034.2583098,
+                commit_oid: "70deb1e28791d8e7dd5a1f0c871a51b91282562f".parse()?,
+            },
+            Obsmarker::Unobsolete {
+                timestamp: 3.0,
+                commit_oid: "70deb1e28791d8e7dd5a1f0c871a51b91282562f".parse()?,
+            },
+        ];
+        for obsmarker in obsmarkers {
+            let parsed: Obsmarker = obsmarker.to_string().parse()?;
+            assert_eq!(parsed, obsmarker);
+        }
+
+        assert!("rewrite 1.0 abc".parse::<Obsmarker>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_remote_obsmarkers_ref() {
+        assert!(is_remote_obsmarkers_ref(&get_remote_obsmarkers_ref_name(
+            "origin"
+        )));
+        assert!(!is_remote_obsmarkers_ref(&ReferenceName::from(
+            OBSMARKERS_REF_NAME
+        )));
+        assert!(!is_remote_obsmarkers_ref(&ReferenceName::from(
+            "refs/branchless/62fc20d2a290daea0d52bdc2ed2ad4be6491010e"
+        )));
+    }
+}
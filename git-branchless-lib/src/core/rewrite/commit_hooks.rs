@@ -0,0 +1,242 @@
+//! Run the user's commit hooks for commits created during in-memory rebases,
+//! which would otherwise bypass them, since they don't invoke `git commit`.
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output};
+
+use thiserror::Error;
+use tracing::instrument;
+
+use crate::core::config::{get_hooks_dir, get_restack_run_commit_hooks};
+use crate::core::eventlog::EventTransactionId;
+use crate::git::{GitRunInfo, NonZeroOid, Repo, Tree};
+
+/// The environment variable set to the OID of the newly-created commit when
+/// running the `post-commit` hook for an in-memory rebase. (Since `HEAD`
+/// doesn't point to the new commit, hooks can't look it up there.)
+pub const IN_MEMORY_COMMIT_ENV_VAR: &str = "BRANCHLESS_IN_MEMORY_COMMIT";
+
+/// The line which `git branchless init` writes before the lines it adds to a
+/// hook script.
+pub const UPDATE_MARKER_START: &str = "## START BRANCHLESS CONFIG";
+
+/// The line which `git branchless init` writes after the lines it adds to a
+/// hook script.
+pub const UPDATE_MARKER_END: &str = "## END BRANCHLESS CONFIG";
+
+const PRE_COMMIT_HOOK: &str = "pre-commit";
+const COMMIT_MSG_HOOK: &str = "commit-msg";
+const POST_COMMIT_HOOK: &str = "post-commit";
+
+/// A commit hook exited unsuccessfully, so the commit should not be created.
+#[allow(missing_docs)]
+#[derive(Debug, Error)]
+#[error("the {hook_name} hook rejected commit {commit_oid} ({status})")]
+pub struct CommitHookRejected {
+    pub hook_name: &'static str,
+    pub commit_oid: NonZeroOid,
+    pub status: ExitStatus,
+
+    /// The combined output of the hook.
+    pub output: String,
+}
+
+/// The commit hooks installed in the repository, if running them during
+/// in-memory rebases is enabled via `branchless.restack.runCommitHooks`.
+#[derive(Debug)]
+pub struct CommitHooks {
+    git_run_info: GitRunInfo,
+    event_tx_id: EventTransactionId,
+    hook_dir: PathBuf,
+    has_pre_commit: bool,
+    has_commit_msg: bool,
+    has_post_commit: bool,
+}
+
+impl CommitHooks {
+    /// Look up which commit hooks are installed. Returns `None` if commit
+    /// hooks shouldn't be run, either because the user hasn't enabled it or
+    /// because no commit hooks are installed.
+    #[instrument]
+    pub fn from_config(
+        git_run_info: &GitRunInfo,
+        repo: &Repo,
+        event_tx_id: EventTransactionId,
+    ) -> eyre::Result<Option<Self>> {
+        if !get_restack_run_commit_hooks(repo)? {
+            return Ok(None);
+        }
+
+        let hook_dir = get_hooks_dir(git_run_info, repo, Some(event_tx_id))?;
+        let has_pre_commit = is_user_hook_installed(&hook_dir.join(PRE_COMMIT_HOOK))?;
+        let has_commit_msg = is_user_hook_installed(&hook_dir.join(COMMIT_MSG_HOOK))?;
+        let has_post_commit = is_user_hook_installed(&hook_dir.join(POST_COMMIT_HOOK))?;
+        if !(has_pre_commit || has_commit_msg || has_post_commit) {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            git_run_info: git_run_info.clone(),
+            event_tx_id,
+            hook_dir,
+            has_pre_commit,
+            has_commit_msg,
+            has_post_commit,
+        }))
+    }
+
+    /// Run the `pre-commit` hook, with `GIT_INDEX_FILE` pointing to an index
+    /// containing `tree`, and then the `commit-msg` hook with `message`.
+    /// Returns the message to commit with, which the `commit-msg` hook may
+    /// have edited. Any hook output is appended to `output`.
+    ///
+    /// If either hook fails, returns a [`CommitHookRejected`] error mentioning
+    /// `commit_oid`, the commit being rewritten.
+    #[instrument(skip(output))]
+    pub fn run_pre_commit_hooks<'a>(
+        &self,
+        repo: &Repo,
+        commit_oid: NonZeroOid,
+        tree: &Tree,
+        message: &'a str,
+        output: &mut String,
+    ) -> eyre::Result<Cow<'a, str>> {
+        let temp_dir = tempfile::tempdir()?;
+
+        if self.has_pre_commit {
+            let index_path = temp_dir.path().join("index");
+            repo.write_tree_to_index_file(tree, &index_path)?;
+            let hook_output = self.run_hook(
+                repo,
+                PRE_COMMIT_HOOK,
+                &[],
+                &[("GIT_INDEX_FILE", index_path.as_os_str())],
+            )?;
+            self.check_hook_output(PRE_COMMIT_HOOK, commit_oid, hook_output, output)?;
+        }
+
+        if !self.has_commit_msg {
+            return Ok(Cow::Borrowed(message));
+        }
+        let message_path = temp_dir.path().join("COMMIT_EDITMSG");
+        std::fs::write(&message_path, message)?;
+        let hook_output = self.run_hook(repo, COMMIT_MSG_HOOK, &[message_path.as_os_str()], &[])?;
+        self.check_hook_output(COMMIT_MSG_HOOK, commit_oid, hook_output, output)?;
+        let new_message = std::fs::read_to_string(&message_path)?;
+        if new_message == message {
+            Ok(Cow::Borrowed(message))
+        } else {
+            Ok(Cow::Owned(new_message))
+        }
+    }
+
+    /// Run the `post-commit` hook after `commit_oid` has been created, with
+    /// [`IN_MEMORY_COMMIT_ENV_VAR`] set to it. As with `git commit`, the hook
+    /// can't affect the outcome.
+    #[instrument(skip(output))]
+    pub fn run_post_commit_hook(
+        &self,
+        repo: &Repo,
+        commit_oid: NonZeroOid,
+        output: &mut String,
+    ) -> eyre::Result<()> {
+        if self.has_post_commit {
+            let commit_oid = commit_oid.to_string();
+            let Output {
+                status: _,
+                stdout,
+                stderr,
+            } = self.run_hook(
+                repo,
+                POST_COMMIT_HOOK,
+                &[],
+                &[(IN_MEMORY_COMMIT_ENV_VAR, OsStr::new(&commit_oid))],
+            )?;
+            output.push_str(&String::from_utf8_lossy(&stdout));
+            output.push_str(&String::from_utf8_lossy(&stderr));
+        }
+        Ok(())
+    }
+
+    fn run_hook(
+        &self,
+        repo: &Repo,
+        hook_name: &str,
+        args: &[&OsStr],
+        envs: &[(&str, &OsStr)],
+    ) -> eyre::Result<Output> {
+        self.git_run_info.run_hook_with_output(
+            repo,
+            &self.hook_dir,
+            hook_name,
+            self.event_tx_id,
+            args,
+            envs,
+        )
+    }
+
+    fn check_hook_output(
+        &self,
+        hook_name: &'static str,
+        commit_oid: NonZeroOid,
+        hook_output: Output,
+        output: &mut String,
+    ) -> eyre::Result<()> {
+        let Output {
+            status,
+            stdout,
+            stderr,
+        } = hook_output;
+        let hook_output = format!(
+            "{}{}",
+            String::from_utf8_lossy(&stdout),
+            String::from_utf8_lossy(&stderr)
+        );
+        if status.success() {
+            output.push_str(&hook_output);
+            Ok(())
+        } else {
+            Err(CommitHookRejected {
+                hook_name,
+                commit_oid,
+                status,
+                output: hook_output,
+            }
+            .into())
+        }
+    }
+}
+
+/// Determine whether the hook at `path` exists, is executable (otherwise Git
+/// ignores it), and does anything besides invoke `git-branchless` itself, as
+/// installed by `git branchless init`.
+/// (The `post-commit` hook installed by `git branchless init` does nothing
+/// for in-memory rebases, so there's no need to run it.)
+fn is_user_hook_installed(path: &Path) -> eyre::Result<bool> {
+    if !path.is_file() {
+        return Ok(false);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if std::fs::metadata(path)?.permissions().mode() & 0o111 == 0 {
+            return Ok(false);
+        }
+    }
+    let contents = std::fs::read(path)?;
+    let contents = String::from_utf8_lossy(&contents);
+    let mut is_branchless_section = false;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line == UPDATE_MARKER_START {
+            is_branchless_section = true;
+        } else if line == UPDATE_MARKER_END {
+            is_branchless_section = false;
+        } else if !is_branchless_section && !line.is_empty() && !line.starts_with('#') {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
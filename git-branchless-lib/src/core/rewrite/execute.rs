@@ -6,20 +6,25 @@ use std::time::SystemTime;
 
 use bstr::BString;
 use eyre::Context;
-use tracing::warn;
+use itertools::Itertools;
+use tracing::{instrument, warn};
 
 use crate::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use crate::core::conflicts::get_conflict_ref_name;
 use crate::core::effects::Effects;
 use crate::core::eventlog::{EventLogDb, EventTransactionId};
 use crate::core::formatting::Pluralize;
 use crate::core::repo_ext::RepoExt;
 use crate::git::{
-    BranchType, CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName,
-    Repo, ResolvedReferenceInfo,
+    BranchType, CategorizedReferenceName, CommitSigner, GitRunInfo, MaybeZeroOid, NonZeroOid,
+    ReferenceName, Repo, ResolvedReferenceInfo, SignError,
 };
 use crate::util::ExitCode;
 
+use super::commit_hooks::{CommitHookRejected, CommitHooks};
+use super::journal::{BranchMove, RewriteJournal};
 use super::plan::RebasePlan;
+use super::rerere::Rerere;
 
 /// Given a list of rewritten OIDs, move the branches attached to those OIDs
 /// from their old commits to their new commits. Invoke the
@@ -31,18 +36,22 @@ pub fn move_branches<'a>(
     event_tx_id: EventTransactionId,
     rewritten_oids_map: &'a HashMap<NonZeroOid, MaybeZeroOid>,
 ) -> eyre::Result<()> {
+    let branch_moves = plan_branch_moves(repo, rewritten_oids_map)?;
+    apply_branch_moves(effects, git_run_info, repo, event_tx_id, &branch_moves)
+}
+
+/// Determine how the branches attached to the given rewritten OIDs should be
+/// updated, without updating them.
+pub fn plan_branch_moves(
+    repo: &Repo,
+    rewritten_oids_map: &HashMap<NonZeroOid, MaybeZeroOid>,
+) -> eyre::Result<Vec<BranchMove>> {
     let main_branch = repo.get_main_branch()?;
     let main_branch_name = main_branch.get_reference_name()?;
     let branch_oid_to_names = repo.get_branch_oid_to_names()?;
 
-    // We may experience an error in the case of a branch move. Ideally, we
-    // would use `git2::Transaction::commit`, which stops the transaction at the
-    // first error, but we don't know which references we successfully committed
-    // in that case. Instead, we just do things non-atomically and record which
-    // ones succeeded. See https://github.com/libgit2/libgit2/issues/5918
-    let mut branch_moves: Vec<(NonZeroOid, MaybeZeroOid, &ReferenceName)> = Vec::new();
-    let mut branch_move_err: Option<eyre::Error> = None;
-    'outer: for (old_oid, names) in branch_oid_to_names.iter() {
+    let mut branch_moves = Vec::new();
+    for (old_oid, names) in branch_oid_to_names.iter() {
         let new_oid = match rewritten_oids_map.get(old_oid) {
             Some(new_oid) => new_oid,
             None => continue,
@@ -52,29 +61,17 @@ pub fn move_branches<'a>(
         names.sort_unstable();
         match new_oid {
             MaybeZeroOid::NonZero(new_oid) => {
-                let new_commit = match repo.find_commit_or_fail(*new_oid).wrap_err_with(|| {
+                repo.find_commit_or_fail(*new_oid).wrap_err_with(|| {
                     format!(
                         "Could not find newly-rewritten commit with old OID: {old_oid:?}, new OID: {new_oid:?}",
                     )
-                }) {
-                    Ok(commit) => commit,
-                    Err(err) => {
-                        branch_move_err = Some(err);
-                        break 'outer;
-                    }
-                };
-
+                })?;
                 for reference_name in names {
-                    if let Err(err) = repo.create_reference(
-                        reference_name,
-                        new_commit.get_oid(),
-                        true,
-                        "move branches",
-                    ) {
-                        branch_move_err = Some(eyre::eyre!(err));
-                        break 'outer;
-                    }
-                    branch_moves.push((*old_oid, MaybeZeroOid::NonZero(*new_oid), reference_name));
+                    branch_moves.push(BranchMove {
+                        reference_name: reference_name.clone(),
+                        old_oid: *old_oid,
+                        new_oid: MaybeZeroOid::NonZero(*new_oid),
+                    });
                 }
             }
 
@@ -86,55 +83,27 @@ pub fn move_branches<'a>(
                         // skipped. For a regular branch, we would delete the branch, but for the
                         // main branch, we should update it to point directly to the upstream
                         // version.
-                        let target_oid = match main_branch.get_upstream_branch_target()? {
-                            Some(target_oid) => {
-                                if let Err(err) = repo.create_reference(
-                                    &main_branch_name,
-                                    target_oid,
-                                    true,
-                                    "move main branch",
-                                ) {
-                                    branch_move_err = Some(eyre::eyre!(err));
-                                    break 'outer;
-                                }
-                                MaybeZeroOid::NonZero(target_oid)
-                            }
-                            None => {
-                                let mut main_branch_reference =
-                                    repo.get_main_branch()?.into_reference();
-                                if let Err(err) = main_branch_reference.delete() {
-                                    branch_move_err = Some(eyre::eyre!(err));
-                                    break 'outer;
-                                }
-                                MaybeZeroOid::Zero
-                            }
+                        let new_oid = match main_branch.get_upstream_branch_target()? {
+                            Some(target_oid) => MaybeZeroOid::NonZero(target_oid),
+                            None => MaybeZeroOid::Zero,
                         };
-                        branch_moves.push((*old_oid, target_oid, reference_name));
+                        branch_moves.push(BranchMove {
+                            reference_name: reference_name.clone(),
+                            old_oid: *old_oid,
+                            new_oid,
+                        });
                     } else {
-                        let branch_name = CategorizedReferenceName::new(reference_name);
-                        match branch_name {
+                        match CategorizedReferenceName::new(reference_name) {
                             CategorizedReferenceName::RemoteBranch { .. }
                             | CategorizedReferenceName::OtherRef { .. } => {
                                 warn!(?reference_name, "Not deleting non-local-branch reference");
                             }
                             CategorizedReferenceName::LocalBranch { .. } => {
-                                let branch_name = branch_name.remove_prefix()?;
-                                match repo.find_branch(&branch_name, BranchType::Local) {
-                                    Ok(Some(mut branch)) => {
-                                        if let Err(err) = branch.delete() {
-                                            branch_move_err = Some(eyre::eyre!(err));
-                                            break 'outer;
-                                        }
-                                    }
-                                    Ok(None) => {
-                                        warn!(?branch_name, "Branch not found, not deleting")
-                                    }
-                                    Err(err) => {
-                                        branch_move_err = Some(eyre::eyre!(err));
-                                        break 'outer;
-                                    }
-                                };
-                                branch_moves.push((*old_oid, MaybeZeroOid::Zero, reference_name));
+                                branch_moves.push(BranchMove {
+                                    reference_name: reference_name.clone(),
+                                    old_oid: *old_oid,
+                                    new_oid: MaybeZeroOid::Zero,
+                                });
                             }
                         }
                     }
@@ -142,13 +111,86 @@ pub fn move_branches<'a>(
             }
         }
     }
+    Ok(branch_moves)
+}
 
-    let branch_moves_stdin: String = branch_moves
-        .into_iter()
-        .map(|(old_oid, new_oid, name)| {
-            format!("{old_oid} {new_oid} {name}\n", name = name.as_str())
+/// Apply the branch updates planned by [`plan_branch_moves`]. Branches which
+/// are moved to new commits are updated as a single transaction. Invoke the
+/// `reference-transaction` hook with the updates which were applied.
+pub fn apply_branch_moves(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    branch_moves: &[BranchMove],
+) -> eyre::Result<()> {
+    let updates: Vec<(&ReferenceName, NonZeroOid)> = branch_moves
+        .iter()
+        .filter_map(|branch_move| match branch_move.new_oid {
+            MaybeZeroOid::NonZero(new_oid) => Some((&branch_move.reference_name, new_oid)),
+            MaybeZeroOid::Zero => None,
         })
         .collect();
+    let mut applied_moves: Vec<&BranchMove> = Vec::new();
+    let mut branch_move_err: Option<eyre::Error> = None;
+    match repo.update_references_atomically(&updates, "move branches") {
+        Ok(()) => {
+            applied_moves.extend(
+                branch_moves
+                    .iter()
+                    .filter(|branch_move| branch_move.new_oid != MaybeZeroOid::Zero),
+            );
+        }
+        Err(err) => branch_move_err = Some(eyre::eyre!(err)),
+    }
+
+    // Deleting a branch also removes its configuration, so branches are
+    // deleted individually rather than as part of the transaction.
+    if branch_move_err.is_none() {
+        for branch_move in branch_moves
+            .iter()
+            .filter(|branch_move| branch_move.new_oid == MaybeZeroOid::Zero)
+        {
+            let reference_name = &branch_move.reference_name;
+            let result = match CategorizedReferenceName::new(reference_name) {
+                name @ CategorizedReferenceName::LocalBranch { .. } => {
+                    let branch_name = name.remove_prefix()?;
+                    match repo.find_branch(&branch_name, BranchType::Local) {
+                        Ok(Some(mut branch)) => branch.delete(),
+                        Ok(None) => {
+                            warn!(?branch_name, "Branch not found, not deleting");
+                            Ok(())
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+                CategorizedReferenceName::RemoteBranch { .. }
+                | CategorizedReferenceName::OtherRef { .. } => {
+                    match repo.find_reference(reference_name) {
+                        Ok(Some(mut reference)) => reference.delete(),
+                        Ok(None) => Ok(()),
+                        Err(err) => Err(err),
+                    }
+                }
+            };
+            if let Err(err) = result {
+                branch_move_err = Some(eyre::eyre!(err));
+                break;
+            }
+            applied_moves.push(branch_move);
+        }
+    }
+
+    let branch_moves_stdin: String = applied_moves
+        .into_iter()
+        .map(
+            |BranchMove {
+                 reference_name,
+                 old_oid,
+                 new_oid,
+             }| format!("{old_oid} {new_oid} {}\n", reference_name.as_str()),
+        )
+        .collect();
     let branch_moves_stdin = BString::from(branch_moves_stdin);
     git_run_info.run_hook(
         effects,
@@ -326,6 +368,10 @@ pub enum MergeConflictRemediation {
 
     /// Indicate that the user should run `git move -m -s 'siblings(.)'`.
     Insert,
+
+    /// Indicate that the user should retry the operation with
+    /// `--commit-conflicts`.
+    CommitConflicts,
 }
 
 /// Information about a failure to merge that occurred while moving commits.
@@ -340,7 +386,8 @@ pub enum FailedMergeInfo {
         conflicting_paths: HashSet<PathBuf>,
     },
 
-    /// A merge commit could not be rebased in memory.
+    /// A merge commit could not be rebased in memory. Only merge commits with
+    /// exactly two parents are supported.
     CannotRebaseMergeInMemory {
         /// The OID of the merge commit that could not be moved.
         commit_oid: NonZeroOid,
@@ -383,7 +430,7 @@ impl FailedMergeInfo {
             FailedMergeInfo::CannotRebaseMergeInMemory { commit_oid } => {
                 writeln!(
                     effects.get_output_stream(),
-                    "Merge commits currently can't be rebased in-memory."
+                    "Merge commits with more than two parents currently can't be rebased in-memory."
                 )?;
                 writeln!(
                     effects.get_output_stream(),
@@ -414,6 +461,12 @@ impl FailedMergeInfo {
                     "To resolve merge conflicts, run: git move -m -s 'siblings(.)'"
                 )?;
             }
+            MergeConflictRemediation::CommitConflicts => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "To commit the conflicts and resolve them later, retry this operation with the --commit-conflicts option."
+                )?;
+            }
         }
 
         Ok(())
@@ -421,21 +474,31 @@ impl FailedMergeInfo {
 }
 
 mod in_memory {
-    use std::collections::HashMap;
+    use std::borrow::Cow;
+    use std::collections::{HashMap, HashSet};
     use std::fmt::Write;
+    use std::path::PathBuf;
 
-    use bstr::{BString, ByteSlice};
+    use bstr::{BString, ByteSlice, ByteVec};
     use eyre::Context;
+    use itertools::Itertools;
+    use rayon::prelude::*;
+    use rayon::ThreadPoolBuilder;
     use tracing::{instrument, warn};
 
-    use crate::core::effects::{Effects, OperationIcon, OperationType};
+    use crate::core::change_id::ensure_change_id;
+    use crate::core::config::get_change_ids_enabled;
+    use crate::core::conflicts::{create_conflict_commit, get_commit_conflict, CommitConflict};
+    use crate::core::effects::{Effects, OperationIcon, OperationType, ProgressHandle};
     use crate::core::eventlog::EventLogDb;
     use crate::core::gc::mark_commit_reachable;
     use crate::core::rewrite::execute::check_out_updated_head;
-    use crate::core::rewrite::move_branches;
     use crate::core::rewrite::plan::{OidOrLabel, RebaseCommand, RebasePlan};
+    use crate::core::rewrite::{apply_branch_moves, plan_branch_moves, RewriteJournal};
+    use crate::core::rewrite::{CommitHooks, RepoResource, Rerere};
     use crate::git::{
-        CherryPickFastError, CherryPickFastOptions, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo,
+        CherryPickFastError, CherryPickFastOptions, Commit, CommitSigner, GitRunInfo, MaybeZeroOid,
+        NonZeroOid, Repo, Tree,
     };
     use crate::util::ExitCode;
 
@@ -445,49 +508,426 @@ mod in_memory {
         Succeeded {
             rewritten_oids: Vec<(NonZeroOid, MaybeZeroOid)>,
 
-            /// The new OID that `HEAD` should point to, based on the rebase.
-            ///
-            /// - This is only `None` if `HEAD` was unborn.
-            /// - This doesn't capture if `HEAD` was pointing to a branch. The
-            /// caller will need to figure that out.
-            new_head_oid: Option<NonZeroOid>,
+            /// The commits which were skipped, each paired with the commit
+            /// that its descendants were applied onto instead. Used to
+            /// determine the new `HEAD` OID with [`get_new_head_oid`].
+            skipped_commit_oids: Vec<(NonZeroOid, NonZeroOid)>,
+
+            /// The commits which were committed with merge conflicts, if
+            /// `create_conflict_commits` was set.
+            conflict_commit_oids: Vec<NonZeroOid>,
         },
         MergeFailed(FailedMergeInfo),
     }
 
+    /// A run of commands from a rebase plan which starts at a fixed commit.
+    /// Segments don't depend on each other's results, so they can be rebased
+    /// in parallel.
+    #[derive(Debug)]
+    struct RebasePlanSegment<'a> {
+        plan_idx: usize,
+        dest_oid: NonZeroOid,
+        commands: &'a [RebaseCommand],
+
+        /// The number of picks in the plan before this segment, so that picks
+        /// are numbered the same way as in a sequential rebase.
+        num_previous_picks: usize,
+
+        /// The number of picks in the whole plan.
+        num_plan_picks: usize,
+    }
+
+    /// The commits created by rebasing a [`RebasePlanSegment`].
+    struct RebasedSegment {
+        rewritten_oids: Vec<(NonZeroOid, MaybeZeroOid)>,
+        skipped_commit_oids: Vec<(NonZeroOid, NonZeroOid)>,
+        conflict_commit_oids: Vec<NonZeroOid>,
+    }
+
+    fn is_pick_command(command: &RebaseCommand) -> bool {
+        match command {
+            RebaseCommand::CreateLabel { .. }
+            | RebaseCommand::Reset { .. }
+            | RebaseCommand::Break
+            | RebaseCommand::RegisterExtraPostRewriteHook
+            | RebaseCommand::DetectEmptyCommit { .. } => false,
+            RebaseCommand::Pick { .. }
+            | RebaseCommand::Squash { .. }
+            | RebaseCommand::Fixup { .. }
+            | RebaseCommand::Merge { .. }
+            | RebaseCommand::Replace { .. }
+            | RebaseCommand::SkipUpstreamAppliedCommit { .. }
+            | RebaseCommand::DropCommit { .. } => true,
+        }
+    }
+
+    fn get_referenced_labels(command: &RebaseCommand) -> Vec<&str> {
+        let targets: Vec<&OidOrLabel> = match command {
+            RebaseCommand::Reset { target } => vec![target],
+            RebaseCommand::Merge {
+                commit_oid: _,
+                commits_to_merge,
+            } => commits_to_merge.iter().collect(),
+            RebaseCommand::Replace {
+                commit_oid: _,
+                replacement_commit_oid: _,
+                parents,
+            } => parents.iter().collect(),
+            RebaseCommand::CreateLabel { .. }
+            | RebaseCommand::Pick { .. }
+            | RebaseCommand::Squash { .. }
+            | RebaseCommand::Fixup { .. }
+            | RebaseCommand::Break
+            | RebaseCommand::RegisterExtraPostRewriteHook
+            | RebaseCommand::DetectEmptyCommit { .. }
+            | RebaseCommand::SkipUpstreamAppliedCommit { .. }
+            | RebaseCommand::DropCommit { .. } => Vec::new(),
+        };
+        targets
+            .into_iter()
+            .filter_map(|target| match target {
+                OidOrLabel::Oid(_) => None,
+                OidOrLabel::Label(label_name) => Some(label_name.as_str()),
+            })
+            .collect()
+    }
+
+    /// Split the rebase plan into segments which can be rebased independently
+    /// of each other.
+    ///
+    /// A new segment starts at each `reset` to a fixed commit, since the
+    /// commands after it can only depend on the commits created before it by
+    /// referring to them by label. If any segment refers to a label which it
+    /// didn't create, then the whole plan is returned as a single segment.
+    fn split_rebase_plan(plan_idx: usize, rebase_plan: &RebasePlan) -> Vec<RebasePlanSegment<'_>> {
+        let RebasePlan {
+            first_dest_oid,
+            commands,
+        } = rebase_plan;
+        let num_plan_picks = commands
+            .iter()
+            .filter(|command| is_pick_command(command))
+            .count();
+
+        let mut segment_starts = vec![(*first_dest_oid, 0)];
+        for (command_idx, command) in commands.iter().enumerate() {
+            if let RebaseCommand::Reset {
+                target: OidOrLabel::Oid(commit_oid),
+            } = command
+            {
+                segment_starts.push((*commit_oid, command_idx + 1));
+            }
+        }
+        let segments: Vec<(NonZeroOid, &[RebaseCommand])> = segment_starts
+            .iter()
+            .enumerate()
+            .map(|(segment_idx, (dest_oid, start_idx))| {
+                let end_idx = match segment_starts.get(segment_idx + 1) {
+                    // Exclude the `reset` command which starts the next segment.
+                    Some((_, next_start_idx)) => next_start_idx - 1,
+                    None => commands.len(),
+                };
+                (*dest_oid, &commands[*start_idx..end_idx])
+            })
+            .filter(|(_, commands)| !commands.is_empty())
+            .collect();
+
+        let is_independent = segments.iter().all(|(_, commands)| {
+            let created_labels: HashSet<&str> = commands
+                .iter()
+                .filter_map(|command| match command {
+                    RebaseCommand::CreateLabel { label_name } => Some(label_name.as_str()),
+                    _ => None,
+                })
+                .collect();
+            commands
+                .iter()
+                .flat_map(get_referenced_labels)
+                .all(|label_name| created_labels.contains(label_name))
+        });
+        let segments = if is_independent {
+            segments
+        } else {
+            vec![(*first_dest_oid, commands.as_slice())]
+        };
+
+        let mut num_previous_picks = 0;
+        segments
+            .into_iter()
+            .map(|(dest_oid, commands)| {
+                let segment = RebasePlanSegment {
+                    plan_idx,
+                    dest_oid,
+                    commands,
+                    num_previous_picks,
+                    num_plan_picks,
+                };
+                num_previous_picks += commands
+                    .iter()
+                    .filter(|command| is_pick_command(command))
+                    .count();
+                segment
+            })
+            .collect()
+    }
+
+    /// Only merge commits with exactly two parents can be rebased in memory.
+    /// Check up front so that we don't do any work before falling back to an
+    /// on-disk rebase.
+    fn check_merge_commits(
+        repo: &Repo,
+        rebase_plan: &RebasePlan,
+    ) -> eyre::Result<Option<FailedMergeInfo>> {
+        for command in rebase_plan.commands.iter() {
+            match command {
+                RebaseCommand::Merge {
+                    commit_oid,
+                    commits_to_merge,
+                } => {
+                    let merge_commit = repo.find_commit_or_fail(*commit_oid)?;
+                    if merge_commit.get_parent_count() != 2 || commits_to_merge.len() != 1 {
+                        return Ok(Some(FailedMergeInfo::CannotRebaseMergeInMemory {
+                            commit_oid: *commit_oid,
+                        }));
+                    }
+                }
+                RebaseCommand::CreateLabel { .. }
+                | RebaseCommand::Reset { .. }
+                | RebaseCommand::Pick { .. }
+                | RebaseCommand::Squash { .. }
+                | RebaseCommand::Fixup { .. }
+                | RebaseCommand::Replace { .. }
+                | RebaseCommand::Break
+                | RebaseCommand::RegisterExtraPostRewriteHook
+                | RebaseCommand::DetectEmptyCommit { .. }
+                | RebaseCommand::SkipUpstreamAppliedCommit { .. }
+                | RebaseCommand::DropCommit { .. } => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Rebase the provided plans in memory. The plans must not depend on each
+    /// other, as is the case for the plans for different stacks.
+    ///
+    /// Independent subtrees of the plans are rebased in parallel, unless
+    /// `commit_hooks` is provided. The output for each plan is buffered and
+    /// returned along with its result, so that the caller can print it in plan
+    /// order, as if the plans had been rebased sequentially.
+    ///
+    /// If `signer` is provided, then the new commits are signed with it. If
+    /// `commit_hooks` is provided, then the `pre-commit` and `commit-msg` hooks
+    /// are run for each new commit, and their output is included in the
+    /// commit's output. (The `post-commit` hook is only run once the rebase has
+    /// succeeded; see `run_post_commit_hooks`.) If `rerere`
+    /// is provided, then merge conflicts are resolved using the resolutions
+    /// recorded by `git rerere`, if possible.
     #[instrument]
-    pub fn rebase_in_memory(
+    pub fn rebase_plans_in_memory(
         effects: &Effects,
         repo: &Repo,
-        rebase_plan: &RebasePlan,
+        rebase_plans: &[&RebasePlan],
         options: &ExecuteRebasePlanOptions,
-    ) -> eyre::Result<RebaseInMemoryResult> {
-        if let Some(merge_commit_oid) =
-            rebase_plan
-                .commands
+        signer: Option<&CommitSigner>,
+        commit_hooks: Option<&CommitHooks>,
+        rerere: Option<&Rerere>,
+    ) -> eyre::Result<Vec<(String, RebaseInMemoryResult)>> {
+        let mut precheck_failures: HashMap<usize, FailedMergeInfo> = HashMap::new();
+        for (plan_idx, rebase_plan) in rebase_plans.iter().enumerate() {
+            if let Some(failed_merge_info) = check_merge_commits(repo, rebase_plan)? {
+                precheck_failures.insert(plan_idx, failed_merge_info);
+            }
+        }
+
+        let segments: Vec<RebasePlanSegment> = rebase_plans
+            .iter()
+            .enumerate()
+            .filter(|(plan_idx, _)| !precheck_failures.contains_key(plan_idx))
+            .flat_map(|(plan_idx, rebase_plan)| split_rebase_plan(plan_idx, rebase_plan))
+            .collect();
+        let num_picks = segments
+            .iter()
+            .flat_map(|segment| segment.commands.iter())
+            .filter(|command| is_pick_command(command))
+            .count();
+        let (effects, progress) = effects.start_operation(OperationType::RebaseCommits);
+        progress.notify_progress(0, num_picks);
+
+        let run_segment = |repo: &Repo, segment: &RebasePlanSegment| {
+            let mut output = String::new();
+            let result = rebase_segment(
+                &effects,
+                &progress,
+                repo,
+                segment,
+                options,
+                signer,
+                commit_hooks,
+                rerere,
+                &mut output,
+            );
+            (output, result)
+        };
+        let segment_results: Vec<(
+            String,
+            eyre::Result<Result<RebasedSegment, FailedMergeInfo>>,
+        )> = if segments.len() > 1 && commit_hooks.is_none() {
+            // The user's hooks may not be safe to run concurrently (for
+            // example, if they write to the working copy), so only rebase in
+            // parallel if there are no hooks to run.
+            let pool = ThreadPoolBuilder::new().build()?;
+            let repo_pool = RepoResource::new_pool(repo)?;
+            pool.install(|| {
+                segments
+                    .par_iter()
+                    .map(|segment| -> eyre::Result<_> {
+                        let repo = repo_pool.try_create()?;
+                        Ok(run_segment(&repo, segment))
+                    })
+                    .collect::<eyre::Result<Vec<_>>>()
+            })?
+        } else {
+            segments
                 .iter()
-                .find_map(|command| match command {
-                    RebaseCommand::Merge {
-                        commit_oid,
-                        commits_to_merge: _,
-                    } => Some(commit_oid),
-                    RebaseCommand::CreateLabel { .. }
-                    | RebaseCommand::Reset { .. }
-                    | RebaseCommand::Pick { .. }
-                    | RebaseCommand::Replace { .. }
-                    | RebaseCommand::Break
-                    | RebaseCommand::RegisterExtraPostRewriteHook
-                    | RebaseCommand::DetectEmptyCommit { .. }
-                    | RebaseCommand::SkipUpstreamAppliedCommit { .. } => None,
-                })
-        {
-            return Ok(RebaseInMemoryResult::MergeFailed(
-                FailedMergeInfo::CannotRebaseMergeInMemory {
-                    commit_oid: *merge_commit_oid,
+                .map(|segment| run_segment(repo, segment))
+                .collect()
+        };
+
+        let mut segment_results = segments
+            .iter()
+            .map(|segment| segment.plan_idx)
+            .zip(segment_results)
+            .peekable();
+        let mut results = Vec::new();
+        for plan_idx in 0..rebase_plans.len() {
+            if let Some(failed_merge_info) = precheck_failures.remove(&plan_idx) {
+                results.push((
+                    String::new(),
+                    RebaseInMemoryResult::MergeFailed(failed_merge_info),
+                ));
+                continue;
+            }
+
+            let mut output = String::new();
+            let mut rewritten_oids = Vec::new();
+            let mut skipped_commit_oids = Vec::new();
+            let mut conflict_commit_oids = Vec::new();
+            let mut failed_merge_info = None;
+            while let Some((_, (segment_output, segment_result))) =
+                segment_results.next_if(|(segment_plan_idx, _)| *segment_plan_idx == plan_idx)
+            {
+                // A sequential rebase would have stopped at the first failed
+                // segment, so discard the results of any later segments.
+                if failed_merge_info.is_some() {
+                    continue;
+                }
+
+                output.push_str(&segment_output);
+                match segment_result? {
+                    Ok(rebased_segment) => {
+                        let RebasedSegment {
+                            rewritten_oids: segment_rewritten_oids,
+                            skipped_commit_oids: segment_skipped_commit_oids,
+                            conflict_commit_oids: segment_conflict_commit_oids,
+                        } = rebased_segment;
+                        rewritten_oids.extend(segment_rewritten_oids);
+                        skipped_commit_oids.extend(segment_skipped_commit_oids);
+                        conflict_commit_oids.extend(segment_conflict_commit_oids);
+                    }
+                    Err(segment_failed_merge_info) => {
+                        failed_merge_info = Some(segment_failed_merge_info);
+                    }
+                }
+            }
+
+            let result = match failed_merge_info {
+                Some(failed_merge_info) => RebaseInMemoryResult::MergeFailed(failed_merge_info),
+                None => RebaseInMemoryResult::Succeeded {
+                    rewritten_oids,
+                    skipped_commit_oids,
+                    conflict_commit_oids,
                 },
-            ));
+            };
+            results.push((output, result));
         }
+        Ok(results)
+    }
+
+    /// Remove the subject (the first paragraph) from a commit message.
+    fn strip_subject_line(message: &[u8]) -> &[u8] {
+        match message.find("\n\n") {
+            Some(index) => &message[index..],
+            None => b"",
+        }
+    }
+
+    /// Try to resolve the merge conflicts from cherry-picking `patch_commit`
+    /// onto `target_commit` using the resolutions recorded by `git rerere`.
+    /// Returns the resolved tree and the resolved paths, or `None` if the
+    /// conflicts couldn't all be resolved.
+    fn resolve_with_rerere<'repo>(
+        repo: &'repo Repo,
+        rerere: Option<&Rerere>,
+        patch_commit: &Commit,
+        target_commit: &Commit,
+        commit_num: &str,
+        output: &mut String,
+    ) -> eyre::Result<Option<(Tree<'repo>, Vec<PathBuf>)>> {
+        let rerere = match rerere {
+            Some(rerere) => rerere,
+            None => return Ok(None),
+        };
+        let result = rerere.resolve_cherry_pick(repo, patch_commit, target_commit)?;
+        if let Some((_tree, resolved_paths)) = &result {
+            for path in resolved_paths {
+                writeln!(
+                    output,
+                    "{commit_num} Resolved conflict in {} using previous resolution",
+                    path.display()
+                )?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Rebase a single plan in memory, printing its output.
+    #[instrument]
+    pub fn rebase_in_memory(
+        effects: &Effects,
+        repo: &Repo,
+        rebase_plan: &RebasePlan,
+        options: &ExecuteRebasePlanOptions,
+        signer: Option<&CommitSigner>,
+        commit_hooks: Option<&CommitHooks>,
+        rerere: Option<&Rerere>,
+    ) -> eyre::Result<RebaseInMemoryResult> {
+        let (output, result) = rebase_plans_in_memory(
+            effects,
+            repo,
+            &[rebase_plan],
+            options,
+            signer,
+            commit_hooks,
+            rerere,
+        )?
+        .into_iter()
+        .exactly_one()
+        .map_err(|_| eyre::eyre!("BUG: expected exactly one in-memory rebase result"))?;
+        write!(effects.get_output_stream(), "{output}")?;
+        Ok(result)
+    }
 
+    fn rebase_segment(
+        effects: &Effects,
+        progress: &ProgressHandle,
+        repo: &Repo,
+        segment: &RebasePlanSegment,
+        options: &ExecuteRebasePlanOptions,
+        signer: Option<&CommitSigner>,
+        commit_hooks: Option<&CommitHooks>,
+        rerere: Option<&Rerere>,
+        output: &mut String,
+    ) -> eyre::Result<Result<RebasedSegment, FailedMergeInfo>> {
         let ExecuteRebasePlanOptions {
             now,
             // Transaction ID will be passed to the `post-rewrite` hook via
@@ -497,45 +937,26 @@ mod in_memory {
             force_in_memory: _,
             force_on_disk: _,
             resolve_merge_conflicts: _, // May be needed once we can resolve merge conflicts in memory.
+            create_conflict_commits,
             check_out_commit_options: _, // Caller is responsible for checking out to new HEAD.
         } = options;
-
-        let mut current_oid = rebase_plan.first_dest_oid;
+        let RebasePlanSegment {
+            plan_idx: _,
+            dest_oid,
+            commands,
+            num_previous_picks,
+            num_plan_picks: num_picks,
+        } = segment;
+
+        let add_change_ids = get_change_ids_enabled(repo)?;
+        let mut current_oid = *dest_oid;
         let mut labels: HashMap<String, NonZeroOid> = HashMap::new();
         let mut rewritten_oids: Vec<(NonZeroOid, MaybeZeroOid)> = Vec::new();
+        let mut skipped_commit_oids: Vec<(NonZeroOid, NonZeroOid)> = Vec::new();
+        let mut conflict_commit_oids: Vec<NonZeroOid> = Vec::new();
 
-        // Normally, we can determine the new `HEAD` OID by looking at the
-        // rewritten commits. However, if `HEAD` pointed to a commit that was
-        // skipped, then the rewritten OID is zero. In that case, we need to
-        // delete the branch (responsibility of the caller) and choose a
-        // different `HEAD` OID.
-        let head_oid = repo.get_head_info()?.oid;
-        let mut skipped_head_new_oid = None;
-        let mut maybe_set_skipped_head_new_oid = |skipped_head_oid, current_oid| {
-            if Some(skipped_head_oid) == head_oid {
-                skipped_head_new_oid.get_or_insert(current_oid);
-            }
-        };
-
-        let mut i = 0;
-        let num_picks = rebase_plan
-            .commands
-            .iter()
-            .filter(|command| match command {
-                RebaseCommand::CreateLabel { .. }
-                | RebaseCommand::Reset { .. }
-                | RebaseCommand::Break
-                | RebaseCommand::RegisterExtraPostRewriteHook
-                | RebaseCommand::DetectEmptyCommit { .. } => false,
-                RebaseCommand::Pick { .. }
-                | RebaseCommand::Merge { .. }
-                | RebaseCommand::Replace { .. }
-                | RebaseCommand::SkipUpstreamAppliedCommit { .. } => true,
-            })
-            .count();
-        let (effects, progress) = effects.start_operation(OperationType::RebaseCommits);
-
-        for command in rebase_plan.commands.iter() {
+        let mut i = *num_previous_picks;
+        for command in commands.iter() {
             match command {
                 RebaseCommand::CreateLabel { label_name } => {
                     labels.insert(label_name.clone(), current_oid);
@@ -572,42 +993,87 @@ mod in_memory {
                         .get_glyphs()
                         .render(commit_to_apply.friendly_describe(effects.get_glyphs())?)?;
                     let commit_num = format!("[{i}/{num_picks}]");
-                    progress.notify_progress(i, num_picks);
+                    progress.notify_progress_inc(1);
 
                     if commit_to_apply.get_parent_count() > 1 {
                         warn!(
                             ?commit_to_apply_oid,
                             "BUG: Merge commit should have been detected during planning phase"
                         );
-                        return Ok(RebaseInMemoryResult::MergeFailed(
-                            FailedMergeInfo::CannotRebaseMergeInMemory {
-                                commit_oid: *commit_to_apply_oid,
-                            },
-                        ));
+                        return Ok(Err(FailedMergeInfo::CannotRebaseMergeInMemory {
+                            commit_oid: *commit_to_apply_oid,
+                        }));
                     };
 
                     progress.notify_status(
                         OperationIcon::InProgress,
                         format!("Applying patch for commit: {commit_description}"),
                     );
-                    let commit_tree = match repo.cherry_pick_fast(
-                        &commit_to_apply,
-                        &current_commit,
-                        &CherryPickFastOptions {
-                            reuse_parent_tree_if_possible: true,
-                        },
-                    ) {
-                        Ok(rebased_commit) => rebased_commit,
-                        Err(CherryPickFastError::MergeConflict { conflicting_paths }) => {
-                            return Ok(RebaseInMemoryResult::MergeFailed(
-                                FailedMergeInfo::Conflict {
-                                    commit_oid: *commit_to_apply_oid,
-                                    conflicting_paths,
+                    let (commit_tree, commit_conflict) =
+                        match get_commit_conflict(repo, &commit_to_apply)? {
+                            Some(commit_conflict) => {
+                                // Re-do the conflicted merge onto the new parent,
+                                // since the parent's changes may resolve (or
+                                // introduce) conflicts.
+                                let commit_conflict = CommitConflict {
+                                    our_tree_oid: current_commit.get_tree()?.get_oid(),
+                                    ..commit_conflict
+                                };
+                                let (commit_tree, conflicting_paths) = commit_conflict.merge(
+                                    repo,
+                                    *commit_to_apply_oid,
+                                    current_oid,
+                                )?;
+                                if conflicting_paths.is_empty() {
+                                    (commit_tree, None)
+                                } else if *create_conflict_commits {
+                                    (commit_tree, Some(commit_conflict))
+                                } else {
+                                    return Ok(Err(FailedMergeInfo::Conflict {
+                                        commit_oid: *commit_to_apply_oid,
+                                        conflicting_paths,
+                                    }));
+                                }
+                            }
+
+                            None => match repo.cherry_pick_fast(
+                                &commit_to_apply,
+                                &current_commit,
+                                &CherryPickFastOptions {
+                                    reuse_parent_tree_if_possible: true,
                                 },
-                            ))
-                        }
-                        Err(other) => eyre::bail!(other),
-                    };
+                            ) {
+                                Ok(rebased_commit) => (rebased_commit, None),
+                                Err(CherryPickFastError::MergeConflict { conflicting_paths }) => {
+                                    if let Some((commit_tree, _resolved_paths)) =
+                                        resolve_with_rerere(
+                                            repo,
+                                            rerere,
+                                            &commit_to_apply,
+                                            &current_commit,
+                                            &commit_num,
+                                            output,
+                                        )?
+                                    {
+                                        (commit_tree, None)
+                                    } else if !*create_conflict_commits {
+                                        return Ok(Err(FailedMergeInfo::Conflict {
+                                            commit_oid: *commit_to_apply_oid,
+                                            conflicting_paths,
+                                        }));
+                                    } else {
+                                        let commit_conflict = CommitConflict::from_cherry_pick(
+                                            &commit_to_apply,
+                                            &current_commit,
+                                        )?;
+                                        let (commit_tree, _conflicting_paths) = commit_conflict
+                                            .merge(repo, *commit_to_apply_oid, current_oid)?;
+                                        (commit_tree, Some(commit_conflict))
+                                    }
+                                }
+                                Err(other) => eyre::bail!(other),
+                            },
+                        };
 
                     let commit_message = commit_to_apply.get_message_raw()?;
                     let commit_message = commit_message.to_str().with_context(|| {
@@ -616,6 +1082,36 @@ mod in_memory {
                             commit_to_apply_oid
                         )
                     })?;
+                    let commit_message = if add_change_ids {
+                        ensure_change_id(commit_message, *commit_to_apply_oid)?
+                    } else {
+                        Cow::Borrowed(commit_message)
+                    };
+                    let commit_message = commit_message.as_ref();
+
+                    // Conflict commits are placeholders to be resolved later,
+                    // and now-empty commits will be skipped, so don't run the
+                    // commit hooks for them.
+                    let commit_message = match commit_hooks {
+                        Some(commit_hooks)
+                            if commit_conflict.is_none()
+                                && MaybeZeroOid::NonZero(commit_tree.get_oid())
+                                    != current_commit.get_tree_oid() =>
+                        {
+                            progress.notify_status(
+                                OperationIcon::InProgress,
+                                format!("Running commit hooks: {commit_description}"),
+                            );
+                            commit_hooks.run_pre_commit_hooks(
+                                repo,
+                                *commit_to_apply_oid,
+                                &commit_tree,
+                                commit_message,
+                                output,
+                            )?
+                        }
+                        _ => Cow::Borrowed(commit_message),
+                    };
 
                     progress.notify_status(
                         OperationIcon::InProgress,
@@ -626,16 +1122,30 @@ mod in_memory {
                     } else {
                         commit_to_apply.get_committer().update_timestamp(*now)?
                     };
-                    let rebased_commit_oid = repo
-                        .create_commit(
-                            None,
+                    let rebased_commit_oid = match &commit_conflict {
+                        None => repo
+                            .create_commit(
+                                None,
+                                &commit_to_apply.get_author(),
+                                &committer_signature,
+                                &commit_message,
+                                &commit_tree,
+                                vec![&current_commit],
+                                signer,
+                            )
+                            .wrap_err("Applying rebased commit")?,
+                        Some(commit_conflict) => create_conflict_commit(
+                            repo,
                             &commit_to_apply.get_author(),
                             &committer_signature,
-                            commit_message,
+                            &commit_message,
                             &commit_tree,
                             vec![&current_commit],
+                            commit_conflict,
+                            signer,
                         )
-                        .wrap_err("Applying rebased commit")?;
+                        .wrap_err("Applying rebased conflict commit")?,
+                    };
 
                     let rebased_commit = repo
                         .find_commit_or_fail(rebased_commit_oid)
@@ -647,12 +1157,24 @@ mod in_memory {
                                 effects.get_glyphs(),
                                 rebased_commit_oid,
                             )?)?;
-                    if rebased_commit.is_empty() {
+                    if commit_conflict.is_some() {
+                        rewritten_oids.push((
+                            *original_commit_oid,
+                            MaybeZeroOid::NonZero(rebased_commit_oid),
+                        ));
+                        conflict_commit_oids.push(rebased_commit_oid);
+                        current_oid = rebased_commit_oid;
+
+                        writeln!(
+                            output,
+                            "{commit_num} Committed with conflicts as: {commit_description}"
+                        )?;
+                    } else if rebased_commit.is_empty() {
                         rewritten_oids.push((*original_commit_oid, MaybeZeroOid::Zero));
-                        maybe_set_skipped_head_new_oid(*original_commit_oid, current_oid);
+                        skipped_commit_oids.push((*original_commit_oid, current_oid));
 
                         writeln!(
-                            effects.get_output_stream(),
+                            output,
                             "[{i}/{num_picks}] Skipped now-empty commit: {commit_description}"
                         )?;
                     } else {
@@ -662,26 +1184,331 @@ mod in_memory {
                         ));
                         current_oid = rebased_commit_oid;
 
+                        writeln!(output, "{commit_num} Committed as: {commit_description}")?;
+                    }
+                }
+
+                RebaseCommand::Squash { commit_oid }
+                | RebaseCommand::Fixup {
+                    commit_oid,
+                    use_commit_message: _,
+                } => {
+                    // Only commits created by this rebase may be squashed
+                    // into; otherwise, we would be rewriting a commit which
+                    // isn't part of the rebase.
+                    if !rewritten_oids
+                        .iter()
+                        .any(|(_, dest_oid)| *dest_oid == MaybeZeroOid::NonZero(current_oid))
+                    {
+                        eyre::bail!(
+                            "BUG: cannot squash commit {} into commit {}, which was not created by this rebase",
+                            commit_oid,
+                            current_oid
+                        );
+                    }
+
+                    let current_commit = repo
+                        .find_commit_or_fail(current_oid)
+                        .wrap_err("Finding current commit")?;
+                    let commit_to_squash = repo
+                        .find_commit_or_fail(*commit_oid)
+                        .wrap_err("Finding commit to squash")?;
+                    i += 1;
+
+                    let commit_description = effects
+                        .get_glyphs()
+                        .render(commit_to_squash.friendly_describe(effects.get_glyphs())?)?;
+                    let commit_num = format!("[{i}/{num_picks}]");
+                    progress.notify_progress_inc(1);
+
+                    if commit_to_squash.get_parent_count() > 1 {
+                        return Ok(Err(FailedMergeInfo::CannotRebaseMergeInMemory {
+                            commit_oid: *commit_oid,
+                        }));
+                    };
+
+                    progress.notify_status(
+                        OperationIcon::InProgress,
+                        format!("Squashing commit: {commit_description}"),
+                    );
+                    let (commit_tree, commit_conflict) = match repo.cherry_pick_fast(
+                        &commit_to_squash,
+                        &current_commit,
+                        &CherryPickFastOptions {
+                            reuse_parent_tree_if_possible: true,
+                        },
+                    ) {
+                        Ok(commit_tree) => (commit_tree, None),
+                        Err(CherryPickFastError::MergeConflict { conflicting_paths }) => {
+                            if let Some((commit_tree, _resolved_paths)) = resolve_with_rerere(
+                                repo,
+                                rerere,
+                                &commit_to_squash,
+                                &current_commit,
+                                &commit_num,
+                                output,
+                            )? {
+                                (commit_tree, None)
+                            } else if !*create_conflict_commits {
+                                return Ok(Err(FailedMergeInfo::Conflict {
+                                    commit_oid: *commit_oid,
+                                    conflicting_paths,
+                                }));
+                            } else {
+                                let commit_conflict = CommitConflict::from_cherry_pick(
+                                    &commit_to_squash,
+                                    &current_commit,
+                                )?;
+                                let (commit_tree, _conflicting_paths) =
+                                    commit_conflict.merge(repo, *commit_oid, current_oid)?;
+                                (commit_tree, Some(commit_conflict))
+                            }
+                        }
+                        Err(other) => eyre::bail!(other),
+                    };
+                    // If the rebase head was itself committed with conflicts,
+                    // then the squashed commit still has those conflicts.
+                    let commit_conflict = match commit_conflict {
+                        Some(commit_conflict) => Some(commit_conflict),
+                        None => get_commit_conflict(repo, &current_commit)?,
+                    };
+
+                    let commit_message = {
+                        let current_message = current_commit.get_message_raw()?;
+                        let squash_message = commit_to_squash.get_message_raw()?;
+                        match command {
+                            RebaseCommand::Fixup {
+                                commit_oid: _,
+                                use_commit_message: false,
+                            } => current_message,
+                            RebaseCommand::Fixup {
+                                commit_oid: _,
+                                use_commit_message: true,
+                            } => {
+                                // The subject line of an `amend!` commit only
+                                // names its target, so use the rest of the
+                                // message.
+                                let mut message =
+                                    BString::from(strip_subject_line(&squash_message).trim());
+                                message.push_str("\n");
+                                message
+                            }
+                            _ => {
+                                // Likewise, the subject line of a `squash!`
+                                // commit isn't part of the combined message.
+                                let squash_message = if squash_message.starts_with(b"squash! ") {
+                                    strip_subject_line(&squash_message)
+                                } else {
+                                    squash_message.as_bytes()
+                                };
+                                let mut message = BString::from(current_message.trim_end());
+                                message.push_str("\n\n");
+                                message.push_str(squash_message.trim());
+                                message.push_str("\n");
+                                message
+                            }
+                        }
+                    };
+                    let commit_message = commit_message.to_str().with_context(|| {
+                        eyre::eyre!(
+                            "Could not decode commit message for commit: {:?}",
+                            commit_oid
+                        )
+                    })?;
+                    let commit_message = match commit_hooks {
+                        Some(commit_hooks) if commit_conflict.is_none() => {
+                            progress.notify_status(
+                                OperationIcon::InProgress,
+                                format!("Running commit hooks: {commit_description}"),
+                            );
+                            commit_hooks.run_pre_commit_hooks(
+                                repo,
+                                *commit_oid,
+                                &commit_tree,
+                                commit_message,
+                                output,
+                            )?
+                        }
+                        _ => Cow::Borrowed(commit_message),
+                    };
+
+                    progress.notify_status(
+                        OperationIcon::InProgress,
+                        format!("Committing to repository: {commit_description}"),
+                    );
+                    let committer_signature = if *preserve_timestamps {
+                        current_commit.get_committer()
+                    } else {
+                        current_commit.get_committer().update_timestamp(*now)?
+                    };
+                    let parents = current_commit.get_parents();
+                    let squashed_commit_oid = match &commit_conflict {
+                        None => repo
+                            .create_commit(
+                                None,
+                                &current_commit.get_author(),
+                                &committer_signature,
+                                &commit_message,
+                                &commit_tree,
+                                parents.iter().collect(),
+                                signer,
+                            )
+                            .wrap_err("Applying squashed commit")?,
+                        Some(commit_conflict) => create_conflict_commit(
+                            repo,
+                            &current_commit.get_author(),
+                            &committer_signature,
+                            &commit_message,
+                            &commit_tree,
+                            parents.iter().collect(),
+                            commit_conflict,
+                            signer,
+                        )
+                        .wrap_err("Applying squashed conflict commit")?,
+                    };
+
+                    // Any commits which were rewritten into the rebase head
+                    // have now been rewritten into the squashed commit.
+                    for (_, dest_oid) in rewritten_oids.iter_mut() {
+                        if *dest_oid == MaybeZeroOid::NonZero(current_oid) {
+                            *dest_oid = MaybeZeroOid::NonZero(squashed_commit_oid);
+                        }
+                    }
+                    rewritten_oids.push((*commit_oid, MaybeZeroOid::NonZero(squashed_commit_oid)));
+                    conflict_commit_oids.retain(|oid| *oid != current_oid);
+                    if commit_conflict.is_some() {
+                        conflict_commit_oids.push(squashed_commit_oid);
+                    }
+                    current_oid = squashed_commit_oid;
+
+                    let commit_description =
+                        effects
+                            .get_glyphs()
+                            .render(repo.friendly_describe_commit_from_oid(
+                                effects.get_glyphs(),
+                                squashed_commit_oid,
+                            )?)?;
+                    if commit_conflict.is_some() {
                         writeln!(
-                            effects.get_output_stream(),
-                            "{commit_num} Committed as: {commit_description}"
+                            output,
+                            "{commit_num} Squashed with conflicts into: {commit_description}"
                         )?;
+                    } else {
+                        writeln!(output, "{commit_num} Squashed into: {commit_description}")?;
                     }
                 }
 
                 RebaseCommand::Merge {
                     commit_oid,
-                    commits_to_merge: _,
+                    commits_to_merge,
                 } => {
-                    warn!(
-                        ?commit_oid,
-                        "BUG: Merge commit without replacement should have been detected when starting in-memory rebase"
-                    );
-                    return Ok(RebaseInMemoryResult::MergeFailed(
-                        FailedMergeInfo::CannotRebaseMergeInMemory {
-                            commit_oid: *commit_oid,
+                    let current_commit = repo
+                        .find_commit_or_fail(current_oid)
+                        .wrap_err("Finding current commit")?;
+                    let merge_commit = repo
+                        .find_commit_or_fail(*commit_oid)
+                        .wrap_err("Finding merge commit")?;
+                    let other_parent_oid = match commits_to_merge.as_slice() {
+                        [OidOrLabel::Oid(oid)] => *oid,
+                        [OidOrLabel::Label(label)] => match labels.get(label) {
+                            Some(oid) => *oid,
+                            None => eyre::bail!("BUG: no associated OID for label: {}", label),
                         },
-                    ));
+                        _ => {
+                            warn!(
+                                ?commit_oid,
+                                "BUG: Octopus merge should have been detected when starting in-memory rebase"
+                            );
+                            return Ok(Err(FailedMergeInfo::CannotRebaseMergeInMemory {
+                                commit_oid: *commit_oid,
+                            }));
+                        }
+                    };
+                    let other_parent_commit = repo
+                        .find_commit_or_fail(other_parent_oid)
+                        .wrap_err("Finding merge parent commit")?;
+                    i += 1;
+
+                    let commit_description = effects
+                        .get_glyphs()
+                        .render(merge_commit.friendly_describe(effects.get_glyphs())?)?;
+                    let commit_num = format!("[{i}/{num_picks}]");
+                    progress.notify_progress_inc(1);
+                    progress.notify_status(
+                        OperationIcon::InProgress,
+                        format!("Merging commit: {commit_description}"),
+                    );
+                    let merge_tree = match repo.rebase_merge_fast(
+                        &merge_commit,
+                        &current_commit,
+                        &other_parent_commit,
+                    ) {
+                        Ok(merge_tree) => merge_tree,
+                        Err(CherryPickFastError::MergeConflict { conflicting_paths }) => {
+                            return Ok(Err(FailedMergeInfo::Conflict {
+                                commit_oid: *commit_oid,
+                                conflicting_paths,
+                            }))
+                        }
+                        Err(other) => eyre::bail!(other),
+                    };
+
+                    let commit_message = merge_commit.get_message_raw()?;
+                    let commit_message = commit_message.to_str().with_context(|| {
+                        eyre::eyre!(
+                            "Could not decode commit message for commit: {:?}",
+                            commit_oid
+                        )
+                    })?;
+                    let commit_message = match commit_hooks {
+                        Some(commit_hooks) => {
+                            progress.notify_status(
+                                OperationIcon::InProgress,
+                                format!("Running commit hooks: {commit_description}"),
+                            );
+                            commit_hooks.run_pre_commit_hooks(
+                                repo,
+                                *commit_oid,
+                                &merge_tree,
+                                commit_message,
+                                output,
+                            )?
+                        }
+                        None => Cow::Borrowed(commit_message),
+                    };
+
+                    progress.notify_status(
+                        OperationIcon::InProgress,
+                        format!("Committing to repository: {commit_description}"),
+                    );
+                    let committer_signature = if *preserve_timestamps {
+                        merge_commit.get_committer()
+                    } else {
+                        merge_commit.get_committer().update_timestamp(*now)?
+                    };
+                    let rebased_commit_oid = repo
+                        .create_commit(
+                            None,
+                            &merge_commit.get_author(),
+                            &committer_signature,
+                            &commit_message,
+                            &merge_tree,
+                            vec![&current_commit, &other_parent_commit],
+                            signer,
+                        )
+                        .wrap_err("Applying rebased merge commit")?;
+
+                    let commit_description =
+                        effects
+                            .get_glyphs()
+                            .render(repo.friendly_describe_commit_from_oid(
+                                effects.get_glyphs(),
+                                rebased_commit_oid,
+                            )?)?;
+                    rewritten_oids.push((*commit_oid, MaybeZeroOid::NonZero(rebased_commit_oid)));
+                    current_oid = rebased_commit_oid;
+
+                    writeln!(output, "{commit_num} Committed as: {commit_description}")?;
                 }
 
                 RebaseCommand::Replace {
@@ -698,7 +1525,7 @@ mod in_memory {
 
                     i += 1;
                     let commit_num = format!("[{i}/{num_picks}]");
-                    progress.notify_progress(i, num_picks);
+                    progress.notify_progress_inc(1);
                     progress.notify_status(
                         OperationIcon::InProgress,
                         format!("Replacing commit: {original_commit_description}"),
@@ -718,6 +1545,22 @@ mod in_memory {
                     let replacement_commit_description = effects
                         .get_glyphs()
                         .render(replacement_commit.friendly_describe(effects.get_glyphs())?)?;
+                    let replacement_commit_message = match commit_hooks {
+                        Some(commit_hooks) => {
+                            progress.notify_status(
+                                OperationIcon::InProgress,
+                                format!("Running commit hooks: {replacement_commit_description}"),
+                            );
+                            commit_hooks.run_pre_commit_hooks(
+                                repo,
+                                *commit_oid,
+                                &replacement_tree,
+                                replacement_commit_message,
+                                output,
+                            )?
+                        }
+                        None => Cow::Borrowed(replacement_commit_message),
+                    };
                     progress.notify_status(
                         OperationIcon::InProgress,
                         format!("Committing to repository: {replacement_commit_description}"),
@@ -751,9 +1594,10 @@ mod in_memory {
                             None,
                             &replacement_commit.get_author(),
                             &committer_signature,
-                            replacement_commit_message,
+                            &replacement_commit_message,
                             &replacement_tree,
                             parents.iter().collect(),
+                            signer,
                         )
                         .wrap_err("Applying rebased commit")?;
 
@@ -767,10 +1611,7 @@ mod in_memory {
                     rewritten_oids.push((*commit_oid, MaybeZeroOid::NonZero(rebased_commit_oid)));
                     current_oid = rebased_commit_oid;
 
-                    writeln!(
-                        effects.get_output_stream(),
-                        "{commit_num} Committed as: {commit_description}"
-                    )?;
+                    writeln!(output, "{commit_num} Committed as: {commit_description}")?;
                 }
 
                 RebaseCommand::Break => {
@@ -783,16 +1624,29 @@ mod in_memory {
 
                     let commit = repo.find_commit_or_fail(*commit_oid)?;
                     rewritten_oids.push((*commit_oid, MaybeZeroOid::Zero));
-                    maybe_set_skipped_head_new_oid(*commit_oid, current_oid);
+                    skipped_commit_oids.push((*commit_oid, current_oid));
 
                     let commit_description = commit.friendly_describe(effects.get_glyphs())?;
                     let commit_description = effects.get_glyphs().render(commit_description)?;
                     writeln!(
-                        effects.get_output_stream(),
+                        output,
                         "{commit_num} Skipped commit (was already applied upstream): {commit_description}"
                     )?;
                 }
 
+                RebaseCommand::DropCommit { commit_oid } => {
+                    i += 1;
+                    let commit_num = format!("[{i}/{num_picks}]");
+
+                    let commit = repo.find_commit_or_fail(*commit_oid)?;
+                    rewritten_oids.push((*commit_oid, MaybeZeroOid::Zero));
+                    skipped_commit_oids.push((*commit_oid, current_oid));
+
+                    let commit_description = commit.friendly_describe(effects.get_glyphs())?;
+                    let commit_description = effects.get_glyphs().render(commit_description)?;
+                    writeln!(output, "{commit_num} Dropped commit: {commit_description}")?;
+                }
+
                 RebaseCommand::RegisterExtraPostRewriteHook
                 | RebaseCommand::DetectEmptyCommit { .. } => {
                     // Do nothing. We'll carry out post-rebase operations after the
@@ -801,6 +1655,30 @@ mod in_memory {
             }
         }
 
+        Ok(Ok(RebasedSegment {
+            rewritten_oids,
+            skipped_commit_oids,
+            conflict_commit_oids,
+        }))
+    }
+
+    /// Determine the new OID that `HEAD` should point to after an in-memory
+    /// rebase, based on the current `HEAD`.
+    ///
+    /// - This is only `None` if `HEAD` is unborn.
+    /// - This doesn't capture if `HEAD` was pointing to a branch. The caller
+    /// will need to figure that out.
+    pub fn get_new_head_oid(
+        repo: &Repo,
+        rewritten_oids: &[(NonZeroOid, MaybeZeroOid)],
+        skipped_commit_oids: &[(NonZeroOid, NonZeroOid)],
+    ) -> eyre::Result<Option<NonZeroOid>> {
+        // Normally, we can determine the new `HEAD` OID by looking at the
+        // rewritten commits. However, if `HEAD` pointed to a commit that was
+        // skipped, then the rewritten OID is zero. In that case, we need to
+        // delete the branch (responsibility of the caller) and choose a
+        // different `HEAD` OID.
+        let head_oid = repo.get_head_info()?.oid;
         let new_head_oid: Option<NonZeroOid> = match head_oid {
             None => {
                 // `HEAD` is unborn, so keep it that way.
@@ -822,6 +1700,16 @@ mod in_memory {
                     Some(MaybeZeroOid::Zero) => {
                         // `HEAD` was rewritten, but its associated commit was
                         // skipped. Use whatever saved new `HEAD` OID we have.
+                        let skipped_head_new_oid =
+                            skipped_commit_oids
+                                .iter()
+                                .find_map(|(skipped_oid, new_oid)| {
+                                    if *skipped_oid == head_oid {
+                                        Some(*new_oid)
+                                    } else {
+                                        None
+                                    }
+                                });
                         let new_head_oid = match skipped_head_new_oid {
                             Some(new_head_oid) => new_head_oid,
                             None => {
@@ -841,10 +1729,37 @@ mod in_memory {
                 }
             }
         };
-        Ok(RebaseInMemoryResult::Succeeded {
-            rewritten_oids,
-            new_head_oid,
-        })
+        Ok(new_head_oid)
+    }
+
+    /// Run the `post-commit` hook for each commit created by a successful
+    /// in-memory rebase, in the order in which the commits were created. This
+    /// should only be called after the references have been moved, so that
+    /// the hook doesn't observe commits from a rebase which was later aborted.
+    pub fn run_post_commit_hooks(
+        effects: &Effects,
+        repo: &Repo,
+        commit_hooks: Option<&CommitHooks>,
+        rewritten_oids: &[(NonZeroOid, MaybeZeroOid)],
+    ) -> eyre::Result<()> {
+        let commit_hooks = match commit_hooks {
+            Some(commit_hooks) => commit_hooks,
+            None => return Ok(()),
+        };
+
+        let mut output = String::new();
+        let new_commit_oids = rewritten_oids
+            .iter()
+            .filter_map(|(_old_commit_oid, new_commit_oid)| match new_commit_oid {
+                MaybeZeroOid::NonZero(new_commit_oid) => Some(*new_commit_oid),
+                MaybeZeroOid::Zero => None,
+            })
+            .unique();
+        for new_commit_oid in new_commit_oids {
+            commit_hooks.run_post_commit_hook(repo, new_commit_oid, &mut output)?;
+        }
+        write!(effects.get_output_stream(), "{output}")?;
+        Ok(())
     }
 
     pub fn post_rebase_in_memory(
@@ -863,6 +1778,7 @@ mod in_memory {
             force_in_memory: _,
             force_on_disk: _,
             resolve_merge_conflicts: _,
+            create_conflict_commits: _,
             check_out_commit_options,
         } = options;
 
@@ -877,19 +1793,32 @@ mod in_memory {
             }
         }
 
-        let head_info = repo.get_head_info()?;
+        // Record what we're about to do before updating any references, so
+        // that `git branchless repair` can finish or roll back the operation
+        // if it's interrupted.
+        let mut journal = RewriteJournal {
+            event_tx_id: *event_tx_id,
+            head_info: repo.get_head_info()?,
+            rewritten_oids: rewritten_oids.to_vec(),
+            skipped_head_updated_oid,
+            branch_moves: plan_branch_moves(repo, &rewritten_oids_map)?,
+            rewrites_recorded: false,
+        };
+        journal.write(repo)?;
+
+        let head_info = &journal.head_info;
         if head_info.oid.is_some() {
             // Avoid moving the branch which HEAD points to, or else the index will show
             // a lot of changes in the working copy.
-            repo.detach_head(&head_info)?;
+            repo.detach_head(head_info)?;
         }
 
-        move_branches(
+        apply_branch_moves(
             effects,
             git_run_info,
             repo,
             *event_tx_id,
-            &rewritten_oids_map,
+            &journal.branch_moves,
         )?;
 
         // Call the `post-rewrite` hook only after moving branches so that we don't
@@ -907,6 +1836,7 @@ mod in_memory {
             &["rebase"],
             Some(post_rewrite_stdin),
         )?;
+        journal.mark_rewrites_recorded(repo)?;
 
         let exit_code = check_out_updated_head(
             effects,
@@ -915,10 +1845,11 @@ mod in_memory {
             event_log_db,
             *event_tx_id,
             &rewritten_oids_map,
-            &head_info,
-            skipped_head_updated_oid,
+            &journal.head_info,
+            journal.skipped_head_updated_oid,
             check_out_commit_options,
         )?;
+        journal.remove(repo)?;
         Ok(exit_code)
     }
 }
@@ -929,6 +1860,7 @@ mod on_disk {
     use eyre::Context;
     use tracing::instrument;
 
+    use crate::core::conflicts::is_conflict_commit;
     use crate::core::effects::{Effects, OperationType};
     use crate::core::rewrite::plan::RebaseCommand;
     use crate::core::rewrite::plan::RebasePlan;
@@ -957,6 +1889,7 @@ mod on_disk {
             force_in_memory: _,
             force_on_disk: _,
             resolve_merge_conflicts: _,
+            create_conflict_commits: _,
             check_out_commit_options: _, // Checkout happens after rebase has concluded.
         } = options;
 
@@ -971,6 +1904,21 @@ mod on_disk {
             }));
         }
 
+        for command in rebase_plan.commands.iter() {
+            if let RebaseCommand::Pick {
+                original_commit_oid: _,
+                commit_to_apply_oid,
+            } = command
+            {
+                let commit_to_apply = repo.find_commit_or_fail(*commit_to_apply_oid)?;
+                if is_conflict_commit(&commit_to_apply)? {
+                    eyre::bail!(
+                        "Not implemented: rebasing conflict commit {commit_to_apply_oid} in an on-disk rebase; resolve it with `git branchless resolve` first"
+                    );
+                }
+            }
+        }
+
         if repo.has_changed_files(&effects, git_run_info)? {
             return Ok(Err(Error::ChangedFilesInRepository));
         }
@@ -1055,6 +2003,14 @@ mod on_disk {
         }) {
             eyre::bail!("Not implemented: replacing commits in an on disk rebase");
         }
+        if rebase_plan.commands.iter().any(|command| {
+            matches!(
+                command,
+                RebaseCommand::Squash { .. } | RebaseCommand::Fixup { .. }
+            )
+        }) {
+            eyre::bail!("Not implemented: squashing commits in an on disk rebase");
+        }
 
         let todo_file_path = rebase_state_dir.join("git-rebase-todo");
         std::fs::write(
@@ -1130,6 +2086,7 @@ mod on_disk {
             force_in_memory: _,
             force_on_disk: _,
             resolve_merge_conflicts: _,
+            create_conflict_commits: _,
             check_out_commit_options: _, // Checkout happens after rebase has concluded.
         } = options;
 
@@ -1171,6 +2128,11 @@ pub struct ExecuteRebasePlanOptions {
     /// rather than failing-fast.
     pub resolve_merge_conflicts: bool,
 
+    /// If a merge conflict occurs during an in-memory rebase, commit the
+    /// conflicted result as a conflict commit and continue rebasing, rather
+    /// than failing.
+    pub create_conflict_commits: bool,
+
     /// If `HEAD` was moved, the options for checking out the new `HEAD` commit.
     pub check_out_commit_options: CheckOutCommitOptions,
 }
@@ -1209,31 +2171,178 @@ pub fn execute_rebase_plan(
     event_log_db: &EventLogDb,
     rebase_plan: &RebasePlan,
     options: &ExecuteRebasePlanOptions,
+) -> eyre::Result<ExecuteRebasePlanResult> {
+    let results = execute_rebase_plans(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        &[rebase_plan],
+        options,
+    )?;
+    match results.into_iter().exactly_one() {
+        Ok(result) => Ok(result),
+        Err(_) => eyre::bail!("BUG: expected exactly one rebase plan result"),
+    }
+}
+
+/// Execute several rebase plans which don't depend on each other, such as the
+/// plans for different stacks. The commits for all of the plans are first
+/// rebased in memory in parallel, and then each plan's rewrites are applied to
+/// the repository in order.
+///
+/// Returns the result for each plan. Execution stops after the first plan
+/// which fails outright, so there may be fewer results than plans.
+pub fn execute_rebase_plans(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    rebase_plans: &[&RebasePlan],
+    options: &ExecuteRebasePlanOptions,
+) -> eyre::Result<Vec<ExecuteRebasePlanResult>> {
+    // Starting a new rewrite would overwrite the journal of the interrupted
+    // one, after which it could no longer be repaired.
+    if RewriteJournal::load(repo)?.is_some() {
+        writeln!(
+            effects.get_output_stream(),
+            "A previous rewrite operation was interrupted before it finished updating branches."
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "Run git branchless repair --no-dry-run to finish it (or add --rollback to roll it back), then try again."
+        )?;
+        return Ok(vec![ExecuteRebasePlanResult::Failed {
+            exit_code: ExitCode(1),
+        }]);
+    }
+
+    let commit_hooks = CommitHooks::from_config(git_run_info, repo, options.event_tx_id)?;
+    let in_memory_results: Vec<Option<(String, in_memory::RebaseInMemoryResult)>> = if options
+        .force_on_disk
+    {
+        rebase_plans.iter().map(|_| None).collect()
+    } else {
+        let rerere = Rerere::from_config(git_run_info, repo)?;
+        let in_memory_results = CommitSigner::from_config(repo).and_then(|signer| {
+            in_memory::rebase_plans_in_memory(
+                effects,
+                repo,
+                rebase_plans,
+                options,
+                signer.as_ref(),
+                commit_hooks.as_ref(),
+                rerere.as_ref(),
+            )
+        });
+        match in_memory_results {
+            Ok(in_memory_results) => in_memory_results.into_iter().map(Some).collect(),
+            Err(err) => {
+                // Nothing has been applied to the repository yet, so it's safe
+                // to abort.
+                if let Some(sign_error) =
+                    err.chain().find_map(|err| err.downcast_ref::<SignError>())
+                {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "Failed to sign rebased commit: {sign_error}"
+                    )?;
+                    writeln!(
+                        effects.get_error_stream(),
+                        "No commits were rewritten. Check your commit.gpgSign, gpg.format, and user.signingKey configuration and try again."
+                    )?;
+                } else if let Some(CommitHookRejected {
+                    hook_name,
+                    commit_oid,
+                    status: _,
+                    output,
+                }) = err
+                    .chain()
+                    .find_map(|err| err.downcast_ref::<CommitHookRejected>())
+                {
+                    write!(effects.get_output_stream(), "{output}")?;
+                    writeln!(
+                        effects.get_output_stream(),
+                        "The {hook_name} hook failed for commit: {}",
+                        effects
+                            .get_glyphs()
+                            .render(repo.friendly_describe_commit_from_oid(
+                                effects.get_glyphs(),
+                                *commit_oid
+                            )?)?
+                    )?;
+                    writeln!(effects.get_output_stream(), "No commits were rewritten.")?;
+                } else {
+                    return Err(err);
+                }
+                return Ok(vec![ExecuteRebasePlanResult::Failed {
+                    exit_code: ExitCode(1),
+                }]);
+            }
+        }
+    };
+
+    let mut results = Vec::new();
+    for (rebase_plan, in_memory_result) in rebase_plans.iter().zip(in_memory_results) {
+        let result = apply_rebase_plan(
+            effects,
+            git_run_info,
+            repo,
+            event_log_db,
+            rebase_plan,
+            in_memory_result,
+            commit_hooks.as_ref(),
+            options,
+        )?;
+        let is_failed = matches!(result, ExecuteRebasePlanResult::Failed { .. });
+        results.push(result);
+        if is_failed {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+/// Apply the result of rebasing `rebase_plan` in memory, if it was attempted,
+/// or else fall back to an on-disk rebase.
+fn apply_rebase_plan(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    rebase_plan: &RebasePlan,
+    in_memory_result: Option<(String, in_memory::RebaseInMemoryResult)>,
+    commit_hooks: Option<&CommitHooks>,
+    options: &ExecuteRebasePlanOptions,
 ) -> eyre::Result<ExecuteRebasePlanResult> {
     let ExecuteRebasePlanOptions {
         now: _,
         event_tx_id: _,
         preserve_timestamps: _,
         force_in_memory,
-        force_on_disk,
+        force_on_disk: _,
         resolve_merge_conflicts,
+        create_conflict_commits: _,
         check_out_commit_options: _,
     } = options;
 
-    if !force_on_disk {
+    if let Some((output, in_memory_result)) = in_memory_result {
         use in_memory::*;
         writeln!(
             effects.get_output_stream(),
             "Attempting rebase in-memory..."
         )?;
+        write!(effects.get_output_stream(), "{output}")?;
 
-        let failed_merge_info = match rebase_in_memory(effects, repo, rebase_plan, options)? {
+        let failed_merge_info = match in_memory_result {
             RebaseInMemoryResult::MergeFailed(failed_merge_info) => failed_merge_info,
 
             RebaseInMemoryResult::Succeeded {
                 rewritten_oids,
-                new_head_oid,
+                skipped_commit_oids,
+                conflict_commit_oids,
             } => {
+                let new_head_oid = get_new_head_oid(repo, &rewritten_oids, &skipped_commit_oids)?;
                 // Ignore the return code, as it probably indicates that the
                 // checkout failed (which might happen if the user has changes
                 // which don't merge cleanly). The user can resolve that
@@ -1250,10 +2359,26 @@ pub fn execute_rebase_plan(
                     new_head_oid,
                     options,
                 )?;
+                run_post_commit_hooks(effects, repo, commit_hooks, &rewritten_oids)?;
 
                 let rewritten_oids: HashMap<NonZeroOid, MaybeZeroOid> =
                     rewritten_oids.into_iter().collect();
                 writeln!(effects.get_output_stream(), "In-memory rebase succeeded.")?;
+                if !conflict_commit_oids.is_empty() {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "{} committed with merge conflicts. To find them, run: git query 'conflicted()'",
+                        Pluralize {
+                            determiner: None,
+                            amount: conflict_commit_oids.len(),
+                            unit: ("commit was", "commits were"),
+                        },
+                    )?;
+                    writeln!(
+                        effects.get_output_stream(),
+                        "To resolve the conflicts in a commit, run: git branchless resolve <commit>"
+                    )?;
+                }
                 return Ok(ExecuteRebasePlanResult::Succeeded {
                     rewritten_oids: Some(rewritten_oids),
                 });
@@ -1320,3 +2445,79 @@ Commit your changes and then try again.
 
     eyre::bail!("Both force_in_memory and force_on_disk were requested, but these options conflict")
 }
+
+/// The predicted result of executing one or more rebase plans, as computed by
+/// [`preview_rebase_plan`].
+#[derive(Clone, Debug, Default)]
+pub struct RebasePlanPreview {
+    /// Mapping from each commit which would be rewritten to the commit that it
+    /// would be rewritten as, or to the zero OID if it would be skipped.
+    pub rewritten_oids: HashMap<NonZeroOid, MaybeZeroOid>,
+
+    /// The commit that `HEAD` would point to after the rebase, if it would be
+    /// moved.
+    pub new_head_oid: Option<NonZeroOid>,
+
+    /// The rewritten commits which would have merge conflicts.
+    pub conflict_commit_oids: HashSet<NonZeroOid>,
+}
+
+/// Predict the result of executing the provided rebase plan, and add it to
+/// `preview`.
+///
+/// The rebase plan is carried out in memory, but no references, the working
+/// copy, or the event log are updated. Merge conflicts don't abort the
+/// prediction; the conflicted commits are recorded in the preview instead.
+#[instrument]
+pub fn preview_rebase_plan(
+    effects: &Effects,
+    repo: &Repo,
+    rebase_plan: &RebasePlan,
+    options: &ExecuteRebasePlanOptions,
+    preview: &mut RebasePlanPreview,
+) -> eyre::Result<Result<(), FailedMergeInfo>> {
+    use in_memory::*;
+
+    let options = ExecuteRebasePlanOptions {
+        create_conflict_commits: true,
+        ..options.clone()
+    };
+    let (rewritten_oids, new_head_oid, conflict_commit_oids) =
+        // The commits are only created to preview the result, so don't bother
+        // signing them or running the commit hooks.
+        match rebase_in_memory(effects, repo, rebase_plan, &options, None, None, None)? {
+            RebaseInMemoryResult::MergeFailed(failed_merge_info) => {
+                return Ok(Err(failed_merge_info))
+            }
+            RebaseInMemoryResult::Succeeded {
+                rewritten_oids,
+                skipped_commit_oids,
+                conflict_commit_oids,
+            } => {
+                let new_head_oid = get_new_head_oid(repo, &rewritten_oids, &skipped_commit_oids)?;
+                (rewritten_oids, new_head_oid, conflict_commit_oids)
+            }
+        };
+
+    // The conflict commits only exist for the sake of the preview, so don't
+    // keep their conflict sides alive.
+    for conflict_commit_oid in conflict_commit_oids.iter() {
+        let conflict_ref_name = get_conflict_ref_name(*conflict_commit_oid);
+        if let Some(mut reference) = repo.find_reference(&conflict_ref_name)? {
+            reference.delete()?;
+        }
+    }
+
+    let RebasePlanPreview {
+        rewritten_oids: preview_rewritten_oids,
+        new_head_oid: preview_new_head_oid,
+        conflict_commit_oids: preview_conflict_commit_oids,
+    } = preview;
+    preview_rewritten_oids.extend(rewritten_oids);
+    preview_conflict_commit_oids.extend(conflict_commit_oids);
+    let head_oid = repo.get_head_info()?.oid;
+    if new_head_oid != head_oid {
+        *preview_new_head_oid = new_head_oid;
+    }
+    Ok(Ok(()))
+}
@@ -0,0 +1,251 @@
+//! Record the reference updates that a rewrite operation is about to make, so
+//! that if the operation is interrupted partway through (such as by a crash or
+//! by `Ctrl-C`), `git branchless repair` can finish or roll it back.
+//!
+//! The journal is written before any references are updated, and removed once
+//! the operation has completed. It's stored as a plain-text file with one
+//! entry per line, similar to Git's `rewritten-list`.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use eyre::Context;
+use tempfile::NamedTempFile;
+use tracing::instrument;
+
+use crate::core::eventlog::EventTransactionId;
+use crate::git::{MaybeZeroOid, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo};
+
+/// An update to a branch which a rewrite operation makes after the rewritten
+/// commits have been created.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BranchMove {
+    /// The branch to update.
+    pub reference_name: ReferenceName,
+
+    /// The commit that the branch pointed to before the operation.
+    pub old_oid: NonZeroOid,
+
+    /// The commit that the branch should point to after the operation, or the
+    /// zero OID if the branch should be deleted.
+    pub new_oid: MaybeZeroOid,
+}
+
+/// The current state of a [`BranchMove`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BranchMoveStatus {
+    /// The branch still points to its old commit.
+    Pending,
+
+    /// The branch has been updated.
+    Applied,
+
+    /// The branch points to some other commit, presumably because it was
+    /// updated by another operation since.
+    Diverged {
+        /// The commit that the branch currently points to, or the zero OID if
+        /// the branch doesn't exist.
+        current_oid: MaybeZeroOid,
+    },
+}
+
+impl BranchMove {
+    /// Determine whether this update has been applied to the repository.
+    #[instrument]
+    pub fn get_status(&self, repo: &Repo) -> eyre::Result<BranchMoveStatus> {
+        let current_oid = match repo.find_reference(&self.reference_name)? {
+            Some(reference) => match repo.resolve_reference(&reference)?.oid {
+                Some(oid) => MaybeZeroOid::NonZero(oid),
+                None => MaybeZeroOid::Zero,
+            },
+            None => MaybeZeroOid::Zero,
+        };
+        let status = if current_oid == self.new_oid {
+            BranchMoveStatus::Applied
+        } else if current_oid == MaybeZeroOid::NonZero(self.old_oid) {
+            BranchMoveStatus::Pending
+        } else {
+            BranchMoveStatus::Diverged { current_oid }
+        };
+        Ok(status)
+    }
+}
+
+/// The journal of an in-progress rewrite operation.
+#[derive(Debug)]
+pub struct RewriteJournal {
+    /// The event transaction of the operation.
+    pub event_tx_id: EventTransactionId,
+
+    /// The `HEAD` before the operation started.
+    pub head_info: ResolvedReferenceInfo,
+
+    /// The commits which were rewritten, and what they were rewritten to.
+    pub rewritten_oids: Vec<(NonZeroOid, MaybeZeroOid)>,
+
+    /// The commit to check out if the commit at `HEAD` was skipped by the
+    /// operation, such as because it became empty. See
+    /// [`check_out_updated_head`](super::check_out_updated_head).
+    pub skipped_head_updated_oid: Option<NonZeroOid>,
+
+    /// The branch updates which the operation makes.
+    pub branch_moves: Vec<BranchMove>,
+
+    /// Whether the rewritten commits have been recorded in the event log,
+    /// which happens after the branches have been moved.
+    pub rewrites_recorded: bool,
+}
+
+fn get_journal_path(repo: &Repo) -> PathBuf {
+    repo.get_path().join("branchless").join("rewrite-journal")
+}
+
+impl RewriteJournal {
+    /// Load the journal of the interrupted rewrite operation, if there is one.
+    #[instrument]
+    pub fn load(repo: &Repo) -> eyre::Result<Option<Self>> {
+        let path = get_journal_path(repo);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).wrap_err_with(|| format!("Opening rewrite journal at {path:?}"))
+            }
+        };
+
+        let mut event_tx_id = None;
+        let mut head_info = ResolvedReferenceInfo {
+            oid: None,
+            reference_name: None,
+        };
+        let mut rewritten_oids = Vec::new();
+        let mut skipped_head_updated_oid = None;
+        let mut branch_moves = Vec::new();
+        let mut rewrites_recorded = false;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            match *line.splitn(4, ' ').collect::<Vec<_>>().as_slice() {
+                ["event-tx-id", id] => {
+                    event_tx_id = Some(id.parse()?);
+                }
+                ["head", oid, reference_name] => {
+                    head_info = ResolvedReferenceInfo {
+                        oid: match oid.parse()? {
+                            MaybeZeroOid::NonZero(oid) => Some(oid),
+                            MaybeZeroOid::Zero => None,
+                        },
+                        reference_name: match reference_name {
+                            "-" => None,
+                            reference_name => Some(ReferenceName::from(reference_name)),
+                        },
+                    };
+                }
+                ["rewrite", old_oid, new_oid] => {
+                    rewritten_oids.push((old_oid.parse()?, new_oid.parse()?));
+                }
+                ["skipped-head-updated-oid", oid] => {
+                    skipped_head_updated_oid = Some(oid.parse()?);
+                }
+                ["branch", old_oid, new_oid, reference_name] => {
+                    branch_moves.push(BranchMove {
+                        reference_name: ReferenceName::from(reference_name),
+                        old_oid: old_oid.parse()?,
+                        new_oid: new_oid.parse()?,
+                    });
+                }
+                ["rewrites-recorded"] => {
+                    rewrites_recorded = true;
+                }
+                _ => eyre::bail!("Invalid rewrite journal line: {:?}", &line),
+            }
+        }
+
+        let event_tx_id = match event_tx_id {
+            Some(event_tx_id) => event_tx_id,
+            None => eyre::bail!("Rewrite journal at {:?} has no event transaction ID", path),
+        };
+        Ok(Some(Self {
+            event_tx_id,
+            head_info,
+            rewritten_oids,
+            skipped_head_updated_oid,
+            branch_moves,
+            rewrites_recorded,
+        }))
+    }
+
+    /// Write the journal to disk, replacing any existing journal. The journal
+    /// is written to a temporary file first, so that it's never observed in a
+    /// partially-written state.
+    #[instrument]
+    pub fn write(&self, repo: &Repo) -> eyre::Result<()> {
+        let Self {
+            event_tx_id,
+            head_info,
+            rewritten_oids,
+            skipped_head_updated_oid,
+            branch_moves,
+            rewrites_recorded,
+        } = self;
+
+        let tempfile_dir = repo.get_tempfile_dir();
+        std::fs::create_dir_all(&tempfile_dir).wrap_err("Creating tempfile dir")?;
+        let mut tempfile = NamedTempFile::new_in(&tempfile_dir)
+            .wrap_err("Creating temporary rewrite journal file")?;
+        let file = tempfile.as_file_mut();
+        writeln!(file, "event-tx-id {event_tx_id}")?;
+        writeln!(
+            file,
+            "head {} {}",
+            MaybeZeroOid::from(head_info.oid),
+            match &head_info.reference_name {
+                Some(reference_name) => reference_name.as_str(),
+                None => "-",
+            }
+        )?;
+        for (old_oid, new_oid) in rewritten_oids {
+            writeln!(file, "rewrite {old_oid} {new_oid}")?;
+        }
+        if let Some(skipped_head_updated_oid) = skipped_head_updated_oid {
+            writeln!(file, "skipped-head-updated-oid {skipped_head_updated_oid}")?;
+        }
+        for BranchMove {
+            reference_name,
+            old_oid,
+            new_oid,
+        } in branch_moves
+        {
+            writeln!(
+                file,
+                "branch {old_oid} {new_oid} {}",
+                reference_name.as_str()
+            )?;
+        }
+        if *rewrites_recorded {
+            writeln!(file, "rewrites-recorded")?;
+        }
+        file.sync_all()?;
+        tempfile
+            .persist(get_journal_path(repo))
+            .wrap_err("Moving new rewrite journal into place")?;
+        Ok(())
+    }
+
+    /// Record that the rewritten commits have been recorded in the event log.
+    #[instrument]
+    pub fn mark_rewrites_recorded(&mut self, repo: &Repo) -> eyre::Result<()> {
+        self.rewrites_recorded = true;
+        self.write(repo)
+    }
+
+    /// Remove the journal, since the operation has completed.
+    #[instrument]
+    pub fn remove(self, repo: &Repo) -> eyre::Result<()> {
+        match std::fs::remove_file(get_journal_path(repo)) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).wrap_err("Removing rewrite journal"),
+        }
+    }
+}
@@ -1,21 +1,35 @@
 //! Tools for editing the commit graph.
 
+mod commit_hooks;
 mod evolve;
 mod execute;
+mod journal;
 mod plan;
+mod rerere;
 pub mod rewrite_hooks;
+mod todo;
 
 use std::sync::Mutex;
 
+pub use commit_hooks::{
+    CommitHookRejected, CommitHooks, IN_MEMORY_COMMIT_ENV_VAR, UPDATE_MARKER_END,
+    UPDATE_MARKER_START,
+};
 pub use evolve::{find_abandoned_children, find_rewrite_target};
 pub use execute::{
-    execute_rebase_plan, move_branches, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    FailedMergeInfo, MergeConflictRemediation,
+    apply_branch_moves, check_out_updated_head, execute_rebase_plan, execute_rebase_plans,
+    move_branches, plan_branch_moves, preview_rebase_plan, ExecuteRebasePlanOptions,
+    ExecuteRebasePlanResult, FailedMergeInfo, MergeConflictRemediation, RebasePlanPreview,
 };
+pub use journal::{BranchMove, BranchMoveStatus, RewriteJournal};
 pub use plan::{
     BuildRebasePlanError, BuildRebasePlanOptions, OidOrLabel, RebaseCommand, RebasePlan,
     RebasePlanBuilder, RebasePlanPermissions,
 };
+pub use rerere::Rerere;
+pub use todo::{
+    parse_rebase_plan_todo, write_rebase_plan_todo, ParseRebasePlanTodoError, REBASE_PLAN_TODO_HELP,
+};
 use tracing::instrument;
 
 use crate::core::task::{Resource, ResourcePool};
@@ -66,6 +66,29 @@ pub enum RebaseCommand {
         commit_to_apply_oid: NonZeroOid,
     },
 
+    /// Apply the provided commit on top of the rebase head, and then fold the
+    /// result into the rebase head, rather than creating a new commit. The
+    /// commit messages are concatenated. The rebase head must be a commit
+    /// which was created as part of this rebase.
+    Squash {
+        /// The commit whose changes and message should be folded into the
+        /// rebase head.
+        commit_oid: NonZeroOid,
+    },
+
+    /// Like [`RebaseCommand::Squash`], but discard the commit's message. If
+    /// `use_commit_message` is set, then the rebase head's message is instead
+    /// replaced with the body of the commit's message, as done for `amend!`
+    /// commits.
+    Fixup {
+        /// The commit whose changes should be folded into the rebase head.
+        commit_oid: NonZeroOid,
+
+        /// Whether to replace the rebase head's message with this commit's
+        /// message.
+        use_commit_message: bool,
+    },
+
     /// Merge two or more parent commits.
     Merge {
         /// The original merge commit to copy the commit contents from.
@@ -116,6 +139,13 @@ pub enum RebaseCommand {
         /// The original commit, which will be recorded as skipped.
         commit_oid: NonZeroOid,
     },
+
+    /// The commit was removed from the rebase plan, such as by editing it.
+    /// Don't apply it, and record it as skipped in the `rewritten-list`.
+    DropCommit {
+        /// The original commit, which will be recorded as skipped.
+        commit_oid: NonZeroOid,
+    },
 }
 
 /// Represents a sequence of commands that can be executed to carry out a rebase
@@ -139,6 +169,17 @@ impl ToString for RebaseCommand {
                 original_commit_oid: _,
                 commit_to_apply_oid: commit_oid,
             } => format!("pick {commit_oid}"),
+            RebaseCommand::Squash { commit_oid } => format!("squash {commit_oid}"),
+            RebaseCommand::Fixup {
+                commit_oid,
+                use_commit_message,
+            } => {
+                if *use_commit_message {
+                    format!("fixup -C {commit_oid}")
+                } else {
+                    format!("fixup {commit_oid}")
+                }
+            }
             RebaseCommand::Merge {
                 commit_oid,
                 commits_to_merge,
@@ -180,6 +221,9 @@ impl ToString for RebaseCommand {
             RebaseCommand::SkipUpstreamAppliedCommit { commit_oid } => {
                 format!("exec git branchless hook-skip-upstream-applied-commit {commit_oid}")
             }
+            RebaseCommand::DropCommit { commit_oid } => {
+                format!("exec git branchless hook-drop-commit {commit_oid}")
+            }
         }
     }
 }
@@ -334,6 +378,7 @@ impl<'a> ConstraintGraph<'a> {
     fn collect_descendants(
         &self,
         visible_commits: &CommitSet,
+        moved_commits: &CommitSet,
         acc: &mut Vec<Constraint>,
         current_oid: NonZeroOid,
     ) -> eyre::Result<()> {
@@ -347,11 +392,27 @@ impl<'a> ConstraintGraph<'a> {
             if self.commits_to_move().contains(&child_oid) {
                 continue;
             }
+
+            // A merge commit may descend from several commits being moved. It
+            // has to be constrained to all of them, so that it's only applied
+            // once all of its moved parents have been applied.
+            let parent_oids = {
+                let parent_oids = self
+                    .dag
+                    .query()
+                    .parents(CommitSet::from(child_oid))?
+                    .intersection(moved_commits);
+                let mut parent_oids = commit_set_to_vec(&parent_oids)?;
+                if !parent_oids.contains(&current_oid) {
+                    parent_oids.push(current_oid);
+                }
+                parent_oids
+            };
             acc.push(Constraint::MoveSubtree {
-                parent_oids: vec![current_oid],
+                parent_oids,
                 child_oid,
             });
-            self.collect_descendants(visible_commits, acc, child_oid)?;
+            self.collect_descendants(visible_commits, moved_commits, acc, child_oid)?;
         }
         Ok(())
     }
@@ -369,9 +430,14 @@ impl<'a> ConstraintGraph<'a> {
 
             let mut acc = Vec::new();
             let parents = self.commits_to_move();
+            let moved_commits = self
+                .dag
+                .query()
+                .descendants(parents.iter().copied().collect())?
+                .intersection(visible_commits);
             progress.notify_progress(0, parents.len());
             for parent_oid in parents {
-                self.collect_descendants(visible_commits, &mut acc, parent_oid)?;
+                self.collect_descendants(visible_commits, &moved_commits, &mut acc, parent_oid)?;
                 progress.notify_progress_inc(1);
             }
             acc
@@ -1141,7 +1207,13 @@ impl<'a> RebasePlanBuilder<'a> {
                     replacement_commit_oid: _,
                     parents: _,
                 }
-                | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid } => vec![*commit_oid],
+                | RebaseCommand::Squash { commit_oid }
+                | RebaseCommand::Fixup {
+                    commit_oid,
+                    use_commit_message: _,
+                }
+                | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid }
+                | RebaseCommand::DropCommit { commit_oid } => vec![*commit_oid],
             })
             .collect();
         let missing_commit_oids = state
@@ -1985,7 +2057,6 @@ mod tests {
             Ok(())
         })?;
 
-        // FIXME: this is wrong, the merge commit should be moved as well.
         let stdout = git.smartlog()?;
         insta::assert_snapshot!(stdout, @r###"
         O f777ecc (master) create initial.txt
@@ -1993,26 +2064,17 @@ mod tests {
         o 62fc20d create test1.txt
         |
         o 96d1c37 create test2.txt
-        |\
-        | o b8f27a8 create test3.txt
-        | |\
-        | | @ 2b47b50 create test5.txt
-        | |
-        | o 22cf458 create test4.txt
         |
-        x 70deb1e (rewritten as b8f27a86) create test3.txt
+        o b8f27a8 create test3.txt
         |\
-        | x 355e173 (rewritten as 22cf4586) create test4.txt
-        | & (merge) 8fb706a Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
-        |
-        x 9ea1b36 (rewritten as 2b47b505) create test5.txt
+        | @ 2b47b50 create test5.txt
+        | |
+        | | & (merge) 22cf458 create test4.txt
+        | |/
+        | o d26203c Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
         |
-        | & (merge) 355e173 (rewritten as 22cf4586) create test4.txt
-        |/
-        o 8fb706a Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
-        hint: there is 1 abandoned commit in your commit graph
-        hint: to fix this, run: git restack
-        hint: disable this hint by running: git config --global branchless.hint.smartlogFixAbandoned false
+        o 22cf458 create test4.txt
+        & (merge) d26203c Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
         "###);
 
         Ok(())
@@ -2073,6 +2135,7 @@ mod tests {
             force_in_memory: true,
             force_on_disk: false,
             resolve_merge_conflicts: false,
+            create_conflict_commits: false,
             check_out_commit_options: CheckOutCommitOptions {
                 additional_args: Default::default(),
                 reset: false,
@@ -0,0 +1,440 @@
+//! Reuse the conflict resolutions recorded by `git rerere` ("reuse recorded
+//! resolution") during in-memory rebases.
+//!
+//! Git records resolutions under `.git/rr-cache/<conflict-id>/`, where the
+//! conflict ID is a hash of the conflict hunks in the file. The `preimage` file
+//! contains the file with its conflict markers normalized, and the `postimage`
+//! file contains the file as the user resolved it. (Additional resolutions for
+//! the same conflict ID are stored as `preimage.<n>` and `postimage.<n>`.)
+//!
+//! When an in-memory cherry-pick has conflicts, we produce the conflicted file
+//! contents the same way that Git would, compute the conflict ID, and then
+//! apply the recorded resolution, if any. Recording new resolutions is left to
+//! Git itself: on-disk rebases record them as usual, and we invoke
+//! `git rerere` when conflicts are written to or resolved in the working copy.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use eyre::Context;
+use itertools::Itertools;
+use tracing::instrument;
+
+use crate::core::conflicts::CommitConflict;
+use crate::core::effects::Effects;
+use crate::core::eventlog::EventTransactionId;
+use crate::git::{
+    hydrate_tree, Commit, ConfigRead, FileMode, GitRunInfo, GitRunOpts, NonZeroOid, Repo, Tree,
+};
+use crate::util::ExitCode;
+
+/// The length of the conflict markers produced by `git merge-file` by default.
+const MARKER_SIZE: usize = 7;
+
+/// The file in the `.git` directory which lists the paths whose conflicts
+/// `git rerere` is waiting to see resolved.
+const MERGE_RR_FILE_NAME: &str = "MERGE_RR";
+
+/// Access to the resolutions recorded by `git rerere`, if it's enabled.
+#[derive(Debug)]
+pub struct Rerere {
+    git_run_info: GitRunInfo,
+    rr_cache_dir: PathBuf,
+}
+
+impl Rerere {
+    /// Returns `None` if `git rerere` isn't enabled. As with Git, it's enabled
+    /// if `rerere.enabled` is set, or if it's unset and the `rr-cache`
+    /// directory exists.
+    #[instrument]
+    pub fn from_config(git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<Option<Self>> {
+        // The `rr-cache` directory is shared between worktrees.
+        let common_dir = match repo.open_worktree_parent_repo()? {
+            Some(parent_repo) => parent_repo.get_path().to_path_buf(),
+            None => repo.get_path().to_path_buf(),
+        };
+        let rr_cache_dir = common_dir.join("rr-cache");
+
+        let config = repo.get_readonly_config()?;
+        let is_enabled = match config.get("rerere.enabled")? {
+            Some(is_enabled) => is_enabled,
+            None => rr_cache_dir.is_dir(),
+        };
+        if !is_enabled {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            git_run_info: git_run_info.clone(),
+            rr_cache_dir,
+        }))
+    }
+
+    /// Attempt to resolve the conflicts from cherry-picking `patch_commit`
+    /// onto `target_commit` using the recorded resolutions. Returns the
+    /// resolved tree and the paths which were resolved, or `None` if any
+    /// conflicting path has no applicable resolution.
+    #[instrument]
+    pub fn resolve_cherry_pick<'repo>(
+        &self,
+        repo: &'repo Repo,
+        patch_commit: &Commit,
+        target_commit: &Commit,
+    ) -> eyre::Result<Option<(Tree<'repo>, Vec<PathBuf>)>> {
+        let commit_conflict = CommitConflict::from_cherry_pick(patch_commit, target_commit)?;
+        let (merged_tree, conflicting_paths) =
+            commit_conflict.merge(repo, patch_commit.get_oid(), target_commit.get_oid())?;
+        let conflicting_paths = conflicting_paths.into_iter().sorted().collect_vec();
+        if conflicting_paths.is_empty() {
+            return Ok(Some((merged_tree, conflicting_paths)));
+        }
+
+        let parent = patch_commit.get_only_parent();
+        let base_tree = match &parent {
+            Some(parent) => Some(parent.get_tree()?),
+            None => None,
+        };
+        let our_tree = target_commit.get_tree()?;
+        let their_tree = patch_commit.get_tree()?;
+
+        let temp_dir = tempfile::tempdir()?;
+        let mut resolved_entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> = HashMap::new();
+        for path in conflicting_paths.iter() {
+            let base_content = match &base_tree {
+                Some(base_tree) => get_blob_content(repo, base_tree, path)?,
+                None => None,
+            };
+            let (our_content, their_content) = match (
+                get_blob_content(repo, &our_tree, path)?,
+                get_blob_content(repo, &their_tree, path)?,
+            ) {
+                (Some(our_content), Some(their_content)) => (our_content, their_content),
+                // Conflicts involving deleted or non-file entries aren't
+                // recorded by `git rerere` in the first place.
+                _ => return Ok(None),
+            };
+
+            let base_path = temp_dir.path().join("base");
+            let our_path = temp_dir.path().join("ours");
+            let their_path = temp_dir.path().join("theirs");
+            std::fs::write(&base_path, base_content.unwrap_or_default())?;
+            std::fs::write(&our_path, our_content)?;
+            std::fs::write(&their_path, their_content)?;
+            let conflicted_content = match self.merge_file(
+                repo,
+                &our_path,
+                &base_path,
+                &their_path,
+                &["ours", "base", "theirs"],
+            )? {
+                MergeFileResult::Clean(_) => {
+                    // `git merge-file` may succeed where `libgit2` didn't,
+                    // but that isn't a recorded resolution.
+                    return Ok(None);
+                }
+                MergeFileResult::Conflicted(conflicted_content) => conflicted_content,
+                MergeFileResult::Failed => return Ok(None),
+            };
+
+            let resolved_content =
+                match self.find_resolution(repo, temp_dir.path(), &conflicted_content)? {
+                    Some(resolved_content) => resolved_content,
+                    None => return Ok(None),
+                };
+            let file_mode = match our_tree.get_path(path)? {
+                Some(entry) => entry.get_filemode(),
+                None => FileMode::Blob,
+            };
+            let resolved_oid = repo.create_blob_from_contents(&resolved_content)?;
+            resolved_entries.insert(path.clone(), Some((resolved_oid, file_mode)));
+        }
+
+        let resolved_tree_oid = hydrate_tree(repo, Some(&merged_tree), resolved_entries)?;
+        let resolved_tree = repo.find_tree_or_fail(resolved_tree_oid)?;
+        Ok(Some((resolved_tree, conflicting_paths)))
+    }
+
+    /// Run `git rerere` to record the resolutions of any conflicts which have
+    /// been resolved in the working copy, and to record the conflicts which
+    /// are currently in the index, as `git commit` does.
+    #[instrument]
+    pub fn record(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        event_tx_id: EventTransactionId,
+        has_new_conflicts: bool,
+    ) -> eyre::Result<ExitCode> {
+        if !has_new_conflicts && !repo.get_path().join(MERGE_RR_FILE_NAME).exists() {
+            // There are no conflicts waiting to be resolved.
+            return Ok(ExitCode(0));
+        }
+        self.git_run_info
+            .run(effects, Some(event_tx_id), &["rerere"])
+    }
+
+    /// Look up the recorded resolution for the file with the given conflicted
+    /// contents, and apply it.
+    fn find_resolution(
+        &self,
+        repo: &Repo,
+        temp_dir: &Path,
+        conflicted_content: &[u8],
+    ) -> eyre::Result<Option<Vec<u8>>> {
+        let (normalized_content, conflict_id) = match normalize_conflicts(conflicted_content) {
+            Some(result) => result,
+            None => return Ok(None),
+        };
+        let conflict_dir = self.rr_cache_dir.join(conflict_id);
+        if !conflict_dir.is_dir() {
+            return Ok(None);
+        }
+
+        let mut variants = Vec::new();
+        for entry in std::fs::read_dir(&conflict_dir)
+            .wrap_err_with(|| format!("Reading rerere directory: {conflict_dir:?}"))?
+        {
+            let file_name = entry?.file_name();
+            let file_name = match file_name.to_str() {
+                Some(file_name) => file_name,
+                None => continue,
+            };
+            if let Some(suffix) = file_name.strip_prefix("postimage") {
+                if suffix.is_empty() || suffix.starts_with('.') {
+                    variants.push(suffix.to_owned());
+                }
+            }
+        }
+        variants.sort();
+
+        let this_path = temp_dir.join("thisimage");
+        std::fs::write(&this_path, &normalized_content)?;
+        for suffix in variants {
+            let preimage_path = conflict_dir.join(format!("preimage{suffix}"));
+            let postimage_path = conflict_dir.join(format!("postimage{suffix}"));
+            if !preimage_path.exists() {
+                continue;
+            }
+            // Like `git rerere`, apply the resolution as a three-way merge, so
+            // that it still applies if the parts of the file outside of the
+            // conflict hunks have changed.
+            match self.merge_file(
+                repo,
+                &this_path,
+                &preimage_path,
+                &postimage_path,
+                &["this", "preimage", "postimage"],
+            )? {
+                MergeFileResult::Clean(resolved_content) => return Ok(Some(resolved_content)),
+                MergeFileResult::Conflicted(_) | MergeFileResult::Failed => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn merge_file(
+        &self,
+        repo: &Repo,
+        current_path: &Path,
+        base_path: &Path,
+        other_path: &Path,
+        labels: &[&str; 3],
+    ) -> eyre::Result<MergeFileResult> {
+        let [current_label, base_label, other_label] = labels;
+        let args = [
+            OsStr::new("merge-file"),
+            OsStr::new("-p"),
+            OsStr::new("-L"),
+            OsStr::new(current_label),
+            OsStr::new("-L"),
+            OsStr::new(base_label),
+            OsStr::new("-L"),
+            OsStr::new(other_label),
+            current_path.as_os_str(),
+            base_path.as_os_str(),
+            other_path.as_os_str(),
+        ];
+        let args = args
+            .iter()
+            .map(|arg| arg.to_str().map(ToOwned::to_owned))
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| eyre::eyre!("Invalid UTF-8 in temporary file path"))?;
+        let result = self.git_run_info.run_silent(
+            repo,
+            None,
+            &args,
+            GitRunOpts {
+                treat_git_failure_as_error: false,
+                stdin: None,
+            },
+        )?;
+        // `git merge-file` exits with the number of conflicts, or a negative
+        // number on error.
+        let result = match result.exit_code {
+            ExitCode(0) => MergeFileResult::Clean(result.stdout),
+            ExitCode(exit_code) if (1..128).contains(&exit_code) => {
+                MergeFileResult::Conflicted(result.stdout)
+            }
+            ExitCode(_) => MergeFileResult::Failed,
+        };
+        Ok(result)
+    }
+}
+
+enum MergeFileResult {
+    Clean(Vec<u8>),
+    Conflicted(Vec<u8>),
+    Failed,
+}
+
+fn get_blob_content(repo: &Repo, tree: &Tree, path: &Path) -> eyre::Result<Option<Vec<u8>>> {
+    let entry = match tree.get_path(path)? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    match entry.get_filemode() {
+        FileMode::Blob | FileMode::BlobExecutable => {}
+        FileMode::Unreadable | FileMode::Tree | FileMode::Link | FileMode::Commit => {
+            return Ok(None)
+        }
+    }
+    let blob = repo.find_blob_or_fail(entry.get_oid())?;
+    Ok(Some(blob.get_content().to_vec()))
+}
+
+fn is_conflict_marker(line: &[u8], marker_char: u8) -> bool {
+    if line.len() <= MARKER_SIZE || !line[..MARKER_SIZE].iter().all(|c| *c == marker_char) {
+        return false;
+    }
+    let next_char = line[MARKER_SIZE];
+    // The markers for the two sides are always followed by a label, but the
+    // marker for the common ancestor may not be.
+    let wants_space = marker_char == b'<' || marker_char == b'>';
+    if wants_space && next_char != b' ' {
+        return false;
+    }
+    next_char.is_ascii_whitespace()
+}
+
+/// Normalize the conflict hunks in the provided file contents as `git rerere`
+/// does: strip the marker labels and the common ancestor section, and order
+/// the two sides of each hunk so that the result doesn't depend on which side
+/// was "ours". Returns the normalized contents and the conflict ID, or `None`
+/// if there were no conflict hunks or they couldn't be parsed (such as if they
+/// were nested).
+fn normalize_conflicts(content: &[u8]) -> Option<(Vec<u8>, String)> {
+    enum State {
+        Outside,
+        FirstSide,
+        Ancestor,
+        SecondSide,
+    }
+
+    let mut state = State::Outside;
+    let mut normalized = Vec::new();
+    let mut hasher = sha1_smol::Sha1::new();
+    let mut num_hunks = 0;
+    let mut first_side = Vec::new();
+    let mut second_side = Vec::new();
+    let marker = |marker_char: u8| {
+        let mut marker = vec![marker_char; MARKER_SIZE];
+        marker.push(b'\n');
+        marker
+    };
+    for line in content.split_inclusive(|c| *c == b'\n') {
+        state = match state {
+            State::Outside => {
+                if is_conflict_marker(line, b'<') {
+                    first_side.clear();
+                    second_side.clear();
+                    State::FirstSide
+                } else {
+                    normalized.extend_from_slice(line);
+                    State::Outside
+                }
+            }
+            State::FirstSide | State::Ancestor if is_conflict_marker(line, b'=') => {
+                State::SecondSide
+            }
+            State::FirstSide | State::Ancestor if is_conflict_marker(line, b'|') => State::Ancestor,
+            State::SecondSide if is_conflict_marker(line, b'>') => {
+                if first_side > second_side {
+                    std::mem::swap(&mut first_side, &mut second_side);
+                }
+                normalized.extend(marker(b'<'));
+                normalized.extend_from_slice(&first_side);
+                normalized.extend(marker(b'='));
+                normalized.extend_from_slice(&second_side);
+                normalized.extend(marker(b'>'));
+                hasher.update(&first_side);
+                hasher.update(b"\0");
+                hasher.update(&second_side);
+                hasher.update(b"\0");
+                num_hunks += 1;
+                State::Outside
+            }
+            _ if is_conflict_marker(line, b'<')
+                || is_conflict_marker(line, b'=')
+                || is_conflict_marker(line, b'|')
+                || is_conflict_marker(line, b'>') =>
+            {
+                return None;
+            }
+            State::FirstSide => {
+                first_side.extend_from_slice(line);
+                State::FirstSide
+            }
+            State::Ancestor => State::Ancestor,
+            State::SecondSide => {
+                second_side.extend_from_slice(line);
+                State::SecondSide
+            }
+        };
+    }
+
+    match state {
+        State::Outside if num_hunks > 0 => {
+            let conflict_id = hasher.digest().to_string();
+            Some((normalized, conflict_id))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_conflicts() {
+        let content = b"\
+before
+<<<<<<< ours
+zzz
+||||||| base
+base
+=======
+aaa
+>>>>>>> theirs
+after
+";
+        let (normalized, conflict_id) = normalize_conflicts(content).unwrap();
+        insta::assert_snapshot!(String::from_utf8(normalized).unwrap(), @r###"
+        before
+        <<<<<<<
+        aaa
+        =======
+        zzz
+        >>>>>>>
+        after
+        "###);
+        assert_eq!(conflict_id.len(), 40);
+
+        assert_eq!(normalize_conflicts(b"no conflicts\n"), None);
+        assert_eq!(
+            normalize_conflicts(b"<<<<<<< ours\n<<<<<<< nested\n=======\n>>>>>>> theirs\n"),
+            None
+        );
+    }
+}
@@ -27,7 +27,7 @@ use crate::git::{
 };
 
 use super::execute::check_out_updated_head;
-use super::{find_abandoned_children, move_branches};
+use super::{apply_branch_moves, find_abandoned_children, plan_branch_moves, RewriteJournal};
 
 #[instrument(skip(stream))]
 fn read_rewritten_list_entries(
@@ -126,16 +126,16 @@ pub fn hook_post_rewrite(
                 new_commit_oid,
             })
             .collect_vec();
-        let rewritten_oids_map: HashMap<NonZeroOid, MaybeZeroOid> =
-            rewritten_oids.into_iter().collect();
-        (rewritten_oids_map, events)
+        (rewritten_oids, events)
     };
+    let rewritten_oids_map: HashMap<NonZeroOid, MaybeZeroOid> =
+        rewritten_oids.iter().copied().collect();
 
     let is_spurious_event = rewrite_type == "amend" && repo.is_rebase_underway()?;
     if !is_spurious_event {
         let message_rewritten_commits = Pluralize {
             determiner: None,
-            amount: rewritten_oids.len(),
+            amount: rewritten_oids_map.len(),
             unit: ("rewritten commit", "rewritten commits"),
         }
         .to_string();
@@ -155,20 +155,39 @@ pub fn hook_post_rewrite(
         // Make sure to resolve `ORIG_HEAD` before we potentially delete the
         // branch it points to, so that we can get the original OID of `HEAD`.
         let previous_head_info = load_original_head_info(&repo)?;
-        move_branches(effects, git_run_info, &repo, event_tx_id, &rewritten_oids)?;
 
-        let skipped_head_updated_oid = load_updated_head_oid(&repo)?;
+        // Record the branch updates before making them, so that `git
+        // branchless repair` can finish or roll them back if we're
+        // interrupted. The rewrite events were already added above.
+        let journal = RewriteJournal {
+            event_tx_id,
+            head_info: previous_head_info,
+            rewritten_oids,
+            skipped_head_updated_oid: load_updated_head_oid(&repo)?,
+            branch_moves: plan_branch_moves(&repo, &rewritten_oids_map)?,
+            rewrites_recorded: true,
+        };
+        journal.write(&repo)?;
+        apply_branch_moves(
+            effects,
+            git_run_info,
+            &repo,
+            event_tx_id,
+            &journal.branch_moves,
+        )?;
+
         let exit_code = check_out_updated_head(
             effects,
             git_run_info,
             &repo,
             &event_log_db,
             event_tx_id,
-            &rewritten_oids,
-            &previous_head_info,
-            skipped_head_updated_oid,
+            &rewritten_oids_map,
+            &journal.head_info,
+            journal.skipped_head_updated_oid,
             &CheckOutCommitOptions::default(),
         )?;
+        journal.remove(&repo)?;
         if !exit_code.is_success() {
             eyre::bail!("Could not check out your updated `HEAD` commit.");
         }
@@ -181,7 +200,7 @@ pub fn hook_post_rewrite(
             &repo,
             &conn,
             &event_log_db,
-            rewritten_oids.keys().copied(),
+            rewritten_oids_map.keys().copied(),
         )?;
         if printed_hint {
             print_hint_suppression_notice(effects, Hint::RestackWarnAbandoned)?;
@@ -486,14 +505,32 @@ pub fn hook_skip_upstream_applied_commit(
             .get_glyphs()
             .render(commit.friendly_describe(effects.get_glyphs())?)?
     )?;
+    record_skipped_commit(&repo, commit_oid)
+}
+
+/// For rebases, if a commit was dropped from the rebase plan, skip it without
+/// attempting to apply it.
+pub fn hook_drop_commit(effects: &Effects, commit_oid: NonZeroOid) -> eyre::Result<()> {
+    let repo = Repo::from_current_dir()?;
+    let commit = repo.find_commit_or_fail(commit_oid)?;
+    writeln!(
+        effects.get_output_stream(),
+        "Dropping commit: {}",
+        effects
+            .get_glyphs()
+            .render(commit.friendly_describe(effects.get_glyphs())?)?
+    )?;
+    record_skipped_commit(&repo, commit_oid)
+}
 
+fn record_skipped_commit(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Result<()> {
     if let Some(orig_head_reference) = repo.find_reference(&"ORIG_HEAD".into())? {
         let resolved_orig_head = repo.resolve_reference(&orig_head_reference)?;
         if let Some(original_head_oid) = resolved_orig_head.oid {
             if original_head_oid == commit_oid {
                 let current_head_oid = repo.get_head_info()?.oid;
                 if let Some(current_head_oid) = current_head_oid {
-                    save_updated_head_oid(&repo, current_head_oid)?;
+                    save_updated_head_oid(repo, current_head_oid)?;
                 }
             }
         }
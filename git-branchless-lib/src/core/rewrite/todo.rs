@@ -0,0 +1,504 @@
+//! Convert rebase plans to and from a human-editable "todo" format, similar to
+//! the one used by `git rebase --interactive`.
+//!
+//! Bookkeeping commands which the user shouldn't have to manage (such as
+//! [`RebaseCommand::DetectEmptyCommit`]) are omitted when writing the todo
+//! list, and added back automatically when parsing it.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use bstr::ByteSlice;
+use itertools::Itertools;
+use thiserror::Error;
+use tracing::instrument;
+
+use crate::git::{NonZeroOid, Repo};
+
+use super::{OidOrLabel, RebaseCommand, RebasePlan};
+
+/// Help text to append to the todo list when presenting it to the user.
+pub const REBASE_PLAN_TODO_HELP: &str = "\
+#
+# Commands:
+# reset <commit | label> = move the rebase head to the given commit or label
+# pick <commit> = apply the commit on top of the rebase head
+# squash <commit> = fold the commit into the rebase head, combining their
+#     messages
+# fixup [-C] <commit> = like squash, but keep only the rebase head's message
+#     (or with -C, only the commit's message)
+# label <label> = name the rebase head, so that it can be referred to later
+# merge <commit> <parent>... = re-create the merge commit on top of the rebase
+#     head, merging in the given parents
+# replace <commit> <replacement> <parent>... = use the replacement commit's
+#     contents for the commit, with the given parents
+# skip <commit> = skip the commit, as it was already applied upstream
+# drop <commit> = remove the commit
+# break = stop the rebase (on-disk rebases only)
+#
+# Lines can be re-ordered. If you remove a line for a commit, that commit
+# will be dropped. If you remove all the commands, the rebase will be aborted.
+# Everything after a `#` is ignored.
+";
+
+/// An error encountered when parsing an edited todo list.
+#[allow(missing_docs)]
+#[derive(Debug, Error)]
+pub enum ParseRebasePlanTodoError {
+    #[error("line {line_num}: unknown command: {command}")]
+    UnknownCommand { line_num: usize, command: String },
+
+    #[error("line {line_num}: wrong number of arguments, expected: {usage}")]
+    WrongNumberOfArguments {
+        line_num: usize,
+        usage: &'static str,
+    },
+
+    #[error("line {line_num}: could not find commit: {spec}")]
+    UnknownCommit { line_num: usize, spec: String },
+
+    #[error("line {line_num}: not a commit or a previously-created label: {spec}")]
+    UnknownTarget { line_num: usize, spec: String },
+
+    #[error("line {line_num}: commit {commit_oid} was not part of the original rebase plan")]
+    CommitNotInPlan {
+        line_num: usize,
+        commit_oid: NonZeroOid,
+    },
+
+    #[error("line {line_num}: commit {commit_oid} appears more than once")]
+    DuplicateCommit {
+        line_num: usize,
+        commit_oid: NonZeroOid,
+    },
+
+    #[error("the first command must be `reset` to a commit")]
+    MissingInitialReset,
+}
+
+/// Get the commits which are rewritten (or skipped) by the given rebase plan,
+/// in the order they appear in the plan.
+fn get_plan_commit_oids(rebase_plan: &RebasePlan) -> Vec<NonZeroOid> {
+    rebase_plan
+        .commands
+        .iter()
+        .filter_map(|command| match command {
+            RebaseCommand::CreateLabel { label_name: _ }
+            | RebaseCommand::Reset { target: _ }
+            | RebaseCommand::Break
+            | RebaseCommand::RegisterExtraPostRewriteHook
+            | RebaseCommand::DetectEmptyCommit { commit_oid: _ } => None,
+            RebaseCommand::Pick {
+                original_commit_oid: commit_oid,
+                commit_to_apply_oid: _,
+            }
+            | RebaseCommand::Merge {
+                commit_oid,
+                commits_to_merge: _,
+            }
+            | RebaseCommand::Replace {
+                commit_oid,
+                replacement_commit_oid: _,
+                parents: _,
+            }
+            | RebaseCommand::Squash { commit_oid }
+            | RebaseCommand::Fixup {
+                commit_oid,
+                use_commit_message: _,
+            }
+            | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid }
+            | RebaseCommand::DropCommit { commit_oid } => Some(*commit_oid),
+        })
+        .unique()
+        .collect()
+}
+
+/// Render the rebase plan as a todo list, one command per line. The summary
+/// of each commit being rewritten is included as a comment.
+#[instrument]
+pub fn write_rebase_plan_todo(repo: &Repo, rebase_plan: &RebasePlan) -> eyre::Result<String> {
+    let describe_target = |target: &OidOrLabel| -> eyre::Result<String> {
+        match target {
+            OidOrLabel::Oid(oid) => Ok(repo.find_commit_or_fail(*oid)?.get_short_oid()?),
+            OidOrLabel::Label(label_name) => Ok(label_name.clone()),
+        }
+    };
+    let short_oid = |oid: NonZeroOid| -> eyre::Result<String> {
+        Ok(repo.find_commit_or_fail(oid)?.get_short_oid()?)
+    };
+    let summary = |oid: NonZeroOid| -> eyre::Result<String> {
+        Ok(repo
+            .find_commit_or_fail(oid)?
+            .get_summary()?
+            .to_str_lossy()
+            .into_owned())
+    };
+
+    let mut result = String::new();
+    for command in rebase_plan.commands.iter() {
+        match command {
+            RebaseCommand::CreateLabel { label_name } => {
+                writeln!(result, "label {label_name}")?;
+            }
+            RebaseCommand::Reset { target } => {
+                writeln!(result, "reset {}", describe_target(target)?)?;
+            }
+            RebaseCommand::Pick {
+                original_commit_oid,
+                commit_to_apply_oid,
+            } => {
+                if original_commit_oid == commit_to_apply_oid {
+                    writeln!(
+                        result,
+                        "pick {} # {}",
+                        short_oid(*original_commit_oid)?,
+                        summary(*original_commit_oid)?
+                    )?;
+                } else {
+                    writeln!(
+                        result,
+                        "pick {} {} # {}",
+                        short_oid(*original_commit_oid)?,
+                        short_oid(*commit_to_apply_oid)?,
+                        summary(*original_commit_oid)?
+                    )?;
+                }
+            }
+            RebaseCommand::Squash { commit_oid } => {
+                writeln!(
+                    result,
+                    "squash {} # {}",
+                    short_oid(*commit_oid)?,
+                    summary(*commit_oid)?
+                )?;
+            }
+            RebaseCommand::Fixup {
+                commit_oid,
+                use_commit_message,
+            } => {
+                writeln!(
+                    result,
+                    "fixup {}{} # {}",
+                    if *use_commit_message { "-C " } else { "" },
+                    short_oid(*commit_oid)?,
+                    summary(*commit_oid)?
+                )?;
+            }
+            RebaseCommand::Merge {
+                commit_oid,
+                commits_to_merge,
+            } => {
+                writeln!(
+                    result,
+                    "merge {} {} # {}",
+                    short_oid(*commit_oid)?,
+                    commits_to_merge
+                        .iter()
+                        .map(describe_target)
+                        .collect::<eyre::Result<Vec<_>>>()?
+                        .join(" "),
+                    summary(*commit_oid)?
+                )?;
+            }
+            RebaseCommand::Replace {
+                commit_oid,
+                replacement_commit_oid,
+                parents,
+            } => {
+                writeln!(
+                    result,
+                    "replace {} {} {} # {}",
+                    short_oid(*commit_oid)?,
+                    short_oid(*replacement_commit_oid)?,
+                    parents
+                        .iter()
+                        .map(describe_target)
+                        .collect::<eyre::Result<Vec<_>>>()?
+                        .join(" "),
+                    summary(*commit_oid)?
+                )?;
+            }
+            RebaseCommand::Break => {
+                writeln!(result, "break")?;
+            }
+            RebaseCommand::SkipUpstreamAppliedCommit { commit_oid } => {
+                writeln!(
+                    result,
+                    "skip {} # {}",
+                    short_oid(*commit_oid)?,
+                    summary(*commit_oid)?
+                )?;
+            }
+            RebaseCommand::DropCommit { commit_oid } => {
+                writeln!(
+                    result,
+                    "drop {} # {}",
+                    short_oid(*commit_oid)?,
+                    summary(*commit_oid)?
+                )?;
+            }
+            RebaseCommand::RegisterExtraPostRewriteHook
+            | RebaseCommand::DetectEmptyCommit { commit_oid: _ } => {
+                // Added back automatically when parsing.
+            }
+        }
+    }
+    Ok(result)
+}
+
+enum ParseLineError {
+    Todo(ParseRebasePlanTodoError),
+    Other(eyre::Error),
+}
+
+impl From<ParseRebasePlanTodoError> for ParseLineError {
+    fn from(err: ParseRebasePlanTodoError) -> Self {
+        ParseLineError::Todo(err)
+    }
+}
+
+impl From<eyre::Error> for ParseLineError {
+    fn from(err: eyre::Error) -> Self {
+        ParseLineError::Other(err)
+    }
+}
+
+impl From<crate::git::RepoError> for ParseLineError {
+    fn from(err: crate::git::RepoError) -> Self {
+        ParseLineError::Other(err.into())
+    }
+}
+
+struct ParseState<'a> {
+    repo: &'a Repo,
+    allowed_commit_oids: HashSet<NonZeroOid>,
+    seen_commit_oids: HashSet<NonZeroOid>,
+    labels: HashSet<String>,
+    commands: Vec<RebaseCommand>,
+}
+
+impl ParseState<'_> {
+    fn resolve_commit(&self, line_num: usize, spec: &str) -> Result<NonZeroOid, ParseLineError> {
+        match self.repo.revparse_single_commit(spec)? {
+            Some(commit) => Ok(commit.get_oid()),
+            None => Err(ParseRebasePlanTodoError::UnknownCommit {
+                line_num,
+                spec: spec.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    fn resolve_target(&self, line_num: usize, spec: &str) -> Result<OidOrLabel, ParseLineError> {
+        if self.labels.contains(spec) {
+            return Ok(OidOrLabel::Label(spec.to_string()));
+        }
+        match self.repo.revparse_single_commit(spec)? {
+            Some(commit) => Ok(OidOrLabel::Oid(commit.get_oid())),
+            None => Err(ParseRebasePlanTodoError::UnknownTarget {
+                line_num,
+                spec: spec.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    fn record_commit(
+        &mut self,
+        line_num: usize,
+        commit_oid: NonZeroOid,
+    ) -> Result<(), ParseRebasePlanTodoError> {
+        if !self.allowed_commit_oids.contains(&commit_oid) {
+            return Err(ParseRebasePlanTodoError::CommitNotInPlan {
+                line_num,
+                commit_oid,
+            });
+        }
+        if !self.seen_commit_oids.insert(commit_oid) {
+            return Err(ParseRebasePlanTodoError::DuplicateCommit {
+                line_num,
+                commit_oid,
+            });
+        }
+        Ok(())
+    }
+
+    fn parse_line(
+        &mut self,
+        line_num: usize,
+        command: &str,
+        args: &[&str],
+    ) -> Result<(), ParseLineError> {
+        match (command, args) {
+            ("label", [label_name]) => {
+                self.labels.insert(label_name.to_string());
+                self.commands.push(RebaseCommand::CreateLabel {
+                    label_name: label_name.to_string(),
+                });
+            }
+            ("reset", [target]) => {
+                let target = self.resolve_target(line_num, target)?;
+                self.commands.push(RebaseCommand::Reset { target });
+            }
+            ("pick", [commit]) | ("pick", [commit, _]) => {
+                let original_commit_oid = self.resolve_commit(line_num, commit)?;
+                let commit_to_apply_oid = match args {
+                    [_, commit_to_apply] => self.resolve_commit(line_num, commit_to_apply)?,
+                    _ => original_commit_oid,
+                };
+                self.record_commit(line_num, original_commit_oid)?;
+                self.commands.push(RebaseCommand::Pick {
+                    original_commit_oid,
+                    commit_to_apply_oid,
+                });
+                self.commands.push(RebaseCommand::DetectEmptyCommit {
+                    commit_oid: original_commit_oid,
+                });
+            }
+            ("squash", [commit]) => {
+                let commit_oid = self.resolve_commit(line_num, commit)?;
+                self.record_commit(line_num, commit_oid)?;
+                self.commands.push(RebaseCommand::Squash { commit_oid });
+            }
+            ("fixup", [commit]) | ("fixup", ["-C", commit]) => {
+                let commit_oid = self.resolve_commit(line_num, commit)?;
+                self.record_commit(line_num, commit_oid)?;
+                self.commands.push(RebaseCommand::Fixup {
+                    commit_oid,
+                    use_commit_message: args.len() == 2,
+                });
+            }
+            ("merge", [commit, parents @ ..]) if !parents.is_empty() => {
+                let commit_oid = self.resolve_commit(line_num, commit)?;
+                let commits_to_merge = parents
+                    .iter()
+                    .map(|parent| self.resolve_target(line_num, parent))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.record_commit(line_num, commit_oid)?;
+                self.commands.push(RebaseCommand::Merge {
+                    commit_oid,
+                    commits_to_merge,
+                });
+            }
+            ("replace", [commit, replacement_commit, parents @ ..]) => {
+                let commit_oid = self.resolve_commit(line_num, commit)?;
+                let replacement_commit_oid = self.resolve_commit(line_num, replacement_commit)?;
+                let parents = parents
+                    .iter()
+                    .map(|parent| self.resolve_target(line_num, parent))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.record_commit(line_num, commit_oid)?;
+                self.commands.push(RebaseCommand::Replace {
+                    commit_oid,
+                    replacement_commit_oid,
+                    parents,
+                });
+            }
+            ("skip", [commit]) => {
+                let commit_oid = self.resolve_commit(line_num, commit)?;
+                self.record_commit(line_num, commit_oid)?;
+                self.commands
+                    .push(RebaseCommand::SkipUpstreamAppliedCommit { commit_oid });
+            }
+            ("drop", [commit]) => {
+                let commit_oid = self.resolve_commit(line_num, commit)?;
+                self.record_commit(line_num, commit_oid)?;
+                self.commands.push(RebaseCommand::DropCommit { commit_oid });
+            }
+            ("break", []) => {
+                self.commands.push(RebaseCommand::Break);
+            }
+
+            (command, _) => {
+                let usage = match command {
+                    "label" => "label <label>",
+                    "reset" => "reset <commit | label>",
+                    "pick" => "pick <commit>",
+                    "squash" => "squash <commit>",
+                    "fixup" => "fixup [-C] <commit>",
+                    "merge" => "merge <commit> <parent>...",
+                    "replace" => "replace <commit> <replacement> <parent>...",
+                    "skip" => "skip <commit>",
+                    "drop" => "drop <commit>",
+                    "break" => "break",
+                    command => {
+                        return Err(ParseRebasePlanTodoError::UnknownCommand {
+                            line_num,
+                            command: command.to_string(),
+                        }
+                        .into())
+                    }
+                };
+                return Err(
+                    ParseRebasePlanTodoError::WrongNumberOfArguments { line_num, usage }.into(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse an edited todo list back into a rebase plan.
+///
+/// The edited plan may only rewrite commits which were part of
+/// `original_plan`. Any such commits which no longer appear in the todo list
+/// are dropped. Returns `None` if the todo list contains no commands.
+#[instrument]
+pub fn parse_rebase_plan_todo(
+    repo: &Repo,
+    original_plan: &RebasePlan,
+    todo: &str,
+) -> eyre::Result<Result<Option<RebasePlan>, ParseRebasePlanTodoError>> {
+    let original_commit_oids = get_plan_commit_oids(original_plan);
+    let mut state = ParseState {
+        repo,
+        allowed_commit_oids: original_commit_oids.iter().copied().collect(),
+        seen_commit_oids: Default::default(),
+        labels: Default::default(),
+        commands: Default::default(),
+    };
+    for (line_num, line) in todo.lines().enumerate() {
+        let line = match line.split_once('#') {
+            Some((line, _comment)) => line,
+            None => line,
+        };
+        let (command, args) = match line.split_whitespace().collect_vec().as_slice() {
+            [] => continue,
+            [command, args @ ..] => (*command, args.to_vec()),
+        };
+        match state.parse_line(line_num + 1, command, &args) {
+            Ok(()) => {}
+            Err(ParseLineError::Todo(err)) => return Ok(Err(err)),
+            Err(ParseLineError::Other(err)) => return Err(err),
+        }
+    }
+
+    let ParseState {
+        repo: _,
+        allowed_commit_oids: _,
+        seen_commit_oids,
+        labels: _,
+        mut commands,
+    } = state;
+    if commands.is_empty() {
+        return Ok(Ok(None));
+    }
+    let first_dest_oid = match commands.first() {
+        Some(RebaseCommand::Reset {
+            target: OidOrLabel::Oid(first_dest_oid),
+        }) => *first_dest_oid,
+        _ => return Ok(Err(ParseRebasePlanTodoError::MissingInitialReset)),
+    };
+
+    for commit_oid in original_commit_oids {
+        if !seen_commit_oids.contains(&commit_oid) {
+            commands.push(RebaseCommand::DropCommit { commit_oid });
+        }
+    }
+    commands.push(RebaseCommand::RegisterExtraPostRewriteHook);
+
+    Ok(Ok(Some(RebasePlan {
+        first_dest_oid,
+        commands,
+    })))
+}
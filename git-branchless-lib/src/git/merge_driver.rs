@@ -0,0 +1,226 @@
+//! Apply the merge behavior configured via `.gitattributes` to in-memory
+//! merges, as `git merge` does: custom merge drivers configured by
+//! `merge.<driver>.driver`, and line ending normalization when
+//! `merge.renormalize` is set.
+
+use std::borrow::Cow;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use bstr::ByteSlice;
+use thiserror::Error;
+use tracing::instrument;
+
+use crate::git::config::ConfigRead;
+use crate::git::repo::Repo;
+
+/// The default length of conflict markers, used when the `conflict-marker-size`
+/// attribute isn't set.
+const DEFAULT_MARKER_SIZE: usize = 7;
+
+#[allow(missing_docs)]
+#[derive(Debug, Error)]
+pub enum MergeDriverError {
+    #[error("could not read attribute {name:?} for path {path:?}: {source}")]
+    ReadAttribute {
+        source: git2::Error,
+        name: &'static str,
+        path: PathBuf,
+    },
+
+    #[error("could not read merge driver configuration: {0}")]
+    ReadConfig(eyre::Error),
+
+    #[error("could not write temporary file for merge driver: {0}")]
+    WriteTempFile(#[source] io::Error),
+
+    #[error("could not run merge driver {name:?}: {source}")]
+    SpawnDriver { source: io::Error, name: String },
+
+    #[error("could not read result of merge driver {name:?}: {source}")]
+    ReadResult { source: io::Error, name: String },
+}
+
+/// A custom merge driver, as selected by the `merge` attribute for a path and
+/// configured by `merge.<driver>.driver`.
+#[derive(Clone, Debug)]
+pub struct MergeDriver {
+    name: String,
+    command: String,
+    marker_size: usize,
+}
+
+impl MergeDriver {
+    /// Look up the custom merge driver for the given path. Returns `None` if
+    /// the path uses one of the built-in drivers (which `libgit2` already
+    /// handles) or if the driver named by the `merge` attribute isn't
+    /// configured, in which case Git falls back to the built-in `text` driver.
+    #[instrument]
+    pub fn for_path(repo: &Repo, path: &Path) -> Result<Option<Self>, MergeDriverError> {
+        let name = match get_attr(repo, path, "merge")? {
+            git2::AttrValue::String(name) => name,
+            git2::AttrValue::True
+            | git2::AttrValue::False
+            | git2::AttrValue::Unspecified
+            | git2::AttrValue::Bytes(_) => return Ok(None),
+        };
+        if matches!(name, "text" | "binary" | "union") {
+            return Ok(None);
+        }
+        let name = name.to_owned();
+
+        let config = repo
+            .get_readonly_config()
+            .map_err(|err| MergeDriverError::ReadConfig(err.into()))?;
+        let command: Option<String> = config
+            .get(format!("merge.{name}.driver"))
+            .map_err(MergeDriverError::ReadConfig)?;
+        let command = match command {
+            Some(command) => command,
+            None => return Ok(None),
+        };
+
+        let marker_size = match get_attr(repo, path, "conflict-marker-size")? {
+            git2::AttrValue::String(size) => size.parse().unwrap_or(DEFAULT_MARKER_SIZE),
+            _ => DEFAULT_MARKER_SIZE,
+        };
+
+        Ok(Some(Self {
+            name,
+            command,
+            marker_size,
+        }))
+    }
+
+    /// Run the merge driver on the given file contents. Returns the merged
+    /// contents, or `None` if the driver reported that the merge had
+    /// conflicts.
+    #[instrument(skip(ancestor, ours, theirs))]
+    pub fn merge(
+        &self,
+        repo: &Repo,
+        path: &Path,
+        ancestor: &[u8],
+        ours: &[u8],
+        theirs: &[u8],
+    ) -> Result<Option<Vec<u8>>, MergeDriverError> {
+        let Self {
+            name,
+            command,
+            marker_size,
+        } = self;
+
+        let temp_dir = tempfile::tempdir().map_err(MergeDriverError::WriteTempFile)?;
+        let ancestor_path = temp_dir.path().join("ancestor");
+        let ours_path = temp_dir.path().join("ours");
+        let theirs_path = temp_dir.path().join("theirs");
+        for (path, contents) in [
+            (&ancestor_path, ancestor),
+            (&ours_path, ours),
+            (&theirs_path, theirs),
+        ] {
+            std::fs::write(path, contents).map_err(MergeDriverError::WriteTempFile)?;
+        }
+
+        let command = expand_placeholders(
+            command,
+            &[
+                ('O', shell_quote(&ancestor_path.to_string_lossy())),
+                ('A', shell_quote(&ours_path.to_string_lossy())),
+                ('B', shell_quote(&theirs_path.to_string_lossy())),
+                ('L', marker_size.to_string()),
+                ('P', shell_quote(&path.to_string_lossy())),
+            ],
+        );
+        // Like Git, run the driver from the root of the working copy.
+        let working_dir = repo
+            .get_working_copy_path()
+            .unwrap_or_else(|| repo.get_path());
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .current_dir(working_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|source| MergeDriverError::SpawnDriver {
+                source,
+                name: name.clone(),
+            })?;
+        if !status.success() {
+            return Ok(None);
+        }
+
+        let merged = std::fs::read(&ours_path).map_err(|source| MergeDriverError::ReadResult {
+            source,
+            name: name.clone(),
+        })?;
+        Ok(Some(merged))
+    }
+}
+
+/// Normalize the line endings of the given file contents for a merge, as Git
+/// does when `merge.renormalize` is set. Returns `None` if the contents
+/// shouldn't be normalized, such as if they're binary or the path is marked
+/// with `-text`.
+#[instrument(skip(contents))]
+pub fn renormalize<'a>(
+    repo: &Repo,
+    path: &Path,
+    contents: &'a [u8],
+) -> Result<Option<Cow<'a, [u8]>>, MergeDriverError> {
+    if matches!(get_attr(repo, path, "text")?, git2::AttrValue::False) {
+        return Ok(None);
+    }
+    // Same heuristic as Git's `buffer_is_binary`.
+    let is_binary = contents.iter().take(8000).any(|&c| c == b'\0');
+    if is_binary || !contents.contains_str("\r\n") {
+        return Ok(None);
+    }
+    Ok(Some(Cow::Owned(contents.replace("\r\n", "\n"))))
+}
+
+fn get_attr<'repo>(
+    repo: &'repo Repo,
+    path: &Path,
+    name: &'static str,
+) -> Result<git2::AttrValue<'repo>, MergeDriverError> {
+    let value = repo
+        .inner
+        .get_attr(path, name, git2::AttrCheckFlags::default())
+        .map_err(|source| MergeDriverError::ReadAttribute {
+            source,
+            name,
+            path: path.to_owned(),
+        })?;
+    Ok(git2::AttrValue::from_string(value))
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn expand_placeholders(command: &str, placeholders: &[(char, String)]) -> String {
+    let mut result = String::new();
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => result.push('%'),
+            Some(placeholder) => match placeholders.iter().find(|(key, _)| *key == placeholder) {
+                Some((_, value)) => result.push_str(value),
+                None => {
+                    result.push('%');
+                    result.push(placeholder);
+                }
+            },
+            None => result.push('%'),
+        }
+    }
+    result
+}
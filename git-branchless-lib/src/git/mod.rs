@@ -3,11 +3,13 @@
 mod config;
 mod diff;
 mod index;
+mod merge_driver;
 mod object;
 mod oid;
 mod reference;
 mod repo;
 mod run;
+mod sign;
 mod snapshot;
 mod status;
 mod test;
@@ -16,6 +18,7 @@ mod tree;
 pub use config::{Config, ConfigRead, ConfigValue, ConfigWrite};
 pub use diff::{process_diff_for_record, Diff};
 pub use index::{update_index, Index, IndexEntry, Stage, UpdateIndexCommand};
+pub use merge_driver::MergeDriverError;
 pub use object::Commit;
 pub use oid::{MaybeZeroOid, NonZeroOid};
 pub use reference::{
@@ -24,9 +27,10 @@ pub use reference::{
 pub use repo::{
     message_prettify, AmendFastOptions, CherryPickFastError, CherryPickFastOptions,
     Error as RepoError, GitVersion, PatchId, Repo, ResolvedReferenceInfo, Result as RepoResult,
-    Time,
+    Signature, Time,
 };
 pub use run::{GitRunInfo, GitRunOpts, GitRunResult};
+pub use sign::{CommitSigner, SignError, SignFormat};
 pub use snapshot::{WorkingCopyChangesType, WorkingCopySnapshot};
 pub use status::{FileMode, FileStatus, StatusEntry};
 pub use test::{
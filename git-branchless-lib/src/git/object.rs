@@ -147,6 +147,17 @@ impl<'repo> Commit<'repo> {
         Ok(Tree { inner: tree })
     }
 
+    /// Get the value of the given header field of this commit, or `None` if the
+    /// commit doesn't have that header field.
+    #[instrument]
+    pub fn get_header_field(&self, name: &str) -> Result<Option<BString>> {
+        match self.inner.header_field_bytes(name) {
+            Ok(value) => Ok(Some(BString::from(value.to_vec()))),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(Error::Git(err)),
+        }
+    }
+
     /// Get the "trailer" metadata from this commit's message. These are strings
     /// like `Signed-off-by: foo` which appear at the end of the commit message.
     #[instrument]
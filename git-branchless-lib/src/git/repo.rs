@@ -19,11 +19,11 @@ use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 use std::{io, time};
 
-use bstr::ByteVec;
+use bstr::{ByteSlice, ByteVec};
 use chrono::NaiveDateTime;
 use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
-use git2::DiffOptions;
+use git2::{DiffFindOptions, DiffOptions};
 use itertools::Itertools;
 use thiserror::Error;
 use tracing::{instrument, warn};
@@ -31,15 +31,20 @@ use tracing::{instrument, warn};
 use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::EventTransactionId;
 use crate::core::formatting::Glyphs;
+use crate::core::migrations::{apply_migrations, MigrationError};
 use crate::git::config::{Config, ConfigRead};
 use crate::git::object::Blob;
 use crate::git::oid::{make_non_zero_oid, MaybeZeroOid, NonZeroOid};
 use crate::git::reference::ReferenceNameError;
 use crate::git::run::GitRunInfo;
-use crate::git::tree::{dehydrate_tree, get_changed_paths_between_trees, hydrate_tree, Tree};
+use crate::git::tree::{
+    dehydrate_tree, get_changed_paths_between_trees, hydrate_tree, make_empty_tree, Tree,
+};
 use crate::git::{Branch, BranchType, Commit, Reference, ReferenceName};
 
 use super::index::{Index, IndexEntry};
+use super::merge_driver::{renormalize, MergeDriver, MergeDriverError};
+use super::sign::{CommitSigner, SignError};
 use super::snapshot::WorkingCopySnapshot;
 use super::status::FileMode;
 use super::{tree, Diff, StatusEntry};
@@ -68,12 +73,24 @@ pub enum Error {
         path: PathBuf,
     },
 
+    #[error("could not migrate database at {path}: {source}")]
+    MigrateDatabase {
+        source: MigrationError,
+        path: PathBuf,
+    },
+
     #[error("this repository does not have an associated working copy")]
     NoWorkingCopyPath,
 
     #[error("could not read config: {0}")]
     ReadConfig(#[source] git2::Error),
 
+    #[error("could not read config value {key:?}: {source}")]
+    ReadConfigValue {
+        source: eyre::Error,
+        key: &'static str,
+    },
+
     #[error("could not set HEAD (detached) to {oid}: {source}")]
     SetHead {
         source: git2::Error,
@@ -111,6 +128,9 @@ pub enum Error {
     #[error("could not create commit: {0}")]
     CreateCommit(#[source] git2::Error),
 
+    #[error("could not sign commit: {0}")]
+    SignCommit(#[source] SignError),
+
     #[error("could not cherry-pick commit {commit} onto {onto}: {source}")]
     CherryPickCommit {
         source: git2::Error,
@@ -125,6 +145,14 @@ pub enum Error {
         onto: NonZeroOid,
     },
 
+    #[error("could not merge trees {our} and {their} with ancestor {ancestor}: {source}")]
+    MergeTrees {
+        source: git2::Error,
+        ancestor: NonZeroOid,
+        our: NonZeroOid,
+        their: NonZeroOid,
+    },
+
     #[error("could not amend the current commit: {0}")]
     Amend(#[source] git2::Error),
 
@@ -146,6 +174,13 @@ pub enum Error {
     #[error("could not write index as tree: {0}")]
     WriteIndexToTree(#[source] git2::Error),
 
+    #[error("could not write tree {tree_oid} to index file at {path}: {source}")]
+    WriteTreeToIndexFile {
+        source: git2::Error,
+        tree_oid: NonZeroOid,
+        path: PathBuf,
+    },
+
     #[error("could not read branch information: {0}")]
     ReadBranch(#[source] git2::Error),
 
@@ -198,6 +233,9 @@ pub enum Error {
     #[error("could not create reference: {0}")]
     CreateReference(#[source] git2::Error),
 
+    #[error("could not update references: {0}")]
+    UpdateReferences(#[source] git2::Error),
+
     #[error("could not calculate changed paths: {0}")]
     GetChangedPaths(#[source] super::tree::Error),
 
@@ -252,6 +290,13 @@ pub enum Error {
     #[error("could not read message trailers: {0}")]
     ReadMessageTrailer(#[source] git2::Error),
 
+    #[error("could not count commits between {local_oid} and {upstream_oid}: {source}")]
+    GetAheadBehind {
+        source: git2::Error,
+        local_oid: NonZeroOid,
+        upstream_oid: NonZeroOid,
+    },
+
     #[error("could not describe commit {commit}: {source}")]
     DescribeCommit {
         source: eyre::Error,
@@ -277,6 +322,25 @@ pub enum Error {
 /// Result type.
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Sign the provided commit contents and write the signed commit to the object
+/// database.
+fn write_signed_commit(
+    repo: &git2::Repository,
+    contents: &[u8],
+    signer: &CommitSigner,
+) -> Result<git2::Oid> {
+    let signature = signer.sign(contents).map_err(Error::SignCommit)?;
+    // The contents were produced from `&str` values, so they should be valid
+    // UTF-8.
+    let contents = std::str::from_utf8(contents).map_err(|err| {
+        Error::CreateCommit(git2::Error::from_str(&format!(
+            "commit contents are not valid UTF-8: {err}"
+        )))
+    })?;
+    repo.commit_signed(contents, &signature, None)
+        .map_err(Error::CreateCommit)
+}
+
 /// Convert a `git2::Error` into an `eyre::Error` with an auto-generated message.
 pub(super) fn wrap_git_error(error: git2::Error) -> eyre::Error {
     eyre::eyre!("Git error {:?}: {}", error.code(), error.message())
@@ -414,9 +478,20 @@ pub enum CherryPickFastError {
         item: &'static str,
     },
 
+    #[error(
+        "merge commit {commit} has {num_parents} parents, but only merge commits with 2 parents can be rebased in memory"
+    )]
+    UnsupportedMergeCommit {
+        commit: NonZeroOid,
+        num_parents: usize,
+    },
+
     #[error(transparent)]
     HydrateTree(tree::Error),
 
+    #[error(transparent)]
+    MergeDriver(MergeDriverError),
+
     #[error(transparent)]
     Repo(#[from] Error),
 
@@ -424,6 +499,77 @@ pub enum CherryPickFastError {
     Git(git2::Error),
 }
 
+/// The entries for a path which is in conflict in an index, as the path and OID
+/// for each side of the conflict.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct IndexConflict {
+    ancestor: Option<(PathBuf, MaybeZeroOid)>,
+    our: Option<(PathBuf, MaybeZeroOid)>,
+    their: Option<(PathBuf, MaybeZeroOid)>,
+}
+
+impl IndexConflict {
+    fn get_paths(&self) -> impl Iterator<Item = &Path> {
+        let Self {
+            ancestor,
+            our,
+            their,
+        } = self;
+        [ancestor, our, their]
+            .into_iter()
+            .flatten()
+            .map(|(path, _oid)| path.as_path())
+    }
+}
+
+/// Get the conflicts in the provided index. The `commit` and `onto` OIDs are
+/// used for error reporting.
+fn get_index_conflicts(
+    index: &Index,
+    commit: NonZeroOid,
+    onto: NonZeroOid,
+) -> std::result::Result<Vec<IndexConflict>, CherryPickFastError> {
+    let convert_entry =
+        |entry: Option<git2::IndexEntry>,
+         item: &'static str|
+         -> std::result::Result<Option<(PathBuf, MaybeZeroOid)>, CherryPickFastError> {
+            match entry {
+                Some(entry) => {
+                    let oid = MaybeZeroOid::from(entry.id);
+                    let path = entry
+                        .path
+                        .into_path_buf()
+                        .map_err(|err| CherryPickFastError::DecodePath { source: err, item })?;
+                    Ok(Some((path, oid)))
+                }
+                None => Ok(None),
+            }
+        };
+
+    let mut result = Vec::new();
+    for conflict in index
+        .inner
+        .conflicts()
+        .map_err(|err| CherryPickFastError::GetConflicts {
+            source: err,
+            commit,
+            onto,
+        })?
+    {
+        let conflict = conflict.map_err(|err| CherryPickFastError::GetConflicts {
+            source: err,
+            commit,
+            onto,
+        })?;
+        result.push(IndexConflict {
+            ancestor: convert_entry(conflict.ancestor, "ancestor")?,
+            our: convert_entry(conflict.our, "our")?,
+            their: convert_entry(conflict.their, "their")?,
+        });
+    }
+    Ok(result)
+}
+
 /// Options for `Repo::amend_fast`
 #[derive(Debug)]
 pub enum AmendFastOptions {
@@ -524,6 +670,22 @@ impl Repo {
         Ok(Index { inner: index })
     }
 
+    /// Write an index file at `path` whose contents are the provided tree, such
+    /// as to run a command with `GIT_INDEX_FILE` set to a commit which isn't
+    /// checked out.
+    #[instrument]
+    pub fn write_tree_to_index_file(&self, tree: &Tree, path: &Path) -> Result<()> {
+        let make_error = |source| Error::WriteTreeToIndexFile {
+            source,
+            tree_oid: tree.get_oid(),
+            path: path.to_owned(),
+        };
+        let mut index = git2::Index::open(path).map_err(make_error)?;
+        index.read_tree(&tree.inner).map_err(make_error)?;
+        index.write().map_err(make_error)?;
+        Ok(())
+    }
+
     /// If this repository is a worktree for another "parent" repository, return a [`Repo`] object
     /// corresponding to that repository.
     #[instrument]
@@ -605,6 +767,7 @@ impl Repo {
             source: err,
             path: path.clone(),
         })?;
+        apply_migrations(&conn).map_err(|err| Error::MigrateDatabase { source: err, path })?;
         Ok(conn)
     }
 
@@ -856,6 +1019,23 @@ impl Repo {
         Ok(Some(PatchId { patch_id }))
     }
 
+    /// Get the patch ID for the changes between two trees, such as the combined
+    /// changes of a range of commits.
+    #[instrument]
+    pub fn get_patch_id_between_trees(
+        &self,
+        effects: &Effects,
+        old_tree: Option<&Tree>,
+        new_tree: &Tree,
+    ) -> Result<PatchId> {
+        let diff = self.get_diff_between_trees(effects, old_tree, new_tree, 3)?;
+        let patch_id = {
+            let (_effects, _progress) = effects.start_operation(OperationType::CalculatePatchId);
+            diff.inner.patchid(None).map_err(Error::GetPatchId)?
+        };
+        Ok(PatchId { patch_id })
+    }
+
     /// Attempt to parse the user-provided object descriptor.
     pub fn revparse_single_commit(&self, spec: &str) -> Result<Option<Commit>> {
         if spec.ends_with('@') && spec.len() > 1 {
@@ -989,6 +1169,32 @@ impl Repo {
         Ok(Reference { inner: reference })
     }
 
+    /// Point each of the given references to the corresponding commit,
+    /// creating them if necessary, as a single transaction. All of the
+    /// references are locked before any of them are updated, so either all of
+    /// the updates are applied or none of them are (barring an I/O error
+    /// partway through committing the transaction).
+    #[instrument]
+    pub fn update_references_atomically(
+        &self,
+        updates: &[(&ReferenceName, NonZeroOid)],
+        log_message: &str,
+    ) -> Result<()> {
+        let mut transaction = self.inner.transaction().map_err(Error::UpdateReferences)?;
+        for (name, _oid) in updates {
+            transaction
+                .lock_ref(name.as_str())
+                .map_err(Error::UpdateReferences)?;
+        }
+        for (name, oid) in updates {
+            transaction
+                .set_target(name.as_str(), oid.inner, None, log_message)
+                .map_err(Error::UpdateReferences)?;
+        }
+        transaction.commit().map_err(Error::UpdateReferences)?;
+        Ok(())
+    }
+
     /// Get a list of all remote names.
     #[instrument]
     pub fn get_all_remote_names(&self) -> Result<Vec<String>> {
@@ -1054,6 +1260,23 @@ impl Repo {
         }
     }
 
+    /// Count the commits which are reachable from `local_oid` but not from
+    /// `upstream_oid` (ahead), and vice-versa (behind).
+    #[instrument]
+    pub fn get_ahead_behind(
+        &self,
+        local_oid: NonZeroOid,
+        upstream_oid: NonZeroOid,
+    ) -> Result<(usize, usize)> {
+        self.inner
+            .graph_ahead_behind(local_oid.inner, upstream_oid.inner)
+            .map_err(|err| Error::GetAheadBehind {
+                source: err,
+                local_oid,
+                upstream_oid,
+            })
+    }
+
     /// Look up a commit with the given OID. Returns `None` if not found.
     #[instrument]
     pub fn find_commit(&self, oid: NonZeroOid) -> Result<Option<Commit>> {
@@ -1150,7 +1373,8 @@ impl Repo {
         Ok(make_non_zero_oid(oid))
     }
 
-    /// Create a new commit.
+    /// Create a new commit. If `signer` is provided, then the commit is
+    /// signed with it.
     #[instrument]
     pub fn create_commit(
         &self,
@@ -1160,15 +1384,131 @@ impl Repo {
         message: &str,
         tree: &Tree,
         parents: Vec<&Commit>,
+        signer: Option<&CommitSigner>,
     ) -> Result<NonZeroOid> {
         let parents = parents
             .iter()
             .map(|commit| &commit.inner)
             .collect::<Vec<_>>();
-        let oid = self
+        let oid = match signer {
+            None => self
+                .inner
+                .commit(
+                    update_ref,
+                    &author.inner,
+                    &committer.inner,
+                    message,
+                    &tree.inner,
+                    parents.as_slice(),
+                )
+                .map_err(Error::CreateCommit)?,
+            Some(signer) => {
+                let buf = self
+                    .inner
+                    .commit_create_buffer(
+                        &author.inner,
+                        &committer.inner,
+                        message,
+                        &tree.inner,
+                        parents.as_slice(),
+                    )
+                    .map_err(Error::CreateCommit)?;
+                let oid = write_signed_commit(&self.inner, &buf, signer)?;
+                if let Some(update_ref) = update_ref {
+                    self.inner
+                        .reference(update_ref, oid, true, "branchless: create signed commit")
+                        .map_err(Error::CreateCommit)?;
+                }
+                oid
+            }
+        };
+        Ok(make_non_zero_oid(oid))
+    }
+
+    /// Amend an existing commit, like [`Commit::amend_commit`]. If `signer` is
+    /// provided, then the new commit is signed with it.
+    /// Returns the OID of the resulting new commit.
+    #[instrument]
+    pub fn amend_commit(
+        &self,
+        commit: &Commit,
+        update_ref: Option<&str>,
+        author: Option<&Signature>,
+        committer: Option<&Signature>,
+        message: Option<&str>,
+        tree: Option<&Tree>,
+        signer: Option<&CommitSigner>,
+    ) -> Result<NonZeroOid> {
+        let signer = match signer {
+            Some(signer) => signer,
+            None => {
+                return commit.amend_commit(update_ref, author, committer, message, tree);
+            }
+        };
+
+        // `git2::Commit::amend` can't sign commits, so create the amended
+        // commit from scratch.
+        let author = match author {
+            Some(author) => author.inner.to_owned(),
+            None => commit.inner.author().to_owned(),
+        };
+        let committer = match committer {
+            Some(committer) => committer.inner.to_owned(),
+            None => commit.inner.committer().to_owned(),
+        };
+        let message = match message {
+            Some(message) => message,
+            None => commit.inner.message_raw().ok_or_else(|| {
+                Error::Amend(git2::Error::from_str(
+                    "could not decode commit message as UTF-8",
+                ))
+            })?,
+        };
+        let tree = match tree {
+            Some(tree) => tree.inner.clone(),
+            None => commit.inner.tree().map_err(Error::Amend)?,
+        };
+        let parents = commit.inner.parents().collect::<Vec<_>>();
+        let buf = self
             .inner
-            .commit(
-                update_ref,
+            .commit_create_buffer(
+                &author,
+                &committer,
+                message,
+                &tree,
+                parents.iter().collect::<Vec<_>>().as_slice(),
+            )
+            .map_err(Error::Amend)?;
+        let oid = write_signed_commit(&self.inner, &buf, signer)?;
+        if let Some(update_ref) = update_ref {
+            self.inner
+                .reference(update_ref, oid, true, "branchless: amend signed commit")
+                .map_err(Error::Amend)?;
+        }
+        Ok(make_non_zero_oid(oid))
+    }
+
+    /// Create a new commit with additional header fields, which are written
+    /// after the standard header fields. Git preserves unknown header fields,
+    /// but most tools ignore them.
+    #[instrument]
+    pub fn create_commit_with_extra_headers(
+        &self,
+        author: &Signature,
+        committer: &Signature,
+        message: &str,
+        tree: &Tree,
+        parents: Vec<&Commit>,
+        extra_headers: &[(&str, String)],
+        signer: Option<&CommitSigner>,
+    ) -> Result<NonZeroOid> {
+        let parents = parents
+            .iter()
+            .map(|commit| &commit.inner)
+            .collect::<Vec<_>>();
+        let buf = self
+            .inner
+            .commit_create_buffer(
                 &author.inner,
                 &committer.inner,
                 message,
@@ -1176,6 +1516,27 @@ impl Repo {
                 parents.as_slice(),
             )
             .map_err(Error::CreateCommit)?;
+
+        // The header fields are separated from the message by the first empty
+        // line.
+        let (header, message) = match buf.find("\n\n") {
+            Some(index) => buf.split_at(index + 1),
+            None => (buf.as_ref(), [].as_slice()),
+        };
+        let mut contents = header.to_vec();
+        for (name, value) in extra_headers {
+            contents.push_str(format!("{name} {value}\n"));
+        }
+        contents.extend_from_slice(message);
+
+        let oid = match signer {
+            None => {
+                let odb = self.inner.odb().map_err(Error::CreateCommit)?;
+                odb.write(git2::ObjectType::Commit, &contents)
+                    .map_err(Error::CreateCommit)?
+            }
+            Some(signer) => write_signed_commit(&self.inner, &contents, signer)?,
+        };
         Ok(make_non_zero_oid(oid))
     }
 
@@ -1230,12 +1591,20 @@ impl Repo {
             };
         }
 
-        let changed_pathbufs = self
+        let patch_paths = self
             .get_paths_touched_by_commit(patch_commit)?
             .ok_or_else(|| CherryPickFastError::GetPatch {
                 commit: patch_commit.get_oid(),
-            })?
-            .into_iter()
+            })?;
+        // If any of the paths were renamed in the target commit, then include
+        // the new paths as well, so that `libgit2` can detect the rename and
+        // apply the changes to the renamed files.
+        let renamed_paths = self.find_renamed_paths(patch_commit, target_commit, &patch_paths)?;
+        let changed_pathbufs = patch_paths
+            .iter()
+            .cloned()
+            .chain(renamed_paths.into_values())
+            .unique()
             .collect_vec();
         let changed_paths = changed_pathbufs.iter().map(PathBuf::borrow).collect_vec();
 
@@ -1247,60 +1616,40 @@ impl Repo {
         let rebased_index =
             self.cherry_pick_commit(&dehydrated_patch_commit, &dehydrated_target_commit, 0)?;
         let rebased_tree = {
-            if rebased_index.has_conflicts() {
-                let conflicting_paths = {
-                    let mut result = HashSet::new();
-                    for conflict in rebased_index.inner.conflicts().map_err(|err| {
-                        CherryPickFastError::GetConflicts {
-                            source: err,
-                            commit: patch_commit.get_oid(),
-                            onto: target_commit.get_oid(),
-                        }
-                    })? {
-                        let conflict =
-                            conflict.map_err(|err| CherryPickFastError::GetConflicts {
-                                source: err,
-                                commit: patch_commit.get_oid(),
-                                onto: target_commit.get_oid(),
-                            })?;
-                        if let Some(ancestor) = conflict.ancestor {
-                            result.insert(ancestor.path.into_path_buf().map_err(|err| {
-                                CherryPickFastError::DecodePath {
-                                    source: err,
-                                    item: "ancestor",
-                                }
-                            })?);
-                        }
-                        if let Some(our) = conflict.our {
-                            result.insert(our.path.into_path_buf().map_err(|err| {
-                                CherryPickFastError::DecodePath {
-                                    source: err,
-                                    item: "our",
-                                }
-                            })?);
-                        }
-                        if let Some(their) = conflict.their {
-                            result.insert(their.path.into_path_buf().map_err(|err| {
-                                CherryPickFastError::DecodePath {
-                                    source: err,
-                                    item: "their",
-                                }
-                            })?);
-                        }
-                    }
-                    result
-                };
+            let mut conflicting_paths: HashSet<PathBuf> = if rebased_index.has_conflicts() {
+                let conflicting_paths: HashSet<PathBuf> = get_index_conflicts(
+                    &rebased_index,
+                    patch_commit.get_oid(),
+                    target_commit.get_oid(),
+                )?
+                .iter()
+                .flat_map(|conflict| conflict.get_paths().map(Path::to_path_buf))
+                .collect();
 
                 if conflicting_paths.is_empty() {
                     warn!("BUG: A merge conflict was detected, but there were no entries in `conflicting_paths`. Maybe the wrong index entry was used?")
                 }
-
+                conflicting_paths
+            } else {
+                Default::default()
+            };
+            let merged_entries = self.merge_paths_with_attributes(
+                patch_commit,
+                target_commit,
+                &patch_paths,
+                &mut conflicting_paths,
+            )?;
+            if !conflicting_paths.is_empty() {
                 return Err(CherryPickFastError::MergeConflict { conflicting_paths });
             }
+
             let rebased_entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> =
                 changed_pathbufs
                     .into_iter()
                     .map(|changed_path| {
+                        if let Some(entry) = merged_entries.get(&changed_path) {
+                            return (changed_path, Some(*entry));
+                        }
                         let value = match rebased_index.get_entry(&changed_path) {
                             Some(IndexEntry {
                                 oid: MaybeZeroOid::Zero,
@@ -1332,6 +1681,424 @@ impl Repo {
         Ok(rebased_tree)
     }
 
+    /// Find the paths among `paths` which exist in the parent of
+    /// `patch_commit`, but which were renamed in `target_commit`. Returns a
+    /// mapping from the old path to the new path.
+    ///
+    /// Since `cherry_pick_fast` only merges the paths touched by the patch,
+    /// `libgit2` wouldn't otherwise see the renamed path and so couldn't detect
+    /// the rename.
+    #[instrument]
+    fn find_renamed_paths(
+        &self,
+        patch_commit: &Commit,
+        target_commit: &Commit,
+        paths: &HashSet<PathBuf>,
+    ) -> Result<HashMap<PathBuf, PathBuf>> {
+        let parent = match patch_commit.get_only_parent() {
+            Some(parent) => parent,
+            None => return Ok(Default::default()),
+        };
+        let parent_tree = parent.get_tree()?;
+        let target_tree = target_commit.get_tree()?;
+
+        let mut missing_paths = HashSet::new();
+        for path in paths {
+            let in_parent = parent_tree
+                .get_path(path)
+                .map_err(Error::ReadTreeEntry)?
+                .is_some();
+            let in_target = target_tree
+                .get_path(path)
+                .map_err(Error::ReadTreeEntry)?
+                .is_some();
+            if in_parent && !in_target {
+                missing_paths.insert(path.as_path());
+            }
+        }
+        if missing_paths.is_empty() {
+            return Ok(Default::default());
+        }
+
+        let find_renames = self
+            .get_readonly_config()?
+            .get_or("merge.renames", true)
+            .map_err(|source| Error::ReadConfigValue {
+                source,
+                key: "merge.renames",
+            })?;
+        if !find_renames {
+            return Ok(Default::default());
+        }
+
+        let mut diff = self
+            .inner
+            .diff_tree_to_tree(Some(&parent_tree.inner), Some(&target_tree.inner), None)
+            .map_err(|err| Error::DiffTreeToTree {
+                source: err,
+                old_tree: parent_tree.get_oid().into(),
+                new_tree: target_tree.get_oid().into(),
+            })?;
+        diff.find_similar(Some(DiffFindOptions::new().renames(true)))
+            .map_err(|err| Error::DiffTreeToTree {
+                source: err,
+                old_tree: parent_tree.get_oid().into(),
+                new_tree: target_tree.get_oid().into(),
+            })?;
+
+        let renamed_paths = diff
+            .deltas()
+            .filter(|delta| delta.status() == git2::Delta::Renamed)
+            .filter_map(|delta| {
+                let old_path = delta.old_file().path()?;
+                let new_path = delta.new_file().path()?;
+                if missing_paths.contains(old_path) {
+                    Some((old_path.to_path_buf(), new_path.to_path_buf()))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        Ok(renamed_paths)
+    }
+
+    /// Merge the paths touched by `patch_commit` according to their
+    /// `.gitattributes`, for the cases which `libgit2` doesn't handle itself:
+    ///
+    /// - Paths modified on both sides which use a custom merge driver (as
+    /// configured by `merge.<driver>.driver`) are merged by running the
+    /// driver.
+    /// - Paths in `conflicting_paths` are merged again after normalizing their
+    /// line endings, if `merge.renormalize` is set.
+    ///
+    /// Returns the merged entries. Paths which were merged successfully are
+    /// removed from `conflicting_paths`, and paths whose merge driver failed
+    /// are added to it.
+    #[instrument]
+    fn merge_paths_with_attributes(
+        &self,
+        patch_commit: &Commit,
+        target_commit: &Commit,
+        paths: &HashSet<PathBuf>,
+        conflicting_paths: &mut HashSet<PathBuf>,
+    ) -> std::result::Result<HashMap<PathBuf, (NonZeroOid, FileMode)>, CherryPickFastError> {
+        let parent = match patch_commit.get_only_parent() {
+            Some(parent) => parent,
+            None => return Ok(Default::default()),
+        };
+        let ancestor_tree = parent.get_tree()?;
+        let our_tree = target_commit.get_tree()?;
+        let their_tree = patch_commit.get_tree()?;
+        let get_blob_entry = |tree: &Tree, path: &Path| -> Result<Option<(NonZeroOid, FileMode)>> {
+            let entry = tree.get_path(path).map_err(Error::ReadTreeEntry)?;
+            Ok(entry.and_then(|entry| match entry.get_filemode() {
+                file_mode @ (FileMode::Blob | FileMode::BlobExecutable) => {
+                    Some((entry.get_oid(), file_mode))
+                }
+                FileMode::Unreadable | FileMode::Tree | FileMode::Link | FileMode::Commit => None,
+            }))
+        };
+
+        let mut renormalize_conflicts: Option<bool> = None;
+        let mut result = HashMap::new();
+        for path in paths {
+            let (ancestor, ours, theirs) = match (
+                get_blob_entry(&ancestor_tree, path)?,
+                get_blob_entry(&our_tree, path)?,
+                get_blob_entry(&their_tree, path)?,
+            ) {
+                (Some(ancestor), Some(ours), Some(theirs)) => (ancestor, ours, theirs),
+                _ => continue,
+            };
+            let (ancestor_oid, ancestor_mode) = ancestor;
+            let (our_oid, our_mode) = ours;
+            let (their_oid, their_mode) = theirs;
+            if ancestor_oid == our_oid || ancestor_oid == their_oid || our_oid == their_oid {
+                // Only one side changed the contents, so there's nothing to
+                // merge.
+                continue;
+            }
+            let file_mode = if their_mode != ancestor_mode {
+                their_mode
+            } else {
+                our_mode
+            };
+
+            let ancestor_blob = self.find_blob_or_fail(ancestor_oid)?;
+            let our_blob = self.find_blob_or_fail(our_oid)?;
+            let their_blob = self.find_blob_or_fail(their_oid)?;
+            if let Some(driver) =
+                MergeDriver::for_path(self, path).map_err(CherryPickFastError::MergeDriver)?
+            {
+                let merged = driver
+                    .merge(
+                        self,
+                        path,
+                        ancestor_blob.get_content(),
+                        our_blob.get_content(),
+                        their_blob.get_content(),
+                    )
+                    .map_err(CherryPickFastError::MergeDriver)?;
+                match merged {
+                    Some(merged) => {
+                        let merged_oid = self.create_blob_from_contents(&merged)?;
+                        result.insert(path.clone(), (merged_oid, file_mode));
+                        conflicting_paths.remove(path);
+                    }
+                    None => {
+                        conflicting_paths.insert(path.clone());
+                    }
+                }
+                continue;
+            }
+
+            if !conflicting_paths.contains(path) {
+                continue;
+            }
+            let renormalize_conflicts = match renormalize_conflicts {
+                Some(renormalize_conflicts) => renormalize_conflicts,
+                None => {
+                    let value = self
+                        .get_readonly_config()?
+                        .get_or("merge.renormalize", false)
+                        .map_err(|source| Error::ReadConfigValue {
+                            source,
+                            key: "merge.renormalize",
+                        })?;
+                    *renormalize_conflicts.get_or_insert(value)
+                }
+            };
+            if !renormalize_conflicts {
+                continue;
+            }
+
+            let mut normalized_oids = Vec::new();
+            let mut any_normalized = false;
+            for blob in [&ancestor_blob, &our_blob, &their_blob] {
+                let content = blob.get_content();
+                let normalized =
+                    renormalize(self, path, content).map_err(CherryPickFastError::MergeDriver)?;
+                let oid = match normalized {
+                    Some(normalized) => {
+                        any_normalized = true;
+                        self.create_blob_from_contents(&normalized)?
+                    }
+                    None => self.create_blob_from_contents(content)?,
+                };
+                normalized_oids.push(oid);
+            }
+            if !any_normalized {
+                continue;
+            }
+
+            let make_tree = |oid: NonZeroOid| -> std::result::Result<Tree, CherryPickFastError> {
+                let tree_oid = hydrate_tree(
+                    self,
+                    None,
+                    HashMap::from([(path.clone(), Some((oid, file_mode)))]),
+                )
+                .map_err(CherryPickFastError::HydrateTree)?;
+                Ok(self.find_tree_or_fail(tree_oid)?)
+            };
+            let merged_index = self.merge_trees(
+                &make_tree(normalized_oids[0])?,
+                &make_tree(normalized_oids[1])?,
+                &make_tree(normalized_oids[2])?,
+            )?;
+            if merged_index.has_conflicts() {
+                continue;
+            }
+            if let Some(IndexEntry {
+                oid: MaybeZeroOid::NonZero(merged_oid),
+                file_mode: _,
+            }) = merged_index.get_entry(path)
+            {
+                result.insert(path.clone(), (merged_oid, file_mode));
+                conflicting_paths.remove(path);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Perform a three-way merge of the provided trees in memory and return the
+    /// resulting index, which may contain conflicts.
+    #[instrument]
+    pub fn merge_trees(&self, ancestor: &Tree, our: &Tree, their: &Tree) -> Result<Index> {
+        let index = self
+            .inner
+            .merge_trees(&ancestor.inner, &our.inner, &their.inner, None)
+            .map_err(|err| Error::MergeTrees {
+                source: err,
+                ancestor: ancestor.get_oid(),
+                our: our.get_oid(),
+                their: their.get_oid(),
+            })?;
+        Ok(Index { inner: index })
+    }
+
+    /// Merge the provided commits with respect to their merge base. Conflicting
+    /// paths are resolved by taking their contents from `resolution_tree`.
+    /// Returns the merged tree and the conflicts which were resolved.
+    fn merge_commits_with_resolution<'repo>(
+        &'repo self,
+        our_commit: &Commit,
+        their_commit: &Commit,
+        resolution_tree: &Tree,
+    ) -> std::result::Result<(Tree<'repo>, HashSet<IndexConflict>), CherryPickFastError> {
+        let merge_base_commit =
+            match self.find_merge_base(our_commit.get_oid(), their_commit.get_oid())? {
+                Some(merge_base_oid) => Some(self.find_commit_or_fail(merge_base_oid)?),
+                None => None,
+            };
+        let ancestor_tree = match &merge_base_commit {
+            Some(merge_base_commit) => merge_base_commit.get_tree()?,
+            None => make_empty_tree(self).map_err(CherryPickFastError::HydrateTree)?,
+        };
+        self.merge_trees_with_resolution(
+            &ancestor_tree,
+            &our_commit.get_tree()?,
+            &their_commit.get_tree()?,
+            resolution_tree,
+            their_commit.get_oid(),
+            our_commit.get_oid(),
+        )
+    }
+
+    /// Merge the provided trees. Conflicting paths are resolved by taking their
+    /// contents from `resolution_tree`. Returns the merged tree and the
+    /// conflicts which were resolved. The `commit` and `onto` OIDs are used for
+    /// error reporting.
+    fn merge_trees_with_resolution<'repo>(
+        &'repo self,
+        ancestor_tree: &Tree,
+        our_tree: &Tree,
+        their_tree: &Tree,
+        resolution_tree: &Tree,
+        commit: NonZeroOid,
+        onto: NonZeroOid,
+    ) -> std::result::Result<(Tree<'repo>, HashSet<IndexConflict>), CherryPickFastError> {
+        let mut index = self.merge_trees(ancestor_tree, our_tree, their_tree)?;
+
+        let conflicts: HashSet<IndexConflict> = get_index_conflicts(&index, commit, onto)?
+            .into_iter()
+            .collect();
+        let conflicting_paths: HashSet<&Path> = conflicts
+            .iter()
+            .flat_map(|conflict| conflict.get_paths())
+            .collect();
+        for path in conflicting_paths.iter() {
+            index
+                .inner
+                .remove_path(path)
+                .map_err(CherryPickFastError::Git)?;
+        }
+        let merged_tree_oid = self.write_index_to_tree(&mut index)?;
+        if conflicting_paths.is_empty() {
+            return Ok((self.find_tree_or_fail(merged_tree_oid)?, conflicts));
+        }
+
+        let merged_tree = self.find_tree_or_fail(merged_tree_oid)?;
+        let mut resolved_entries = HashMap::new();
+        for path in conflicting_paths {
+            let entry = resolution_tree
+                .get_path(path)
+                .map_err(CherryPickFastError::HydrateTree)?
+                .map(|entry| (entry.get_oid(), entry.get_filemode()));
+            resolved_entries.insert(path.to_path_buf(), entry);
+        }
+        let resolved_tree_oid = hydrate_tree(self, Some(&merged_tree), resolved_entries)
+            .map_err(CherryPickFastError::HydrateTree)?;
+        Ok((self.find_tree_or_fail(resolved_tree_oid)?, conflicts))
+    }
+
+    /// Perform a three-way merge of the provided trees in memory, even if it
+    /// results in merge conflicts. Conflicting paths take their contents from
+    /// `our` tree. Returns the merged tree and the paths which were in
+    /// conflict, if any. The `commit` and `onto` OIDs are used for error
+    /// reporting.
+    #[instrument]
+    pub fn merge_trees_with_conflicts<'repo>(
+        &'repo self,
+        ancestor: &Tree,
+        our: &Tree,
+        their: &Tree,
+        commit: NonZeroOid,
+        onto: NonZeroOid,
+    ) -> std::result::Result<(Tree<'repo>, HashSet<PathBuf>), CherryPickFastError> {
+        let (merged_tree, conflicts) =
+            self.merge_trees_with_resolution(ancestor, our, their, our, commit, onto)?;
+        let conflicting_paths = conflicts
+            .iter()
+            .flat_map(|conflict| conflict.get_paths().map(Path::to_path_buf))
+            .collect();
+        Ok((merged_tree, conflicting_paths))
+    }
+
+    /// Rebase a merge commit with two parents onto the provided new parents in
+    /// memory and return the resulting tree.
+    ///
+    /// The new parents are merged, and then the changes which the original
+    /// merge commit made relative to the automatic merge of its original
+    /// parents (i.e. its conflict resolutions and any other amendments) are
+    /// applied on top. If merging the new parents produces a conflict which
+    /// is identical to one resolved in the original merge commit, then the
+    /// original resolution is reused. Any other conflict is reported as
+    /// `CherryPickFastError::MergeConflict`.
+    #[instrument]
+    pub fn rebase_merge_fast<'repo>(
+        &'repo self,
+        merge_commit: &'repo Commit,
+        new_first_parent: &'repo Commit,
+        new_second_parent: &'repo Commit,
+    ) -> std::result::Result<Tree<'repo>, CherryPickFastError> {
+        let merge_tree = merge_commit.get_tree()?;
+        let old_parents = merge_commit.get_parents();
+        let (old_first_parent, old_second_parent) = match old_parents.as_slice() {
+            [first, second] => (first, second),
+            parents => {
+                return Err(CherryPickFastError::UnsupportedMergeCommit {
+                    commit: merge_commit.get_oid(),
+                    num_parents: parents.len(),
+                })
+            }
+        };
+        if old_first_parent.get_tree_oid() == new_first_parent.get_tree_oid()
+            && old_second_parent.get_tree_oid() == new_second_parent.get_tree_oid()
+        {
+            return Ok(merge_tree);
+        }
+
+        let (old_merged_tree, old_conflicts) =
+            self.merge_commits_with_resolution(old_first_parent, old_second_parent, &merge_tree)?;
+        let (new_merged_tree, new_conflicts) =
+            self.merge_commits_with_resolution(new_first_parent, new_second_parent, &merge_tree)?;
+        let unresolved_paths: HashSet<PathBuf> = new_conflicts
+            .difference(&old_conflicts)
+            .flat_map(|conflict| conflict.get_paths().map(Path::to_path_buf))
+            .collect();
+        if !unresolved_paths.is_empty() {
+            return Err(CherryPickFastError::MergeConflict {
+                conflicting_paths: unresolved_paths,
+            });
+        }
+
+        let mut rebased_index =
+            self.merge_trees(&old_merged_tree, &new_merged_tree, &merge_tree)?;
+        if rebased_index.has_conflicts() {
+            let conflicting_paths: HashSet<PathBuf> = get_index_conflicts(
+                &rebased_index,
+                merge_commit.get_oid(),
+                new_first_parent.get_oid(),
+            )?
+            .iter()
+            .flat_map(|conflict| conflict.get_paths().map(Path::to_path_buf))
+            .collect();
+            return Err(CherryPickFastError::MergeConflict { conflicting_paths });
+        }
+        let rebased_tree_oid = self.write_index_to_tree(&mut rebased_index)?;
+        Ok(self.find_tree_or_fail(rebased_tree_oid)?)
+    }
+
     #[instrument]
     fn dehydrate_commit(
         &self,
@@ -1370,6 +2137,7 @@ impl Repo {
             &message,
             &dehydrated_tree,
             parents.iter().collect_vec(),
+            None,
         )?;
         let dehydrated_commit = self.find_commit_or_fail(dehydrated_commit_oid)?;
         Ok(dehydrated_commit)
@@ -1529,6 +2297,8 @@ impl std::fmt::Debug for Signature<'_> {
 }
 
 impl<'repo> Signature<'repo> {
+    /// Create the signature used for commits which git-branchless makes on
+    /// its own behalf, rather than on behalf of the user.
     #[instrument]
     pub fn automated() -> Result<Self> {
         Ok(Signature {
@@ -1578,10 +2348,12 @@ impl<'repo> Signature<'repo> {
         }
     }
 
+    /// Get the name in this signature, if it's valid UTF-8.
     pub fn get_name(&self) -> Option<&str> {
         self.inner.name()
     }
 
+    /// Get the email address in this signature, if it's valid UTF-8.
     pub fn get_email(&self) -> Option<&str> {
         self.inner.email()
     }
@@ -1606,6 +2378,12 @@ pub struct PatchId {
     patch_id: git2::Oid,
 }
 
+impl std::fmt::Display for PatchId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.patch_id)
+    }
+}
+
 /// A timestamp as used in a [`Signature`].
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Time {
@@ -3,8 +3,8 @@ use std::convert::TryInto;
 use std::ffi::{OsStr, OsString};
 use std::fmt::Write;
 use std::io::{BufRead, BufReader, Read, Write as WriteIo};
-use std::path::PathBuf;
-use std::process::{Command, ExitStatus, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 
@@ -342,20 +342,14 @@ impl GitRunInfo {
         )
     }
 
-    fn run_hook_inner(
+    fn make_hook_command(
         &self,
-        effects: &Effects,
         repo: &Repo,
+        hook_dir: &Path,
         hook_name: &str,
         event_tx_id: EventTransactionId,
-        args: &[&str],
-        stdin: Option<BString>,
-    ) -> eyre::Result<()> {
-        let hook_dir = get_hooks_dir(self, repo, Some(event_tx_id))?;
-        if !hook_dir.exists() {
-            return Ok(());
-        }
-
+        args: &[&OsStr],
+    ) -> eyre::Result<Command> {
         let GitRunInfo {
             // We're calling a Git hook, but not Git itself.
             path_to_git: _,
@@ -366,35 +360,56 @@ impl GitRunInfo {
         } = self;
         let path = {
             let mut path_components: Vec<PathBuf> =
-                vec![std::fs::canonicalize(&hook_dir).wrap_err("Canonicalizing hook dir")?];
+                vec![std::fs::canonicalize(hook_dir).wrap_err("Canonicalizing hook dir")?];
             if let Some(path) = env.get(OsStr::new("PATH")) {
                 path_components.extend(std::env::split_paths(path));
             }
             std::env::join_paths(path_components).wrap_err("Joining path components")?
         };
 
+        let mut command = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?);
+        command
+            // From `githooks(5)`: Before Git invokes a hook, it changes its
+            // working directory to either $GIT_DIR in a bare repository or the
+            // root of the working tree in a non-bare repository.
+            .current_dir(
+                repo.get_working_copy_path()
+                    .unwrap_or_else(|| repo.get_path()),
+            )
+            .arg("-c")
+            .arg(format!("{hook_name} \"$@\""))
+            .arg(hook_name) // "$@" expands "$1" "$2" "$3" ... but we also must specify $0.
+            .args(args)
+            .env_clear()
+            .envs(env.iter())
+            .env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string())
+            .env("PATH", &path);
+        Ok(command)
+    }
+
+    fn run_hook_inner(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        hook_name: &str,
+        event_tx_id: EventTransactionId,
+        args: &[&str],
+        stdin: Option<BString>,
+    ) -> eyre::Result<()> {
+        let hook_dir = get_hooks_dir(self, repo, Some(event_tx_id))?;
+        if !hook_dir.exists() {
+            return Ok(());
+        }
+
         if hook_dir.join(hook_name).exists() {
-            let mut child = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?)
-                // From `githooks(5)`: Before Git invokes a hook, it changes its
-                // working directory to either $GIT_DIR in a bare repository or the
-                // root of the working tree in a non-bare repository.
-                .current_dir(
-                    repo.get_working_copy_path()
-                        .unwrap_or_else(|| repo.get_path()),
-                )
-                .arg("-c")
-                .arg(format!("{hook_name} \"$@\""))
-                .arg(hook_name) // "$@" expands "$1" "$2" "$3" ... but we also must specify $0.
-                .args(args)
-                .env_clear()
-                .envs(env.iter())
-                .env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string())
-                .env("PATH", &path)
+            let args = args.iter().map(OsStr::new).collect_vec();
+            let mut child = self
+                .make_hook_command(repo, &hook_dir, hook_name, event_tx_id, &args)?
                 .stdin(Stdio::piped())
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped())
                 .spawn()
-                .wrap_err_with(|| format!("Invoking {} hook with PATH: {:?}", &hook_name, &path))?;
+                .wrap_err_with(|| format!("Invoking {hook_name} hook"))?;
 
             if let Some(stdin) = stdin {
                 child
@@ -440,6 +455,29 @@ impl GitRunInfo {
             stdin,
         )
     }
+
+    /// Run the provided Git hook from `hook_dir` (as returned by
+    /// [`get_hooks_dir`]), with additional environment variables, and capture
+    /// its output instead of writing it to the terminal. The caller is
+    /// responsible for checking that the hook exists.
+    ///
+    /// See the man page for `githooks(5)` for more detail on Git hooks.
+    #[instrument]
+    pub fn run_hook_with_output(
+        &self,
+        repo: &Repo,
+        hook_dir: &Path,
+        hook_name: &str,
+        event_tx_id: EventTransactionId,
+        args: &[&OsStr],
+        envs: &[(&str, &OsStr)],
+    ) -> eyre::Result<Output> {
+        self.make_hook_command(repo, hook_dir, hook_name, event_tx_id, args)?
+            .envs(envs.iter().copied())
+            .stdin(Stdio::null())
+            .output()
+            .wrap_err_with(|| format!("Invoking {hook_name} hook"))
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,246 @@
+//! Sign commits according to the user's Git configuration, as `git commit`
+//! does when `commit.gpgSign` is set.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use bstr::ByteSlice;
+use thiserror::Error;
+use tracing::instrument;
+
+use crate::git::config::ConfigRead;
+use crate::git::repo::Repo;
+
+#[allow(missing_docs)]
+#[derive(Debug, Error)]
+pub enum SignError {
+    #[error("unsupported value for gpg.format: {format:?} (expected one of: openpgp, x509, ssh)")]
+    UnknownFormat { format: String },
+
+    #[error("user.signingKey must be set to sign commits with gpg.format=ssh")]
+    NoSshSigningKey,
+
+    #[error("could not write temporary file for signing: {0}")]
+    WriteTempFile(#[source] io::Error),
+
+    #[error("could not run signing program {program:?}: {source}")]
+    SpawnProgram { source: io::Error, program: String },
+
+    #[error("signing program {program:?} failed ({status}): {stderr}")]
+    ProgramFailed {
+        program: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+
+    #[error("could not read signature produced by {program:?}: {source}")]
+    ReadSignature { source: io::Error, program: String },
+}
+
+/// The kind of signature to produce, as configured by `gpg.format`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SignFormat {
+    /// Sign with an OpenPGP key using `gpg`.
+    OpenPgp,
+
+    /// Sign with an X.509 certificate using `gpgsm`.
+    X509,
+
+    /// Sign with an SSH key using `ssh-keygen`.
+    Ssh,
+}
+
+/// Signs commits on behalf of the user.
+#[derive(Clone, Debug)]
+pub struct CommitSigner {
+    format: SignFormat,
+    program: String,
+    signing_key: Option<String>,
+}
+
+impl CommitSigner {
+    /// Construct a signer from the repository's configuration. Returns `None`
+    /// if `commit.gpgSign` isn't set, in which case commits shouldn't be
+    /// signed.
+    #[instrument]
+    pub fn from_config(repo: &Repo) -> eyre::Result<Option<Self>> {
+        let config = repo.get_readonly_config()?;
+        if !config.get_or("commit.gpgSign", false)? {
+            return Ok(None);
+        }
+
+        let format: String = config.get_or("gpg.format", "openpgp".to_string())?;
+        let format = match format.as_str() {
+            "openpgp" => SignFormat::OpenPgp,
+            "x509" => SignFormat::X509,
+            "ssh" => SignFormat::Ssh,
+            _ => return Err(SignError::UnknownFormat { format }.into()),
+        };
+        let program = match format {
+            SignFormat::OpenPgp => match config.get("gpg.openpgp.program")? {
+                Some(program) => program,
+                None => config.get_or("gpg.program", "gpg".to_string())?,
+            },
+            SignFormat::X509 => config.get_or("gpg.x509.program", "gpgsm".to_string())?,
+            SignFormat::Ssh => config.get_or("gpg.ssh.program", "ssh-keygen".to_string())?,
+        };
+
+        let signing_key: Option<String> = config.get("user.signingKey")?;
+        let signing_key = match (format, signing_key) {
+            (SignFormat::Ssh, None) => return Err(SignError::NoSshSigningKey.into()),
+            (_, Some(signing_key)) => Some(signing_key),
+            // Like Git, default to the key associated with the committer
+            // identity, if any.
+            (SignFormat::OpenPgp | SignFormat::X509, None) => {
+                let name: Option<String> = config.get("user.name")?;
+                let email: Option<String> = config.get("user.email")?;
+                match (name, email) {
+                    (Some(name), Some(email)) => Some(format!("{name} <{email}>")),
+                    _ => None,
+                }
+            }
+        };
+
+        Ok(Some(Self {
+            format,
+            program,
+            signing_key,
+        }))
+    }
+
+    /// Produce a signature for the provided commit contents, which can be
+    /// stored in the commit's `gpgsig` header.
+    #[instrument]
+    pub fn sign(&self, buffer: &[u8]) -> Result<String, SignError> {
+        match self.format {
+            SignFormat::OpenPgp | SignFormat::X509 => self.sign_gpg(buffer),
+            SignFormat::Ssh => self.sign_ssh(buffer),
+        }
+    }
+
+    fn sign_gpg(&self, buffer: &[u8]) -> Result<String, SignError> {
+        let Self {
+            format: _,
+            program,
+            signing_key,
+        } = self;
+
+        let mut command = Command::new(program);
+        command.arg("--status-fd=2");
+        match signing_key {
+            Some(signing_key) => command.args(["-bsau", signing_key]),
+            None => command.arg("-bsa"),
+        };
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|source| SignError::SpawnProgram {
+                source,
+                program: program.clone(),
+            })?;
+        {
+            let mut stdin = child.stdin.take().ok_or_else(|| SignError::SpawnProgram {
+                source: io::Error::new(io::ErrorKind::BrokenPipe, "stdin was not captured"),
+                program: program.clone(),
+            })?;
+            stdin
+                .write_all(buffer)
+                .map_err(|source| SignError::SpawnProgram {
+                    source,
+                    program: program.clone(),
+                })?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|source| SignError::SpawnProgram {
+                source,
+                program: program.clone(),
+            })?;
+
+        // Like Git, check the status output as well as the exit code, since
+        // some versions of `gpg` exit successfully without signing.
+        let is_signed = output.stderr.contains_str("\n[GNUPG:] SIG_CREATED ")
+            || output.stderr.starts_with(b"[GNUPG:] SIG_CREATED ");
+        if !output.status.success() || !is_signed {
+            return Err(SignError::ProgramFailed {
+                program: program.clone(),
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr)
+                    .trim_end()
+                    .to_owned(),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn sign_ssh(&self, buffer: &[u8]) -> Result<String, SignError> {
+        let Self {
+            format: _,
+            program,
+            signing_key,
+        } = self;
+        let signing_key = signing_key.as_ref().ok_or(SignError::NoSshSigningKey)?;
+
+        let temp_dir = tempfile::tempdir().map_err(SignError::WriteTempFile)?;
+
+        // The signing key may be a path to a key file or a literal public key
+        // (whose private key is held by `ssh-agent`).
+        let literal_key = match signing_key.strip_prefix("key::") {
+            Some(literal_key) => Some(literal_key),
+            None if signing_key.starts_with("ssh-") => Some(signing_key.as_str()),
+            None => None,
+        };
+        let key_path = match literal_key {
+            Some(literal_key) => {
+                let key_path = temp_dir.path().join("signing_key.pub");
+                std::fs::write(&key_path, literal_key).map_err(SignError::WriteTempFile)?;
+                key_path
+            }
+            None => expand_home_dir(signing_key),
+        };
+
+        let buffer_path = temp_dir.path().join("buffer");
+        std::fs::write(&buffer_path, buffer).map_err(SignError::WriteTempFile)?;
+
+        let mut command = Command::new(program);
+        command.args(["-Y", "sign", "-n", "git", "-f"]);
+        command.arg(&key_path);
+        if literal_key.is_some() {
+            command.arg("-U");
+        }
+        command.arg(&buffer_path);
+        let output =
+            command
+                .stdin(Stdio::null())
+                .output()
+                .map_err(|source| SignError::SpawnProgram {
+                    source,
+                    program: program.clone(),
+                })?;
+        if !output.status.success() {
+            return Err(SignError::ProgramFailed {
+                program: program.clone(),
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr)
+                    .trim_end()
+                    .to_owned(),
+            });
+        }
+
+        let signature_path = temp_dir.path().join("buffer.sig");
+        std::fs::read_to_string(signature_path).map_err(|source| SignError::ReadSignature {
+            source,
+            program: program.clone(),
+        })
+    }
+}
+
+fn expand_home_dir(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home_dir)) => Path::new(&home_dir).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
@@ -568,6 +568,55 @@ then you can only run tests in the main `git-branchless` and \
         Ok(version >= GitVersion(2, 27, 0))
     }
 
+    /// Configure the repository to sign new commits with a freshly-generated
+    /// SSH key, and to trust that key when verifying signatures (such as with
+    /// `git log --format=%G?`).
+    ///
+    /// Returns `false` if SSH signing isn't supported, either because
+    /// `ssh-keygen` isn't available or because the Git version is too old.
+    #[instrument]
+    pub fn set_up_ssh_signing(&self) -> eyre::Result<bool> {
+        // SSH signing was introduced in Git v2.34.
+        if self.get_version()? < GitVersion(2, 34, 0) {
+            return Ok(false);
+        }
+
+        let key_path = self.repo_path.join(".git").join("signing_key");
+        let output = match Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", DUMMY_EMAIL, "-f"])
+            .arg(&key_path)
+            .stdin(Stdio::null())
+            .output()
+        {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        if !output.status.success() {
+            eyre::bail!(
+                "Generating SSH signing key failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        let public_key = fs::read_to_string(key_path.with_extension("pub"))?;
+        let allowed_signers_path = self.repo_path.join(".git").join("allowed_signers");
+        fs::write(
+            &allowed_signers_path,
+            format!("{DUMMY_EMAIL} {}", public_key.trim()),
+        )?;
+
+        self.run(&["config", "commit.gpgSign", "true"])?;
+        self.run(&["config", "gpg.format", "ssh"])?;
+        self.run(&["config", "user.signingKey", key_path.to_str().unwrap()])?;
+        self.run(&[
+            "config",
+            "gpg.ssh.allowedSignersFile",
+            allowed_signers_path.to_str().unwrap(),
+        ])?;
+        Ok(true)
+    }
+
     /// Resolve a file during a merge or rebase conflict with the provided
     /// contents.
     #[instrument]
@@ -18,6 +18,7 @@ use std::fmt::Write;
 use std::time::SystemTime;
 
 use eden_dag::{DagAlgorithm, VertexName};
+use eyre::Context;
 use lib::core::repo_ext::RepoExt;
 use lib::util::ExitCode;
 use rayon::ThreadPoolBuilder;
@@ -25,16 +26,20 @@ use tracing::instrument;
 
 use git_branchless_opts::{MoveOptions, ResolveRevsetOptions, Revset};
 use git_branchless_revset::resolve_commits;
+use git_branchless_reword::dialoguer_edit::Editor;
+use git_branchless_smartlog::smartlog_preview;
 use lib::core::config::{
-    get_hint_enabled, get_hint_string, get_restack_preserve_timestamps,
+    get_editor, get_hint_enabled, get_hint_string, get_restack_preserve_timestamps,
     print_hint_suppression_notice, Hint,
 };
 use lib::core::dag::{commit_set_to_vec, sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{EventLogDb, EventReplayer};
 use lib::core::rewrite::{
-    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    MergeConflictRemediation, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+    execute_rebase_plan, parse_rebase_plan_todo, preview_rebase_plan, write_rebase_plan_todo,
+    BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
+    MergeConflictRemediation, ParseRebasePlanTodoError, RebasePlan, RebasePlanBuilder,
+    RebasePlanPermissions, RebasePlanPreview, RepoResource, REBASE_PLAN_TODO_HELP,
 };
 use lib::git::{GitRunInfo, NonZeroOid, Repo};
 
@@ -64,6 +69,32 @@ fn resolve_base_commit(
     }
 }
 
+/// Open the rebase plan in the user's editor and parse the result.
+#[instrument]
+fn edit_rebase_plan(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    rebase_plan: &RebasePlan,
+) -> eyre::Result<Result<Option<RebasePlan>, ParseRebasePlanTodoError>> {
+    let todo = format!(
+        "{}{}",
+        write_rebase_plan_todo(repo, rebase_plan)?,
+        REBASE_PLAN_TODO_HELP
+    );
+    let mut editor = Editor::new();
+    let (editor, editor_program) = match get_editor(git_run_info, repo)? {
+        Some(editor_program) => (editor.executable(&editor_program), editor_program),
+        None => (&mut editor, "<default>".into()),
+    };
+    let todo = editor
+        .require_save(false)
+        .edit(&todo)
+        .with_context(|| format!("Invoking editor: '{}'", editor_program.to_string_lossy()))?
+        .expect("`Editor::edit` should not return `None` when `require_save` is `false`");
+    parse_rebase_plan_todo(repo, rebase_plan, &todo)
+}
+
 /// Move a subtree from one place to another.
 #[instrument]
 pub fn r#move(
@@ -76,6 +107,8 @@ pub fn r#move(
     resolve_revset_options: &ResolveRevsetOptions,
     move_options: &MoveOptions,
     insert: bool,
+    edit_plan: bool,
+    dry_run: bool,
 ) -> eyre::Result<ExitCode> {
     let sources_provided = !sources.is_empty();
     let bases_provided = !bases.is_empty();
@@ -262,6 +295,7 @@ pub fn r#move(
         force_on_disk,
         detect_duplicate_commits_via_patch_id,
         resolve_merge_conflicts,
+        create_conflict_commits,
         dump_rebase_constraints,
         dump_rebase_plan,
     } = *move_options;
@@ -483,6 +517,24 @@ pub fn r#move(
             return Ok(ExitCode(0));
         }
         Ok(Some(rebase_plan)) => {
+            let rebase_plan = if edit_plan {
+                match edit_rebase_plan(effects, git_run_info, &repo, &rebase_plan)? {
+                    Ok(Some(rebase_plan)) => rebase_plan,
+                    Ok(None) => {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "The rebase plan was empty, aborting."
+                        )?;
+                        return Ok(ExitCode(1));
+                    }
+                    Err(err) => {
+                        writeln!(effects.get_error_stream(), "Invalid rebase plan: {err}")?;
+                        return Ok(ExitCode(1));
+                    }
+                }
+            } else {
+                rebase_plan
+            };
             let options = ExecuteRebasePlanOptions {
                 now,
                 event_tx_id,
@@ -490,8 +542,32 @@ pub fn r#move(
                 force_in_memory,
                 force_on_disk,
                 resolve_merge_conflicts,
+                create_conflict_commits,
                 check_out_commit_options: Default::default(),
             };
+            if dry_run {
+                let mut preview = RebasePlanPreview::default();
+                return match preview_rebase_plan(
+                    effects,
+                    &repo,
+                    &rebase_plan,
+                    &options,
+                    &mut preview,
+                )? {
+                    Ok(()) => {
+                        smartlog_preview(effects, &repo, &event_log_db, event_tx_id, &preview)?;
+                        Ok(ExitCode(0))
+                    }
+                    Err(failed_merge_info) => {
+                        failed_merge_info.describe(
+                            effects,
+                            &repo,
+                            MergeConflictRemediation::Retry,
+                        )?;
+                        Ok(ExitCode(1))
+                    }
+                };
+            }
             execute_rebase_plan(
                 effects,
                 git_run_info,
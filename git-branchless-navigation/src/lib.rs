@@ -36,6 +36,7 @@ use lib::core::formatting::Pluralize;
 use lib::core::node_descriptors::{
     BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
     DifferentialRevisionDescriptor, NodeDescriptor, Redactor, RelativeTimeDescriptor,
+    RemoteStatusDescriptor,
 };
 use lib::git::{GitRunInfo, NonZeroOid, Repo};
 
@@ -108,7 +109,7 @@ fn advance(
         }
     };
 
-    let public_commits = dag.query().ancestors(dag.main_branch_commit.clone())?;
+    let public_commits = dag.query_public_commits_slow()?.clone();
 
     let glyphs = effects.get_glyphs();
     let mut current_oid = current_oid;
@@ -396,6 +397,7 @@ pub fn traverse_commits(
                 &references_snapshot,
                 &Redactor::Disabled,
             )?,
+            &mut RemoteStatusDescriptor::new(&repo, &references_snapshot, &Redactor::Disabled)?,
             &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
             &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
         ],
@@ -544,6 +546,11 @@ pub fn switch(
                         &references_snapshot,
                         &Redactor::Disabled,
                     )?,
+                    &mut RemoteStatusDescriptor::new(
+                        &repo,
+                        &references_snapshot,
+                        &Redactor::Disabled,
+                    )?,
                     &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
                     &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
                 ],
@@ -89,6 +89,17 @@ pub struct MoveOptions {
     #[clap(action, name = "merge", short = 'm', long = "merge")]
     pub resolve_merge_conflicts: bool,
 
+    /// If a merge conflict occurs during an in-memory rebase, commit the
+    /// conflicted result as a "conflict commit" and continue rebasing the
+    /// remaining commits. Conflict commits can be found with the
+    /// `conflicted()` revset and resolved later with `git branchless resolve`.
+    #[clap(
+        action,
+        long = "commit-conflicts",
+        conflicts_with_all(&["force_on_disk", "merge"])
+    )]
+    pub create_conflict_commits: bool,
+
     /// Debugging option. Print the constraints used to create the rebase
     /// plan before executing it.
     #[clap(action, long = "debug-dump-rebase-constraints")]
@@ -195,6 +206,12 @@ pub enum HookSubcommand {
         old_commit_oid: String,
     },
     /// Internal use.
+    DropCommit {
+        /// The OID of the commit that was dropped.
+        #[clap(value_parser)]
+        commit_oid: String,
+    },
+    /// Internal use.
     PreAutoGc,
     /// Internal use.
     PostApplypatch,
@@ -357,6 +374,31 @@ pub struct SubmitArgs {
     #[clap(action, short = 'c', long = "create")]
     pub create: bool,
 
+    /// Create a branch for each commit which doesn't have one, and push it
+    /// along with the others, as if by `--create`. Branch names are generated
+    /// from the template in the `branchless.submit.branchNameTemplate` config
+    /// option (by default `{user}/{summary_slug}`), which may also use
+    /// `{stack}` and `{index}`. A rewritten commit is given the same name as
+    /// the commit it was rewritten from.
+    #[clap(action, long = "auto-branch")]
+    pub auto_branch: bool,
+
+    /// The forge to submit the commits to. Defaults to the value of the
+    /// `branchless.submit.forge` config option, or `branch` if not set.
+    #[clap(value_enum, long = "forge")]
+    pub forge: Option<ForgeKind>,
+
+    /// Instead of pushing, print which branches would be created,
+    /// fast-forwarded, force-updated, or skipped on which remotes, and which
+    /// commits would become visible on them. This is based on the
+    /// remote-tracking branches as of the last fetch.
+    #[clap(action, long = "dry-run")]
+    pub dry_run: bool,
+
+    /// With `--dry-run`, print the plan as JSON.
+    #[clap(action, long = "json", requires = "dry_run")]
+    pub json: bool,
+
     /// The commits to push. All branches attached to those commits will be
     /// pushed.
     #[clap(value_parser, default_value = "stack()")]
@@ -367,6 +409,27 @@ pub struct SubmitArgs {
     pub resolve_revset_options: ResolveRevsetOptions,
 }
 
+/// A service which commits can be submitted to with `git submit`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ForgeKind {
+    /// Push the branches attached to the commits to their remote. This is
+    /// the default.
+    Branch,
+
+    /// Push the branches attached to the commits, and create or update a
+    /// GitHub pull request for each of them, based on the branch of the
+    /// preceding commit in the stack.
+    Github,
+
+    /// Push each commit to Gerrit for review as its own change, adding a
+    /// `Change-Id` trailer to commits which don't have one.
+    Gerrit,
+
+    /// Create or update a Phabricator revision for each commit with `arc
+    /// diff`, recording the URL of new revisions in their commit messages.
+    Phabricator,
+}
+
 /// Run a command on each commit in a given set and aggregate the results.
 #[derive(Debug, Parser)]
 pub struct TestArgs {
@@ -395,6 +458,14 @@ pub enum Command {
     /// report.
     BugReport,
 
+    /// Export or import the event log, such as to preserve undo history when
+    /// moving a repository to another machine.
+    Eventlog {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: EventlogSubcommand,
+    },
+
     /// Run internal garbage collection.
     Gc,
 
@@ -433,7 +504,9 @@ pub enum Command {
     ///
     /// By default, `git move` attempts to rebase all commits in-memory. If you
     /// want to force an on-disk rebase, pass the `--on-disk` flag. Note that
-    /// `post-commit` hooks are not called during in-memory rebases.
+    /// `pre-commit`, `commit-msg`, and `post-commit` hooks are not called
+    /// during in-memory rebases unless `branchless.restack.runCommitHooks` is
+    /// set.
     Move {
         /// The source commit to move. This commit, and all of its descendants,
         /// will be moved.
@@ -479,6 +552,17 @@ pub enum Command {
         /// Only supported if the moved subtree has a single head.
         #[clap(action, short = 'I', long = "insert")]
         insert: bool,
+
+        /// Open the rebase plan in your editor before carrying it out. Commands
+        /// can be re-ordered, and commits can be dropped by removing their
+        /// lines.
+        #[clap(action, long = "edit-plan")]
+        edit_plan: bool,
+
+        /// Don't actually rewrite any commits. Instead, carry out the rebase
+        /// in memory and print the smartlog as it would look afterwards.
+        #[clap(action, long = "dry-run")]
+        dry_run: bool,
     },
 
     /// Move to a later commit in the current stack.
@@ -488,6 +572,19 @@ pub enum Command {
         traverse_commits_options: TraverseCommitsOptions,
     },
 
+    /// Share obsolescence information (i.e. which commits were rewritten or
+    /// hidden) with other clones of this repository.
+    ///
+    /// Markers are exported to `refs/branchless/obsmarkers`, which can be
+    /// pushed to a remote. To import markers automatically whenever they're
+    /// fetched, add a refspec for the remote such as
+    /// `+refs/branchless/obsmarkers:refs/branchless/remotes/origin/obsmarkers`.
+    Obsmarkers {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: ObsmarkersSubcommand,
+    },
+
     /// Move to an earlier commit in the current stack.
     Prev {
         /// Options for traversing commits.
@@ -507,10 +604,36 @@ pub enum Command {
 
     /// Restore internal invariants by reconciling the internal operation log
     /// with the state of the Git repository.
+    ///
+    /// If a rewrite operation such as `git move` was interrupted while
+    /// updating branches, the operation is finished, or rolled back if
+    /// `--rollback` is passed.
     Repair {
         /// Apply changes.
         #[clap(action(clap::ArgAction::SetFalse), long = "no-dry-run")]
         dry_run: bool,
+
+        /// Roll back an interrupted rewrite operation instead of finishing
+        /// it.
+        #[clap(action, long = "rollback")]
+        rollback: bool,
+    },
+
+    /// Check out a conflict commit and write its merge conflicts to the
+    /// working copy, so that they can be resolved.
+    ///
+    /// Conflict commits are created by passing `--commit-conflicts` to
+    /// commands like `git move`, and can be found with the `conflicted()`
+    /// revset. Once the conflicts have been resolved, stage the files and run
+    /// `git amend` to turn the conflict commit into a regular commit.
+    Resolve {
+        /// The conflict commit to resolve.
+        #[clap(value_parser, default_value = ".")]
+        revset: Revset,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
     },
 
     /// Fix up commits abandoned by a previous rewrite operation.
@@ -527,11 +650,52 @@ pub enum Command {
         /// Options for moving commits.
         #[clap(flatten)]
         move_options: MoveOptions,
+
+        /// Instead of restacking abandoned commits, fold each `fixup!`,
+        /// `squash!`, and `amend!` commit into the commit it targets, like
+        /// `git rebase --autosquash`. If revsets are provided, only the
+        /// `fixup!` commits among them are folded. The commits are always
+        /// rewritten in memory.
+        #[clap(action, long = "autosquash")]
+        autosquash: bool,
+
+        /// Don't actually rewrite any commits. Instead, carry out the rebase
+        /// in memory and print the smartlog as it would look afterwards.
+        #[clap(action, long = "dry-run")]
+        dry_run: bool,
     },
 
     /// Create a commit by interactively selecting which changes to include.
     Record(RecordArgs),
 
+    /// Interactively reorder the commits in a stack, or mark them to be
+    /// squashed or dropped.
+    ///
+    /// The commits are rewritten in memory as a single operation, which can be
+    /// reverted with `git undo`.
+    Reorder {
+        /// The commits to reorder. They must form a single linear stack.
+        /// Descendants of the last commit are moved on top of the reordered
+        /// stack.
+        #[clap(value_parser, default_value = "stack()")]
+        revset: Revset,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+
+        /// Force reordering public commits, even though other people may have
+        /// access to those commits.
+        #[clap(action, short = 'f', long = "force-rewrite", visible_alias = "fr")]
+        force_rewrite_public_commits: bool,
+
+        /// If a merge conflict occurs, commit the conflicted result as a
+        /// "conflict commit" instead of aborting. Conflict commits can be
+        /// resolved later with `git branchless resolve`.
+        #[clap(action, long = "commit-conflicts")]
+        create_conflict_commits: bool,
+    },
+
     /// Reword commits.
     Reword {
         /// Zero or more commits to reword.
@@ -591,7 +755,9 @@ pub enum Command {
         switch_options: SwitchOptions,
     },
 
-    /// Move any local commit stacks on top of the main branch.
+    /// Move any local commit stacks on top of the main branch, or on top of
+    /// the public branch that they're based on (see
+    /// `branchless.core.publicBranch`).
     Sync {
         /// Run `git fetch` to update remote references before carrying out the
         /// sync.
@@ -608,6 +774,17 @@ pub enum Command {
         #[clap(flatten)]
         move_options: MoveOptions,
 
+        /// Don't actually rewrite any commits. Instead, carry out the rebase
+        /// in memory and print the smartlog as it would look afterwards.
+        #[clap(action, long = "dry-run", conflicts_with = "pull")]
+        dry_run: bool,
+
+        /// Before syncing, hide the commits which have already landed on the
+        /// main branch, either as identical patches or squashed together, and
+        /// delete the branches pointing to them.
+        #[clap(action, long = "clean")]
+        clean: bool,
+
         /// The commits whose stacks will be moved on top of the main branch. If
         /// no commits are provided, all draft commits will be synced.
         #[clap(value_parser)]
@@ -747,6 +924,49 @@ pub enum SnapshotSubcommand {
     },
 }
 
+/// `eventlog` subcommands.
+#[derive(Debug, Parser)]
+pub enum EventlogSubcommand {
+    /// Write the events and transaction messages in the event log to a
+    /// versioned JSON file.
+    Export {
+        /// The file to write to. If not provided, writes to stdout.
+        #[clap(value_parser, short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        /// Redact branch names in the exported events, as is done for bug
+        /// reports.
+        #[clap(action, long = "redact")]
+        redact: bool,
+    },
+
+    /// Merge the events from a file written by `eventlog export` into the
+    /// event log. Transactions which are already present in the event log are
+    /// skipped.
+    Import {
+        /// The file to read from.
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+}
+
+/// `obsmarkers` subcommands.
+#[derive(Debug, Parser)]
+pub enum ObsmarkersSubcommand {
+    /// Export the obsolescence markers from the event log to
+    /// `refs/branchless/obsmarkers`, merging them with any markers fetched
+    /// from remotes.
+    Export,
+
+    /// Import obsolescence markers into the event log.
+    Import {
+        /// The references to import markers from. If not provided, imports
+        /// from all references under `refs/branchless/remotes/`.
+        #[clap(value_parser)]
+        refs: Vec<String>,
+    },
+}
+
 /// `test` subcommands.
 #[derive(Debug, Parser)]
 pub enum TestSubcommand {
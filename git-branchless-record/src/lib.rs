@@ -20,13 +20,15 @@ use cursive::CursiveRunnable;
 use cursive_buffered_backend::BufferedBackend;
 
 use eden_dag::DagAlgorithm;
+use eyre::Context;
 use git_branchless_invoke::CommandContext;
 use git_branchless_opts::RecordArgs;
 use git_record::Recorder;
 use git_record::{RecordError, RecordState};
 use itertools::Itertools;
+use lib::core::change_id::{add_change_id, find_change_id, make_change_id, CHANGE_ID_TRAILER};
 use lib::core::check_out::{check_out_commit, CheckOutCommitOptions};
-use lib::core::config::get_restack_preserve_timestamps;
+use lib::core::config::{get_change_ids_enabled, get_restack_preserve_timestamps};
 use lib::core::dag::{commit_set_to_vec, CommitSet, Dag};
 use lib::core::effects::{Effects, OperationType};
 use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
@@ -39,8 +41,8 @@ use lib::core::rewrite::{
 };
 use lib::git::{
     process_diff_for_record, update_index, CategorizedReferenceName, FileMode, GitRunInfo,
-    NonZeroOid, Repo, ResolvedReferenceInfo, Stage, UpdateIndexCommand, WorkingCopyChangesType,
-    WorkingCopySnapshot,
+    GitVersion, NonZeroOid, Repo, ResolvedReferenceInfo, Stage, UpdateIndexCommand,
+    WorkingCopyChangesType, WorkingCopySnapshot,
 };
 use lib::util::ExitCode;
 use rayon::ThreadPoolBuilder;
@@ -137,6 +139,18 @@ fn record(
         }
     }
 
+    let change_id = if get_change_ids_enabled(&repo)? {
+        let seed = format!(
+            "{:?} {:?} {}",
+            repo.get_head_info()?.oid,
+            now,
+            std::process::id()
+        );
+        Some(make_change_id(&seed)?)
+    } else {
+        None
+    };
+
     let commit_exit_code = if interactive {
         if working_copy_changes_type == WorkingCopyChangesType::Staged {
             writeln!(
@@ -156,16 +170,19 @@ fn record(
                 &snapshot,
                 event_tx_id,
                 message.as_deref(),
+                change_id.as_deref(),
             )?
         }
     } else {
         let args = {
-            let mut args = vec!["commit"];
-            if let Some(message) = &message {
-                args.extend(["--message", message]);
-            }
+            let mut args = get_commit_args(
+                git_run_info,
+                &repo,
+                message.as_deref(),
+                change_id.as_deref(),
+            )?;
             if working_copy_changes_type == WorkingCopyChangesType::Unstaged {
-                args.push("--all");
+                args.push("--all".to_owned());
             }
             args
         };
@@ -234,6 +251,7 @@ fn record_interactive(
     snapshot: &WorkingCopySnapshot,
     event_tx_id: EventTransactionId,
     message: Option<&str>,
+    change_id: Option<&str>,
 ) -> eyre::Result<ExitCode> {
     let file_states = {
         let (effects, _progress) = effects.start_operation(OperationType::CalculateDiff);
@@ -293,14 +311,61 @@ fn record_interactive(
         &update_index_script,
     )?;
 
-    let args = {
-        let mut args = vec!["commit"];
-        if let Some(message) = message {
-            args.extend(["--message", message]);
+    let args = get_commit_args(git_run_info, repo, message, change_id)?;
+    git_run_info.run_direct_no_wrapping(Some(event_tx_id), &args)
+}
+
+/// Get the arguments for a `git commit` invocation with the given message,
+/// which adds the given `Change-Id` trailer to the commit unless the message
+/// already has one.
+///
+/// If no message is provided, the trailer can only be added with `git commit
+/// --trailer`, which requires Git v2.32. On older versions, the commit is
+/// created without a `Change-Id`, and one is added when it's next rewritten or
+/// submitted.
+fn get_commit_args(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    message: Option<&str>,
+    change_id: Option<&str>,
+) -> eyre::Result<Vec<String>> {
+    let args = match (message, change_id) {
+        (Some(message), Some(change_id)) if find_change_id(message).is_none() => vec![
+            "commit".to_owned(),
+            "--message".to_owned(),
+            add_change_id(message, change_id),
+        ],
+        (Some(message), _) => vec![
+            "commit".to_owned(),
+            "--message".to_owned(),
+            message.to_owned(),
+        ],
+        (None, Some(change_id)) if get_git_version(git_run_info, repo)? >= GitVersion(2, 32, 0) => {
+            vec![
+                "-c".to_owned(),
+                "trailer.ifExists=doNothing".to_owned(),
+                "commit".to_owned(),
+                "--trailer".to_owned(),
+                format!("{CHANGE_ID_TRAILER}: {change_id}"),
+            ]
         }
-        args
+        (None, _) => vec!["commit".to_owned()],
     };
-    git_run_info.run_direct_no_wrapping(Some(event_tx_id), &args)
+    Ok(args)
+}
+
+fn get_git_version(git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<GitVersion> {
+    let version_str = git_run_info
+        .run_silent(repo, None, &["version"], Default::default())
+        .wrap_err("Determining Git version")?
+        .stdout;
+    let version_str =
+        String::from_utf8(version_str).wrap_err("Decoding stdout from Git subprocess")?;
+    let version_str = version_str.trim();
+    let version = version_str
+        .parse()
+        .wrap_err_with(|| format!("Parsing Git version string: {version_str}"))?;
+    Ok(version)
 }
 
 #[instrument]
@@ -435,6 +500,7 @@ To proceed anyways, run: git move -f -s 'siblings(.)",
         force_in_memory: true,
         force_on_disk: false,
         resolve_merge_conflicts: false,
+        create_conflict_commits: false,
         check_out_commit_options: Default::default(),
     };
     let result = execute_rebase_plan(
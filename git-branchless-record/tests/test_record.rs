@@ -465,3 +465,45 @@ fn test_record_insert_obsolete_siblings() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_record_change_id() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "branchless.changeIds", "true"])?;
+
+    git.commit_file("test1", 1)?;
+    git.write_file_txt("test1", "contents1\n")?;
+    git.branchless("record", &["-m", "foo"])?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%(trailers:key=Change-Id)"])?;
+        let change_id = stdout.trim().strip_prefix("Change-Id: I").unwrap();
+        assert_eq!(change_id.len(), 40);
+        assert!(change_id.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    // A message which already has a `Change-Id` is left as-is.
+    git.write_file_txt("test1", "contents2\n")?;
+    git.branchless(
+        "record",
+        &[
+            "-m",
+            "bar\n\nChange-Id: I0123456789abcdef0123456789abcdef01234567",
+        ],
+    )?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%B"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        bar
+
+        Change-Id: I0123456789abcdef0123456789abcdef01234567
+
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,354 @@
+//! Interactively reorder the commits in a stack, and mark commits to be
+//! squashed or dropped.
+
+#![warn(missing_docs)]
+#![warn(
+    clippy::all,
+    clippy::as_conversions,
+    clippy::clone_on_ref_ptr,
+    clippy::dbg_macro
+)]
+#![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use bstr::ByteSlice;
+use eden_dag::DagAlgorithm;
+use lib::core::check_out::CheckOutCommitOptions;
+use lib::core::config::get_restack_preserve_timestamps;
+use lib::core::dag::{commit_set_to_vec, sorted_commit_set, union_all, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::{
+    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
+    MergeConflictRemediation, RebaseCommand, RebasePlan, RebasePlanBuilder, RebasePlanPermissions,
+    RepoResource,
+};
+use lib::git::{Commit, GitRunInfo, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use rayon::ThreadPoolBuilder;
+use scm_record::{RecordError, ReorderAction, ReorderItem, ReorderState, Reorderer};
+use tracing::instrument;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+
+/// Interactively reorder the commits in `revset`, which must form a single
+/// linear stack, and then rewrite them in memory.
+#[instrument]
+pub fn reorder(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revset: Revset,
+    resolve_revset_options: &ResolveRevsetOptions,
+    force_rewrite_public_commits: bool,
+    create_conflict_commits: bool,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    reorder_with(
+        effects,
+        git_run_info,
+        &repo,
+        revset,
+        resolve_revset_options,
+        force_rewrite_public_commits,
+        create_conflict_commits,
+        Reorderer::run,
+    )
+}
+
+fn reorder_with(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    revset: Revset,
+    resolve_revset_options: &ResolveRevsetOptions,
+    force_rewrite_public_commits: bool,
+    create_conflict_commits: bool,
+    select: impl FnOnce(ReorderState<'static>) -> Result<ReorderState<'static>, RecordError>,
+) -> eyre::Result<ExitCode> {
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_set = match resolve_commits(
+        effects,
+        repo,
+        &mut dag,
+        &[revset.clone()],
+        resolve_revset_options,
+    ) {
+        Ok(commit_sets) => union_all(&commit_sets),
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(ExitCode(1));
+        }
+    };
+    let commits = sorted_commit_set(repo, &dag, &commit_set)?;
+    if commits.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "No commits to reorder in: {revset}"
+        )?;
+        return Ok(ExitCode(0));
+    }
+    if let Some(commit) = find_nonlinear_commit(&commits) {
+        writeln!(
+            effects.get_error_stream(),
+            "The commits to reorder must form a single linear stack, but this commit does not: {}",
+            effects
+                .get_glyphs()
+                .render(commit.friendly_describe(effects.get_glyphs())?)?
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: false,
+    };
+    let permissions =
+        match RebasePlanPermissions::verify_rewrite_set(&dag, build_options, &commit_set)? {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                err.describe(effects, repo)?;
+                return Ok(ExitCode(1));
+            }
+        };
+
+    let state = ReorderState {
+        items: commits
+            .iter()
+            .enumerate()
+            .map(|(id, commit)| -> eyre::Result<ReorderItem<'static>> {
+                Ok(ReorderItem {
+                    id,
+                    label: format!(
+                        "{} {}",
+                        commit.get_short_oid()?,
+                        commit.get_summary()?.to_str_lossy()
+                    )
+                    .into(),
+                    action: ReorderAction::Pick,
+                })
+            })
+            .collect::<eyre::Result<_>>()?,
+    };
+    let ReorderState { items } = match select(state) {
+        Ok(state) => state,
+        Err(RecordError::Cancelled) => {
+            writeln!(effects.get_output_stream(), "Aborted.")?;
+            return Ok(ExitCode(1));
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let is_unchanged = items
+        .iter()
+        .enumerate()
+        .all(|(idx, item)| item.id == idx && item.action == ReorderAction::Pick);
+    if is_unchanged {
+        writeln!(
+            effects.get_output_stream(),
+            "The commits were not changed; nothing to do."
+        )?;
+        return Ok(ExitCode(0));
+    }
+    let items: Vec<(&Commit, ReorderAction)> = items
+        .iter()
+        .map(|item| (&commits[item.id], item.action))
+        .collect();
+    if let Some((commit, _action)) = items
+        .iter()
+        .find(|(_commit, action)| *action != ReorderAction::Drop)
+        .filter(|(_commit, action)| *action == ReorderAction::Squash)
+    {
+        writeln!(
+            effects.get_error_stream(),
+            "Cannot squash this commit, as there is no earlier commit to squash it into: {}",
+            effects
+                .get_glyphs()
+                .render(commit.friendly_describe(effects.get_glyphs())?)?
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let rebase_plan = {
+        let pool = ThreadPoolBuilder::new().build()?;
+        let repo_pool = RepoResource::new_pool(repo)?;
+        let mut builder = RebasePlanBuilder::new(&dag, permissions);
+
+        // The commits are known to be linear, so each one has exactly one
+        // parent.
+        let mut parent_oid = dag.get_only_parent_oid(commits[0].get_oid())?;
+        for (commit, _action) in items.iter() {
+            builder.move_subtree(commit.get_oid(), vec![parent_oid])?;
+            parent_oid = commit.get_oid();
+        }
+
+        // Any descendants of the original top of the stack belong on top of
+        // the new top of the stack.
+        let last_commit_oid = commits[commits.len() - 1].get_oid();
+        let children = dag
+            .query()
+            .children(CommitSet::from(last_commit_oid))?
+            .difference(&commit_set);
+        let children = dag.filter_visible_commits(children)?;
+        for child_oid in commit_set_to_vec(&children)? {
+            builder.move_subtree(child_oid, vec![parent_oid])?;
+        }
+
+        match builder.build(effects, &pool, &repo_pool)? {
+            Ok(Some(rebase_plan)) => rebase_plan,
+            Ok(None) => {
+                eyre::bail!("BUG: rebase plan indicates nothing to do, but reordering should always do something.");
+            }
+            Err(err) => {
+                err.describe(effects, repo)?;
+                return Ok(ExitCode(1));
+            }
+        }
+    };
+    let actions: HashMap<NonZeroOid, ReorderAction> = items
+        .iter()
+        .map(|(commit, action)| (commit.get_oid(), *action))
+        .collect();
+    let rebase_plan = apply_actions(rebase_plan, &actions);
+
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "reorder")?;
+    let execute_options = ExecuteRebasePlanOptions {
+        now,
+        event_tx_id,
+        preserve_timestamps: get_restack_preserve_timestamps(repo)?,
+        force_in_memory: true,
+        force_on_disk: false,
+        resolve_merge_conflicts: false,
+        create_conflict_commits,
+        check_out_commit_options: CheckOutCommitOptions::default(),
+    };
+    let result = execute_rebase_plan(
+        effects,
+        git_run_info,
+        repo,
+        &event_log_db,
+        &rebase_plan,
+        &execute_options,
+    )?;
+    match result {
+        ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => Ok(ExitCode(0)),
+        ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info } => {
+            failed_merge_info.describe(effects, repo, MergeConflictRemediation::CommitConflicts)?;
+            Ok(ExitCode(1))
+        }
+        ExecuteRebasePlanResult::Failed { exit_code } => Ok(exit_code),
+    }
+}
+
+/// Find the first commit in `commits` (sorted topologically) whose only parent
+/// isn't the commit before it, if any.
+fn find_nonlinear_commit<'a, 'repo>(commits: &'a [Commit<'repo>]) -> Option<&'a Commit<'repo>> {
+    let mut prev_oid: Option<NonZeroOid> = None;
+    for commit in commits {
+        let is_linear = match commit.get_parent_oids().as_slice() {
+            [parent_oid] => prev_oid.map_or(true, |prev_oid| prev_oid == *parent_oid),
+            _ => false,
+        };
+        if !is_linear {
+            return Some(commit);
+        }
+        prev_oid = Some(commit.get_oid());
+    }
+    None
+}
+
+/// Replace the picks for commits marked to be squashed or dropped with the
+/// corresponding rebase commands.
+fn apply_actions(
+    rebase_plan: RebasePlan,
+    actions: &HashMap<NonZeroOid, ReorderAction>,
+) -> RebasePlan {
+    let RebasePlan {
+        first_dest_oid,
+        commands,
+    } = rebase_plan;
+    let commands = commands
+        .into_iter()
+        .filter_map(|command| match command {
+            RebaseCommand::Pick {
+                original_commit_oid,
+                commit_to_apply_oid: _,
+            } => match actions.get(&original_commit_oid) {
+                Some(ReorderAction::Squash) => Some(RebaseCommand::Squash {
+                    commit_oid: original_commit_oid,
+                }),
+                Some(ReorderAction::Drop) => Some(RebaseCommand::DropCommit {
+                    commit_oid: original_commit_oid,
+                }),
+                Some(ReorderAction::Pick) | None => Some(command),
+            },
+            RebaseCommand::DetectEmptyCommit { commit_oid } => match actions.get(&commit_oid) {
+                Some(ReorderAction::Squash | ReorderAction::Drop) => None,
+                Some(ReorderAction::Pick) | None => Some(command),
+            },
+            command => Some(command),
+        })
+        .collect();
+    RebasePlan {
+        first_dest_oid,
+        commands,
+    }
+}
+
+#[allow(missing_docs)]
+pub mod testing {
+    use crossterm::event::Event;
+    use lib::core::effects::Effects;
+    use lib::git::{GitRunInfo, Repo};
+    use lib::util::ExitCode;
+    use scm_record::Reorderer;
+
+    use git_branchless_opts::{ResolveRevsetOptions, Revset};
+
+    /// Run `git reorder` using the provided events as the user input. Also
+    /// returns the last screen rendered by the user interface, if any.
+    pub fn reorder(
+        effects: &Effects,
+        git_run_info: &GitRunInfo,
+        repo: &Repo,
+        revset: Revset,
+        create_conflict_commits: bool,
+        events: Vec<Event>,
+    ) -> eyre::Result<(ExitCode, Option<String>)> {
+        let mut screen = None;
+        let exit_code = super::reorder_with(
+            effects,
+            git_run_info,
+            repo,
+            revset,
+            &ResolveRevsetOptions::default(),
+            false,
+            create_conflict_commits,
+            |state| {
+                let (state, last_screen) = Reorderer::run_with_events(state, 80, 10, events)?;
+                screen = Some(last_screen);
+                Ok(state)
+            },
+        )?;
+        Ok((exit_code, screen))
+    }
+}
@@ -1,5 +1,6 @@
 use bstr::ByteSlice;
 use eden_dag::DagAlgorithm;
+use lib::core::conflicts::CONFLICT_HEADER_NAME;
 use lib::core::dag::CommitSet;
 use lib::core::eventlog::{EventLogDb, EventReplayer};
 use lib::core::rewrite::find_rewrite_target;
@@ -63,6 +64,7 @@ lazy_static! {
             ("tests.passed", &fn_tests_passed),
             ("tests.failed", &fn_tests_failed),
             ("tests.fixable", &fn_tests_fixable),
+            ("conflicted", &fn_conflicted),
         ];
         functions.iter().cloned().collect()
     };
@@ -658,3 +660,19 @@ fn fn_tests_fixable(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult
         }),
     )
 }
+
+#[instrument]
+fn fn_conflicted(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
+    eval0(ctx, name, args)?;
+    make_pattern_matcher(
+        ctx,
+        name,
+        args,
+        Box::new(|_repo, commit| {
+            let header = commit
+                .get_header_field(CONFLICT_HEADER_NAME)
+                .map_err(PatternError::Repo)?;
+            Ok(header.is_some())
+        }),
+    )
+}
@@ -28,8 +28,10 @@ use eden_dag::DagAlgorithm;
 use eyre::Context;
 use tracing::{instrument, warn};
 
+use lib::core::change_id::{add_change_id, find_change_id, make_change_id};
 use lib::core::config::{
-    get_comment_char, get_commit_template, get_editor, get_restack_preserve_timestamps,
+    get_change_ids_enabled, get_comment_char, get_commit_template, get_editor,
+    get_restack_preserve_timestamps,
 };
 use lib::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
@@ -40,7 +42,9 @@ use lib::core::rewrite::{
     execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
     RebasePlanBuilder, RebasePlanPermissions, RepoResource,
 };
-use lib::git::{message_prettify, Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use lib::git::{
+    message_prettify, Commit, CommitSigner, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo, RepoError,
+};
 
 use git_branchless_opts::{ResolveRevsetOptions, Revset};
 use git_branchless_revset::resolve_commits;
@@ -253,12 +257,44 @@ pub fn reword(
         let pool = ThreadPoolBuilder::new().build()?;
         let repo_pool = RepoResource::new_pool(&repo)?;
         let mut builder = RebasePlanBuilder::new(&dag, permissions);
+        let signer = CommitSigner::from_config(&repo)?;
+        let add_change_ids = get_change_ids_enabled(&repo)?;
 
         for commit in commits.iter() {
             let message = messages.get(&commit.get_oid()).unwrap();
+            let message = if add_change_ids && find_change_id(message).is_none() {
+                // Keep the commit's existing change ID, if any, so that its
+                // change is updated rather than replaced.
+                let original_message =
+                    String::from_utf8_lossy(&commit.get_message_raw()?).into_owned();
+                let change_id = match find_change_id(&original_message) {
+                    Some(change_id) => change_id.to_owned(),
+                    None => make_change_id(&commit.get_oid().to_string())?,
+                };
+                add_change_id(message, &change_id)
+            } else {
+                message.clone()
+            };
             // This looks funny, but just means "leave everything but the message as is"
-            let replacement_oid =
-                commit.amend_commit(None, None, None, Some(message.as_str()), None)?;
+            let replacement_oid = match repo.amend_commit(
+                commit,
+                None,
+                None,
+                None,
+                Some(message.as_str()),
+                None,
+                signer.as_ref(),
+            ) {
+                Ok(replacement_oid) => replacement_oid,
+                Err(RepoError::SignCommit(err)) => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "Failed to sign reworded commit: {err}"
+                    )?;
+                    return Ok(ExitCode(1));
+                }
+                Err(err) => return Err(err.into()),
+            };
             builder.move_subtree(commit.get_oid(), commit.get_parent_oids())?;
             builder.replace_commit(commit.get_oid(), replacement_oid)?;
         }
@@ -286,6 +322,7 @@ pub fn reword(
         force_in_memory: true,
         force_on_disk: false,
         resolve_merge_conflicts: false,
+        create_conflict_commits: false,
         check_out_commit_options: CheckOutCommitOptions {
             additional_args: Default::default(),
             reset: false,
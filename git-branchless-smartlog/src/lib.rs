@@ -13,6 +13,7 @@
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::time::SystemTime;
 
@@ -27,19 +28,22 @@ use tracing::instrument;
 
 use lib::core::dag::{CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer, EventTransactionId};
 use lib::core::formatting::Pluralize;
 use lib::core::node_descriptors::{
-    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
+    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor, ConflictDescriptor,
     DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
-    RelativeTimeDescriptor,
+    RelativeTimeDescriptor, RemoteStatusDescriptor,
 };
-use lib::git::{GitRunInfo, Repo};
+use lib::core::repo_ext::RepoReferencesSnapshot;
+use lib::core::rewrite::RebasePlanPreview;
+use lib::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo};
 
 pub use graph::{make_smartlog_graph, SmartlogGraph};
 pub use render::{render_graph, SmartlogOptions};
 
 use git_branchless_revset::resolve_commits;
+use graph::make_hypothetical_smartlog_graph;
 
 mod graph {
     use std::collections::HashMap;
@@ -96,6 +100,12 @@ mod graph {
         /// workflow is different than expected.
         pub is_main: bool,
 
+        /// Indicates that this commit is reachable from one of the public
+        /// branches (the main branch or any configured release branch). Such
+        /// commits are rendered as public, but only commits on the main branch
+        /// are laid out along the main-branch line of the graph.
+        pub is_public: bool,
+
         /// Indicates that this commit has been marked as obsolete.
         ///
         /// Commits are marked as obsolete when they've been rewritten into another
@@ -186,7 +196,8 @@ mod graph {
                             children: Vec::new(), // populated below
                             ancestor_info: None,
                             descendants: Vec::new(), // populated below
-                            is_main: dag.is_public_commit(oid)?,
+                            is_main: dag.is_main_branch_ancestor(oid)?,
+                            is_public: dag.is_public_commit(oid)?,
                             is_obsolete: dag.query_obsolete_commits().contains(&oid.into())?,
                             num_omitted_descendants: 0, // populated below
                         },
@@ -368,6 +379,32 @@ mod graph {
         sort_children(&mut graph);
         Ok(graph)
     }
+
+    /// Construct the smartlog graph for a hypothetical state of the repo, such
+    /// as the predicted result of a rebase. Unlike `make_smartlog_graph`, this
+    /// doesn't mark the commits as reachable, so they can be garbage-collected
+    /// later.
+    #[instrument]
+    pub fn make_hypothetical_smartlog_graph<'repo>(
+        effects: &Effects,
+        repo: &'repo Repo,
+        dag: &Dag,
+        commits: &CommitSet,
+    ) -> eyre::Result<SmartlogGraph<'repo>> {
+        let (effects, _progress) = effects.start_operation(OperationType::MakeGraph);
+
+        let mut graph = {
+            let (effects, _progress) = effects.start_operation(OperationType::WalkCommits);
+
+            // HEAD and main head must be included
+            let commits = commits
+                .union(&dag.head_commit)
+                .union(&dag.main_branch_commit);
+            build_graph(&effects, repo, dag, &commits)?
+        };
+        sort_children(&mut graph);
+        Ok(graph)
+    }
 }
 
 mod render {
@@ -510,7 +547,7 @@ mod render {
         }
 
         lines.push({
-            let cursor = match (current_node.is_main, current_node.is_obsolete, is_head) {
+            let cursor = match (current_node.is_public, current_node.is_obsolete, is_head) {
                 (false, false, false) => glyphs.commit_visible,
                 (false, false, true) => glyphs.commit_visible_head,
                 (false, true, false) => glyphs.commit_obsolete,
@@ -848,12 +885,14 @@ pub fn smartlog(
                 &event_replayer,
                 event_replayer.make_default_cursor(),
             )?,
+            &mut ConflictDescriptor::new()?,
             &mut BranchesDescriptor::new(
                 &repo,
                 &head_info,
                 &references_snapshot,
                 &Redactor::Disabled,
             )?,
+            &mut RemoteStatusDescriptor::new(&repo, &references_snapshot, &Redactor::Disabled)?,
             &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
             &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
         ],
@@ -912,6 +951,141 @@ pub fn smartlog(
     Ok(ExitCode(0))
 }
 
+/// Display the smartlog as it would look after carrying out the rewrites in
+/// the provided preview, without actually carrying them out.
+#[instrument]
+pub fn smartlog_preview(
+    effects: &Effects,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    preview: &RebasePlanPreview,
+) -> eyre::Result<()> {
+    let RebasePlanPreview {
+        rewritten_oids,
+        new_head_oid,
+        conflict_commit_oids: _,
+    } = preview;
+
+    // Replay the predicted rewrites on top of the existing event log, but
+    // don't write them to the database.
+    let mut event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs_f64();
+    let mut rewritten_oids_sorted: Vec<_> = rewritten_oids.iter().collect();
+    rewritten_oids_sorted.sort();
+    for (old_commit_oid, new_commit_oid) in rewritten_oids_sorted {
+        event_replayer.process_event(&Event::RewriteEvent {
+            timestamp,
+            event_tx_id,
+            old_commit_oid: MaybeZeroOid::NonZero(*old_commit_oid),
+            new_commit_oid: *new_commit_oid,
+        });
+    }
+    let event_cursor = event_replayer.make_default_cursor();
+
+    // Move branches in the same way that `move_branches` would.
+    let head_info = repo.get_head_info()?;
+    let references_snapshot = {
+        let RepoReferencesSnapshot {
+            head_oid,
+            main_branch_oid,
+            branch_oid_to_names,
+        } = repo.get_references_snapshot()?;
+        let main_branch_reference_name = repo.get_main_branch()?.get_reference_name()?;
+        let mut new_branch_oid_to_names: HashMap<NonZeroOid, HashSet<ReferenceName>> =
+            HashMap::new();
+        for (oid, names) in branch_oid_to_names {
+            match rewritten_oids.get(&oid) {
+                None => new_branch_oid_to_names
+                    .entry(oid)
+                    .or_default()
+                    .extend(names),
+                Some(MaybeZeroOid::NonZero(new_oid)) => new_branch_oid_to_names
+                    .entry(*new_oid)
+                    .or_default()
+                    .extend(names),
+                Some(MaybeZeroOid::Zero) => new_branch_oid_to_names.entry(oid).or_default().extend(
+                    names
+                        .into_iter()
+                        .filter(|name| name == &main_branch_reference_name),
+                ),
+            }
+        }
+        new_branch_oid_to_names.retain(|_oid, names| !names.is_empty());
+        let main_branch_oid = match rewritten_oids.get(&main_branch_oid) {
+            Some(MaybeZeroOid::NonZero(new_oid)) => *new_oid,
+            Some(MaybeZeroOid::Zero) | None => main_branch_oid,
+        };
+        RepoReferencesSnapshot {
+            head_oid: new_head_oid.or(head_oid),
+            main_branch_oid,
+            branch_oid_to_names: new_branch_oid_to_names,
+        }
+    };
+    let head_info = ResolvedReferenceInfo {
+        oid: references_snapshot.head_oid,
+        reference_name: head_info.reference_name,
+    };
+
+    let mut dag = Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    let commits = match resolve_commits(
+        effects,
+        repo,
+        &mut dag,
+        &[Revset::default_smartlog_revset()],
+        &Default::default(),
+    ) {
+        Ok(result) => match result.as_slice() {
+            [commit_set] => commit_set.clone(),
+            other => panic!("Expected exactly 1 result from resolve commits, got: {other:?}"),
+        },
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(());
+        }
+    };
+
+    let graph = make_hypothetical_smartlog_graph(effects, repo, &dag, &commits)?;
+    let lines = render_graph(
+        effects,
+        repo,
+        &dag,
+        &graph,
+        references_snapshot.head_oid,
+        &mut [
+            &mut CommitOidDescriptor::new(true)?,
+            &mut RelativeTimeDescriptor::new(repo, SystemTime::now())?,
+            &mut ObsolescenceExplanationDescriptor::new(&event_replayer, event_cursor)?,
+            &mut ConflictDescriptor::new()?,
+            &mut BranchesDescriptor::new(
+                repo,
+                &head_info,
+                &references_snapshot,
+                &Redactor::Disabled,
+            )?,
+            &mut RemoteStatusDescriptor::new(repo, &references_snapshot, &Redactor::Disabled)?,
+            &mut DifferentialRevisionDescriptor::new(repo, &Redactor::Disabled)?,
+            &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
+        ],
+    )?;
+    for line in lines {
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            effects.get_glyphs().render(line)?
+        )?;
+    }
+    Ok(())
+}
+
 /// `smartlog` command.
 #[instrument]
 pub fn command_main(ctx: CommandContext, args: SmartlogArgs) -> eyre::Result<ExitCode> {
@@ -1,4 +1,7 @@
-use lib::testing::{extract_hint_command, make_git, GitRunOptions};
+use lib::testing::{
+    extract_hint_command, make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions,
+    GitWrapperWithRemoteRepo,
+};
 
 #[test]
 fn test_init_smartlog() -> eyre::Result<()> {
@@ -703,3 +706,59 @@ fn test_smartlog_sparse_vertical_ellipsis_sibling_commits() -> eyre::Result<()>
 
     Ok(())
 }
+
+#[test]
+fn test_smartlog_remote_status() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&[
+        "config",
+        "branchless.commitDescriptors.remoteStatus",
+        "true",
+    ])?;
+
+    cloned_repo.run(&["checkout", "-b", "pushed"])?;
+    cloned_repo.commit_file("test2", 2)?;
+    cloned_repo.run(&["push", "--set-upstream", "origin", "pushed"])?;
+
+    cloned_repo.run(&["checkout", "-b", "ahead"])?;
+    cloned_repo.run(&["push", "--set-upstream", "origin", "ahead"])?;
+    cloned_repo.commit_file("test3", 3)?;
+
+    cloned_repo.run(&["checkout", "-b", "diverged", "master"])?;
+    cloned_repo.commit_file("test5", 5)?;
+    cloned_repo.run(&["push", "--set-upstream", "origin", "diverged"])?;
+    cloned_repo.run(&["commit", "--amend", "-m", "amend test5.txt"])?;
+
+    cloned_repo.run(&["checkout", "master"])?;
+    cloned_repo.run(&["branch", "local-only"])?;
+    original_repo.commit_file("test4", 4)?;
+    cloned_repo.run(&["fetch", "origin"])?;
+
+    {
+        let stdout = cloned_repo.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master, local-only) local-only unpushed, master -1 create test1.txt
+        |\
+        | o c093023 (diverged) diverged +1 -1 amend test5.txt
+        |
+        o 96d1c37 (pushed) pushed create test2.txt
+        |
+        o 70deb1e (ahead) +1 create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,367 @@
+//! Generate branch names for commits which don't have a branch, so that they
+//! can be submitted with `git submit --auto-branch`.
+//!
+//! Names are generated from the template in the
+//! `branchless.submit.branchNameTemplate` config option. Each generated name
+//! is recorded alongside the commit it was generated for, so that if the
+//! commit is later rewritten (such as by rewording it, which would change its
+//! `{summary_slug}`), the rewritten commit is given the same name again
+//! rather than a new one.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use eyre::Context;
+use lib::core::change_id::CommitPredecessors;
+use lib::core::dag::{commit_set_to_vec, sorted_commit_set, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventTransactionId};
+use lib::core::repo_ext::RepoReferencesSnapshot;
+use lib::git::{BranchType, Commit, ConfigRead, GitRunInfo, NonZeroOid, Reference, Repo};
+use lib::util::ExitCode;
+use tempfile::NamedTempFile;
+use tracing::instrument;
+
+use crate::get_default_remote;
+
+/// The config option which holds the template for generated branch names.
+const BRANCH_NAME_TEMPLATE_CONFIG_KEY: &str = "branchless.submit.branchNameTemplate";
+
+/// The template used if `branchless.submit.branchNameTemplate` isn't set.
+const DEFAULT_BRANCH_NAME_TEMPLATE: &str = "{user}/{summary_slug}";
+
+/// The placeholders which can appear in a branch name template.
+const PLACEHOLDERS: &[&str] = &["user", "summary_slug", "stack", "index"];
+
+/// The maximum length of a slug generated from a commit summary.
+const MAX_SLUG_LEN: usize = 50;
+
+fn get_branch_names_path(repo: &Repo) -> PathBuf {
+    repo.get_path()
+        .join("branchless")
+        .join("submit-branch-names")
+}
+
+fn load_branch_names(repo: &Repo) -> eyre::Result<HashMap<NonZeroOid, String>> {
+    let path = get_branch_names_path(repo);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Reading {path:?}")),
+    };
+    let mut result = HashMap::new();
+    for line in contents.lines() {
+        match line.split_once(' ') {
+            Some((commit_oid, branch_name)) => {
+                result.insert(commit_oid.parse()?, branch_name.to_owned());
+            }
+            None => eyre::bail!("Invalid line in {:?}: {:?}", path, line),
+        }
+    }
+    Ok(result)
+}
+
+/// Write the recorded branch names, dropping those for commits which have
+/// since been hidden or landed. Names for hidden commits are kept if a draft
+/// commit was rewritten from them, since that commit may still reuse the name.
+fn save_branch_names(
+    repo: &Repo,
+    dag: &Dag,
+    predecessors: &CommitPredecessors,
+    branch_names: &HashMap<NonZeroOid, String>,
+) -> eyre::Result<()> {
+    let mut live_oids: HashSet<NonZeroOid> = HashSet::new();
+    for commit_oid in commit_set_to_vec(dag.query_draft_commits()?)? {
+        live_oids.insert(commit_oid);
+        live_oids.extend(predecessors.iter_predecessors(commit_oid));
+    }
+    let mut branch_names: Vec<_> = branch_names
+        .iter()
+        .filter(|(commit_oid, _)| live_oids.contains(commit_oid))
+        .collect();
+    branch_names.sort_unstable();
+
+    let tempfile_dir = repo.get_tempfile_dir();
+    std::fs::create_dir_all(&tempfile_dir).wrap_err("Creating tempfile dir")?;
+    let mut tempfile = NamedTempFile::new_in(&tempfile_dir)
+        .wrap_err("Creating temporary submit branch names file")?;
+    let file = tempfile.as_file_mut();
+    for (commit_oid, branch_name) in branch_names {
+        use std::io::Write;
+        writeln!(file, "{commit_oid} {branch_name}")?;
+    }
+    file.sync_all()?;
+    let path = get_branch_names_path(repo);
+    tempfile
+        .persist(&path)
+        .wrap_err_with(|| format!("Moving new submit branch names file into place at {path:?}"))?;
+    Ok(())
+}
+
+/// Convert the given text into a form suitable for use in a branch name:
+/// lowercase alphanumeric words separated by hyphens.
+fn slugify(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            if result.len() >= MAX_SLUG_LEN {
+                break;
+            }
+            result.push(c.to_ascii_lowercase());
+        } else if !result.is_empty() && !result.ends_with('-') {
+            result.push('-');
+        }
+    }
+    result.trim_end_matches('-').to_owned()
+}
+
+/// Replace each `{placeholder}` in `template` with its value. Returns the
+/// unknown placeholder as the error if there is one.
+fn expand_template(template: &str, values: &HashMap<&str, String>) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let placeholder_and_rest = &rest[start + 1..];
+        let end = match placeholder_and_rest.find('}') {
+            Some(end) => end,
+            None => return Err(rest[start..].to_owned()),
+        };
+        let placeholder = &placeholder_and_rest[..end];
+        match values.get(placeholder) {
+            Some(value) => result.push_str(value),
+            None => return Err(format!("{{{placeholder}}}")),
+        }
+        rest = &placeholder_and_rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn get_user_slug(commit: &Commit) -> String {
+    let author = commit.get_author();
+    let user = match author.get_email() {
+        Some(email) => email.split('@').next().unwrap_or(email).to_owned(),
+        None => author.get_name().unwrap_or_default().to_owned(),
+    };
+    match slugify(&user) {
+        user if user.is_empty() => "user".to_owned(),
+        user => user,
+    }
+}
+
+fn get_summary_slug(commit: &Commit) -> eyre::Result<String> {
+    let summary = commit.get_summary()?;
+    let slug = slugify(&String::from_utf8_lossy(&summary));
+    if slug.is_empty() {
+        Ok(commit.get_oid().to_string()[..7].to_owned())
+    } else {
+        Ok(slug)
+    }
+}
+
+/// A branch to be created for a commit which doesn't have one.
+#[derive(Debug)]
+pub struct GeneratedBranch<'repo> {
+    /// The commit which the branch will point to.
+    pub commit: Commit<'repo>,
+
+    /// The name of the branch.
+    pub branch_name: String,
+
+    /// Whether the name was previously generated for this commit or one of
+    /// its predecessors, rather than newly generated.
+    pub is_reused: bool,
+}
+
+impl GeneratedBranch<'_> {
+    /// Get the remote branch which the generated branch will track, if any.
+    /// If a reused branch was pushed before, then it will track the remote
+    /// branch again, so that it's updated rather than created.
+    pub fn get_upstream_branch_name(&self, repo: &Repo) -> eyre::Result<Option<String>> {
+        let remote_name = match (self.is_reused, get_default_remote(repo)?) {
+            (true, Some(remote_name)) => remote_name,
+            (false, _) | (_, None) => return Ok(None),
+        };
+        let upstream_name = format!("{remote_name}/{}", self.branch_name);
+        if repo
+            .find_branch(&upstream_name, BranchType::Remote)?
+            .is_some()
+        {
+            Ok(Some(upstream_name))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Determine the local branch to create for each draft commit in `commit_set`
+/// which doesn't already have one, named according to the configured
+/// template. No branches are created.
+#[instrument]
+pub fn plan_branches_for_unbranched_commits<'repo>(
+    effects: &Effects,
+    repo: &'repo Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    references_snapshot: &RepoReferencesSnapshot,
+    commit_set: &CommitSet,
+) -> eyre::Result<Result<Vec<GeneratedBranch<'repo>>, ExitCode>> {
+    let template: String = repo
+        .get_readonly_config()?
+        .get_or_else(BRANCH_NAME_TEMPLATE_CONFIG_KEY, || {
+            DEFAULT_BRANCH_NAME_TEMPLATE.to_owned()
+        })?;
+
+    let commit_set = commit_set.intersection(dag.query_draft_commits()?);
+    let has_local_branch =
+        |commit_oid: NonZeroOid| match references_snapshot.branch_oid_to_names.get(&commit_oid) {
+            Some(names) => names
+                .iter()
+                .any(|name| name.as_str().starts_with("refs/heads/")),
+            None => false,
+        };
+
+    let branch_names = load_branch_names(repo)?;
+    let predecessors = CommitPredecessors::from_events(&event_log_db.get_events()?);
+    let mut used_names: HashSet<String> = HashSet::new();
+    let mut new_branches: Vec<GeneratedBranch> = Vec::new();
+    for stack in dag.get_connected_components(&commit_set)? {
+        let stack_commits = sorted_commit_set(repo, dag, &stack)?;
+        let stack_slug = match stack_commits.first() {
+            Some(root_commit) => get_summary_slug(root_commit)?,
+            None => continue,
+        };
+        for (index, commit) in stack_commits.into_iter().enumerate() {
+            if has_local_branch(commit.get_oid()) {
+                continue;
+            }
+
+            let is_available = |name: &str| -> eyre::Result<bool> {
+                Ok(!used_names.contains(name)
+                    && repo.find_branch(name, BranchType::Local)?.is_none())
+            };
+            let previous_name = std::iter::once(commit.get_oid())
+                .chain(predecessors.iter_predecessors(commit.get_oid()))
+                .find_map(|commit_oid| branch_names.get(&commit_oid));
+            let (branch_name, is_reused) = match previous_name {
+                Some(previous_name) if is_available(previous_name)? => {
+                    (previous_name.clone(), true)
+                }
+                _ => {
+                    let values = HashMap::from([
+                        ("user", get_user_slug(&commit)),
+                        ("summary_slug", get_summary_slug(&commit)?),
+                        ("stack", stack_slug.clone()),
+                        ("index", (index + 1).to_string()),
+                    ]);
+                    let base_name = match expand_template(&template, &values) {
+                        Ok(base_name) => base_name,
+                        Err(placeholder) => {
+                            writeln!(
+                                effects.get_output_stream(),
+                                "Invalid placeholder in config value {BRANCH_NAME_TEMPLATE_CONFIG_KEY}: {placeholder}"
+                            )?;
+                            writeln!(
+                                effects.get_output_stream(),
+                                "Expected one of: {}",
+                                PLACEHOLDERS
+                                    .iter()
+                                    .map(|placeholder| format!("{{{placeholder}}}"))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            )?;
+                            return Ok(Err(ExitCode(1)));
+                        }
+                    };
+                    if !Reference::is_valid_name(&format!("refs/heads/{base_name}")) {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "The branch name generated from config value {BRANCH_NAME_TEMPLATE_CONFIG_KEY} is not valid: {base_name}"
+                        )?;
+                        return Ok(Err(ExitCode(1)));
+                    }
+
+                    let mut branch_name = base_name.clone();
+                    let mut suffix = 1;
+                    while !is_available(&branch_name)? {
+                        suffix += 1;
+                        branch_name = format!("{base_name}-{suffix}");
+                    }
+                    (branch_name, false)
+                }
+            };
+            used_names.insert(branch_name.clone());
+            new_branches.push(GeneratedBranch {
+                commit,
+                branch_name,
+                is_reused,
+            });
+        }
+    }
+    Ok(Ok(new_branches))
+}
+
+/// Create a local branch for each draft commit in `commit_set` which doesn't
+/// already have one, named according to the configured template.
+#[instrument]
+pub fn create_branches_for_unbranched_commits(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    references_snapshot: &RepoReferencesSnapshot,
+    event_tx_id: EventTransactionId,
+    commit_set: &CommitSet,
+) -> eyre::Result<Result<(), ExitCode>> {
+    let new_branches = match plan_branches_for_unbranched_commits(
+        effects,
+        repo,
+        dag,
+        event_log_db,
+        references_snapshot,
+        commit_set,
+    )? {
+        Ok(new_branches) => new_branches,
+        Err(exit_code) => return Ok(Err(exit_code)),
+    };
+
+    let mut branch_names = load_branch_names(repo)?;
+    let predecessors = CommitPredecessors::from_events(&event_log_db.get_events()?);
+    for new_branch in &new_branches {
+        let GeneratedBranch {
+            commit,
+            branch_name,
+            is_reused: _,
+        } = new_branch;
+        let exit_code = git_run_info.run(
+            effects,
+            Some(event_tx_id),
+            &["branch", branch_name, &commit.get_oid().to_string()],
+        )?;
+        if !exit_code.is_success() {
+            writeln!(
+                effects.get_output_stream(),
+                "Failed to create branch: {branch_name}"
+            )?;
+            return Ok(Err(exit_code));
+        }
+
+        if let Some(upstream_name) = new_branch.get_upstream_branch_name(repo)? {
+            let exit_code = git_run_info.run(
+                effects,
+                Some(event_tx_id),
+                &["branch", "--set-upstream-to", &upstream_name, branch_name],
+            )?;
+            if !exit_code.is_success() {
+                return Ok(Err(exit_code));
+            }
+        }
+        branch_names.insert(commit.get_oid(), branch_name.clone());
+    }
+    save_branch_names(repo, dag, &predecessors, &branch_names)?;
+
+    Ok(Ok(()))
+}
@@ -0,0 +1,275 @@
+//! Submit commits to Gerrit by pushing them to its `refs/for/<target>`
+//! reference, which creates or updates a change for each commit.
+//!
+//! Gerrit identifies the change that a commit belongs to by the `Change-Id`
+//! trailer in its message, so commits without one are given one first. If a
+//! commit lost its `Change-Id` when it was rewritten, such as by amending its
+//! message, then the one from the commit that it was rewritten from is used
+//! again, so that the existing change is updated rather than replaced.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use eden_dag::DagAlgorithm;
+use eyre::Context;
+use lib::core::change_id::{add_change_id, find_change_id, make_change_id, CommitPredecessors};
+use lib::core::dag::{commit_set_to_vec, sorted_commit_set, CommitSet, Dag};
+use lib::core::effects::{Effects, OperationType};
+use lib::core::eventlog::{EventLogDb, EventTransactionId};
+use lib::core::formatting::StyledStringBuilder;
+use lib::git::{Commit, ConfigRead, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+use crate::{
+    get_default_remote, get_main_branch_remote_name, reword_commits, Forge, SubmitOptions,
+    STYLE_PUSHED, STYLE_SKIPPED,
+};
+
+/// How a change was affected by submitting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChangeStatus {
+    Created,
+    Updated,
+    UpToDate,
+}
+
+/// The commit most recently pushed for each change ID, which is used to tell
+/// whether each change needs to be created or updated.
+fn get_submitted_changes_path(repo: &Repo) -> PathBuf {
+    repo.get_path().join("branchless").join("gerrit-changes")
+}
+
+fn load_submitted_changes(repo: &Repo) -> eyre::Result<HashMap<String, NonZeroOid>> {
+    let path = get_submitted_changes_path(repo);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Reading {path:?}")),
+    };
+    let mut result = HashMap::new();
+    for line in contents.lines() {
+        match line.split_once(' ') {
+            Some((change_id, commit_oid)) => {
+                result.insert(change_id.to_owned(), commit_oid.parse()?);
+            }
+            None => eyre::bail!("Invalid line in {:?}: {:?}", path, line),
+        }
+    }
+    Ok(result)
+}
+
+fn save_submitted_changes(
+    repo: &Repo,
+    submitted_changes: &HashMap<String, NonZeroOid>,
+) -> eyre::Result<()> {
+    let path = get_submitted_changes_path(repo);
+    let mut contents = String::new();
+    let mut submitted_changes: Vec<_> = submitted_changes.iter().collect();
+    submitted_changes.sort_unstable();
+    for (change_id, commit_oid) in submitted_changes {
+        writeln!(contents, "{change_id} {commit_oid}")?;
+    }
+    std::fs::write(&path, contents).wrap_err_with(|| format!("Writing {path:?}"))?;
+    Ok(())
+}
+
+/// Submits each commit as a Gerrit change.
+#[derive(Debug)]
+pub struct GerritForge<'a> {
+    pub effects: &'a Effects,
+    pub git_run_info: &'a GitRunInfo,
+    pub repo: &'a Repo,
+    pub dag: &'a Dag,
+    pub event_log_db: &'a EventLogDb<'a>,
+    pub event_tx_id: EventTransactionId,
+}
+
+impl Forge for GerritForge<'_> {
+    fn submit(
+        &mut self,
+        commit_set: &CommitSet,
+        _options: &SubmitOptions,
+    ) -> eyre::Result<ExitCode> {
+        let Self {
+            effects,
+            git_run_info,
+            repo,
+            dag,
+            event_log_db: _,
+            event_tx_id,
+        } = *self;
+
+        let remote_name = match get_default_remote(repo)? {
+            Some(remote_name) => remote_name,
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "\
+No remote repository was found to push the changes to.
+Configure a value with: git config remote.pushDefault <remote>"
+                )?;
+                return Ok(ExitCode(1));
+            }
+        };
+        let target: Option<String> = repo
+            .get_readonly_config()?
+            .get("branchless.submit.gerrit.target")?;
+        let target = match target {
+            Some(target) => target,
+            None => get_main_branch_remote_name(repo)?,
+        };
+
+        let commit_set = commit_set.intersection(dag.query_draft_commits()?);
+        let commits = sorted_commit_set(repo, dag, &commit_set)?;
+        let rewritten_oids = match self.add_missing_change_ids(&commits)? {
+            Ok(rewritten_oids) => rewritten_oids,
+            Err(exit_code) => return Ok(exit_code),
+        };
+        let get_new_oid = |commit_oid: NonZeroOid| match rewritten_oids.get(&commit_oid) {
+            Some(MaybeZeroOid::NonZero(new_oid)) => *new_oid,
+            Some(MaybeZeroOid::Zero) | None => commit_oid,
+        };
+
+        let mut submitted_changes = load_submitted_changes(repo)?;
+        let mut changes: Vec<(Commit, String, ChangeStatus)> = Vec::new();
+        for commit in &commits {
+            let commit = repo.find_commit_or_fail(get_new_oid(commit.get_oid()))?;
+            let message = String::from_utf8_lossy(&commit.get_message_raw()?).into_owned();
+            let change_id = match find_change_id(&message) {
+                Some(change_id) => change_id.to_owned(),
+                None => eyre::bail!("BUG: commit {:?} has no Change-Id", commit.get_oid()),
+            };
+            let status = match submitted_changes.get(&change_id) {
+                None => ChangeStatus::Created,
+                Some(submitted_oid) if *submitted_oid == commit.get_oid() => ChangeStatus::UpToDate,
+                Some(_) => ChangeStatus::Updated,
+            };
+            changes.push((commit, change_id, status));
+        }
+
+        // Pushing a commit to `refs/for/<target>` also pushes its ancestors,
+        // so only the heads need to be pushed. Gerrit rejects pushes which
+        // contain no new commits, so skip heads whose commits are all
+        // up-to-date.
+        let head_oids = commit_set_to_vec(&dag.query().heads(commit_set.clone())?)?;
+        let mut head_oids_to_push = Vec::new();
+        for head_oid in head_oids {
+            let mut needs_push = false;
+            for (original_commit, (_commit, _change_id, status)) in commits.iter().zip(&changes) {
+                if *status != ChangeStatus::UpToDate
+                    && dag
+                        .query()
+                        .is_ancestor(original_commit.get_oid().into(), head_oid.into())?
+                {
+                    needs_push = true;
+                }
+            }
+            if needs_push {
+                head_oids_to_push.push(get_new_oid(head_oid));
+            }
+        }
+
+        if !head_oids_to_push.is_empty() {
+            let (effects, progress) = effects.start_operation(OperationType::PushCommits);
+            progress.notify_progress(0, head_oids_to_push.len());
+            for head_oid in head_oids_to_push {
+                let refspec = format!("{head_oid}:refs/for/{target}");
+                let exit_code = git_run_info.run(
+                    &effects,
+                    Some(event_tx_id),
+                    &["push", &remote_name, &refspec],
+                )?;
+                if !exit_code.is_success() {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "Failed to push changes to: {remote_name}"
+                    )?;
+                    return Ok(exit_code);
+                }
+                progress.notify_progress_inc(1);
+            }
+        }
+
+        for (commit, change_id, status) in &changes {
+            submitted_changes.insert(change_id.clone(), commit.get_oid());
+            let (verb, suffix, style) = match status {
+                ChangeStatus::Created => ("Created", "", *STYLE_PUSHED),
+                ChangeStatus::Updated => ("Updated", "", *STYLE_PUSHED),
+                ChangeStatus::UpToDate => ("Skipped", " (already up-to-date)", *STYLE_SKIPPED),
+            };
+            writeln!(
+                effects.get_output_stream(),
+                "{verb} change {}{suffix}: {}",
+                effects.get_glyphs().render(
+                    StyledStringBuilder::new()
+                        .append_styled(change_id.as_str(), style)
+                        .build()
+                )?,
+                effects
+                    .get_glyphs()
+                    .render(commit.friendly_describe(effects.get_glyphs())?)?,
+            )?;
+        }
+        save_submitted_changes(repo, &submitted_changes)?;
+
+        Ok(ExitCode(0))
+    }
+}
+
+impl GerritForge<'_> {
+    /// Find the `Change-Id` that the given commit should have, preferring the
+    /// one from the commit it was most recently rewritten from.
+    #[instrument]
+    fn get_change_id(
+        &self,
+        predecessors: &CommitPredecessors,
+        commit: &Commit,
+    ) -> eyre::Result<String> {
+        for predecessor_oid in predecessors.iter_predecessors(commit.get_oid()) {
+            if let Some(predecessor) = self.repo.find_commit(predecessor_oid)? {
+                let message = String::from_utf8_lossy(&predecessor.get_message_raw()?).into_owned();
+                if let Some(change_id) = find_change_id(&message) {
+                    return Ok(change_id.to_owned());
+                }
+            }
+        }
+        make_change_id(&commit.get_oid().to_string())
+    }
+
+    /// Reword the commits which don't have a `Change-Id` trailer to add one.
+    /// Returns the rewritten commits.
+    fn add_missing_change_ids(
+        &self,
+        commits: &[Commit],
+    ) -> eyre::Result<Result<HashMap<NonZeroOid, MaybeZeroOid>, ExitCode>> {
+        let Self {
+            effects,
+            git_run_info,
+            repo,
+            dag,
+            event_log_db,
+            event_tx_id,
+        } = *self;
+
+        let predecessors = CommitPredecessors::from_events(&event_log_db.get_events()?);
+        let mut messages: Vec<(&Commit, String)> = Vec::new();
+        for commit in commits {
+            let message = String::from_utf8_lossy(&commit.get_message_raw()?).into_owned();
+            if find_change_id(&message).is_none() {
+                let change_id = self.get_change_id(&predecessors, commit)?;
+                messages.push((commit, add_change_id(&message, &change_id)));
+            }
+        }
+        reword_commits(
+            effects,
+            git_run_info,
+            repo,
+            dag,
+            event_log_db,
+            event_tx_id,
+            &messages,
+        )
+    }
+}
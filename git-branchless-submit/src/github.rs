@@ -0,0 +1,564 @@
+//! Submit commits as GitHub pull requests via the GitHub REST API.
+//!
+//! Each branch in the stack gets its own pull request, whose base is the
+//! branch of the nearest ancestor commit (or the main branch), so that each
+//! pull request only shows the changes of its own commits. The body of each
+//! pull request is initially generated from the commit message. A list of the
+//! other pull requests in the stack is kept up-to-date at the end of the body,
+//! between HTML comment markers, so that the rest of the body can be edited
+//! on GitHub without being overwritten.
+
+use std::fmt::Write;
+
+use eden_dag::DagAlgorithm;
+use eyre::Context;
+use itertools::Itertools;
+use lib::core::dag::{sorted_commit_set, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::EventTransactionId;
+use lib::core::formatting::StyledStringBuilder;
+use lib::core::repo_ext::RepoReferencesSnapshot;
+use lib::git::{
+    BranchType, CategorizedReferenceName, Commit, ConfigRead, GitRunInfo, NonZeroOid, Repo,
+};
+use lib::util::ExitCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::branch_forge::BranchForge;
+use crate::{
+    get_default_remote, get_main_branch_remote_name, Forge, SubmitOptions, STYLE_PUSHED,
+    STYLE_SKIPPED,
+};
+
+/// The environment variable which holds the token used to authenticate with
+/// the GitHub API.
+const GITHUB_TOKEN_ENV_VAR: &str = "GITHUB_TOKEN";
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// The markers around the stack section of a pull request body, which are
+/// rendered invisibly by GitHub.
+const STACK_SECTION_START: &str = "<!-- git-branchless stack start -->";
+const STACK_SECTION_END: &str = "<!-- git-branchless stack end -->";
+
+#[derive(Debug, Deserialize)]
+struct PullRequest {
+    number: u64,
+    html_url: String,
+    title: String,
+    body: Option<String>,
+    base: PullRequestRef,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequestRef {
+    #[serde(rename = "ref")]
+    ref_name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CreatePullRequest<'a> {
+    title: &'a str,
+    head: &'a str,
+    base: &'a str,
+    body: &'a str,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct UpdatePullRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<&'a str>,
+}
+
+/// A client for the pull request endpoints of a single GitHub repository.
+struct GithubClient {
+    agent: ureq::Agent,
+    api_url: String,
+    owner: String,
+    name: String,
+    token: String,
+}
+
+impl std::fmt::Debug for GithubClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<GithubClient api_url={:?} owner={:?} name={:?} token=not shown>",
+            self.api_url, self.owner, self.name
+        )
+    }
+}
+
+impl GithubClient {
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let Self {
+            agent,
+            api_url,
+            owner,
+            name,
+            token,
+        } = self;
+        agent
+            .request(
+                method,
+                &format!(
+                    "{}/repos/{owner}/{name}{path}",
+                    api_url.trim_end_matches('/')
+                ),
+            )
+            .set("Accept", "application/vnd.github+json")
+            .set("Authorization", &format!("Bearer {token}"))
+            .set("X-GitHub-Api-Version", "2022-11-28")
+            .set("User-Agent", "git-branchless")
+    }
+
+    #[instrument]
+    fn find_pull_request(&self, branch_name: &str) -> eyre::Result<Option<PullRequest>> {
+        let response = self
+            .request("GET", "/pulls")
+            .query("state", "open")
+            .query("head", &format!("{}:{branch_name}", self.owner))
+            .call();
+        let pull_requests: Vec<PullRequest> = read_response(response)?;
+        Ok(pull_requests.into_iter().next())
+    }
+
+    #[instrument]
+    fn create_pull_request(&self, request: &CreatePullRequest) -> eyre::Result<PullRequest> {
+        let response = self
+            .request("POST", "/pulls")
+            .set("Content-Type", "application/json")
+            .send_string(&serde_json::to_string(request)?);
+        read_response(response)
+    }
+
+    #[instrument]
+    fn update_pull_request(
+        &self,
+        number: u64,
+        request: &UpdatePullRequest,
+    ) -> eyre::Result<PullRequest> {
+        let response = self
+            .request("PATCH", &format!("/pulls/{number}"))
+            .set("Content-Type", "application/json")
+            .send_string(&serde_json::to_string(request)?);
+        read_response(response)
+    }
+}
+
+fn read_response<T: DeserializeOwned>(
+    response: Result<ureq::Response, ureq::Error>,
+) -> eyre::Result<T> {
+    match response {
+        Ok(response) => {
+            let url = response.get_url().to_owned();
+            let body = response
+                .into_string()
+                .wrap_err_with(|| format!("Reading response from {url}"))?;
+            let result = serde_json::from_str(&body)
+                .wrap_err_with(|| format!("Parsing response from {url}: {body}"))?;
+            Ok(result)
+        }
+        Err(ureq::Error::Status(status, response)) => {
+            let url = response.get_url().to_owned();
+            let body = response.into_string().unwrap_or_default();
+            eyre::bail!("GitHub API request to {url} failed with status {status}: {body}")
+        }
+        Err(err) => Err(err).wrap_err("Sending GitHub API request"),
+    }
+}
+
+/// Extract the `owner/name` of a repository hosted on GitHub from its remote
+/// URL.
+fn parse_github_repository(url: &str) -> Option<(String, String)> {
+    let path = [
+        "git@github.com:",
+        "ssh://git@github.com/",
+        "https://github.com/",
+    ]
+    .into_iter()
+    .find_map(|prefix| url.strip_prefix(prefix))?;
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    match path.split_once('/') {
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+            Some((owner.to_owned(), name.to_owned()))
+        }
+        _ => None,
+    }
+}
+
+/// How a pull request was affected by submitting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PullRequestStatus {
+    Created,
+    Updated,
+    UpToDate,
+}
+
+/// Submits each branch as a GitHub pull request.
+#[derive(Debug)]
+pub struct GithubForge<'a> {
+    pub effects: &'a Effects,
+    pub git_run_info: &'a GitRunInfo,
+    pub repo: &'a Repo,
+    pub dag: &'a Dag,
+    pub references_snapshot: &'a RepoReferencesSnapshot,
+    pub event_tx_id: EventTransactionId,
+}
+
+impl Forge for GithubForge<'_> {
+    fn submit(
+        &mut self,
+        commit_set: &CommitSet,
+        _options: &SubmitOptions,
+    ) -> eyre::Result<ExitCode> {
+        let client = match self.make_client()? {
+            Ok(client) => client,
+            Err(exit_code) => return Ok(exit_code),
+        };
+
+        let stack = self.get_stack(commit_set)?;
+        let main_branch_name = get_main_branch_remote_name(self.repo)?;
+        let bases: Vec<Option<(NonZeroOid, String)>> = stack
+            .iter()
+            .map(|(commit, _branch_name)| self.get_base_branch(commit))
+            .collect::<eyre::Result<_>>()?;
+
+        // Pull requests can only be opened for branches which exist on the
+        // remote, so always create them.
+        let branch_commit_set = self.get_commits_to_push(commit_set)?;
+        let exit_code = BranchForge {
+            effects: self.effects,
+            git_run_info: self.git_run_info,
+            repo: self.repo,
+            references_snapshot: self.references_snapshot,
+            event_tx_id: self.event_tx_id,
+        }
+        .submit(&branch_commit_set, &SubmitOptions { create: true })?;
+        if !exit_code.is_success() {
+            return Ok(exit_code);
+        }
+
+        let mut pull_requests: Vec<(PullRequest, PullRequestStatus)> = Vec::new();
+        for ((commit, branch_name), base) in stack.iter().zip(&bases) {
+            let base = match base {
+                Some((_base_oid, base_branch_name)) => base_branch_name.as_str(),
+                None => main_branch_name.as_str(),
+            };
+            let (title, body) = get_title_and_body(commit)?;
+            let pull_request = match client.find_pull_request(branch_name)? {
+                None => {
+                    let pull_request = client.create_pull_request(&CreatePullRequest {
+                        title: &title,
+                        head: branch_name,
+                        base,
+                        body: &body,
+                    })?;
+                    (pull_request, PullRequestStatus::Created)
+                }
+                Some(pull_request)
+                    if pull_request.title != title || pull_request.base.ref_name != base =>
+                {
+                    let pull_request = client.update_pull_request(
+                        pull_request.number,
+                        &UpdatePullRequest {
+                            title: Some(&title),
+                            base: Some(base),
+                            body: None,
+                        },
+                    )?;
+                    (pull_request, PullRequestStatus::Updated)
+                }
+                Some(pull_request) => (pull_request, PullRequestStatus::UpToDate),
+            };
+            pull_requests.push(pull_request);
+        }
+
+        // The stack navigation refers to the other pull requests by number,
+        // so it can only be written once they've all been created.
+        for (i, (commit, _branch_name)) in stack.iter().enumerate() {
+            let mut stack_numbers = Vec::new();
+            for (j, (other_commit, _other_branch_name)) in stack.iter().enumerate() {
+                if i == j
+                    || self.is_ancestor(commit.get_oid(), other_commit.get_oid())?
+                    || self.is_ancestor(other_commit.get_oid(), commit.get_oid())?
+                {
+                    let (other_pull_request, _status) = &pull_requests[j];
+                    stack_numbers.push(other_pull_request.number);
+                }
+            }
+
+            let (pull_request, status) = &mut pull_requests[i];
+            let old_body = pull_request.body.as_deref().unwrap_or_default();
+            let body = make_pull_request_body(old_body, &stack_numbers, pull_request.number);
+            if old_body != body {
+                *pull_request = client.update_pull_request(
+                    pull_request.number,
+                    &UpdatePullRequest {
+                        body: Some(&body),
+                        ..Default::default()
+                    },
+                )?;
+                if *status == PullRequestStatus::UpToDate {
+                    *status = PullRequestStatus::Updated;
+                }
+            }
+        }
+
+        for ((_commit, branch_name), (pull_request, status)) in stack.iter().zip(pull_requests) {
+            let (verb, suffix, style) = match status {
+                PullRequestStatus::Created => ("Created", "", *STYLE_PUSHED),
+                PullRequestStatus::Updated => ("Updated", "", *STYLE_PUSHED),
+                PullRequestStatus::UpToDate => ("Skipped", " (already up-to-date)", *STYLE_SKIPPED),
+            };
+            writeln!(
+                self.effects.get_output_stream(),
+                "{verb} pull request #{} for branch {}{suffix}: {}",
+                pull_request.number,
+                self.effects.get_glyphs().render(
+                    StyledStringBuilder::new()
+                        .append_styled(branch_name.as_str(), style)
+                        .build()
+                )?,
+                pull_request.html_url,
+            )?;
+        }
+
+        Ok(ExitCode(0))
+    }
+}
+
+impl<'a> GithubForge<'a> {
+    /// Get the commits in `commit_set` which have a branch, along with the
+    /// branch to submit for each, in topological order.
+    fn get_stack(&self, commit_set: &CommitSet) -> eyre::Result<Vec<(Commit<'a>, String)>> {
+        sorted_commit_set(self.repo, self.dag, commit_set)?
+            .into_iter()
+            .map(|commit| -> eyre::Result<_> {
+                let branch_name = self.get_branch_name(commit.get_oid())?;
+                Ok(branch_name.map(|branch_name| (commit, branch_name)))
+            })
+            .filter_map(|result| result.transpose())
+            .collect()
+    }
+
+    /// Get the commits whose branches are pushed when submitting
+    /// `commit_set`. This includes the branches that the pull requests are
+    /// based on, which may not be part of the submitted commits.
+    pub fn get_commits_to_push(&self, commit_set: &CommitSet) -> eyre::Result<CommitSet> {
+        let mut result = commit_set.clone();
+        for (commit, _branch_name) in self.get_stack(commit_set)? {
+            if let Some((base_oid, base_branch_name)) = self.get_base_branch(&commit)? {
+                if self.should_push_base_branch(base_oid, &base_branch_name)? {
+                    result = result.union(&CommitSet::from(base_oid));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn make_client(&self) -> eyre::Result<Result<GithubClient, ExitCode>> {
+        let config = self.repo.get_readonly_config()?;
+        let api_url: String = config
+            .get("branchless.submit.github.apiUrl")?
+            .unwrap_or_else(|| DEFAULT_API_URL.to_owned());
+
+        let repository: Option<String> = config.get("branchless.submit.github.repository")?;
+        let repository = match repository {
+            Some(repository) => repository
+                .split_once('/')
+                .map(|(owner, name)| (owner.to_owned(), name.to_owned())),
+            None => match get_default_remote(self.repo)? {
+                Some(remote_name) => {
+                    let url: Option<String> = config.get(format!("remote.{remote_name}.url"))?;
+                    url.as_deref().and_then(parse_github_repository)
+                }
+                None => None,
+            },
+        };
+        let (owner, name) = match repository {
+            Some(repository) => repository,
+            None => {
+                writeln!(
+                    self.effects.get_output_stream(),
+                    "\
+Could not determine the GitHub repository to create pull requests in.
+Configure it with: git config branchless.submit.github.repository <owner>/<name>"
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+
+        let token = match std::env::var(GITHUB_TOKEN_ENV_VAR) {
+            Ok(token) if !token.is_empty() => token,
+            _ => {
+                writeln!(
+                    self.effects.get_output_stream(),
+                    "\
+No GitHub token was provided, so cannot create pull requests.
+Set the {GITHUB_TOKEN_ENV_VAR} environment variable to a personal access token and try again."
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+
+        Ok(Ok(GithubClient {
+            agent: ureq::AgentBuilder::new().build(),
+            api_url,
+            owner,
+            name,
+            token,
+        }))
+    }
+
+    /// Get the name of the branch to submit for the given commit. If there
+    /// are several, the first one in sorted order is used.
+    fn get_branch_name(&self, commit_oid: NonZeroOid) -> eyre::Result<Option<String>> {
+        let reference_names = match self
+            .references_snapshot
+            .branch_oid_to_names
+            .get(&commit_oid)
+        {
+            Some(reference_names) => reference_names,
+            None => return Ok(None),
+        };
+        let branch_names: Vec<String> = reference_names
+            .iter()
+            .filter_map(
+                |reference_name| match CategorizedReferenceName::new(reference_name) {
+                    name @ CategorizedReferenceName::LocalBranch { .. } => {
+                        Some(name.remove_prefix())
+                    }
+                    CategorizedReferenceName::RemoteBranch { .. }
+                    | CategorizedReferenceName::OtherRef { .. } => None,
+                },
+            )
+            .collect::<Result<_, _>>()?;
+        Ok(branch_names.into_iter().sorted().next())
+    }
+
+    /// Get the branch that the pull request for the given commit should be
+    /// based on, and the commit it points to. This is the branch of its
+    /// nearest draft ancestor with a branch, or the public branch it's based
+    /// on if there isn't one. Returns `None` if the pull request should be
+    /// based on the main branch.
+    fn get_base_branch(&self, commit: &Commit) -> eyre::Result<Option<(NonZeroOid, String)>> {
+        let mut parent_oids = commit.get_parent_oids();
+        while let Some(parent_oid) = parent_oids.first().copied() {
+            if self.dag.is_public_commit(parent_oid)? {
+                // The stack may be based on a public branch other than the
+                // main branch, such as a release branch.
+                if !self.dag.is_main_branch_ancestor(parent_oid)? {
+                    if let Some(branch_name) = self.get_branch_name(parent_oid)? {
+                        return Ok(Some((parent_oid, branch_name)));
+                    }
+                }
+                break;
+            }
+            if let Some(branch_name) = self.get_branch_name(parent_oid)? {
+                return Ok(Some((parent_oid, branch_name)));
+            }
+            let parent = self.repo.find_commit_or_fail(parent_oid)?;
+            parent_oids = parent.get_parent_oids();
+        }
+        Ok(None)
+    }
+
+    /// Determine whether the given base branch has to be pushed before a pull
+    /// request can be based on it. Draft branches are pushed like the rest of
+    /// the stack, but public branches are only pushed if they don't exist on
+    /// the remote yet.
+    fn should_push_base_branch(
+        &self,
+        base_oid: NonZeroOid,
+        branch_name: &str,
+    ) -> eyre::Result<bool> {
+        if !self.dag.is_public_commit(base_oid)? {
+            return Ok(true);
+        }
+        match self.repo.find_branch(branch_name, BranchType::Local)? {
+            Some(branch) => Ok(branch.get_upstream_branch()?.is_none()),
+            None => Ok(false),
+        }
+    }
+
+    fn is_ancestor(&self, lhs: NonZeroOid, rhs: NonZeroOid) -> eyre::Result<bool> {
+        let result = self.dag.query().is_ancestor(lhs.into(), rhs.into())?;
+        Ok(result)
+    }
+}
+
+/// Split the commit message into the title and body of its pull request.
+fn get_title_and_body(commit: &Commit) -> eyre::Result<(String, String)> {
+    let title = commit.get_summary()?.to_string();
+    let message = commit.get_message_pretty()?.to_string();
+    let body = match message.trim().split_once("\n\n") {
+        Some((_summary, body)) => body.trim().to_owned(),
+        None => String::new(),
+    };
+    Ok((title, body))
+}
+
+/// Update the list of pull requests in the stack in the body of the pull
+/// request numbered `number`, leaving the rest of the body as-is. The list is
+/// appended to the body if it's not there yet, and removed if the pull request
+/// isn't part of a larger stack.
+fn make_pull_request_body(old_body: &str, stack_numbers: &[u64], number: u64) -> String {
+    let mut stack_section = String::new();
+    if stack_numbers.len() > 1 {
+        stack_section.push_str(STACK_SECTION_START);
+        stack_section.push_str("\nStack:\n");
+        for stack_number in stack_numbers {
+            if *stack_number == number {
+                stack_section.push_str(&format!("- #{stack_number} (this pull request)\n"));
+            } else {
+                stack_section.push_str(&format!("- #{stack_number}\n"));
+            }
+        }
+        stack_section.push_str(STACK_SECTION_END);
+    }
+
+    let existing_section = old_body.find(STACK_SECTION_START).and_then(|start| {
+        old_body[start..]
+            .find(STACK_SECTION_END)
+            .map(|end| (start, start + end + STACK_SECTION_END.len()))
+    });
+    match existing_section {
+        Some((start, end)) if stack_section.is_empty() => {
+            format!("{}{}", old_body[..start].trim_end(), &old_body[end..])
+        }
+        Some((start, end)) => format!("{}{stack_section}{}", &old_body[..start], &old_body[end..]),
+        None if stack_section.is_empty() => old_body.to_owned(),
+        None if old_body.trim().is_empty() => stack_section,
+        None => format!("{}\n\n{stack_section}", old_body.trim_end()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_github_client_debug_hides_token() {
+        let client = GithubClient {
+            agent: ureq::AgentBuilder::new().build(),
+            api_url: DEFAULT_API_URL.to_owned(),
+            owner: "owner".to_owned(),
+            name: "repo".to_owned(),
+            token: "ghp_secret_token".to_owned(),
+        };
+        let debug = format!("{client:?}");
+        assert!(!debug.contains("ghp_secret_token"), "{debug}");
+        assert_eq!(
+            debug,
+            r#"<GithubClient api_url="https://api.github.com" owner="owner" name="repo" token=not shown>"#
+        );
+    }
+}
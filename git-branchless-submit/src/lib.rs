@@ -1,28 +1,75 @@
+//! Push commits to a forge (a code review service, or just a remote
+//! repository) with `git submit`.
+
+#![allow(clippy::too_many_arguments)]
+
+mod branch_forge;
+mod branch_names;
+mod gerrit;
+mod github;
+mod phabricator;
+mod push_plan;
+
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::time::SystemTime;
 
+use clap::ValueEnum;
 use cursive_core::theme::{BaseColor, Effect, Style};
 use git_branchless_invoke::CommandContext;
-use itertools::{Either, Itertools};
+use itertools::Itertools;
 use lazy_static::lazy_static;
-use lib::core::dag::{commit_set_to_vec, Dag};
-use lib::core::effects::{Effects, OperationType};
-use lib::core::eventlog::{EventLogDb, EventReplayer};
-use lib::core::formatting::{Pluralize, StyledStringBuilder};
+use lib::core::check_out::CheckOutCommitOptions;
+use lib::core::config::get_restack_preserve_timestamps;
+use lib::core::dag::{CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
 use lib::core::repo_ext::RepoExt;
-use lib::git::{Branch, BranchType, CategorizedReferenceName, ConfigRead, GitRunInfo, Repo};
+use lib::core::rewrite::{
+    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
+    RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+};
+use lib::git::{
+    BranchType, CategorizedReferenceName, Commit, CommitSigner, ConfigRead, GitRunInfo,
+    MaybeZeroOid, NonZeroOid, Repo, RepoError,
+};
 use lib::util::ExitCode;
+use rayon::ThreadPoolBuilder;
+use tracing::instrument;
 
-use git_branchless_opts::{ResolveRevsetOptions, Revset, SubmitArgs};
+use git_branchless_opts::{ForgeKind, ResolveRevsetOptions, Revset, SubmitArgs};
 use git_branchless_revset::resolve_commits;
 
+use branch_forge::BranchForge;
+use branch_names::{create_branches_for_unbranched_commits, plan_branches_for_unbranched_commits};
+use gerrit::GerritForge;
+use github::GithubForge;
+use phabricator::PhabricatorForge;
+use push_plan::{compute_push_plan, print_push_plan};
+
 lazy_static! {
-    static ref STYLE_PUSHED: Style =
+    pub(crate) static ref STYLE_PUSHED: Style =
         Style::merge(&[BaseColor::Green.light().into(), Effect::Bold.into()]);
-    static ref STYLE_SKIPPED: Style =
+    pub(crate) static ref STYLE_SKIPPED: Style =
         Style::merge(&[BaseColor::Yellow.light().into(), Effect::Bold.into()]);
 }
 
+/// Options which apply to every forge.
+#[derive(Clone, Debug)]
+pub struct SubmitOptions {
+    /// Whether to create remote branches for local branches which aren't
+    /// associated with a remote yet.
+    pub create: bool,
+}
+
+/// A service which commits can be submitted to.
+pub trait Forge {
+    /// Push the given commits to the forge, creating or updating whatever it
+    /// uses to track them, such as branches or code reviews.
+    fn submit(&mut self, commit_set: &CommitSet, options: &SubmitOptions)
+        -> eyre::Result<ExitCode>;
+}
+
 pub fn command_main(ctx: CommandContext, args: SubmitArgs) -> eyre::Result<ExitCode> {
     let CommandContext {
         effects,
@@ -30,6 +77,10 @@ pub fn command_main(ctx: CommandContext, args: SubmitArgs) -> eyre::Result<ExitC
     } = ctx;
     let SubmitArgs {
         create,
+        auto_branch,
+        forge,
+        dry_run,
+        json,
         revset,
         resolve_revset_options,
     } = args;
@@ -39,6 +90,10 @@ pub fn command_main(ctx: CommandContext, args: SubmitArgs) -> eyre::Result<ExitC
         revset,
         &resolve_revset_options,
         create,
+        auto_branch,
+        forge,
+        dry_run,
+        json,
     )
 }
 
@@ -48,6 +103,10 @@ fn submit(
     revset: Revset,
     resolve_revset_options: &ResolveRevsetOptions,
     create: bool,
+    auto_branch: bool,
+    forge_kind: Option<ForgeKind>,
+    dry_run: bool,
+    json: bool,
 ) -> eyre::Result<ExitCode> {
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
@@ -56,7 +115,7 @@ fn submit(
     let event_tx_id = event_log_db.make_transaction_id(now, "submit")?;
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
     let event_cursor = event_replayer.make_default_cursor();
-    let references_snapshot = repo.get_references_snapshot()?;
+    let mut references_snapshot = repo.get_references_snapshot()?;
     let mut dag = Dag::open_and_sync(
         effects,
         &repo,
@@ -74,262 +133,173 @@ fn submit(
             }
         };
 
-    let branches: Vec<Branch> = commit_set_to_vec(&commit_set)?
-        .into_iter()
-        .flat_map(|commit_oid| references_snapshot.branch_oid_to_names.get(&commit_oid))
-        .flatten()
-        .filter_map(
-            |reference_name| match CategorizedReferenceName::new(reference_name) {
-                name @ CategorizedReferenceName::LocalBranch { .. } => name.remove_prefix().ok(),
-                CategorizedReferenceName::RemoteBranch { .. }
-                | CategorizedReferenceName::OtherRef { .. } => None,
-            },
-        )
-        .map(|branch_name| -> eyre::Result<Branch> {
-            let branch = repo.find_branch(&branch_name, BranchType::Local)?;
-            let branch =
-                branch.ok_or_else(|| eyre::eyre!("Could not look up branch {branch_name:?}"))?;
-            Ok(branch)
-        })
-        .collect::<Result<_, _>>()?;
-    let branches_and_remotes: Vec<(Branch, Option<String>)> = branches
-        .into_iter()
-        .map(|branch| -> eyre::Result<_> {
-            let remote_name = branch.get_push_remote_name()?;
-            Ok((branch, remote_name))
-        })
-        .collect::<Result<_, _>>()?;
-    let (branches_without_remotes, branches_with_remotes): (Vec<_>, Vec<_>) = branches_and_remotes
-        .into_iter()
-        .partition_map(|(branch, remote_name)| match remote_name {
-            None => Either::Left(branch),
-            Some(remote_name) => Either::Right((branch, remote_name)),
-        });
-    let remotes_to_branches = branches_with_remotes
-        .into_iter()
-        .map(|(v, k)| (k, v))
-        .into_group_map();
-
-    let (created_branches, uncreated_branches) = {
-        let mut branch_names: Vec<&str> = branches_without_remotes
-            .iter()
-            .map(|branch| branch.get_name())
-            .collect::<Result<_, _>>()?;
-        branch_names.sort_unstable();
-        if branches_without_remotes.is_empty() {
-            Default::default()
-        } else if create {
-            let push_remote: String = match get_default_remote(&repo)? {
-                Some(push_remote) => push_remote,
-                None => {
-                    writeln!(
-                        effects.get_output_stream(),
-                        "\
-No upstream repository was associated with {} and no value was
-specified for `remote.pushDefault`, so cannot push these branches: {}
-Configure a value with: git config remote.pushDefault <remote>
-These remotes are available: {}",
-                        CategorizedReferenceName::new(
-                            &repo.get_main_branch()?.get_reference_name()?,
-                        )
-                        .friendly_describe(),
-                        branch_names.join(", "),
-                        repo.get_all_remote_names()?.join(", "),
-                    )?;
-                    return Ok(ExitCode(1));
-                }
-            };
-
-            // This will fail if somebody else created the branch on the remote and we don't
-            // know about it.
-            let mut args = vec!["push", "--set-upstream", &push_remote];
-            args.extend(branch_names.iter());
-            {
-                let (effects, progress) = effects.start_operation(OperationType::PushBranches);
-                progress.notify_progress(0, branch_names.len());
-                let exit_code = git_run_info.run(&effects, Some(event_tx_id), &args)?;
-                if !exit_code.is_success() {
-                    return Ok(exit_code);
-                }
+    let forge_kind = match forge_kind {
+        Some(forge_kind) => forge_kind,
+        None => {
+            let forge_config_key = "branchless.submit.forge";
+            let forge_kind: Option<String> = repo.get_readonly_config()?.get(forge_config_key)?;
+            match forge_kind {
+                None => ForgeKind::Branch,
+                Some(forge_kind) => match ForgeKind::from_str(&forge_kind, true) {
+                    Ok(forge_kind) => forge_kind,
+                    Err(_) => {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Invalid value for config value {forge_config_key}: {forge_kind}"
+                        )?;
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Expected one of: {}",
+                            ForgeKind::value_variants()
+                                .iter()
+                                .filter_map(|variant| variant.to_possible_value())
+                                .map(|value| value.get_name().to_owned())
+                                .join(", ")
+                        )?;
+                        return Ok(ExitCode(1));
+                    }
+                },
             }
-            (branch_names, Default::default())
-        } else {
-            (Default::default(), branch_names)
         }
     };
 
-    // TODO: explain why fetching here.
-    let remote_names = remotes_to_branches.keys().sorted().collect_vec();
-    if !remote_names.is_empty() {
-        let remote_args = {
-            let mut result = vec!["fetch"];
-            for remote_name in &remote_names {
-                result.push(remote_name.as_str());
+    if auto_branch {
+        match forge_kind {
+            ForgeKind::Branch | ForgeKind::Github => {}
+            ForgeKind::Gerrit | ForgeKind::Phabricator => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "The --auto-branch option is only supported with --forge branch or --forge github, so it can't be used with --forge {}.",
+                    forge_kind
+                        .to_possible_value()
+                        .map(|value| value.get_name().to_owned())
+                        .unwrap_or_default(),
+                )?;
+                return Ok(ExitCode(1));
+            }
+        }
+    }
+
+    if dry_run {
+        let create = match forge_kind {
+            ForgeKind::Branch => create,
+            // The GitHub forge always creates the remote branches, since the
+            // pull requests need them.
+            ForgeKind::Github => true,
+            ForgeKind::Gerrit | ForgeKind::Phabricator => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "The --dry-run option is only supported when submitting branches, so it can't be used with --forge {}.",
+                    forge_kind
+                        .to_possible_value()
+                        .map(|value| value.get_name().to_owned())
+                        .unwrap_or_default(),
+                )?;
+                return Ok(ExitCode(1));
             }
-            result
         };
-        let exit_code = git_run_info.run(effects, Some(event_tx_id), &remote_args)?;
-        if !exit_code.is_success() {
-            writeln!(
-                effects.get_output_stream(),
-                "Failed to fetch from remotes: {}",
-                remote_names.into_iter().join(", ")
-            )?;
-            return Ok(exit_code);
+        let generated_branches = if auto_branch {
+            match plan_branches_for_unbranched_commits(
+                effects,
+                &repo,
+                &dag,
+                &event_log_db,
+                &references_snapshot,
+                &commit_set,
+            )? {
+                Ok(generated_branches) => generated_branches,
+                Err(exit_code) => return Ok(exit_code),
+            }
+        } else {
+            Vec::new()
+        };
+        let commit_set = match forge_kind {
+            ForgeKind::Github => GithubForge {
+                effects,
+                git_run_info,
+                repo: &repo,
+                dag: &dag,
+                references_snapshot: &references_snapshot,
+                event_tx_id,
+            }
+            .get_commits_to_push(&commit_set)?,
+            ForgeKind::Branch | ForgeKind::Gerrit | ForgeKind::Phabricator => commit_set,
+        };
+        let push_plan = compute_push_plan(
+            effects,
+            &repo,
+            &mut dag,
+            &references_snapshot,
+            &commit_set,
+            &generated_branches,
+            create || auto_branch,
+        )?;
+        return print_push_plan(effects, &repo, &push_plan, json);
+    }
+
+    if auto_branch {
+        match create_branches_for_unbranched_commits(
+            effects,
+            git_run_info,
+            &repo,
+            &dag,
+            &event_log_db,
+            &references_snapshot,
+            event_tx_id,
+            &commit_set,
+        )? {
+            Ok(()) => {}
+            Err(exit_code) => return Ok(exit_code),
         }
+        references_snapshot = repo.get_references_snapshot()?;
     }
 
-    let (pushed_branches, skipped_branches) = {
-        let (effects, progress) = effects.start_operation(OperationType::PushBranches);
+    let options = SubmitOptions {
+        create: create || auto_branch,
+    };
+    match forge_kind {
+        ForgeKind::Branch => BranchForge {
+            effects,
+            git_run_info,
+            repo: &repo,
+            references_snapshot: &references_snapshot,
+            event_tx_id,
+        }
+        .submit(&commit_set, &options),
 
-        let mut pushed_branches: Vec<&str> = Vec::new();
-        let mut skipped_branches: Vec<&str> = Vec::new();
-        let total_num_branches = remotes_to_branches
-            .values()
-            .map(|branches| branches.len())
-            .sum();
-        progress.notify_progress(0, total_num_branches);
-        for (remote_name, branches) in remotes_to_branches
-            .iter()
-            .sorted_by(|(k1, _v1), (k2, _v2)| k1.cmp(k2))
-        {
-            let (branches_to_push_names, branches_to_skip_names) = {
-                let mut branches_to_push_names = Vec::new();
-                let mut branches_to_skip_names = Vec::new();
-                for branch in branches {
-                    let branch_name = branch.get_name()?;
-                    if let Some(upstream_branch) = branch.get_upstream_branch()? {
-                        if upstream_branch.get_oid()? == branch.get_oid()? {
-                            branches_to_skip_names.push(branch_name);
-                            continue;
-                        }
-                    }
-                    branches_to_push_names.push(branch_name);
-                }
-                branches_to_push_names.sort_unstable();
-                branches_to_skip_names.sort_unstable();
-                (branches_to_push_names, branches_to_skip_names)
-            };
-            pushed_branches.extend(branches_to_push_names.iter());
-            skipped_branches.extend(branches_to_skip_names.iter());
+        ForgeKind::Github => GithubForge {
+            effects,
+            git_run_info,
+            repo: &repo,
+            dag: &dag,
+            references_snapshot: &references_snapshot,
+            event_tx_id,
+        }
+        .submit(&commit_set, &options),
 
-            if !pushed_branches.is_empty() {
-                let mut args = vec!["push", "--force-with-lease", remote_name];
-                args.extend(branches_to_push_names.iter());
-                let exit_code = git_run_info.run(&effects, Some(event_tx_id), &args)?;
-                if !exit_code.is_success() {
-                    writeln!(
-                        effects.get_output_stream(),
-                        "Failed to push branches: {}",
-                        branches_to_push_names.into_iter().join(", ")
-                    )?;
-                    return Ok(exit_code);
-                }
-            }
-            progress.notify_progress_inc(branches.len());
+        ForgeKind::Gerrit => GerritForge {
+            effects,
+            git_run_info,
+            repo: &repo,
+            dag: &dag,
+            event_log_db: &event_log_db,
+            event_tx_id,
         }
-        (pushed_branches, skipped_branches)
-    };
+        .submit(&commit_set, &options),
 
-    if !created_branches.is_empty() {
-        writeln!(
-            effects.get_output_stream(),
-            "Created {}: {}",
-            Pluralize {
-                determiner: None,
-                amount: created_branches.len(),
-                unit: ("branch", "branches")
-            },
-            created_branches
-                .into_iter()
-                .map(|branch_name| effects
-                    .get_glyphs()
-                    .render(
-                        StyledStringBuilder::new()
-                            .append_styled(branch_name, *STYLE_PUSHED)
-                            .build(),
-                    )
-                    .expect("Rendering branch name"))
-                .join(", ")
-        )?;
-    }
-    if !pushed_branches.is_empty() {
-        writeln!(
-            effects.get_output_stream(),
-            "Pushed {}: {}",
-            Pluralize {
-                determiner: None,
-                amount: pushed_branches.len(),
-                unit: ("branch", "branches")
-            },
-            pushed_branches
-                .into_iter()
-                .map(|branch_name| effects
-                    .get_glyphs()
-                    .render(
-                        StyledStringBuilder::new()
-                            .append_styled(branch_name, *STYLE_PUSHED)
-                            .build(),
-                    )
-                    .expect("Rendering branch name"))
-                .join(", ")
-        )?;
-    }
-    if !skipped_branches.is_empty() {
-        writeln!(
-            effects.get_output_stream(),
-            "Skipped {} (already up-to-date): {}",
-            Pluralize {
-                determiner: None,
-                amount: skipped_branches.len(),
-                unit: ("branch", "branches")
-            },
-            skipped_branches
-                .into_iter()
-                .map(|branch_name| effects
-                    .get_glyphs()
-                    .render(
-                        StyledStringBuilder::new()
-                            .append_styled(branch_name, *STYLE_SKIPPED)
-                            .build(),
-                    )
-                    .expect("Rendering branch name"))
-                .join(", ")
-        )?;
-    }
-    if !uncreated_branches.is_empty() {
-        writeln!(
-            effects.get_output_stream(),
-            "Skipped {} (not yet on remote): {}",
-            Pluralize {
-                determiner: None,
-                amount: uncreated_branches.len(),
-                unit: ("branch", "branches")
-            },
-            uncreated_branches
-                .into_iter()
-                .map(|branch_name| effects
-                    .get_glyphs()
-                    .render(
-                        StyledStringBuilder::new()
-                            .append_styled(branch_name, *STYLE_SKIPPED)
-                            .build(),
-                    )
-                    .expect("Rendering branch name"))
-                .join(", ")
-        )?;
-        writeln!(
-            effects.get_output_stream(),
-            "\
-These branches were skipped because they were not already associated with a remote repository. To
-create and push them, retry this operation with the --create option."
-        )?;
+        ForgeKind::Phabricator => PhabricatorForge {
+            effects,
+            git_run_info,
+            repo: &repo,
+            dag: &dag,
+            event_log_db: &event_log_db,
+            event_tx_id,
+        }
+        .submit(&commit_set, &options),
     }
-
-    Ok(ExitCode(0))
 }
 
-fn get_default_remote(repo: &Repo) -> eyre::Result<Option<String>> {
+pub(crate) fn get_default_remote(repo: &Repo) -> eyre::Result<Option<String>> {
     let main_branch_name = repo.get_main_branch()?.get_reference_name()?;
     match CategorizedReferenceName::new(&main_branch_name) {
         name @ CategorizedReferenceName::LocalBranch { .. } => {
@@ -357,3 +327,140 @@ fn get_default_remote(repo: &Repo) -> eyre::Result<Option<String>> {
     let push_default_remote_opt = repo.get_readonly_config()?.get("remote.pushDefault")?;
     Ok(push_default_remote_opt)
 }
+
+/// Get the name of the main branch as it's known on the remote.
+pub(crate) fn get_main_branch_remote_name(repo: &Repo) -> eyre::Result<String> {
+    let reference_name = repo.get_main_branch()?.get_reference_name()?;
+    let name = CategorizedReferenceName::new(&reference_name);
+    let branch_name = match name {
+        CategorizedReferenceName::LocalBranch { .. } => name.remove_prefix()?,
+        CategorizedReferenceName::RemoteBranch { .. } => {
+            let branch_name = name.remove_prefix()?;
+            match branch_name.split_once('/') {
+                Some((_remote_name, branch_name)) => branch_name.to_owned(),
+                None => branch_name,
+            }
+        }
+        CategorizedReferenceName::OtherRef { .. } => name.render_full(),
+    };
+    Ok(branch_name)
+}
+
+/// Replace the messages of the given commits in memory, and rebase their
+/// descendants onto the rewritten commits. Returns the rewritten commits.
+#[instrument(skip(messages))]
+pub(crate) fn reword_commits(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    messages: &[(&Commit, String)],
+) -> eyre::Result<Result<HashMap<NonZeroOid, MaybeZeroOid>, ExitCode>> {
+    if messages.is_empty() {
+        return Ok(Ok(Default::default()));
+    }
+
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits: false,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: false,
+    };
+    let commit_oids: HashSet<NonZeroOid> = messages
+        .iter()
+        .map(|(commit, _message)| commit.get_oid())
+        .collect();
+    let permissions = match RebasePlanPermissions::verify_rewrite_set(
+        dag,
+        build_options,
+        &commit_oids.into_iter().collect(),
+    )? {
+        Ok(permissions) => permissions,
+        Err(err) => {
+            err.describe(effects, repo)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let rebase_plan = {
+        let pool = ThreadPoolBuilder::new().build()?;
+        let repo_pool = RepoResource::new_pool(repo)?;
+        let mut builder = RebasePlanBuilder::new(dag, permissions);
+        let signer = CommitSigner::from_config(repo)?;
+        for (commit, message) in messages {
+            let replacement_oid = match repo.amend_commit(
+                commit,
+                None,
+                None,
+                None,
+                Some(message.as_str()),
+                None,
+                signer.as_ref(),
+            ) {
+                Ok(replacement_oid) => replacement_oid,
+                Err(RepoError::SignCommit(err)) => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "Failed to sign commit while rewording it: {err}"
+                    )?;
+                    return Ok(Err(ExitCode(1)));
+                }
+                Err(err) => return Err(err.into()),
+            };
+            builder.move_subtree(commit.get_oid(), commit.get_parent_oids())?;
+            builder.replace_commit(commit.get_oid(), replacement_oid)?;
+        }
+        match builder.build(effects, &pool, &repo_pool)? {
+            Ok(Some(rebase_plan)) => rebase_plan,
+            Ok(None) => return Ok(Ok(Default::default())),
+            Err(err) => {
+                err.describe(effects, repo)?;
+                return Ok(Err(ExitCode(1)));
+            }
+        }
+    };
+
+    let execute_options = ExecuteRebasePlanOptions {
+        now: SystemTime::now(),
+        event_tx_id,
+        preserve_timestamps: get_restack_preserve_timestamps(repo)?,
+        force_in_memory: true,
+        force_on_disk: false,
+        resolve_merge_conflicts: false,
+        create_conflict_commits: false,
+        check_out_commit_options: CheckOutCommitOptions {
+            additional_args: Default::default(),
+            reset: false,
+            render_smartlog: false,
+        },
+    };
+    match execute_rebase_plan(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        &rebase_plan,
+        &execute_options,
+    )? {
+        ExecuteRebasePlanResult::Succeeded {
+            rewritten_oids: Some(rewritten_oids),
+        } => Ok(Ok(rewritten_oids)),
+        ExecuteRebasePlanResult::Succeeded {
+            rewritten_oids: None,
+        } => {
+            eyre::bail!("BUG: rewording commits should always be done in memory");
+        }
+        ExecuteRebasePlanResult::DeclinedToMerge {
+            failed_merge_info: _,
+        } => {
+            writeln!(
+                effects.get_error_stream(),
+                "BUG: Merge failed, but rewording shouldn't cause any merge failures."
+            )?;
+            Ok(Err(ExitCode(1)))
+        }
+        ExecuteRebasePlanResult::Failed { exit_code } => Ok(Err(exit_code)),
+    }
+}
@@ -0,0 +1,303 @@
+//! Submit commits to Phabricator as Differential revisions with `arc diff`.
+//!
+//! Each commit becomes its own revision, diffed against its parent, so that a
+//! stack of commits is reviewed as a stack of revisions. The URL of a newly
+//! created revision is recorded in the `Differential Revision:` line of the
+//! commit message, as `arc diff` itself does, so that the revision is updated
+//! rather than replaced when the commit is submitted again. Revisions whose
+//! diff hasn't changed since they were last submitted are skipped.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::process::Command;
+
+use eyre::Context;
+use lib::core::dag::{sorted_commit_set, CommitSet, Dag};
+use lib::core::effects::{Effects, OperationType};
+use lib::core::eventlog::{EventLogDb, EventTransactionId};
+use lib::core::formatting::StyledStringBuilder;
+use lib::core::node_descriptors::extract_diff_number;
+use lib::git::{Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+use crate::{reword_commits, Forge, SubmitOptions, STYLE_PUSHED, STYLE_SKIPPED};
+
+/// The `arc` executable, looked up on `PATH`.
+const ARC_EXECUTABLE: &str = "arc";
+
+/// How a revision was affected by submitting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RevisionStatus {
+    Created,
+    Updated,
+    UpToDate,
+}
+
+/// The patch ID of the diff most recently submitted for each revision, which
+/// is used to skip revisions whose diff hasn't changed.
+fn get_submitted_revisions_path(repo: &Repo) -> PathBuf {
+    repo.get_path()
+        .join("branchless")
+        .join("phabricator-revisions")
+}
+
+fn load_submitted_revisions(repo: &Repo) -> eyre::Result<HashMap<String, String>> {
+    let path = get_submitted_revisions_path(repo);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Reading {path:?}")),
+    };
+    let mut result = HashMap::new();
+    for line in contents.lines() {
+        match line.split_once(' ') {
+            Some((diff_number, patch_id)) => {
+                result.insert(diff_number.to_owned(), patch_id.to_owned());
+            }
+            None => eyre::bail!("Invalid line in {:?}: {:?}", path, line),
+        }
+    }
+    Ok(result)
+}
+
+fn save_submitted_revisions(
+    repo: &Repo,
+    submitted_revisions: &HashMap<String, String>,
+) -> eyre::Result<()> {
+    let path = get_submitted_revisions_path(repo);
+    let mut contents = String::new();
+    let mut submitted_revisions: Vec<_> = submitted_revisions.iter().collect();
+    submitted_revisions.sort_unstable();
+    for (diff_number, patch_id) in submitted_revisions {
+        writeln!(contents, "{diff_number} {patch_id}")?;
+    }
+    std::fs::write(&path, contents).wrap_err_with(|| format!("Writing {path:?}"))?;
+    Ok(())
+}
+
+/// Submits each commit as a Phabricator revision.
+#[derive(Debug)]
+pub struct PhabricatorForge<'a> {
+    pub effects: &'a Effects,
+    pub git_run_info: &'a GitRunInfo,
+    pub repo: &'a Repo,
+    pub dag: &'a Dag,
+    pub event_log_db: &'a EventLogDb<'a>,
+    pub event_tx_id: EventTransactionId,
+}
+
+impl Forge for PhabricatorForge<'_> {
+    fn submit(
+        &mut self,
+        commit_set: &CommitSet,
+        _options: &SubmitOptions,
+    ) -> eyre::Result<ExitCode> {
+        let Self {
+            effects,
+            git_run_info,
+            repo,
+            dag,
+            event_log_db,
+            event_tx_id,
+        } = *self;
+
+        // Submit the commits in topological order, so that the revision for
+        // each commit's parent exists before its own revision is created.
+        let commit_set = commit_set.intersection(dag.query_draft_commits()?);
+        let commits = sorted_commit_set(repo, dag, &commit_set)?;
+
+        let mut submitted_revisions = load_submitted_revisions(repo)?;
+        let mut revisions: Vec<(&Commit, String, RevisionStatus)> = Vec::new();
+        let mut new_messages: Vec<(&Commit, String)> = Vec::new();
+        // If `arc` fails partway through, the revisions created so far still
+        // have to be recorded in their commit messages, or else they would be
+        // created again on the next submit.
+        let mut failed_exit_code = None;
+        {
+            let (effects, progress) = effects.start_operation(OperationType::SubmitRevisions);
+            progress.notify_progress(0, commits.len());
+            for commit in &commits {
+                let message = String::from_utf8_lossy(&commit.get_message_raw()?).into_owned();
+                let existing_diff_number = extract_diff_number(&message);
+                let patch_id = repo
+                    .get_patch_id(&effects, commit)?
+                    .map(|patch_id| patch_id.to_string());
+                if let (Some(diff_number), Some(patch_id)) = (&existing_diff_number, &patch_id) {
+                    if submitted_revisions.get(diff_number) == Some(patch_id) {
+                        revisions.push((commit, diff_number.clone(), RevisionStatus::UpToDate));
+                        progress.notify_progress_inc(1);
+                        continue;
+                    }
+                }
+
+                let revision_url =
+                    match self.run_arc_diff(&effects, commit, existing_diff_number.as_deref())? {
+                        Ok(revision_url) => revision_url,
+                        Err(exit_code) => {
+                            failed_exit_code = Some(exit_code);
+                            break;
+                        }
+                    };
+
+                let diff_number = match existing_diff_number {
+                    Some(diff_number) => {
+                        revisions.push((commit, diff_number.clone(), RevisionStatus::Updated));
+                        diff_number
+                    }
+                    None => {
+                        let new_message = format!(
+                            "{}\n\nDifferential Revision: {revision_url}\n",
+                            message.trim_end()
+                        );
+                        let diff_number = match extract_diff_number(&new_message) {
+                            Some(diff_number) => diff_number,
+                            None => {
+                                writeln!(
+                                    effects.get_output_stream(),
+                                    "Could not determine the revision number from the URL printed by arc: {revision_url}"
+                                )?;
+                                failed_exit_code = Some(ExitCode(1));
+                                break;
+                            }
+                        };
+                        new_messages.push((commit, new_message));
+                        revisions.push((commit, diff_number.clone(), RevisionStatus::Created));
+                        diff_number
+                    }
+                };
+                match patch_id {
+                    Some(patch_id) => submitted_revisions.insert(diff_number, patch_id),
+                    None => submitted_revisions.remove(&diff_number),
+                };
+                progress.notify_progress_inc(1);
+            }
+        }
+        save_submitted_revisions(repo, &submitted_revisions)?;
+
+        let rewritten_oids = match reword_commits(
+            effects,
+            git_run_info,
+            repo,
+            dag,
+            event_log_db,
+            event_tx_id,
+            &new_messages,
+        )? {
+            Ok(rewritten_oids) => rewritten_oids,
+            Err(exit_code) => return Ok(exit_code),
+        };
+        let get_new_oid = |commit_oid: NonZeroOid| match rewritten_oids.get(&commit_oid) {
+            Some(MaybeZeroOid::NonZero(new_oid)) => *new_oid,
+            Some(MaybeZeroOid::Zero) | None => commit_oid,
+        };
+
+        for (commit, diff_number, status) in revisions {
+            let commit = repo.find_commit_or_fail(get_new_oid(commit.get_oid()))?;
+            let (verb, suffix, style) = match status {
+                RevisionStatus::Created => ("Created", "", *STYLE_PUSHED),
+                RevisionStatus::Updated => ("Updated", "", *STYLE_PUSHED),
+                RevisionStatus::UpToDate => ("Skipped", " (already up-to-date)", *STYLE_SKIPPED),
+            };
+            writeln!(
+                effects.get_output_stream(),
+                "{verb} revision {}{suffix}: {}",
+                effects.get_glyphs().render(
+                    StyledStringBuilder::new()
+                        .append_styled(diff_number, style)
+                        .build()
+                )?,
+                effects
+                    .get_glyphs()
+                    .render(commit.friendly_describe(effects.get_glyphs())?)?,
+            )?;
+        }
+
+        Ok(failed_exit_code.unwrap_or(ExitCode(0)))
+    }
+}
+
+impl PhabricatorForge<'_> {
+    /// Run `arc diff` to create a revision for the given commit, or to update
+    /// its existing revision, and return the revision's URL.
+    #[instrument]
+    fn run_arc_diff(
+        &self,
+        effects: &Effects,
+        commit: &Commit,
+        existing_diff_number: Option<&str>,
+    ) -> eyre::Result<Result<String, ExitCode>> {
+        let base_oid = match commit.get_parent_oids().first() {
+            Some(parent_oid) => parent_oid.to_string(),
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Cannot submit root commit {} to Phabricator, since it has no parent to diff against.",
+                    effects
+                        .get_glyphs()
+                        .render(commit.friendly_describe(effects.get_glyphs())?)?,
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+        let head_oid = commit.get_oid().to_string();
+        let mut args = vec![
+            "diff",
+            "--head",
+            &head_oid,
+            "--verbatim",
+            "--no-amend",
+            "--allow-untracked",
+        ];
+        match existing_diff_number {
+            Some(diff_number) => args.extend(["--update", diff_number]),
+            None => args.push("--create"),
+        }
+        args.push(&base_oid);
+
+        let working_copy_path = self
+            .repo
+            .get_working_copy_path()
+            .unwrap_or_else(|| self.repo.get_path());
+        let output = Command::new(ARC_EXECUTABLE)
+            .args(&args)
+            .current_dir(working_copy_path)
+            .output()
+            .wrap_err_with(|| format!("Running {ARC_EXECUTABLE} {}", args.join(" ")))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() {
+            writeln!(
+                effects.get_output_stream(),
+                "Failed to submit commit {}:\n{stdout}{}",
+                effects
+                    .get_glyphs()
+                    .render(commit.friendly_describe(effects.get_glyphs())?)?,
+                String::from_utf8_lossy(&output.stderr),
+            )?;
+            let exit_code = output
+                .status
+                .code()
+                .and_then(|code| isize::try_from(code).ok())
+                .unwrap_or(1);
+            return Ok(Err(ExitCode(exit_code)));
+        }
+
+        match stdout
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Revision URI: "))
+        {
+            Some(revision_url) => Ok(Ok(revision_url.trim().to_owned())),
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Could not find the revision URL in the output of arc for commit {}:\n{stdout}",
+                    effects
+                        .get_glyphs()
+                        .render(commit.friendly_describe(effects.get_glyphs())?)?,
+                )?;
+                Ok(Err(ExitCode(1)))
+            }
+        }
+    }
+}
@@ -0,0 +1,380 @@
+//! Compute which branches `git submit` would push, and how, without pushing
+//! them, for `git submit --dry-run`.
+//!
+//! The plan is based on the remote-tracking references as of the last fetch,
+//! so it may be out of date if the remote has changed since.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use eden_dag::DagAlgorithm;
+use lib::core::dag::{sorted_commit_set, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::formatting::{Pluralize, StyledStringBuilder};
+use lib::core::repo_ext::RepoReferencesSnapshot;
+use lib::git::{BranchType, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::branch_forge::get_branches;
+use crate::branch_names::GeneratedBranch;
+use crate::{get_default_remote, STYLE_PUSHED, STYLE_SKIPPED};
+
+/// What would be done to a branch on its remote.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PushAction {
+    /// The branch doesn't exist on the remote yet, and would be created.
+    Create,
+
+    /// The remote branch would be updated to a descendant of its current
+    /// commit.
+    FastForward,
+
+    /// The remote branch would be updated to a commit which isn't a
+    /// descendant of its current commit, discarding some of its commits.
+    ForceUpdate,
+
+    /// The branch wouldn't be pushed.
+    Skip,
+}
+
+/// Why a branch wouldn't be pushed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// The upstream branch already points to the same commit.
+    UpToDate,
+
+    /// The branch isn't associated with a remote, and `--create` wasn't
+    /// passed.
+    NotOnRemote,
+
+    /// The branch would be created, but there's no remote to create it on.
+    NoRemote,
+}
+
+/// The plan for pushing a single branch.
+#[derive(Debug, Serialize)]
+pub struct BranchPushPlan {
+    /// The name of the local branch.
+    pub branch: String,
+
+    /// The remote which the branch would be pushed to, if any.
+    pub remote: Option<String>,
+
+    /// What would be done to the branch.
+    pub action: PushAction,
+
+    /// If the branch wouldn't be pushed, then why not.
+    pub skip_reason: Option<SkipReason>,
+
+    /// The commit which the local branch points to.
+    pub local_oid: String,
+
+    /// The commit which the branch's upstream branch points to, according to
+    /// its remote-tracking reference.
+    pub remote_oid: Option<String>,
+
+    /// The commits which would become reachable from the remote's branches
+    /// by pushing this branch, in topological order.
+    pub commits: Vec<String>,
+}
+
+/// The plan for pushing all of the submitted branches.
+#[derive(Debug, Serialize)]
+pub struct PushPlan {
+    /// The plan for each branch, sorted by branch name.
+    pub branches: Vec<BranchPushPlan>,
+}
+
+/// Get the commits which the remote-tracking branches of the given remote
+/// point to.
+fn get_remote_heads(repo: &Repo, remote_name: &str) -> eyre::Result<CommitSet> {
+    let remote_branch_prefix = format!("refs/remotes/{remote_name}/");
+    let mut remote_heads = Vec::new();
+    for reference in repo.get_all_references()? {
+        if reference
+            .get_name()?
+            .as_str()
+            .starts_with(&remote_branch_prefix)
+        {
+            if let Some(commit) = reference.peel_to_commit()? {
+                remote_heads.push(commit.get_oid());
+            }
+        }
+    }
+    Ok(remote_heads.into_iter().collect())
+}
+
+/// Compute the push plan for the branches attached to the commits in
+/// `commit_set`, and for the branches in `generated_branches`, which would be
+/// created by `--auto-branch`.
+///
+/// As when actually submitting, a branch is up-to-date if it points to the
+/// same commit as its upstream branch.
+#[instrument]
+pub fn compute_push_plan(
+    effects: &Effects,
+    repo: &Repo,
+    dag: &mut Dag,
+    references_snapshot: &RepoReferencesSnapshot,
+    commit_set: &CommitSet,
+    generated_branches: &[GeneratedBranch],
+    create: bool,
+) -> eyre::Result<PushPlan> {
+    let default_remote = get_default_remote(repo)?;
+    let mut remote_heads: HashMap<String, CommitSet> = HashMap::new();
+    let mut branch_plans = Vec::new();
+    for branch in get_branches(repo, references_snapshot, commit_set)? {
+        let branch_name = branch.get_name()?.to_owned();
+        let local_oid = match branch.get_oid()? {
+            Some(local_oid) => local_oid,
+            None => continue,
+        };
+
+        let (remote, skip_reason) = match branch.get_push_remote_name()? {
+            Some(remote) => (Some(remote), None),
+            None if !create => (None, Some(SkipReason::NotOnRemote)),
+            None => match &default_remote {
+                Some(default_remote) => (Some(default_remote.clone()), None),
+                None => (None, Some(SkipReason::NoRemote)),
+            },
+        };
+        let remote_name = match (remote, skip_reason) {
+            (Some(remote_name), None) => remote_name,
+            (remote, skip_reason) => {
+                branch_plans.push(BranchPushPlan {
+                    branch: branch_name,
+                    remote,
+                    action: PushAction::Skip,
+                    skip_reason,
+                    local_oid: local_oid.to_string(),
+                    remote_oid: None,
+                    commits: Default::default(),
+                });
+                continue;
+            }
+        };
+
+        let upstream_oid = match branch.get_upstream_branch()? {
+            Some(upstream_branch) => upstream_branch.get_oid()?,
+            None => None,
+        };
+        branch_plans.push(plan_branch_push(
+            effects,
+            repo,
+            dag,
+            &mut remote_heads,
+            branch_name,
+            local_oid,
+            remote_name,
+            upstream_oid,
+        )?);
+    }
+
+    for generated_branch in generated_branches {
+        let branch_name = generated_branch.branch_name.clone();
+        let local_oid = generated_branch.commit.get_oid();
+        let remote_name = match &default_remote {
+            Some(default_remote) => default_remote.clone(),
+            None => {
+                branch_plans.push(BranchPushPlan {
+                    branch: branch_name,
+                    remote: None,
+                    action: PushAction::Skip,
+                    skip_reason: Some(SkipReason::NoRemote),
+                    local_oid: local_oid.to_string(),
+                    remote_oid: None,
+                    commits: Default::default(),
+                });
+                continue;
+            }
+        };
+        let upstream_oid = match generated_branch.get_upstream_branch_name(repo)? {
+            Some(upstream_name) => match repo.find_branch(&upstream_name, BranchType::Remote)? {
+                Some(upstream_branch) => upstream_branch.get_oid()?,
+                None => None,
+            },
+            None => None,
+        };
+        branch_plans.push(plan_branch_push(
+            effects,
+            repo,
+            dag,
+            &mut remote_heads,
+            branch_name,
+            local_oid,
+            remote_name,
+            upstream_oid,
+        )?);
+    }
+
+    branch_plans.sort_by(|lhs, rhs| lhs.branch.cmp(&rhs.branch));
+    Ok(PushPlan {
+        branches: branch_plans,
+    })
+}
+
+/// Compute the plan for pushing a branch pointing to `local_oid` to
+/// `remote_name`, given the commit its upstream branch points to, if any.
+fn plan_branch_push(
+    effects: &Effects,
+    repo: &Repo,
+    dag: &mut Dag,
+    remote_heads: &mut HashMap<String, CommitSet>,
+    branch_name: String,
+    local_oid: NonZeroOid,
+    remote_name: String,
+    upstream_oid: Option<NonZeroOid>,
+) -> eyre::Result<BranchPushPlan> {
+    let (action, skip_reason) = match upstream_oid {
+        None => (PushAction::Create, None),
+        Some(upstream_oid) if upstream_oid == local_oid => {
+            (PushAction::Skip, Some(SkipReason::UpToDate))
+        }
+        Some(upstream_oid) => {
+            dag.sync_from_oids(
+                effects,
+                repo,
+                CommitSet::empty(),
+                [upstream_oid, local_oid].into_iter().collect(),
+            )?;
+            if dag
+                .query()
+                .is_ancestor(upstream_oid.into(), local_oid.into())?
+            {
+                (PushAction::FastForward, None)
+            } else {
+                (PushAction::ForceUpdate, None)
+            }
+        }
+    };
+
+    let commits = match action {
+        PushAction::Skip => Default::default(),
+        PushAction::Create | PushAction::FastForward | PushAction::ForceUpdate => {
+            let remote_heads = match remote_heads.entry(remote_name.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let heads = get_remote_heads(repo, &remote_name)?;
+                    dag.sync_from_oids(effects, repo, CommitSet::empty(), heads.clone())?;
+                    entry.insert(heads)
+                }
+            };
+            dag.sync_from_oids(
+                effects,
+                repo,
+                CommitSet::empty(),
+                CommitSet::from(local_oid),
+            )?;
+            let commits = dag
+                .query()
+                .only(CommitSet::from(local_oid), remote_heads.clone())?;
+            sorted_commit_set(repo, dag, &commits)?
+                .into_iter()
+                .map(|commit| commit.get_oid().to_string())
+                .collect()
+        }
+    };
+    Ok(BranchPushPlan {
+        branch: branch_name,
+        remote: Some(remote_name),
+        action,
+        skip_reason,
+        local_oid: local_oid.to_string(),
+        remote_oid: upstream_oid.map(|upstream_oid| upstream_oid.to_string()),
+        commits,
+    })
+}
+
+/// Print the push plan, either for humans or as JSON.
+#[instrument]
+pub fn print_push_plan(
+    effects: &Effects,
+    repo: &Repo,
+    push_plan: &PushPlan,
+    json: bool,
+) -> eyre::Result<ExitCode> {
+    if json {
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            serde_json::to_string_pretty(push_plan)?
+        )?;
+        return Ok(ExitCode(0));
+    }
+
+    let glyphs = effects.get_glyphs();
+    if push_plan.branches.is_empty() {
+        writeln!(effects.get_output_stream(), "No branches would be pushed.")?;
+    }
+    for BranchPushPlan {
+        branch,
+        remote,
+        action,
+        skip_reason,
+        local_oid: _,
+        remote_oid: _,
+        commits,
+    } in &push_plan.branches
+    {
+        let style = match action {
+            PushAction::Create | PushAction::FastForward | PushAction::ForceUpdate => *STYLE_PUSHED,
+            PushAction::Skip => *STYLE_SKIPPED,
+        };
+        let verb = match action {
+            PushAction::Create => "create",
+            PushAction::FastForward => "fast-forward",
+            PushAction::ForceUpdate => "force-update",
+            PushAction::Skip => "skip",
+        };
+        let branch_description = glyphs.render(
+            StyledStringBuilder::new()
+                .append_styled(branch.as_str(), style)
+                .build(),
+        )?;
+        let remote_description = match remote {
+            Some(remote) => format!(" on {remote}"),
+            None => String::new(),
+        };
+        let reason_description = match skip_reason {
+            None => "",
+            Some(SkipReason::UpToDate) => " (already up-to-date)",
+            Some(SkipReason::NotOnRemote) => {
+                " (not yet on remote; retry with --create to create it)"
+            }
+            Some(SkipReason::NoRemote) => {
+                " (no remote to create it on; configure one with: git config remote.pushDefault <remote>)"
+            }
+        };
+        let commits_description = if commits.is_empty() {
+            String::new()
+        } else {
+            format!(
+                ", making {} visible:",
+                Pluralize {
+                    determiner: None,
+                    amount: commits.len(),
+                    unit: ("commit", "commits"),
+                }
+            )
+        };
+        writeln!(
+            effects.get_output_stream(),
+            "Would {verb} branch {branch_description}{remote_description}{reason_description}{commits_description}"
+        )?;
+        for commit_oid in commits {
+            let commit = repo.find_commit_or_fail(commit_oid.parse()?)?;
+            writeln!(
+                effects.get_output_stream(),
+                "    {}",
+                glyphs.render(commit.friendly_describe(glyphs)?)?
+            )?;
+        }
+    }
+
+    Ok(ExitCode(0))
+}
@@ -1,6 +1,13 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
 use lib::{
     git::GitVersion,
-    testing::{make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo},
+    testing::{
+        make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions,
+        GitWrapperWithRemoteRepo,
+    },
 };
 
 /// Minimum version due to changes in the output of `git push`.
@@ -294,3 +301,1008 @@ fn test_submit_up_to_date_branch() -> eyre::Result<()> {
 
     Ok(())
 }
+
+/// A stand-in for the pull request endpoints of the GitHub REST API, which
+/// records the requests that it receives.
+#[derive(Debug, Default)]
+struct MockGithubState {
+    pull_requests: Vec<serde_json::Value>,
+    requests: Vec<String>,
+}
+
+fn decode_query_value(value: &str) -> String {
+    let mut result = Vec::new();
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'%' => {
+                let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+                result.push(u8::from_str_radix(&hex, 16).unwrap());
+            }
+            b'+' => result.push(b' '),
+            byte => result.push(byte),
+        }
+    }
+    String::from_utf8(result).unwrap()
+}
+
+fn handle_mock_github_request(
+    state: &Mutex<MockGithubState>,
+    method: &str,
+    target: &str,
+    body: &str,
+) -> (u16, serde_json::Value) {
+    let mut state = state.lock().unwrap();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let request_body: serde_json::Value = if body.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_str(body).unwrap()
+    };
+    state.requests.push(if body.is_empty() {
+        format!("{method} {}", decode_query_value(target))
+    } else {
+        format!("{method} {target} {request_body}")
+    });
+
+    let path = path.strip_prefix("/repos/owner/repo/pulls").unwrap();
+    match (method, path) {
+        ("GET", "") => {
+            let head = query
+                .split('&')
+                .find_map(|param| param.strip_prefix("head="))
+                .map(decode_query_value)
+                .unwrap();
+            let (_owner, branch_name) = head.split_once(':').unwrap();
+            let pull_requests = state
+                .pull_requests
+                .iter()
+                .filter(|pull_request| pull_request["head"]["ref"] == branch_name)
+                .cloned()
+                .collect();
+            (200, serde_json::Value::Array(pull_requests))
+        }
+
+        ("POST", "") => {
+            let number = state.pull_requests.len() + 1;
+            let pull_request = serde_json::json!({
+                "number": number,
+                "html_url": format!("https://github.com/owner/repo/pull/{number}"),
+                "title": request_body["title"],
+                "body": request_body["body"],
+                "head": { "ref": request_body["head"] },
+                "base": { "ref": request_body["base"] },
+            });
+            state.pull_requests.push(pull_request.clone());
+            (201, pull_request)
+        }
+
+        ("PATCH", number) => {
+            let number: usize = number.strip_prefix('/').unwrap().parse().unwrap();
+            let pull_request = &mut state.pull_requests[number - 1];
+            for key in ["title", "body"] {
+                if let Some(value) = request_body.get(key) {
+                    pull_request[key] = value.clone();
+                }
+            }
+            if let Some(base) = request_body.get("base") {
+                pull_request["base"]["ref"] = base.clone();
+            }
+            (200, pull_request.clone())
+        }
+
+        _ => (404, serde_json::json!({ "message": "Not Found" })),
+    }
+}
+
+/// Start serving the mock GitHub API on a local port, and return its URL.
+fn start_mock_github(state: Arc<Mutex<MockGithubState>>) -> eyre::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let api_url = format!("http://{}", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let (method, target) = {
+                let mut parts = request_line.split_whitespace();
+                (
+                    parts.next().unwrap().to_owned(),
+                    parts.next().unwrap().to_owned(),
+                )
+            };
+
+            let mut content_length = 0;
+            let mut authorization = None;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                let header = header.trim_end();
+                if header.is_empty() {
+                    break;
+                }
+                let (name, value) = header.split_once(": ").unwrap();
+                match name.to_ascii_lowercase().as_str() {
+                    "content-length" => content_length = value.parse().unwrap(),
+                    "authorization" => authorization = Some(value.to_owned()),
+                    _ => {}
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let body = String::from_utf8(body).unwrap();
+
+            let (status, response) = if authorization.as_deref() == Some("Bearer test-token") {
+                handle_mock_github_request(&state, &method, &target, &body)
+            } else {
+                (401, serde_json::json!({ "message": "Bad credentials" }))
+            };
+            let response = response.to_string();
+            write!(
+                stream,
+                "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                response.len()
+            )
+            .unwrap();
+        }
+    });
+    Ok(api_url)
+}
+
+#[test]
+fn test_submit_github() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    {
+        original_repo.init_repo()?;
+        original_repo.commit_file("test1", 1)?;
+        original_repo.commit_file("test2", 2)?;
+
+        original_repo.clone_repo_into(&cloned_repo, &[])?;
+    }
+
+    let state: Arc<Mutex<MockGithubState>> = Default::default();
+    let api_url = start_mock_github(Arc::clone(&state))?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["config", "branchless.submit.github.apiUrl", &api_url])?;
+    cloned_repo.run(&[
+        "config",
+        "branchless.submit.github.repository",
+        "owner/repo",
+    ])?;
+    cloned_repo.run(&["checkout", "-b", "foo"])?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["checkout", "-b", "bar"])?;
+    cloned_repo.commit_file("test4", 4)?;
+
+    let options = GitRunOptions {
+        env: [("GITHUB_TOKEN".to_string(), "test-token".to_string())]
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    };
+    let take_requests = || {
+        state
+            .lock()
+            .unwrap()
+            .requests
+            .drain(..)
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    {
+        let (stdout, _stderr) =
+            cloned_repo.branchless_with_options("submit", &["--forge", "github"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> push --set-upstream origin bar foo
+        branch 'bar' set up to track 'origin/bar'.
+        branch 'foo' set up to track 'origin/foo'.
+        Created 2 branches: bar, foo
+        Created pull request #1 for branch foo: https://github.com/owner/repo/pull/1
+        Created pull request #2 for branch bar: https://github.com/owner/repo/pull/2
+        "###);
+        insta::assert_snapshot!(take_requests(), @r###"
+        GET /repos/owner/repo/pulls?state=open&head=owner:foo
+        POST /repos/owner/repo/pulls {"base":"master","body":"","head":"foo","title":"create test3.txt"}
+        GET /repos/owner/repo/pulls?state=open&head=owner:bar
+        POST /repos/owner/repo/pulls {"base":"foo","body":"","head":"bar","title":"create test4.txt"}
+        PATCH /repos/owner/repo/pulls/1 {"body":"<!-- git-branchless stack start -->\nStack:\n- #1 (this pull request)\n- #2\n<!-- git-branchless stack end -->"}
+        PATCH /repos/owner/repo/pulls/2 {"body":"<!-- git-branchless stack start -->\nStack:\n- #1\n- #2 (this pull request)\n<!-- git-branchless stack end -->"}
+        "###);
+    }
+
+    // Resubmitting without changes shouldn't update anything.
+    {
+        let (stdout, _stderr) =
+            cloned_repo.branchless_with_options("submit", &["--forge", "github"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin
+        Skipped 2 branches (already up-to-date): bar, foo
+        Skipped pull request #1 for branch foo (already up-to-date): https://github.com/owner/repo/pull/1
+        Skipped pull request #2 for branch bar (already up-to-date): https://github.com/owner/repo/pull/2
+        "###);
+        insta::assert_snapshot!(take_requests(), @r###"
+        GET /repos/owner/repo/pulls?state=open&head=owner:foo
+        GET /repos/owner/repo/pulls?state=open&head=owner:bar
+        "###);
+    }
+
+    cloned_repo.run(&[
+        "commit",
+        "--amend",
+        "-m",
+        "updated message\n\nWith a description.",
+    ])?;
+    {
+        let (stdout, _stderr) =
+            cloned_repo.branchless_with_options("submit", &["--forge", "github"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin
+        branchless: running command: <git-executable> push --force-with-lease origin bar
+        Pushed 1 branch: bar
+        Skipped 1 branch (already up-to-date): foo
+        Skipped pull request #1 for branch foo (already up-to-date): https://github.com/owner/repo/pull/1
+        Updated pull request #2 for branch bar: https://github.com/owner/repo/pull/2
+        "###);
+        insta::assert_snapshot!(take_requests(), @r###"
+        GET /repos/owner/repo/pulls?state=open&head=owner:foo
+        GET /repos/owner/repo/pulls?state=open&head=owner:bar
+        PATCH /repos/owner/repo/pulls/2 {"base":"foo","title":"updated message"}
+        "###);
+    }
+
+    // Pull requests should be based on branches which haven't been pushed yet,
+    // after pushing them.
+    cloned_repo.run(&["checkout", "-b", "baz"])?;
+    cloned_repo.commit_file("test5", 5)?;
+    cloned_repo.run(&["checkout", "-b", "qux"])?;
+    cloned_repo.commit_file("test6", 6)?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless_with_options(
+            "submit",
+            &["--forge", "github", "--dry-run", "qux"],
+            &options,
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would create branch baz on origin, making 1 commit visible:
+            f68c161 create test5.txt
+        Would create branch qux on origin, making 2 commits visible:
+            f68c161 create test5.txt
+            28d9bba create test6.txt
+        "###);
+        insta::assert_snapshot!(take_requests(), @"");
+    }
+    {
+        let (stdout, _stderr) = cloned_repo.branchless_with_options(
+            "submit",
+            &["--forge", "github", "qux"],
+            &options,
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> push --set-upstream origin baz qux
+        branch 'baz' set up to track 'origin/baz'.
+        branch 'qux' set up to track 'origin/qux'.
+        Created 2 branches: baz, qux
+        Created pull request #3 for branch qux: https://github.com/owner/repo/pull/3
+        "###);
+        insta::assert_snapshot!(take_requests(), @r###"
+        GET /repos/owner/repo/pulls?state=open&head=owner:qux
+        POST /repos/owner/repo/pulls {"base":"baz","body":"","head":"qux","title":"create test6.txt"}
+        "###);
+    }
+
+    // Edits to the pull request body outside of the stack section should be
+    // kept when the stack section is updated.
+    {
+        let mut state = state.lock().unwrap();
+        let body = state.pull_requests[0]["body"].as_str().unwrap().to_owned();
+        state.pull_requests[0]["body"] =
+            format!("Edited on GitHub.\n\n{body}\nMore edits.\n").into();
+    }
+    {
+        let (stdout, _stderr) =
+            cloned_repo.branchless_with_options("submit", &["--forge", "github"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin
+        Skipped 4 branches (already up-to-date): bar, baz, foo, qux
+        Updated pull request #1 for branch foo: https://github.com/owner/repo/pull/1
+        Updated pull request #2 for branch bar: https://github.com/owner/repo/pull/2
+        Created pull request #4 for branch baz: https://github.com/owner/repo/pull/4
+        Updated pull request #3 for branch qux: https://github.com/owner/repo/pull/3
+        "###);
+        let _ = take_requests();
+        let body = state.lock().unwrap().pull_requests[0]["body"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        insta::assert_snapshot!(body, @r###"
+        Edited on GitHub.
+
+        <!-- git-branchless stack start -->
+        Stack:
+        - #1 (this pull request)
+        - #2
+        - #4
+        - #3
+        <!-- git-branchless stack end -->
+        More edits.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless_with_options(
+            "submit",
+            &["--forge", "github"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No GitHub token was provided, so cannot create pull requests.
+        Set the GITHUB_TOKEN environment variable to a personal access token and try again.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_gerrit() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    {
+        original_repo.init_repo()?;
+        original_repo.commit_file("test1", 1)?;
+        original_repo.commit_file("test2", 2)?;
+
+        original_repo.clone_repo_into(&cloned_repo, &[])?;
+    }
+
+    // Stand in for Gerrit by recording each pushed commit under its change ID,
+    // rather than keeping the `refs/for/*` reference.
+    {
+        let hook_path = original_repo
+            .repo_path
+            .join(".git")
+            .join("hooks")
+            .join("post-receive");
+        std::fs::write(
+            &hook_path,
+            r#"#!/bin/sh
+while read old_oid new_oid ref_name; do
+    case "$ref_name" in
+    refs/for/*)
+        for commit_oid in $(git rev-list "$new_oid" --not --branches); do
+            change_id=$(git log -1 --format='%(trailers:key=Change-Id,valueonly)' "$commit_oid")
+            git update-ref "refs/changes/$change_id" "$commit_oid"
+        done
+        git update-ref -d "$ref_name"
+        ;;
+    esac
+done
+"#,
+        )?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755))?;
+        }
+    }
+
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["config", "branchless.submit.forge", "gerrit"])?;
+    cloned_repo.detach_head()?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.commit_file("test4", 4)?;
+
+    let get_changes = || -> eyre::Result<String> {
+        let (stdout, _stderr) = original_repo.run(&[
+            "for-each-ref",
+            "--format=%(refname) %(subject)",
+            "refs/changes",
+            "refs/for",
+        ])?;
+        Ok(stdout)
+    };
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: b5b2a7e create test3.txt
+        [2/2] Committed as: 725a90b create test4.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout 725a90b38a5ad3351fef62add2ee25aa600d9eec
+        In-memory rebase succeeded.
+        branchless: running command: <git-executable> push origin 725a90b38a5ad3351fef62add2ee25aa600d9eec:refs/for/master
+        Created change I47d6ce045fbf0511edc9889f4c33a0389091f9f2: b5b2a7e create test3.txt
+        Created change I6dea61d75a887ddd3bd844cc1e6de2f2b04f08ad: 725a90b create test4.txt
+        "###);
+        insta::assert_snapshot!(get_changes()?, @r###"
+        refs/changes/I47d6ce045fbf0511edc9889f4c33a0389091f9f2 create test3.txt
+        refs/changes/I6dea61d75a887ddd3bd844cc1e6de2f2b04f08ad create test4.txt
+        "###);
+
+        let (stdout, _stderr) = cloned_repo.run(&["log", "-1", "--format=%B"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test4.txt
+
+        Change-Id: I6dea61d75a887ddd3bd844cc1e6de2f2b04f08ad
+
+        "###);
+    }
+
+    // Rewording a commit drops its `Change-Id`, but it should be restored from
+    // the original commit, so that the existing change is updated.
+    cloned_repo.run(&["commit", "--amend", "-m", "updated message"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 93a0f4e updated message
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 93a0f4e867c03dbe35ee4d26e0dca751457e384f
+        In-memory rebase succeeded.
+        branchless: running command: <git-executable> push origin 93a0f4e867c03dbe35ee4d26e0dca751457e384f:refs/for/master
+        Skipped change I47d6ce045fbf0511edc9889f4c33a0389091f9f2 (already up-to-date): b5b2a7e create test3.txt
+        Updated change I6dea61d75a887ddd3bd844cc1e6de2f2b04f08ad: 93a0f4e updated message
+        "###);
+        insta::assert_snapshot!(get_changes()?, @r###"
+        refs/changes/I47d6ce045fbf0511edc9889f4c33a0389091f9f2 create test3.txt
+        refs/changes/I6dea61d75a887ddd3bd844cc1e6de2f2b04f08ad updated message
+        "###);
+    }
+
+    // Resubmitting without changes shouldn't push anything.
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Skipped change I47d6ce045fbf0511edc9889f4c33a0389091f9f2 (already up-to-date): b5b2a7e create test3.txt
+        Skipped change I6dea61d75a887ddd3bd844cc1e6de2f2b04f08ad (already up-to-date): 93a0f4e updated message
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_phabricator() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    // Stand in for `arc` with a script which logs how it was invoked and
+    // hands out sequential revision numbers.
+    let arc_dir = git.repo_path.join(".git").join("fake-arc");
+    let log_path = arc_dir.join("log");
+    {
+        std::fs::create_dir_all(&arc_dir)?;
+        let arc_path = arc_dir.join("arc");
+        std::fs::write(
+            &arc_path,
+            format!(
+                r#"#!/bin/sh
+echo "arc $*" >> '{log_path}'
+next_id_path='{next_id_path}'
+id=
+head=
+while [ $# -gt 0 ]; do
+    case "$1" in
+    --head)
+        head="$2"
+        shift
+        ;;
+    --update)
+        id="${{2#D}}"
+        shift
+        ;;
+    esac
+    shift
+done
+if [ "$head" = "$(cat '{fail_head_path}' 2>/dev/null)" ]; then
+    echo "Usage Exception: failed to submit $head" >&2
+    exit 1
+fi
+if [ -z "$id" ]; then
+    id=$(cat "$next_id_path" 2>/dev/null || echo 1)
+    echo $((id + 1)) > "$next_id_path"
+    echo "Created a new Differential revision:"
+else
+    echo "Updated an existing Differential revision:"
+fi
+echo "        Revision URI: https://phabricator.example.com/D$id"
+"#,
+                log_path = log_path.display(),
+                next_id_path = arc_dir.join("next-id").display(),
+                fail_head_path = arc_dir.join("fail-head").display(),
+            ),
+        )?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&arc_path, std::fs::Permissions::from_mode(0o755))?;
+        }
+    }
+
+    let fail_head_path = arc_dir.join("fail-head");
+    let mut path = arc_dir.into_os_string();
+    path.push(":");
+    path.push(git.get_path_for_env());
+    let options = GitRunOptions {
+        env: [("PATH".to_string(), path.into_string().unwrap())]
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    };
+    let take_log = || -> eyre::Result<String> {
+        let log = std::fs::read_to_string(&log_path)?;
+        std::fs::remove_file(&log_path)?;
+        Ok(log)
+    };
+
+    {
+        let (stdout, _stderr) =
+            git.branchless_with_options("submit", &["--forge", "phabricator"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 64f94c0 create test1.txt
+        [2/2] Committed as: 2420a75 create test2.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout 2420a75d349ff7147038e61475078e977390744a
+        In-memory rebase succeeded.
+        Created revision D1: 64f94c0 create test1.txt
+        Created revision D2: 2420a75 create test2.txt
+        "###);
+        insta::assert_snapshot!(take_log()?, @r###"
+        arc diff --head 62fc20d2a290daea0d52bdc2ed2ad4be6491010e --verbatim --no-amend --allow-untracked --create f777ecc9b0db5ed372b2615695191a8a17f79f24
+        arc diff --head 96d1c37a3d4363611c49f7e52186e189a04c531f --verbatim --no-amend --allow-untracked --create 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        "###);
+
+        let (stdout, _stderr) = git.run(&["log", "--format=%B", "HEAD~2..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+
+        Differential Revision: https://phabricator.example.com/D2
+
+        create test1.txt
+
+        Differential Revision: https://phabricator.example.com/D1
+
+        "###);
+    }
+
+    // Resubmitting should update the existing revisions rather than creating
+    // new ones, and skip the revisions whose diff hasn't changed.
+    git.write_file_txt("test2", "updated contents")?;
+    git.run(&["commit", "--amend", "--all", "--no-edit"])?;
+    {
+        let (stdout, _stderr) =
+            git.branchless_with_options("submit", &["--forge", "phabricator"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Skipped revision D1 (already up-to-date): 64f94c0 create test1.txt
+        Updated revision D2: ad93f60 create test2.txt
+        "###);
+        insta::assert_snapshot!(take_log()?, @r###"
+        arc diff --head ad93f60553895edaf02ba141bebb57c0762ad9af --verbatim --no-amend --allow-untracked --update D2 64f94c0d1bf502d85a2ccbe61b7e9456e1d477c0
+        "###);
+    }
+
+    // If `arc` fails, the revisions created before the failure should still
+    // be recorded in their commit messages.
+    git.commit_file("test3", 3)?;
+    let test4_oid = git.commit_file("test4", 4)?;
+    std::fs::write(&fail_head_path, test4_oid.to_string())?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "submit",
+            &["--forge", "phabricator"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                env: options.env.clone(),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Failed to submit commit d171d07 create test4.txt:
+        Usage Exception: failed to submit d171d0784077fe8ba2ea587a89622921657d12f5
+
+        Attempting rebase in-memory...
+        [1/2] Committed as: 27bf153 create test3.txt
+        [2/2] Committed as: 21e0e8f create test4.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout 21e0e8f08257db91964baaa98746ffa6ce221af3
+        In-memory rebase succeeded.
+        Skipped revision D1 (already up-to-date): 64f94c0 create test1.txt
+        Skipped revision D2 (already up-to-date): ad93f60 create test2.txt
+        Created revision D3: 27bf153 create test3.txt
+        "###);
+        insta::assert_snapshot!(take_log()?, @r###"
+        arc diff --head a1f539ac0f1cfb3e5bf6c1ccc0157388e95d1631 --verbatim --no-amend --allow-untracked --create ad93f60553895edaf02ba141bebb57c0762ad9af
+        arc diff --head d171d0784077fe8ba2ea587a89622921657d12f5 --verbatim --no-amend --allow-untracked --create a1f539ac0f1cfb3e5bf6c1ccc0157388e95d1631
+        "###);
+
+        let (stdout, _stderr) = git.run(&["log", "--format=%B", "HEAD~2..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test4.txt
+
+        create test3.txt
+
+        Differential Revision: https://phabricator.example.com/D3
+
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_dry_run() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    {
+        original_repo.init_repo()?;
+        original_repo.commit_file("test1", 1)?;
+        original_repo.commit_file("test2", 2)?;
+
+        original_repo.clone_repo_into(&cloned_repo, &[])?;
+    }
+
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["checkout", "-b", "foo"])?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["checkout", "-b", "bar"])?;
+    cloned_repo.commit_file("test4", 4)?;
+    cloned_repo.run(&["checkout", "-b", "baz"])?;
+    cloned_repo.commit_file("test5", 5)?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &["--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would skip branch bar (not yet on remote; retry with --create to create it)
+        Would skip branch baz (not yet on remote; retry with --create to create it)
+        Would skip branch foo (not yet on remote; retry with --create to create it)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &["--dry-run", "--create"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would create branch bar on origin, making 2 commits visible:
+            70deb1e create test3.txt
+            355e173 create test4.txt
+        Would create branch baz on origin, making 3 commits visible:
+            70deb1e create test3.txt
+            355e173 create test4.txt
+            f81d55c create test5.txt
+        Would create branch foo on origin, making 1 commit visible:
+            70deb1e create test3.txt
+        "###);
+    }
+
+    cloned_repo.branchless("submit", &["--create"])?;
+    cloned_repo.run(&["checkout", "bar"])?;
+    cloned_repo.run(&["commit", "--amend", "-m", "amended test4"])?;
+    cloned_repo.run(&["branch", "-f", "foo", "bar"])?;
+    cloned_repo.run(&["checkout", "baz"])?;
+    cloned_repo.run(&["checkout", "-b", "qux"])?;
+    cloned_repo.commit_file("test6", 6)?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &["--dry-run", "--create"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would force-update branch bar on origin, making 1 commit visible:
+            38acdce amended test4
+        Would skip branch baz on origin (already up-to-date)
+        Would fast-forward branch foo on origin, making 1 commit visible:
+            38acdce amended test4
+        Would create branch qux on origin, making 1 commit visible:
+            2831fb5 create test6.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            cloned_repo.branchless("submit", &["--dry-run", "--create", "--json"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "branches": [
+            {
+              "branch": "bar",
+              "remote": "origin",
+              "action": "force-update",
+              "skip_reason": null,
+              "local_oid": "38acdcec3413e6927dc738dae8d45efdbaef94cb",
+              "remote_oid": "355e173bf9c5d2efac2e451da0cdad3fb82b869a",
+              "commits": [
+                "38acdcec3413e6927dc738dae8d45efdbaef94cb"
+              ]
+            },
+            {
+              "branch": "baz",
+              "remote": "origin",
+              "action": "skip",
+              "skip_reason": "up-to-date",
+              "local_oid": "f81d55c0d520ff8d02ef9294d95156dcb78a5255",
+              "remote_oid": "f81d55c0d520ff8d02ef9294d95156dcb78a5255",
+              "commits": []
+            },
+            {
+              "branch": "foo",
+              "remote": "origin",
+              "action": "fast-forward",
+              "skip_reason": null,
+              "local_oid": "38acdcec3413e6927dc738dae8d45efdbaef94cb",
+              "remote_oid": "70deb1e28791d8e7dd5a1f0c871a51b91282562f",
+              "commits": [
+                "38acdcec3413e6927dc738dae8d45efdbaef94cb"
+              ]
+            },
+            {
+              "branch": "qux",
+              "remote": "origin",
+              "action": "create",
+              "skip_reason": null,
+              "local_oid": "2831fb5864ee099dc3e448a38dcb3c8527149510",
+              "remote_oid": null,
+              "commits": [
+                "2831fb5864ee099dc3e448a38dcb3c8527149510"
+              ]
+            }
+          ]
+        }
+        "###);
+    }
+
+    // The upstream branch determines whether a branch is up-to-date, even if
+    // it's not the branch of the same name on the push remote.
+    cloned_repo.run(&["branch", "--set-upstream-to", "origin/foo", "baz"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &["--dry-run", "baz"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would fast-forward branch baz on origin
+        "###);
+    }
+
+    // Nothing should have been pushed.
+    {
+        let (stdout, _stderr) = original_repo.run(&["branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+          bar
+          baz
+          foo
+        * master
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_auto_branch() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    {
+        original_repo.init_repo()?;
+        original_repo.commit_file("test1", 1)?;
+        original_repo.commit_file("test2", 2)?;
+
+        original_repo.clone_repo_into(&cloned_repo, &[])?;
+    }
+
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) =
+            cloned_repo.branchless("submit", &["--auto-branch", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would create branch test/create-test3-txt on origin, making 1 commit visible:
+            70deb1e create test3.txt
+        Would create branch test/create-test4-txt on origin, making 2 commits visible:
+            70deb1e create test3.txt
+            355e173 create test4.txt
+        "###);
+    }
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &["--auto-branch"])?;
+        let stdout = redact_remotes(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> branch test/create-test3-txt 70deb1e28791d8e7dd5a1f0c871a51b91282562f
+        branchless: running command: <git-executable> branch test/create-test4-txt 355e173bf9c5d2efac2e451da0cdad3fb82b869a
+        branchless: running command: <git-executable> push --set-upstream origin test/create-test3-txt test/create-test4-txt
+        branch 'test/create-test3-txt' set up to track 'origin/test/create-test3-txt'.
+        branch 'test/create-test4-txt' set up to track 'origin/test/create-test4-txt'.
+        Created 2 branches: test/create-test3-txt, test/create-test4-txt
+        "###);
+    }
+
+    // The names should be kept even if the branches are deleted and the
+    // commits are rewritten.
+    cloned_repo.run(&[
+        "branch",
+        "-D",
+        "test/create-test3-txt",
+        "test/create-test4-txt",
+    ])?;
+    cloned_repo.run(&["commit", "--amend", "-m", "updated message"])?;
+    {
+        let (stdout, _stderr) =
+            cloned_repo.branchless("submit", &["--auto-branch", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would skip branch test/create-test3-txt on origin (already up-to-date)
+        Would force-update branch test/create-test4-txt on origin, making 1 commit visible:
+            379c2ab updated message
+        "###);
+    }
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &["--auto-branch"])?;
+        let stdout = redact_remotes(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> branch test/create-test3-txt 70deb1e28791d8e7dd5a1f0c871a51b91282562f
+        branchless: running command: <git-executable> branch --set-upstream-to origin/test/create-test3-txt test/create-test3-txt
+        branch 'test/create-test3-txt' set up to track 'origin/test/create-test3-txt'.
+        branchless: running command: <git-executable> branch test/create-test4-txt 379c2ab6166538420119cbfa1d95a6d10451baf4
+        branchless: running command: <git-executable> branch --set-upstream-to origin/test/create-test4-txt test/create-test4-txt
+        branch 'test/create-test4-txt' set up to track 'origin/test/create-test4-txt'.
+        branchless: running command: <git-executable> fetch origin
+        branchless: running command: <git-executable> push --force-with-lease origin test/create-test4-txt
+        Pushed 1 branch: test/create-test4-txt
+        Skipped 1 branch (already up-to-date): test/create-test3-txt
+        "###);
+    }
+
+    cloned_repo.run(&[
+        "config",
+        "branchless.submit.branchNameTemplate",
+        "{stack}/{index}",
+    ])?;
+    cloned_repo.commit_file("test5", 5)?;
+    cloned_repo.commit_file("test6", 6)?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &["--auto-branch"])?;
+        let stdout = redact_remotes(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> branch create-test3-txt/3 1b85823d15e01634900837a97350c8d04fc6035e
+        branchless: running command: <git-executable> branch create-test3-txt/4 3f530c317f9815f24445ab093aeb5715fef6dfbf
+        branchless: running command: <git-executable> push --set-upstream origin create-test3-txt/3 create-test3-txt/4
+        branch 'create-test3-txt/3' set up to track 'origin/create-test3-txt/3'.
+        branch 'create-test3-txt/4' set up to track 'origin/create-test3-txt/4'.
+        branchless: running command: <git-executable> fetch origin
+        Created 2 branches: create-test3-txt/3, create-test3-txt/4
+        Skipped 2 branches (already up-to-date): test/create-test3-txt, test/create-test4-txt
+        "###);
+    }
+
+    cloned_repo.run(&[
+        "config",
+        "branchless.submit.branchNameTemplate",
+        "{author}/{summary_slug}",
+    ])?;
+    cloned_repo.commit_file("test7", 7)?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless_with_options(
+            "submit",
+            &["--auto-branch"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Invalid placeholder in config value branchless.submit.branchNameTemplate: {author}
+        Expected one of: {user}, {summary_slug}, {stack}, {index}
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_auto_branch_prunes_names() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    {
+        original_repo.init_repo()?;
+        original_repo.commit_file("test1", 1)?;
+        original_repo.commit_file("test2", 2)?;
+
+        original_repo.clone_repo_into(&cloned_repo, &[])?;
+    }
+
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.commit_file("test4", 4)?;
+    cloned_repo.branchless("submit", &["--auto-branch"])?;
+
+    let branch_names_path = cloned_repo
+        .repo_path
+        .join(".git")
+        .join("branchless")
+        .join("submit-branch-names");
+    {
+        let branch_names = std::fs::read_to_string(&branch_names_path)?;
+        insta::assert_snapshot!(branch_names, @r###"
+        355e173bf9c5d2efac2e451da0cdad3fb82b869a test/create-test4-txt
+        70deb1e28791d8e7dd5a1f0c871a51b91282562f test/create-test3-txt
+        "###);
+    }
+
+    // The name for the hidden commit should be dropped the next time the
+    // names are saved.
+    cloned_repo.branchless("hide", &["--delete-branches", "HEAD"])?;
+    cloned_repo.run(&["checkout", "HEAD^"])?;
+    cloned_repo.commit_file("test5", 5)?;
+    cloned_repo.branchless("submit", &["--auto-branch"])?;
+    {
+        let branch_names = std::fs::read_to_string(&branch_names_path)?;
+        insta::assert_snapshot!(branch_names, @r###"
+        70deb1e28791d8e7dd5a1f0c871a51b91282562f test/create-test3-txt
+        9ea1b3689df0287d75a3df9924b0ae06671d96c2 test/create-test5-txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -50,9 +50,10 @@ use lib::core::rewrite::{
 };
 use lib::git::{
     get_latest_test_command_path, get_test_locks_dir, get_test_tree_dir, get_test_worktrees_dir,
-    make_test_command_slug, Commit, ConfigRead, GitRunInfo, GitRunResult, MaybeZeroOid, NonZeroOid,
-    Repo, SerializedNonZeroOid, SerializedTestResult, WorkingCopyChangesType, TEST_ABORT_EXIT_CODE,
-    TEST_INDETERMINATE_EXIT_CODE, TEST_SUCCESS_EXIT_CODE,
+    make_test_command_slug, Commit, CommitSigner, ConfigRead, GitRunInfo, GitRunResult,
+    MaybeZeroOid, NonZeroOid, Repo, SerializedNonZeroOid, SerializedTestResult,
+    WorkingCopyChangesType, TEST_ABORT_EXIT_CODE, TEST_INDETERMINATE_EXIT_CODE,
+    TEST_SUCCESS_EXIT_CODE,
 };
 use lib::util::{get_sh, ExitCode};
 use rayon::ThreadPoolBuilder;
@@ -379,6 +380,7 @@ BUG: Expected resolved_interactive ({resolved_interactive:?}) to match interacti
                 force_on_disk,
                 detect_duplicate_commits_via_patch_id,
                 resolve_merge_conflicts,
+                create_conflict_commits,
                 dump_rebase_constraints,
                 dump_rebase_plan,
             } = move_options;
@@ -405,6 +407,7 @@ BUG: Expected resolved_interactive ({resolved_interactive:?}) to match interacti
                 force_in_memory,
                 force_on_disk: *force_on_disk,
                 resolve_merge_conflicts: *resolve_merge_conflicts,
+                create_conflict_commits: *create_conflict_commits,
                 check_out_commit_options: CheckOutCommitOptions {
                     render_smartlog: false,
                     ..Default::default()
@@ -758,6 +761,7 @@ fn set_abort_trap(
             force_in_memory: false,
             force_on_disk: true,
             resolve_merge_conflicts: false,
+            create_conflict_commits: false,
             check_out_commit_options: CheckOutCommitOptions {
                 render_smartlog: false,
                 ..Default::default()
@@ -1890,6 +1894,8 @@ fn apply_fixes(
         })
         .collect();
 
+    let signer = CommitSigner::from_config(repo)?;
+
     #[derive(Debug)]
     struct Fix {
         original_commit_oid: NonZeroOid,
@@ -1921,6 +1927,7 @@ fn apply_fixes(
                 commit_message,
                 &fixed_tree,
                 parents.iter().collect(),
+                signer.as_ref(),
             )?;
             if original_commit_oid == fixed_commit_oid {
                 continue;
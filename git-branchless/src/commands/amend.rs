@@ -24,11 +24,11 @@ use lib::core::gc::mark_commit_reachable;
 use lib::core::repo_ext::RepoExt;
 use lib::core::rewrite::{
     execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+    RebasePlanBuilder, RebasePlanPermissions, RepoResource, Rerere,
 };
 use lib::git::{
-    AmendFastOptions, CategorizedReferenceName, GitRunInfo, MaybeZeroOid, Repo,
-    ResolvedReferenceInfo,
+    AmendFastOptions, CategorizedReferenceName, CommitSigner, GitRunInfo, MaybeZeroOid, Repo,
+    RepoError, ResolvedReferenceInfo,
 };
 use lib::util::ExitCode;
 use rayon::ThreadPoolBuilder;
@@ -97,6 +97,14 @@ pub fn amend(
     };
 
     let event_tx_id = event_log_db.make_transaction_id(now, "amend")?;
+    // Like `git commit`, record the resolutions of any conflicts which were
+    // written to the working copy, such as by `git branchless resolve`.
+    if let Some(rerere) = Rerere::from_config(git_run_info, &repo)? {
+        let exit_code = rerere.record(effects, &repo, event_tx_id, false)?;
+        if !exit_code.is_success() {
+            return Ok(exit_code);
+        }
+    }
     let (snapshot, status) =
         repo.get_status(effects, git_run_info, &index, &head_info, Some(event_tx_id))?;
     {
@@ -157,13 +165,26 @@ pub fn amend(
         )
     };
 
-    let amended_commit_oid = head_commit.amend_commit(
+    let signer = CommitSigner::from_config(&repo)?;
+    let amended_commit_oid = match repo.amend_commit(
+        &head_commit,
         None,
         Some(&author),
         Some(&committer),
         None,
         Some(&amended_tree),
-    )?;
+        signer.as_ref(),
+    ) {
+        Ok(amended_commit_oid) => amended_commit_oid,
+        Err(RepoError::SignCommit(err)) => {
+            writeln!(
+                effects.get_error_stream(),
+                "Failed to sign amended commit: {err}"
+            )?;
+            return Ok(ExitCode(1));
+        }
+        Err(err) => return Err(err.into()),
+    };
     mark_commit_reachable(&repo, amended_commit_oid)
         .wrap_err("Marking commit as reachable for GC purposes.")?;
     event_log_db.add_events(vec![Event::RewriteEvent {
@@ -269,6 +290,7 @@ pub fn amend(
                     descendant_message,
                     &descendant_commit.get_tree()?,
                     parents.iter().collect(),
+                    signer.as_ref(),
                 )?;
                 builder.replace_commit(descendant_oid, reparented_descendant_oid)?;
             }
@@ -293,6 +315,7 @@ pub fn amend(
             force_on_disk: move_options.force_on_disk,
             preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
             resolve_merge_conflicts: move_options.resolve_merge_conflicts,
+            create_conflict_commits: move_options.create_conflict_commits,
             check_out_commit_options: CheckOutCommitOptions {
                 additional_args: Default::default(),
                 reset: true,
@@ -21,14 +21,15 @@ use lib::core::formatting::Glyphs;
 use lib::core::node_descriptors::{
     BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
     DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
-    RelativeTimeDescriptor,
+    RelativeTimeDescriptor, RemoteStatusDescriptor,
 };
 use lib::git::{GitRunInfo, Repo, ResolvedReferenceInfo};
 
 use git_branchless_init::{determine_hook_path, Hook, ALL_HOOKS};
 
-fn redact_event(redactor: &Redactor, event: &Event) -> String {
-    let event = match event.clone() {
+/// Redact any branch names in the provided event.
+pub(crate) fn redact_event(redactor: &Redactor, event: &Event) -> Event {
+    match event.clone() {
         // Explicitly list all variants and fields here so we're forced to audit it if we add any.
         event @ (Event::RewriteEvent {
             timestamp: _,
@@ -87,9 +88,7 @@ fn redact_event(redactor: &Redactor, event: &Event) -> String {
                 ref_name,
             }
         }
-    };
-
-    format!("{event:?}")
+    }
 }
 
 fn describe_event_cursor(
@@ -120,7 +119,7 @@ fn describe_event_cursor(
             lines.extend(
                 events
                     .iter()
-                    .map(|event| format!("1. `{}`", redact_event(redactor, event))),
+                    .map(|event| format!("1. `{:?}`", redact_event(redactor, event))),
             );
             lines
         }
@@ -145,6 +144,7 @@ fn describe_event_cursor(
             &mut RelativeTimeDescriptor::new(repo, now)?,
             &mut ObsolescenceExplanationDescriptor::new(event_replayer, event_cursor)?,
             &mut BranchesDescriptor::new(repo, head_info, references_snapshot, redactor)?,
+            &mut RemoteStatusDescriptor::new(repo, references_snapshot, redactor)?,
             &mut DifferentialRevisionDescriptor::new(repo, redactor)?,
             &mut CommitMessageDescriptor::new(redactor)?,
         ],
@@ -0,0 +1,371 @@
+//! Export the event log to a file and import it again, such as to preserve undo
+//! history when moving a repository to another machine.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use eyre::Context;
+use itertools::Itertools;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventTransactionId};
+use lib::core::formatting::Pluralize;
+use lib::core::node_descriptors::Redactor;
+use lib::core::repo_ext::RepoExt;
+use lib::git::{GitRunInfo, ReferenceName, Repo};
+use lib::util::ExitCode;
+use serde::{Deserialize, Serialize};
+
+use super::bug_report::redact_event;
+
+/// The version of the exported file format. Increment this when making
+/// backwards-incompatible changes to the format.
+const EVENT_LOG_FILE_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct EventLogFile {
+    version: u32,
+    transactions: Vec<SerializedTransaction>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct SerializedTransaction {
+    message: String,
+    events: Vec<SerializedEvent>,
+}
+
+/// An `Event` without its transaction ID, which is specific to the event log it
+/// was exported from.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SerializedEvent {
+    Rewrite {
+        timestamp: f64,
+        old_commit_oid: String,
+        new_commit_oid: String,
+    },
+    RefUpdate {
+        timestamp: f64,
+        ref_name: String,
+        old_oid: String,
+        new_oid: String,
+        message: Option<String>,
+    },
+    Commit {
+        timestamp: f64,
+        commit_oid: String,
+    },
+    Obsolete {
+        timestamp: f64,
+        commit_oid: String,
+    },
+    Unobsolete {
+        timestamp: f64,
+        commit_oid: String,
+    },
+    WorkingCopySnapshot {
+        timestamp: f64,
+        head_oid: String,
+        commit_oid: String,
+        ref_name: Option<String>,
+    },
+}
+
+impl SerializedEvent {
+    fn get_timestamp(&self) -> f64 {
+        match self {
+            SerializedEvent::Rewrite { timestamp, .. }
+            | SerializedEvent::RefUpdate { timestamp, .. }
+            | SerializedEvent::Commit { timestamp, .. }
+            | SerializedEvent::Obsolete { timestamp, .. }
+            | SerializedEvent::Unobsolete { timestamp, .. }
+            | SerializedEvent::WorkingCopySnapshot { timestamp, .. } => *timestamp,
+        }
+    }
+
+    fn into_event(self, event_tx_id: EventTransactionId) -> eyre::Result<Event> {
+        let event = match self {
+            SerializedEvent::Rewrite {
+                timestamp,
+                old_commit_oid,
+                new_commit_oid,
+            } => Event::RewriteEvent {
+                timestamp,
+                event_tx_id,
+                old_commit_oid: old_commit_oid.parse()?,
+                new_commit_oid: new_commit_oid.parse()?,
+            },
+            SerializedEvent::RefUpdate {
+                timestamp,
+                ref_name,
+                old_oid,
+                new_oid,
+                message,
+            } => Event::RefUpdateEvent {
+                timestamp,
+                event_tx_id,
+                ref_name: ReferenceName::from(ref_name),
+                old_oid: old_oid.parse()?,
+                new_oid: new_oid.parse()?,
+                message: message.map(ReferenceName::from),
+            },
+            SerializedEvent::Commit {
+                timestamp,
+                commit_oid,
+            } => Event::CommitEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: commit_oid.parse()?,
+            },
+            SerializedEvent::Obsolete {
+                timestamp,
+                commit_oid,
+            } => Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: commit_oid.parse()?,
+            },
+            SerializedEvent::Unobsolete {
+                timestamp,
+                commit_oid,
+            } => Event::UnobsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: commit_oid.parse()?,
+            },
+            SerializedEvent::WorkingCopySnapshot {
+                timestamp,
+                head_oid,
+                commit_oid,
+                ref_name,
+            } => Event::WorkingCopySnapshot {
+                timestamp,
+                event_tx_id,
+                head_oid: head_oid.parse()?,
+                commit_oid: commit_oid.parse()?,
+                ref_name: ref_name.map(ReferenceName::from),
+            },
+        };
+        Ok(event)
+    }
+}
+
+impl From<Event> for SerializedEvent {
+    fn from(event: Event) -> Self {
+        match event {
+            Event::RewriteEvent {
+                timestamp,
+                event_tx_id: _,
+                old_commit_oid,
+                new_commit_oid,
+            } => SerializedEvent::Rewrite {
+                timestamp,
+                old_commit_oid: old_commit_oid.to_string(),
+                new_commit_oid: new_commit_oid.to_string(),
+            },
+            Event::RefUpdateEvent {
+                timestamp,
+                event_tx_id: _,
+                ref_name,
+                old_oid,
+                new_oid,
+                message,
+            } => SerializedEvent::RefUpdate {
+                timestamp,
+                ref_name: ref_name.as_str().to_owned(),
+                old_oid: old_oid.to_string(),
+                new_oid: new_oid.to_string(),
+                message: message.map(|message| message.as_str().to_owned()),
+            },
+            Event::CommitEvent {
+                timestamp,
+                event_tx_id: _,
+                commit_oid,
+            } => SerializedEvent::Commit {
+                timestamp,
+                commit_oid: commit_oid.to_string(),
+            },
+            Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id: _,
+                commit_oid,
+            } => SerializedEvent::Obsolete {
+                timestamp,
+                commit_oid: commit_oid.to_string(),
+            },
+            Event::UnobsoleteEvent {
+                timestamp,
+                event_tx_id: _,
+                commit_oid,
+            } => SerializedEvent::Unobsolete {
+                timestamp,
+                commit_oid: commit_oid.to_string(),
+            },
+            Event::WorkingCopySnapshot {
+                timestamp,
+                event_tx_id: _,
+                head_oid,
+                commit_oid,
+                ref_name,
+            } => SerializedEvent::WorkingCopySnapshot {
+                timestamp,
+                head_oid: head_oid.to_string(),
+                commit_oid: commit_oid.to_string(),
+                ref_name: ref_name.map(|ref_name| ref_name.as_str().to_owned()),
+            },
+        }
+    }
+}
+
+/// Group the events in the event log into transactions, in the order in which
+/// they were recorded.
+fn get_transactions(
+    event_log_db: &EventLogDb,
+    redactor: &Redactor,
+) -> eyre::Result<Vec<SerializedTransaction>> {
+    let events = event_log_db.get_events()?;
+    let mut transactions = Vec::new();
+    for (event_tx_id, events) in &events.into_iter().group_by(|event| event.get_event_tx_id()) {
+        let message = event_log_db.get_transaction_message(event_tx_id)?;
+        let events = events
+            .map(|event| SerializedEvent::from(redact_event(redactor, &event)))
+            .collect();
+        transactions.push(SerializedTransaction { message, events });
+    }
+    Ok(transactions)
+}
+
+/// Get a key which identifies the event independently of the event log it came
+/// from, for deduplication purposes. The transaction's numeric ID is specific to
+/// the event log, so the event is identified by its transaction's message and
+/// by its own contents (which include its timestamp).
+fn get_event_key(message: &str, event: &SerializedEvent) -> eyre::Result<String> {
+    let key = serde_json::to_string(&(message, event))?;
+    Ok(key)
+}
+
+/// Export the event log as JSON.
+pub fn export(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    output: Option<&Path>,
+    redact: bool,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    let redactor = if redact {
+        Redactor::new({
+            let mut preserved_ref_names = HashSet::new();
+            preserved_ref_names.insert(repo.get_main_branch()?.get_reference_name()?);
+            preserved_ref_names
+        })
+    } else {
+        Redactor::Disabled
+    };
+    let transactions = get_transactions(&event_log_db, &redactor)?;
+    let num_transactions = transactions.len();
+    let event_log_file = EventLogFile {
+        version: EVENT_LOG_FILE_VERSION,
+        transactions,
+    };
+    let json = serde_json::to_string_pretty(&event_log_file)?;
+
+    match output {
+        None => {
+            writeln!(effects.get_output_stream(), "{json}")?;
+        }
+        Some(output) => {
+            std::fs::write(output, json)
+                .wrap_err_with(|| format!("Writing event log to {output:?}"))?;
+            writeln!(
+                effects.get_output_stream(),
+                "Exported {} to {}",
+                Pluralize {
+                    determiner: None,
+                    amount: num_transactions,
+                    unit: ("transaction", "transactions"),
+                },
+                output.display(),
+            )?;
+        }
+    }
+    Ok(ExitCode(0))
+}
+
+/// Import events from a file written by `export` into the event log, skipping
+/// any events which are already present.
+pub fn import(effects: &Effects, git_run_info: &GitRunInfo, path: &Path) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Reading event log from {path:?}"))?;
+    let EventLogFile {
+        version,
+        transactions,
+    } = serde_json::from_str(&contents)
+        .wrap_err_with(|| format!("Parsing event log from {path:?}"))?;
+    if version > EVENT_LOG_FILE_VERSION {
+        writeln!(
+            effects.get_error_stream(),
+            "The event log at {} has version {}, but this version of git-branchless only supports up to version {}. Please upgrade git-branchless.",
+            path.display(),
+            version,
+            EVENT_LOG_FILE_VERSION,
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let mut seen_event_keys: HashSet<String> = HashSet::new();
+    for SerializedTransaction { message, events } in
+        get_transactions(&event_log_db, &Redactor::Disabled)?
+    {
+        for event in events.iter() {
+            seen_event_keys.insert(get_event_key(&message, event)?);
+        }
+    }
+
+    let mut imported_events = Vec::new();
+    let mut num_imported = 0;
+    let mut num_skipped = 0;
+    for SerializedTransaction { message, events } in transactions {
+        let mut new_events = Vec::new();
+        for event in events {
+            if seen_event_keys.insert(get_event_key(&message, &event)?) {
+                new_events.push(event);
+            }
+        }
+        if new_events.is_empty() {
+            num_skipped += 1;
+            continue;
+        }
+
+        let timestamp =
+            SystemTime::UNIX_EPOCH + Duration::from_secs_f64(new_events[0].get_timestamp());
+        let event_tx_id = event_log_db.make_transaction_id(timestamp, message)?;
+        for event in new_events {
+            imported_events.push(event.into_event(event_tx_id)?);
+        }
+        num_imported += 1;
+    }
+    // The imported events may be older than the existing events, so they have
+    // to be interleaved with them rather than appended.
+    event_log_db.merge_events(imported_events)?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "Imported {} from {} (skipped {} already present)",
+        Pluralize {
+            determiner: None,
+            amount: num_imported,
+            unit: ("transaction", "transactions"),
+        },
+        path.display(),
+        num_skipped,
+    )?;
+    Ok(ExitCode(0))
+}
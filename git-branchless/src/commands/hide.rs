@@ -9,12 +9,12 @@ use eden_dag::DagAlgorithm;
 use git_branchless_opts::{ResolveRevsetOptions, Revset};
 use lib::core::dag::{sorted_commit_set, union_all, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{CommitActivityStatus, Event};
+use lib::core::eventlog::{CommitActivityStatus, Event, EventTransactionId};
 use lib::core::eventlog::{EventLogDb, EventReplayer};
 use lib::core::formatting::{Glyphs, Pluralize};
 use lib::core::repo_ext::RepoExt;
 use lib::core::rewrite::move_branches;
-use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use lib::git::{CategorizedReferenceName, Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 use lib::util::ExitCode;
 use tracing::instrument;
 
@@ -64,17 +64,7 @@ pub fn hide(
     let commits = dag.query().sort(&commits)?;
     let commits = sorted_commit_set(&repo, &dag, &commits)?;
 
-    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
     let event_tx_id = event_log_db.make_transaction_id(now, "hide")?;
-    let events = commits
-        .iter()
-        .map(|commit| Event::ObsoleteEvent {
-            timestamp,
-            event_tx_id,
-            commit_oid: commit.get_oid(),
-        })
-        .collect();
-    event_log_db.add_events(events)?;
 
     let cursor = event_replayer.make_default_cursor();
     let num_commits = commits.len();
@@ -94,29 +84,16 @@ pub fn hide(
         }
     }
 
-    if delete_branches {
-        // Save current HEAD info *before* deleting any branches.
-        let head_info = repo.get_head_info()?;
-
-        // Delete any branches pointing to any of the hidden commits by "moving" them from their
-        // current OID to a Zero OID.
-        let abandoned_branches: HashMap<NonZeroOid, MaybeZeroOid> = commits
-            .iter()
-            .map(|commit| (commit.get_oid(), MaybeZeroOid::Zero))
-            .collect();
-        if let Some(head_oid) = head_info.oid {
-            if abandoned_branches.contains_key(&head_oid) {
-                repo.detach_head(&head_info)?;
-            }
-        }
-        move_branches(
-            effects,
-            git_run_info,
-            &repo,
-            event_tx_id,
-            &abandoned_branches,
-        )?;
-    }
+    hide_commits(
+        effects,
+        git_run_info,
+        &repo,
+        &event_log_db,
+        now,
+        event_tx_id,
+        &commits,
+        delete_branches,
+    )?;
 
     let mut abandoned_branches: Vec<String> = commits
         .iter()
@@ -150,19 +127,91 @@ pub fn hide(
         )?;
     }
 
+    write_unhide_hint(
+        effects,
+        num_commits,
+        if delete_branches {
+            Some(abandoned_branches.len())
+        } else {
+            None
+        },
+    )?;
+
+    Ok(ExitCode(0))
+}
+
+/// Mark the provided commits as obsolete. If `delete_branches` is set, also
+/// delete the branches pointing to them, detaching `HEAD` first if it points
+/// to one of them.
+#[instrument(skip(commits))]
+pub(crate) fn hide_commits(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    now: SystemTime,
+    event_tx_id: EventTransactionId,
+    commits: &[Commit],
+    delete_branches: bool,
+) -> eyre::Result<()> {
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let events = commits
+        .iter()
+        .map(|commit| Event::ObsoleteEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: commit.get_oid(),
+        })
+        .collect();
+    event_log_db.add_events(events)?;
+
+    if delete_branches {
+        // Save current HEAD info *before* deleting any branches.
+        let head_info = repo.get_head_info()?;
+
+        // Delete any branches pointing to any of the hidden commits by "moving" them from their
+        // current OID to a Zero OID.
+        let abandoned_branches: HashMap<NonZeroOid, MaybeZeroOid> = commits
+            .iter()
+            .map(|commit| (commit.get_oid(), MaybeZeroOid::Zero))
+            .collect();
+        if let Some(head_oid) = head_info.oid {
+            if abandoned_branches.contains_key(&head_oid) {
+                repo.detach_head(&head_info)?;
+            }
+        }
+        move_branches(
+            effects,
+            git_run_info,
+            repo,
+            event_tx_id,
+            &abandoned_branches,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Tell the user how to undo hiding commits. `num_deleted_branches` is the
+/// number of branches which were deleted, if branches were deleted at all.
+pub(crate) fn write_unhide_hint(
+    effects: &Effects,
+    num_commits: usize,
+    num_deleted_branches: Option<usize>,
+) -> eyre::Result<()> {
     // This message will look like either of these:
     // To unhide these X commits, run: git undo
     // To unhide these X commits and restore X branches, run: git undo
-    let delete_branches_message = match delete_branches {
-        true => format!(
+    let delete_branches_message = match num_deleted_branches {
+        Some(num_deleted_branches) => format!(
             " and restore {}",
             Pluralize {
                 determiner: None,
-                amount: abandoned_branches.len(),
+                amount: num_deleted_branches,
                 unit: ("branch", "branches"),
             }
         ),
-        false => String::new(),
+        None => String::new(),
     };
     writeln!(
         effects.get_output_stream(),
@@ -174,8 +223,7 @@ pub fn hide(
         },
         delete_branches_message
     )?;
-
-    Ok(ExitCode(0))
+    Ok(())
 }
 
 /// Unhide the hashes provided on the command-line.
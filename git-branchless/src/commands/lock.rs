@@ -0,0 +1,127 @@
+//! A repository-wide lock held by commands which rewrite commits or move
+//! branches, so that two such commands (such as `git sync` and `git test fix`)
+//! can't interleave their reference updates.
+//!
+//! The lock is a file lock, so the operating system releases it if the process
+//! holding it exits without doing so, such as if it crashes. Alongside the lock
+//! file, the holder records its PID and command, which is removed when the lock
+//! is released normally. If that record is still present when the lock is
+//! next acquired, then the previous holder must have exited abnormally, and
+//! the stale lock is cleaned up.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use eyre::Context;
+use fslock::LockFile;
+use lib::core::effects::Effects;
+use lib::core::rewrite::RewriteJournal;
+use lib::git::Repo;
+use tracing::{instrument, warn};
+
+/// The process which holds (or held) the lock.
+#[derive(Debug)]
+struct LockHolder {
+    pid: u32,
+    command_name: String,
+}
+
+fn read_lock_holder(path: &Path) -> Option<LockHolder> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let (pid, command_name) = contents.trim_end().split_once('\n')?;
+    Some(LockHolder {
+        pid: pid.parse().ok()?,
+        command_name: command_name.to_owned(),
+    })
+}
+
+/// A held repository-wide operation lock. The lock is released when this is
+/// dropped.
+#[derive(Debug)]
+pub struct OperationLock {
+    holder_path: PathBuf,
+
+    // Released after `holder_path` is removed when dropped, so that another
+    // process never takes the lock while the holder record is still present.
+    _lock_file: LockFile,
+}
+
+impl Drop for OperationLock {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.holder_path) {
+            warn!(?err, holder_path = ?self.holder_path, "Could not remove lock holder file");
+        }
+    }
+}
+
+impl OperationLock {
+    /// Try to take the operation lock for `repo` on behalf of `command_name`.
+    /// If another process holds it, print a message naming that process and
+    /// return `None`.
+    #[instrument]
+    pub fn acquire(
+        effects: &Effects,
+        repo: &Repo,
+        command_name: &str,
+    ) -> eyre::Result<Option<Self>> {
+        // Branches are shared between worktrees, so the lock must be too.
+        let lock_dir = match repo.open_worktree_parent_repo()? {
+            Some(parent_repo) => parent_repo.get_path().join("branchless"),
+            None => repo.get_path().join("branchless"),
+        };
+        std::fs::create_dir_all(&lock_dir)
+            .wrap_err_with(|| format!("Creating lock dir {lock_dir:?}"))?;
+        let lock_path = lock_dir.join("operation.lock");
+        let holder_path = lock_dir.join("operation.lock.holder");
+
+        let mut lock_file = LockFile::open(&lock_path)
+            .wrap_err_with(|| format!("Opening lock file {lock_path:?}"))?;
+        if !lock_file
+            .try_lock_with_pid()
+            .wrap_err_with(|| format!("Locking file {lock_path:?}"))?
+        {
+            match read_lock_holder(&holder_path) {
+                Some(LockHolder { pid, command_name }) => writeln!(
+                    effects.get_error_stream(),
+                    "Another git-branchless operation is in progress: `{command_name}` (PID {pid})."
+                )?,
+                None => writeln!(
+                    effects.get_error_stream(),
+                    "Another git-branchless operation is in progress (lock file: {lock_path:?})."
+                )?,
+            }
+            writeln!(
+                effects.get_error_stream(),
+                "Wait for it to finish, and then try again."
+            )?;
+            return Ok(None);
+        }
+
+        if let Some(LockHolder {
+            pid,
+            command_name: stale_command_name,
+        }) = read_lock_holder(&holder_path)
+        {
+            writeln!(
+                effects.get_error_stream(),
+                "branchless: removed stale lock held by `{stale_command_name}` (PID {pid}), which exited without releasing it."
+            )?;
+            if RewriteJournal::load(repo)?.is_some() {
+                writeln!(
+                    effects.get_error_stream(),
+                    "branchless: it may have been interrupted while moving branches; to finish or roll back that operation, run: git branchless repair"
+                )?;
+            }
+        }
+        std::fs::write(
+            &holder_path,
+            format!("{}\n{command_name}\n", std::process::id()),
+        )
+        .wrap_err_with(|| format!("Writing lock holder file {holder_path:?}"))?;
+
+        Ok(Some(Self {
+            holder_path,
+            _lock_file: lock_file,
+        }))
+    }
+}
@@ -2,8 +2,12 @@
 
 mod amend;
 mod bug_report;
+mod eventlog;
 mod hide;
+mod lock;
+mod obsmarkers;
 mod repair;
+mod resolve;
 mod restack;
 mod snapshot;
 mod sync;
@@ -15,9 +19,56 @@ use lib::core::rewrite::MergeConflictRemediation;
 use lib::util::ExitCode;
 
 use git_branchless_opts::{
-    rewrite_args, Command, Opts, ResolveRevsetOptions, SnapshotSubcommand, WrappedCommand,
+    rewrite_args, Command, EventlogSubcommand, ObsmarkersSubcommand, Opts, ResolveRevsetOptions,
+    SnapshotSubcommand, SubmitArgs, TestArgs, TestSubcommand, WrappedCommand,
 };
-use lib::git::GitRunInfo;
+use lib::git::{GitRunInfo, Repo};
+
+/// Get the name of the command to record as the holder of the operation lock,
+/// if the command rewrites commits, moves branches, writes to the event log, or
+/// updates the working copy, and so must take the lock. Commands run with
+/// `--dry-run` don't write anything, so they don't take the lock.
+fn get_operation_lock_command_name(command: &Command) -> Option<&'static str> {
+    match command {
+        Command::Amend { .. } => Some("git amend"),
+        Command::Eventlog {
+            subcommand: EventlogSubcommand::Import { .. },
+        } => Some("git branchless eventlog import"),
+        Command::Gc => Some("git branchless gc"),
+        Command::Hide { .. } => Some("git hide"),
+        Command::Move { dry_run, .. } => (!dry_run).then(|| "git move"),
+        Command::Next { .. } => Some("git next"),
+        Command::Obsmarkers { .. } => Some("git branchless obsmarkers"),
+        Command::Prev { .. } => Some("git prev"),
+        Command::Record(_) => Some("git record"),
+        Command::Reorder { .. } => Some("git reorder"),
+        Command::Repair { dry_run, .. } => (!dry_run).then(|| "git branchless repair"),
+        Command::Resolve { .. } => Some("git branchless resolve"),
+        Command::Restack { dry_run, .. } => (!dry_run).then(|| "git restack"),
+        Command::Reword { .. } => Some("git reword"),
+        Command::Snapshot { .. } => Some("git branchless snapshot"),
+        Command::Submit(SubmitArgs { dry_run, .. }) => (!dry_run).then(|| "git submit"),
+        Command::Switch { .. } => Some("git switch"),
+        Command::Sync { dry_run, .. } => (!dry_run).then(|| "git sync"),
+        Command::Test(TestArgs {
+            subcommand: TestSubcommand::Fix { dry_run, .. },
+        }) => (!dry_run).then(|| "git test fix"),
+        Command::Undo { .. } => Some("git undo"),
+        Command::Unhide { .. } => Some("git unhide"),
+
+        // Hooks are run by other Git commands, and wrapped commands may
+        // themselves be git-branchless commands, possibly while the lock is
+        // already held, so taking the lock here could deadlock.
+        Command::Hook(_) | Command::Wrap { .. } => None,
+
+        Command::BugReport
+        | Command::Eventlog { .. }
+        | Command::Init(_)
+        | Command::Query(_)
+        | Command::Smartlog(_)
+        | Command::Test(_) => None,
+    }
+}
 
 fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
     let CommandContext {
@@ -29,6 +80,17 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
         command,
     } = opts;
 
+    let _operation_lock = match get_operation_lock_command_name(&command) {
+        Some(command_name) => {
+            let repo = Repo::from_current_dir()?;
+            match lock::OperationLock::acquire(&effects, &repo, command_name)? {
+                Some(operation_lock) => Some(operation_lock),
+                None => return Ok(ExitCode(1)),
+            }
+        }
+        None => None,
+    };
+
     let exit_code = match command {
         Command::Amend {
             move_options,
@@ -43,6 +105,15 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
 
         Command::BugReport => bug_report::bug_report(&effects, &git_run_info)?,
 
+        Command::Eventlog { subcommand } => match subcommand {
+            EventlogSubcommand::Export { output, redact } => {
+                eventlog::export(&effects, &git_run_info, output.as_deref(), redact)?
+            }
+            EventlogSubcommand::Import { path } => {
+                eventlog::import(&effects, &git_run_info, &path)?
+            }
+        },
+
         Command::Switch { switch_options } => {
             git_branchless_navigation::switch(&effects, &git_run_info, &switch_options)?
         }
@@ -78,6 +149,8 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
             resolve_revset_options,
             move_options,
             insert,
+            edit_plan,
+            dry_run,
         } => git_branchless_move::r#move(
             &effects,
             &git_run_info,
@@ -88,6 +161,8 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
             &resolve_revset_options,
             &move_options,
             insert,
+            edit_plan,
+            dry_run,
         )?,
 
         Command::Next {
@@ -108,14 +183,30 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
             &traverse_commits_options,
         )?,
 
+        Command::Obsmarkers { subcommand } => match subcommand {
+            ObsmarkersSubcommand::Export => obsmarkers::export(&effects, &git_run_info)?,
+            ObsmarkersSubcommand::Import { refs } => {
+                obsmarkers::import(&effects, &git_run_info, refs)?
+            }
+        },
+
         Command::Query(args) => git_branchless_query::command_main(ctx, args)?,
 
-        Command::Repair { dry_run } => repair::repair(&effects, dry_run)?,
+        Command::Repair { dry_run, rollback } => {
+            repair::repair(&effects, &git_run_info, dry_run, rollback)?
+        }
+
+        Command::Resolve {
+            revset,
+            resolve_revset_options,
+        } => resolve::resolve(&effects, &git_run_info, revset, &resolve_revset_options)?,
 
         Command::Restack {
             revsets,
             resolve_revset_options,
             move_options,
+            autosquash,
+            dry_run,
         } => restack::restack(
             &effects,
             &git_run_info,
@@ -123,10 +214,26 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
             &resolve_revset_options,
             &move_options,
             MergeConflictRemediation::Retry,
+            autosquash,
+            dry_run,
         )?,
 
         Command::Record(args) => git_branchless_record::command_main(ctx, args)?,
 
+        Command::Reorder {
+            revset,
+            resolve_revset_options,
+            force_rewrite_public_commits,
+            create_conflict_commits,
+        } => git_branchless_reorder::reorder(
+            &effects,
+            &git_run_info,
+            revset,
+            &resolve_revset_options,
+            force_rewrite_public_commits,
+            create_conflict_commits,
+        )?,
+
         Command::Reword {
             revsets,
             resolve_revset_options,
@@ -166,6 +273,8 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
         Command::Sync {
             pull,
             move_options,
+            dry_run,
+            clean,
             revsets,
             resolve_revset_options,
         } => sync::sync(
@@ -173,6 +282,8 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
             &git_run_info,
             pull,
             &move_options,
+            dry_run,
+            clean,
             revsets,
             &resolve_revset_options,
         )?,
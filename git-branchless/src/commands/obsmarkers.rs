@@ -0,0 +1,85 @@
+//! Share obsolescence information between clones of the repository.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use lib::core::effects::Effects;
+use lib::core::eventlog::EventLogDb;
+use lib::core::formatting::Pluralize;
+use lib::core::obsmarkers::{
+    export_obsmarkers, import_obsmarkers, is_remote_obsmarkers_ref, ExportObsmarkersResult,
+    OBSMARKERS_REF_NAME,
+};
+use lib::git::{GitRunInfo, ReferenceName, Repo};
+use lib::util::ExitCode;
+
+pub fn export(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    let ExportObsmarkersResult {
+        commit_oid: _,
+        num_obsmarkers,
+        num_new_obsmarkers,
+    } = export_obsmarkers(&repo, &event_log_db)?;
+    writeln!(
+        effects.get_output_stream(),
+        "Exported {} to {OBSMARKERS_REF_NAME} ({} total)",
+        Pluralize {
+            determiner: None,
+            amount: num_new_obsmarkers,
+            unit: ("new obsolescence marker", "new obsolescence markers"),
+        },
+        num_obsmarkers,
+    )?;
+    Ok(ExitCode(0))
+}
+
+pub fn import(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    refs: Vec<String>,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let now = SystemTime::now();
+
+    let ref_names: Vec<ReferenceName> = if refs.is_empty() {
+        let mut ref_names = Vec::new();
+        for reference in repo.get_all_references()? {
+            let ref_name = reference.get_name()?;
+            if is_remote_obsmarkers_ref(&ref_name) {
+                ref_names.push(ref_name);
+            }
+        }
+        ref_names
+    } else {
+        refs.into_iter().map(ReferenceName::from).collect()
+    };
+
+    for ref_name in ref_names {
+        if repo.find_reference(&ref_name)?.is_none() {
+            writeln!(
+                effects.get_error_stream(),
+                "Reference not found: {}",
+                ref_name.as_str()
+            )?;
+            return Ok(ExitCode(1));
+        }
+
+        let num_imported = import_obsmarkers(&repo, &event_log_db, now, &ref_name)?;
+        writeln!(
+            effects.get_output_stream(),
+            "Imported {} from {}",
+            Pluralize {
+                determiner: None,
+                amount: num_imported,
+                unit: ("obsolescence marker", "obsolescence markers"),
+            },
+            ref_name.as_str(),
+        )?;
+    }
+    Ok(ExitCode(0))
+}
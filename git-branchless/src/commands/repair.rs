@@ -1,22 +1,191 @@
+use std::collections::HashMap;
 use std::fmt::Write;
 use std::{collections::HashSet, time::SystemTime};
 
 use itertools::Itertools;
-use lib::git::{CategorizedReferenceName, MaybeZeroOid};
+use lib::core::check_out::CheckOutCommitOptions;
+use lib::core::rewrite::{apply_branch_moves, BranchMove, BranchMoveStatus, RewriteJournal};
+use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName};
 use lib::{
     core::{
         effects::{Effects, OperationType},
         eventlog::{Event, EventLogDb, EventReplayer},
         formatting::Pluralize,
+        rewrite::check_out_updated_head,
     },
     git::Repo,
     util::ExitCode,
 };
 
-pub fn repair(effects: &Effects, dry_run: bool) -> eyre::Result<ExitCode> {
+/// Finish or roll back the rewrite operation recorded in `journal`, which was
+/// interrupted while updating references.
+fn repair_interrupted_rewrite(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    journal: RewriteJournal,
+    dry_run: bool,
+    rollback: bool,
+) -> eyre::Result<ExitCode> {
+    let mut pending_moves: Vec<BranchMove> = Vec::new();
+    let mut applied_moves: Vec<BranchMove> = Vec::new();
+    for branch_move in journal.branch_moves.iter() {
+        match branch_move.get_status(repo)? {
+            BranchMoveStatus::Pending => pending_moves.push(branch_move.clone()),
+            BranchMoveStatus::Applied => applied_moves.push(branch_move.clone()),
+            BranchMoveStatus::Diverged { current_oid: _ } => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Not updating branch {}, as it was changed after the operation was interrupted.",
+                    CategorizedReferenceName::new(&branch_move.reference_name).render_suffix()
+                )?;
+            }
+        }
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Found interrupted rewrite operation: {} of {} updated.",
+        applied_moves.len(),
+        Pluralize {
+            determiner: None,
+            amount: journal.branch_moves.len(),
+            unit: ("branch", "branches"),
+        },
+    )?;
+    if dry_run {
+        writeln!(
+            effects.get_output_stream(),
+            "The operation will be {} when run with --no-dry-run.",
+            if rollback { "rolled back" } else { "finished" },
+        )?;
+        return Ok(ExitCode(0));
+    }
+
+    let RewriteJournal {
+        event_tx_id,
+        head_info,
+        rewritten_oids,
+        skipped_head_updated_oid,
+        branch_moves: _,
+        rewrites_recorded,
+    } = &journal;
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs_f64();
+    let (rewritten_oids_map, skipped_head_updated_oid): (
+        HashMap<NonZeroOid, MaybeZeroOid>,
+        Option<NonZeroOid>,
+    ) = if rollback {
+        let updates: Vec<(&ReferenceName, NonZeroOid)> = applied_moves
+            .iter()
+            .map(|branch_move| (&branch_move.reference_name, branch_move.old_oid))
+            .collect();
+        repo.update_references_atomically(&updates, "roll back interrupted rewrite")?;
+
+        let ref_events = applied_moves
+            .iter()
+            .map(|branch_move| Event::RefUpdateEvent {
+                timestamp,
+                event_tx_id: *event_tx_id,
+                ref_name: branch_move.reference_name.clone(),
+                old_oid: branch_move.new_oid,
+                new_oid: MaybeZeroOid::NonZero(branch_move.old_oid),
+                message: None,
+            });
+        let commit_events = rewritten_oids.iter().flat_map(|(old_oid, new_oid)| {
+            let unobsolete_event = Event::UnobsoleteEvent {
+                timestamp,
+                event_tx_id: *event_tx_id,
+                commit_oid: *old_oid,
+            };
+            let obsolete_event = match new_oid {
+                MaybeZeroOid::NonZero(new_oid) => Some(Event::ObsoleteEvent {
+                    timestamp,
+                    event_tx_id: *event_tx_id,
+                    commit_oid: *new_oid,
+                }),
+                MaybeZeroOid::Zero => None,
+            };
+            std::iter::once(unobsolete_event).chain(obsolete_event)
+        });
+        event_log_db.add_events(ref_events.chain(commit_events).collect())?;
+
+        // Check out the original `HEAD` again.
+        (HashMap::new(), None)
+    } else {
+        apply_branch_moves(effects, git_run_info, repo, *event_tx_id, &pending_moves)?;
+        if !rewrites_recorded {
+            let events = rewritten_oids
+                .iter()
+                .map(|(old_oid, new_oid)| Event::RewriteEvent {
+                    timestamp,
+                    event_tx_id: *event_tx_id,
+                    old_commit_oid: (*old_oid).into(),
+                    new_commit_oid: *new_oid,
+                })
+                .collect();
+            event_log_db.add_events(events)?;
+        }
+        (
+            rewritten_oids.iter().copied().collect(),
+            *skipped_head_updated_oid,
+        )
+    };
+
+    writeln!(
+        effects.get_output_stream(),
+        "{} the interrupted rewrite operation.",
+        if rollback { "Rolled back" } else { "Finished" },
+    )?;
+
+    // Only update `HEAD` if it's where the operation left it; otherwise, it
+    // was presumably moved by the user afterwards.
+    let exit_code = if repo.get_head_info()?.oid == head_info.oid {
+        check_out_updated_head(
+            effects,
+            git_run_info,
+            repo,
+            event_log_db,
+            *event_tx_id,
+            &rewritten_oids_map,
+            head_info,
+            skipped_head_updated_oid,
+            &CheckOutCommitOptions::default(),
+        )?
+    } else {
+        ExitCode(0)
+    };
+    journal.remove(repo)?;
+
+    Ok(exit_code)
+}
+
+pub fn repair(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    dry_run: bool,
+    rollback: bool,
+) -> eyre::Result<ExitCode> {
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
+
+    if let Some(journal) = RewriteJournal::load(&repo)? {
+        let exit_code = repair_interrupted_rewrite(
+            effects,
+            git_run_info,
+            &repo,
+            &event_log_db,
+            journal,
+            dry_run,
+            rollback,
+        )?;
+        if !exit_code.is_success() {
+            return Ok(exit_code);
+        }
+    }
+
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
     let event_cursor = event_replayer.make_default_cursor();
 
@@ -0,0 +1,203 @@
+//! Materialize the merge conflicts recorded in a conflict commit into the
+//! working copy, so that they can be resolved.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use itertools::Itertools;
+use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use lib::core::conflicts::{get_commit_conflict, CommitConflict};
+use lib::core::dag::{commit_set_to_vec, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::Rerere;
+use lib::git::{update_index, GitRunInfo, Repo, Stage, UpdateIndexCommand};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+use git_branchless_revset::resolve_commits;
+
+/// Check out the provided conflict commit and write its merge conflicts to
+/// the working copy.
+#[instrument]
+pub fn resolve(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revset: Revset,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> eyre::Result<ExitCode> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_sets = match resolve_commits(
+        effects,
+        &repo,
+        &mut dag,
+        std::slice::from_ref(&revset),
+        resolve_revset_options,
+    ) {
+        Ok(commit_sets) => commit_sets,
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(ExitCode(1));
+        }
+    };
+    let commit_oid = match commit_sets.as_slice() {
+        [commit_set] => match commit_set_to_vec(commit_set)?.as_slice() {
+            [commit_oid] => *commit_oid,
+            commit_oids => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Expected exactly 1 commit to resolve, but '{}' evaluated to {}.\nAborting.",
+                    revset,
+                    commit_oids.len()
+                )?;
+                return Ok(ExitCode(1));
+            }
+        },
+        other => eyre::bail!("BUG: expected 1 commit set, but got {}", other.len()),
+    };
+    let commit = repo.find_commit_or_fail(commit_oid)?;
+
+    let conflict = match get_commit_conflict(&repo, &commit)? {
+        Some(conflict) => conflict,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "Not a conflict commit: {}",
+                effects
+                    .get_glyphs()
+                    .render(commit.friendly_describe(effects.get_glyphs())?)?
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+
+    if repo.has_changed_files(effects, git_run_info)? {
+        writeln!(
+            effects.get_error_stream(),
+            "There are uncommitted changes in the working copy. Commit or discard them and try again."
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let event_tx_id = event_log_db.make_transaction_id(now, "resolve")?;
+    let exit_code = check_out_commit(
+        effects,
+        git_run_info,
+        &repo,
+        &event_log_db,
+        event_tx_id,
+        Some(CheckoutTarget::Oid(commit_oid)),
+        &CheckOutCommitOptions {
+            render_smartlog: false,
+            ..Default::default()
+        },
+    )?;
+    if !exit_code.is_success() {
+        return Ok(exit_code);
+    }
+
+    let onto_oid = commit.get_only_parent_oid().unwrap_or(commit_oid);
+    let (_tree, conflicting_paths) = conflict.merge(&repo, commit_oid, onto_oid)?;
+    let conflicting_paths = conflicting_paths.into_iter().sorted().collect_vec();
+
+    let update_index_script = {
+        let CommitConflict {
+            base_tree_oid,
+            our_tree_oid,
+            their_tree_oid,
+        } = conflict;
+        let mut sides = Vec::new();
+        for (stage, tree_oid) in [
+            (Stage::Stage1, base_tree_oid),
+            (Stage::Stage2, Some(our_tree_oid)),
+            (Stage::Stage3, Some(their_tree_oid)),
+        ] {
+            if let Some(tree_oid) = tree_oid {
+                sides.push((stage, repo.find_tree_or_fail(tree_oid)?));
+            }
+        }
+
+        let mut commands = Vec::new();
+        for path in conflicting_paths.iter() {
+            commands.push(UpdateIndexCommand::Delete { path: path.clone() });
+            for (stage, tree) in sides.iter() {
+                if let Some(tree_entry) = tree.get_path(path)? {
+                    commands.push(UpdateIndexCommand::Update {
+                        path: path.clone(),
+                        stage: *stage,
+                        mode: tree_entry.get_filemode(),
+                        oid: tree_entry.get_oid(),
+                    });
+                }
+            }
+        }
+        commands
+    };
+    let index = repo.get_index()?;
+    update_index(
+        git_run_info,
+        &repo,
+        &index,
+        event_tx_id,
+        &update_index_script,
+    )?;
+
+    // Write the conflict markers to the working copy, based on the
+    // higher-stage index entries.
+    let mut args = vec!["checkout", "--merge", "--"];
+    let path_args = conflicting_paths
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect_vec();
+    args.extend(path_args.iter().map(|path| path.as_str()));
+    let exit_code = git_run_info.run(effects, Some(event_tx_id), &args)?;
+    if !exit_code.is_success() {
+        return Ok(exit_code);
+    }
+
+    // Record the conflicts with `git rerere`, so that their resolutions can be
+    // reused later. This also applies any previously-recorded resolutions to
+    // the working copy.
+    if let Some(rerere) = Rerere::from_config(git_run_info, &repo)? {
+        let exit_code = rerere.record(effects, &repo, event_tx_id, true)?;
+        if !exit_code.is_success() {
+            return Ok(exit_code);
+        }
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "Wrote merge conflicts for {} to the working copy:",
+        Pluralize {
+            determiner: None,
+            amount: conflicting_paths.len(),
+            unit: ("path", "paths"),
+        }
+    )?;
+    for path in conflicting_paths.iter() {
+        writeln!(effects.get_output_stream(), "- {}", path.display())?;
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "To finish resolving the conflicts, fix the files, stage them with `git add`, and then run: git amend"
+    )?;
+
+    Ok(ExitCode(0))
+}
@@ -59,6 +59,8 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::time::SystemTime;
 
+use bstr::ByteSlice;
+use eden_dag::DagAlgorithm;
 use lib::core::check_out::CheckOutCommitOptions;
 use lib::core::repo_ext::RepoExt;
 use lib::util::ExitCode;
@@ -67,17 +69,18 @@ use tracing::{instrument, warn};
 
 use git_branchless_opts::{MoveOptions, ResolveRevsetOptions, Revset};
 use git_branchless_revset::resolve_commits;
-use git_branchless_smartlog::smartlog;
+use git_branchless_smartlog::{smartlog, smartlog_preview};
 use lib::core::config::get_restack_preserve_timestamps;
-use lib::core::dag::{commit_set_to_vec, union_all, CommitSet, Dag};
+use lib::core::dag::{commit_set_to_vec, sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{EventCursor, EventLogDb, EventReplayer};
 use lib::core::rewrite::{
     execute_rebase_plan, find_abandoned_children, find_rewrite_target, move_branches,
-    BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    MergeConflictRemediation, RebasePlanBuilder, RebasePlanPermissions, RepoPool, RepoResource,
+    preview_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
+    MergeConflictRemediation, RebaseCommand, RebasePlan, RebasePlanBuilder, RebasePlanPermissions,
+    RebasePlanPreview, RepoPool, RepoResource,
 };
-use lib::git::{GitRunInfo, NonZeroOid, Repo};
+use lib::git::{Commit, GitRunInfo, NonZeroOid, Repo};
 
 #[instrument(skip(commits))]
 fn restack_commits(
@@ -93,6 +96,7 @@ fn restack_commits(
     build_options: BuildRebasePlanOptions,
     execute_options: &ExecuteRebasePlanOptions,
     merge_conflict_remediation: MergeConflictRemediation,
+    preview: Option<&mut RebasePlanPreview>,
 ) -> eyre::Result<ExitCode> {
     let repo = repo_pool.try_create()?;
     let commit_set: CommitSet = match commits {
@@ -168,22 +172,322 @@ fn restack_commits(
         }
     };
 
-    let execute_rebase_plan_result = execute_rebase_plan(
+    execute_restack_plan(
         effects,
         git_run_info,
         &repo,
         event_log_db,
         &rebase_plan,
         execute_options,
+        merge_conflict_remediation,
+        preview,
+        "Finished restacking commits.",
+    )
+}
+
+/// Fold each `fixup!`, `squash!`, and `amend!` commit in `commits` (or among
+/// all draft commits, if not provided) into the commit it targets.
+#[instrument(skip(commits))]
+fn autosquash_commits(
+    effects: &Effects,
+    thread_pool: &ThreadPool,
+    repo_pool: &RepoPool,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    git_run_info: &GitRunInfo,
+    commits: Option<impl IntoIterator<Item = NonZeroOid>>,
+    build_options: BuildRebasePlanOptions,
+    execute_options: &ExecuteRebasePlanOptions,
+    merge_conflict_remediation: MergeConflictRemediation,
+    preview: Option<&mut RebasePlanPreview>,
+) -> eyre::Result<ExitCode> {
+    let repo = repo_pool.try_create()?;
+    let draft_commits = dag.query_draft_commits()?;
+    let commit_set: CommitSet = match commits {
+        Some(commits) => commits
+            .into_iter()
+            .collect::<CommitSet>()
+            .intersection(draft_commits),
+        None => draft_commits.clone(),
+    };
+
+    // Map each fixup commit to its target, and each target to its fixup
+    // commits in topological order.
+    let mut fixup_targets: HashMap<NonZeroOid, NonZeroOid> = HashMap::new();
+    let mut fixup_kinds: HashMap<NonZeroOid, FixupKind> = HashMap::new();
+    let mut target_fixups: HashMap<NonZeroOid, Vec<NonZeroOid>> = HashMap::new();
+    let mut target_oids: Vec<NonZeroOid> = Vec::new();
+    for commit in sorted_commit_set(&repo, dag, &commit_set)? {
+        if commit.get_parent_count() != 1 {
+            continue;
+        }
+        let subject = commit.get_summary()?;
+        let (kind, target_spec) = match parse_fixup_subject(&subject.to_str_lossy()) {
+            Some((kind, target_spec)) => (kind, target_spec.to_owned()),
+            None => continue,
+        };
+        let ancestors = dag
+            .query()
+            .ancestors(CommitSet::from(commit.get_oid()))?
+            .difference(&CommitSet::from(commit.get_oid()))
+            .intersection(draft_commits);
+        let ancestors = sorted_commit_set(&repo, dag, &ancestors)?;
+        let target_oid = match find_fixup_target(&ancestors, &target_spec)? {
+            // If the target is itself a fixup commit, then fold into its
+            // target instead.
+            Some(target_oid) => *fixup_targets.get(&target_oid).unwrap_or(&target_oid),
+            None => {
+                warn!(commit_oid = ?commit.get_oid(), ?target_spec, "Could not find target of fixup commit");
+                continue;
+            }
+        };
+        if repo.find_commit_or_fail(target_oid)?.get_parent_count() != 1 {
+            continue;
+        }
+
+        fixup_targets.insert(commit.get_oid(), target_oid);
+        fixup_kinds.insert(commit.get_oid(), kind);
+        target_fixups
+            .entry(target_oid)
+            .or_insert_with(|| {
+                target_oids.push(target_oid);
+                Vec::new()
+            })
+            .push(commit.get_oid());
+    }
+    if target_oids.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "No fixup commits to autosquash."
+        )?;
+        return Ok(ExitCode(0));
+    }
+
+    let rebase_plan = {
+        let permissions = match RebasePlanPermissions::verify_rewrite_set(
+            dag,
+            build_options,
+            &target_oids.iter().copied().collect(),
+        )? {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                err.describe(effects, &repo)?;
+                return Ok(ExitCode(1));
+            }
+        };
+        let mut builder = RebasePlanBuilder::new(dag, permissions);
+
+        // Each target is followed by its fixup commits, and anything which
+        // was on top of the target or its fixup commits goes on top of the
+        // last fixup commit instead.
+        let mut chain_ends: HashMap<NonZeroOid, NonZeroOid> = HashMap::new();
+        for (target_oid, fixup_oids) in target_fixups.iter() {
+            chain_ends.insert(*target_oid, *fixup_oids.last().unwrap());
+        }
+        let get_new_parent_oid = |oid: NonZeroOid| -> eyre::Result<NonZeroOid> {
+            let mut parent_oid = dag.get_only_parent_oid(oid)?;
+            while fixup_targets.contains_key(&parent_oid) {
+                parent_oid = dag.get_only_parent_oid(parent_oid)?;
+            }
+            Ok(*chain_ends.get(&parent_oid).unwrap_or(&parent_oid))
+        };
+
+        for target_oid in target_oids.iter() {
+            builder.move_subtree(*target_oid, vec![get_new_parent_oid(*target_oid)?])?;
+            let mut parent_oid = *target_oid;
+            for fixup_oid in target_fixups[target_oid].iter() {
+                builder.move_subtree(*fixup_oid, vec![parent_oid])?;
+                parent_oid = *fixup_oid;
+            }
+        }
+        let moved_oids: CommitSet = target_oids
+            .iter()
+            .chain(fixup_targets.keys())
+            .copied()
+            .collect();
+        let children = dag
+            .query()
+            .children(moved_oids.clone())?
+            .difference(&moved_oids);
+        for child_oid in commit_set_to_vec(&dag.filter_visible_commits(children)?)? {
+            let new_parent_oid = match dag.get_only_parent_oid(child_oid) {
+                Ok(_) => get_new_parent_oid(child_oid)?,
+                // Merge commits keep their parents, which are remapped by the
+                // rebase.
+                Err(_) => continue,
+            };
+            builder.move_subtree(child_oid, vec![new_parent_oid])?;
+        }
+
+        match builder.build(effects, thread_pool, repo_pool)? {
+            Ok(Some(rebase_plan)) => rebase_plan,
+            Ok(None) => {
+                eyre::bail!("BUG: rebase plan indicates nothing to do, but autosquashing should always do something.");
+            }
+            Err(err) => {
+                err.describe(effects, &repo)?;
+                return Ok(ExitCode(1));
+            }
+        }
+    };
+    let rebase_plan = apply_fixups(rebase_plan, &fixup_kinds);
+
+    execute_restack_plan(
+        effects,
+        git_run_info,
+        &repo,
+        event_log_db,
+        &rebase_plan,
+        execute_options,
+        merge_conflict_remediation,
+        preview,
+        "Finished autosquashing commits.",
+    )
+}
+
+/// How a fixup commit's message is combined with its target's message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FixupKind {
+    /// `fixup!`: discard the fixup commit's message.
+    Fixup,
+
+    /// `squash!`: append the fixup commit's message.
+    Squash,
+
+    /// `amend!`: replace the target's message with the fixup commit's.
+    Amend,
+}
+
+/// Parse the subject line of a fixup commit into its kind and the subject (or
+/// OID) of the commit it targets. As with `git rebase --autosquash`, repeated
+/// prefixes (e.g. `fixup! fixup! ...`) are stripped, and the first one
+/// determines the kind.
+fn parse_fixup_subject(subject: &str) -> Option<(FixupKind, &str)> {
+    let mut kind = None;
+    let mut rest = subject;
+    loop {
+        let (prefix_kind, stripped) = if let Some(stripped) = rest.strip_prefix("fixup! ") {
+            (FixupKind::Fixup, stripped)
+        } else if let Some(stripped) = rest.strip_prefix("squash! ") {
+            (FixupKind::Squash, stripped)
+        } else if let Some(stripped) = rest.strip_prefix("amend! ") {
+            (FixupKind::Amend, stripped)
+        } else {
+            break;
+        };
+        kind.get_or_insert(prefix_kind);
+        rest = stripped;
+    }
+    kind.map(|kind| (kind, rest))
+}
+
+/// Find the commit targeted by a fixup commit among its `ancestors` (sorted
+/// topologically). Like `git rebase --autosquash`, prefer an exact subject
+/// match, then an OID prefix, and then a subject prefix.
+fn find_fixup_target(ancestors: &[Commit], target_spec: &str) -> eyre::Result<Option<NonZeroOid>> {
+    for commit in ancestors {
+        if commit.get_summary()? == target_spec {
+            return Ok(Some(commit.get_oid()));
+        }
+    }
+    if target_spec.len() >= 4 && target_spec.chars().all(|c| c.is_ascii_hexdigit()) {
+        if let Some(commit) = ancestors
+            .iter()
+            .find(|commit| commit.get_oid().to_string().starts_with(target_spec))
+        {
+            return Ok(Some(commit.get_oid()));
+        }
+    }
+    for commit in ancestors {
+        if commit.get_summary()?.starts_with(target_spec.as_bytes()) {
+            return Ok(Some(commit.get_oid()));
+        }
+    }
+    Ok(None)
+}
+
+/// Replace the picks for fixup commits with the commands to fold them into the
+/// preceding commit.
+fn apply_fixups(
+    rebase_plan: RebasePlan,
+    fixup_kinds: &HashMap<NonZeroOid, FixupKind>,
+) -> RebasePlan {
+    let RebasePlan {
+        first_dest_oid,
+        commands,
+    } = rebase_plan;
+    let commands = commands
+        .into_iter()
+        .filter_map(|command| match command {
+            RebaseCommand::Pick {
+                original_commit_oid,
+                commit_to_apply_oid: _,
+            } => match fixup_kinds.get(&original_commit_oid) {
+                Some(FixupKind::Fixup) => Some(RebaseCommand::Fixup {
+                    commit_oid: original_commit_oid,
+                    use_commit_message: false,
+                }),
+                Some(FixupKind::Squash) => Some(RebaseCommand::Squash {
+                    commit_oid: original_commit_oid,
+                }),
+                Some(FixupKind::Amend) => Some(RebaseCommand::Fixup {
+                    commit_oid: original_commit_oid,
+                    use_commit_message: true,
+                }),
+                None => Some(command),
+            },
+            RebaseCommand::DetectEmptyCommit { commit_oid }
+                if fixup_kinds.contains_key(&commit_oid) =>
+            {
+                None
+            }
+            command => Some(command),
+        })
+        .collect();
+    RebasePlan {
+        first_dest_oid,
+        commands,
+    }
+}
+
+/// Execute (or preview) a rebase plan built by `git restack`.
+fn execute_restack_plan(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    rebase_plan: &RebasePlan,
+    execute_options: &ExecuteRebasePlanOptions,
+    merge_conflict_remediation: MergeConflictRemediation,
+    preview: Option<&mut RebasePlanPreview>,
+    finished_message: &str,
+) -> eyre::Result<ExitCode> {
+    if let Some(preview) = preview {
+        return match preview_rebase_plan(effects, repo, rebase_plan, execute_options, preview)? {
+            Ok(()) => Ok(ExitCode(0)),
+            Err(failed_merge_info) => {
+                failed_merge_info.describe(effects, repo, merge_conflict_remediation)?;
+                Ok(ExitCode(1))
+            }
+        };
+    }
+
+    let execute_rebase_plan_result = execute_rebase_plan(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        rebase_plan,
+        execute_options,
     )?;
     match execute_rebase_plan_result {
         ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => {
-            writeln!(effects.get_output_stream(), "Finished restacking commits.")?;
+            writeln!(effects.get_output_stream(), "{finished_message}")?;
             Ok(ExitCode(0))
         }
 
         ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info } => {
-            failed_merge_info.describe(effects, &repo, merge_conflict_remediation)?;
+            failed_merge_info.describe(effects, repo, merge_conflict_remediation)?;
             Ok(ExitCode(1))
         }
 
@@ -213,6 +517,7 @@ fn restack_branches(
     git_run_info: &GitRunInfo,
     event_log_db: &EventLogDb,
     options: &ExecuteRebasePlanOptions,
+    preview: Option<&mut RebasePlanPreview>,
 ) -> eyre::Result<ExitCode> {
     let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
 
@@ -238,7 +543,13 @@ fn restack_branches(
         };
     }
 
-    if rewritten_oids.is_empty() {
+    if let Some(preview) = preview {
+        // Branches on commits rewritten by the previewed commit restack will
+        // have already been accounted for.
+        for (old_oid, new_oid) in rewritten_oids {
+            preview.rewritten_oids.entry(old_oid).or_insert(new_oid);
+        }
+    } else if rewritten_oids.is_empty() {
         writeln!(
             effects.get_output_stream(),
             "No abandoned branches to restack."
@@ -267,6 +578,8 @@ pub fn restack(
     resolve_revset_options: &ResolveRevsetOptions,
     move_options: &MoveOptions,
     merge_conflict_remediation: MergeConflictRemediation,
+    autosquash: bool,
+    dry_run: bool,
 ) -> eyre::Result<ExitCode> {
     let now = SystemTime::now();
     let repo = Repo::from_current_dir()?;
@@ -309,6 +622,7 @@ pub fn restack(
         force_on_disk,
         detect_duplicate_commits_via_patch_id,
         resolve_merge_conflicts,
+        create_conflict_commits,
         dump_rebase_constraints,
         dump_rebase_plan,
     } = *move_options;
@@ -322,9 +636,11 @@ pub fn restack(
         now,
         event_tx_id,
         preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
-        force_in_memory,
-        force_on_disk,
+        // Squashing commits is only supported in memory.
+        force_in_memory: force_in_memory || autosquash,
+        force_on_disk: force_on_disk && !autosquash,
         resolve_merge_conflicts,
+        create_conflict_commits,
         check_out_commit_options: CheckOutCommitOptions {
             additional_args: Default::default(),
             reset: false,
@@ -333,6 +649,34 @@ pub fn restack(
     };
     let pool = ThreadPoolBuilder::new().build()?;
     let repo_pool = RepoResource::new_pool(&repo)?;
+    let mut preview = if dry_run {
+        Some(RebasePlanPreview::default())
+    } else {
+        None
+    };
+
+    if autosquash {
+        let exit_code = autosquash_commits(
+            effects,
+            &pool,
+            &repo_pool,
+            &dag,
+            &event_log_db,
+            git_run_info,
+            commits,
+            build_options,
+            &execute_options,
+            merge_conflict_remediation,
+            preview.as_mut(),
+        )?;
+        return match preview {
+            Some(preview) if exit_code.is_success() => {
+                smartlog_preview(effects, &repo, &event_log_db, event_tx_id, &preview)?;
+                Ok(exit_code)
+            }
+            _ => Ok(exit_code),
+        };
+    }
 
     let exit_code = restack_commits(
         effects,
@@ -347,6 +691,7 @@ pub fn restack(
         build_options,
         &execute_options,
         merge_conflict_remediation,
+        preview.as_mut(),
     )?;
     if !exit_code.is_success() {
         return Ok(exit_code);
@@ -359,10 +704,17 @@ pub fn restack(
         git_run_info,
         &event_log_db,
         &execute_options,
+        preview.as_mut(),
     )?;
     if !exit_code.is_success() {
         return Ok(exit_code);
     }
 
-    smartlog(effects, git_run_info, &Default::default())
+    match preview {
+        Some(preview) => {
+            smartlog_preview(effects, &repo, &event_log_db, event_tx_id, &preview)?;
+            Ok(ExitCode(0))
+        }
+        None => smartlog(effects, git_run_info, &Default::default()),
+    }
 }
@@ -1,6 +1,7 @@
 //! Implements the `git sync` command.
 
 use cursive_core::theme::BaseColor;
+use std::collections::HashMap;
 use std::fmt::Write;
 use std::time::SystemTime;
 
@@ -14,35 +15,107 @@ use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use git_branchless_opts::{MoveOptions, ResolveRevsetOptions, Revset};
 use git_branchless_revset::{check_revset_syntax, resolve_commits};
+use git_branchless_smartlog::smartlog_preview;
+
+use super::hide::{hide_commits, write_unhide_hint};
 use lib::core::config::get_restack_preserve_timestamps;
 use lib::core::dag::{commit_set_to_vec, sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::{Effects, OperationType};
 use lib::core::eventlog::{EventLogDb, EventReplayer};
 use lib::core::formatting::{Pluralize, StyledStringBuilder};
 use lib::core::rewrite::{
-    execute_rebase_plan, BuildRebasePlanError, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
-    ExecuteRebasePlanResult, FailedMergeInfo, RebasePlan, RebasePlanBuilder, RebasePlanPermissions,
-    RepoPool, RepoResource,
+    execute_rebase_plans, preview_rebase_plan, BuildRebasePlanError, BuildRebasePlanOptions,
+    ExecuteRebasePlanOptions, ExecuteRebasePlanResult, FailedMergeInfo, RebasePlan,
+    RebasePlanBuilder, RebasePlanPermissions, RebasePlanPreview, RepoPool, RepoResource,
 };
 use lib::core::task::ResourcePool;
-use lib::git::{CategorizedReferenceName, Commit, GitRunInfo, NonZeroOid, Repo};
+use lib::git::{CategorizedReferenceName, Commit, GitRunInfo, NonZeroOid, PatchId, Repo};
 
-fn get_stack_roots(dag: &Dag) -> eyre::Result<CommitSet> {
-    let draft_commits = dag.query_draft_commits()?;
+fn get_stack_roots(dag: &Dag, landed_commits: &CommitSet) -> eyre::Result<CommitSet> {
+    // Landed commits are left behind, so the commits after them in their
+    // stacks become the roots of the stacks to move.
+    let draft_commits = dag.query_draft_commits()?.difference(landed_commits);
 
-    // FIXME: if two draft roots are ancestors of a single commit (due to a
-    // merge commit), then the entire unit should be treated as one stack and
-    // moved together, rather than attempting two separate rebases.
+    // If two draft roots are ancestors of a single commit (due to a merge
+    // commit), they are grouped into one stack later by `group_stack_roots`.
     let draft_roots = dag.query().roots(draft_commits.clone())?;
     Ok(draft_roots)
 }
 
-/// Move all commit stacks on top of the main branch.
+/// Group stack roots whose descendants overlap (e.g. because a merge commit
+/// descends from both of them), so that each group is moved as a single unit
+/// and no commit is rebased twice. Each group is keyed by its first root.
+fn group_stack_roots(
+    dag: &Dag,
+    root_commit_oids: Vec<NonZeroOid>,
+) -> eyre::Result<Vec<(NonZeroOid, Vec<NonZeroOid>)>> {
+    let mut groups: Vec<(CommitSet, Vec<NonZeroOid>)> = Vec::new();
+    for root_commit_oid in root_commit_oids {
+        let mut descendants = dag.query().descendants(CommitSet::from(root_commit_oid))?;
+        let mut roots = vec![root_commit_oid];
+        let mut insert_index = None;
+        let mut remaining_groups = Vec::new();
+        for (group_descendants, group_roots) in groups {
+            if group_descendants.intersection(&descendants).is_empty()? {
+                remaining_groups.push((group_descendants, group_roots));
+            } else {
+                insert_index.get_or_insert(remaining_groups.len());
+                descendants = descendants.union(&group_descendants);
+                roots = group_roots.into_iter().chain(roots).collect();
+            }
+        }
+        groups = remaining_groups;
+        let insert_index = insert_index.unwrap_or(groups.len());
+        groups.insert(insert_index, (descendants, roots));
+    }
+    Ok(groups
+        .into_iter()
+        .map(|(_descendants, roots)| (roots[0], roots))
+        .collect())
+}
+
+/// Get the commit that the stack rooted at `root_commit` should be moved on
+/// top of: the head of the public branch which the stack is based on. If the
+/// stack is based on more than one public branch, then the main branch is
+/// preferred, followed by the public branch with the fewest commits since the
+/// stack's base.
+fn get_stack_destination(
+    dag: &Dag,
+    root_commit: &Commit,
+    main_branch_oid: NonZeroOid,
+) -> eyre::Result<NonZeroOid> {
+    let base_commits: CommitSet = root_commit.get_parent_oids().into_iter().collect();
+    let public_branch_heads = dag
+        .public_branch_commits
+        .intersection(&dag.query().descendants(base_commits.clone())?);
+    if public_branch_heads.contains(&main_branch_oid.into())? {
+        return Ok(main_branch_oid);
+    }
+
+    let mut dest_oid = None;
+    for public_branch_head in commit_set_to_vec(&public_branch_heads)? {
+        let num_commits = dag
+            .query()
+            .range(base_commits.clone(), CommitSet::from(public_branch_head))?
+            .count()?;
+        if dest_oid.map_or(true, |(_, min_num_commits)| num_commits < min_num_commits) {
+            dest_oid = Some((public_branch_head, num_commits));
+        }
+    }
+    Ok(match dest_oid {
+        Some((dest_oid, _)) => dest_oid,
+        None => main_branch_oid,
+    })
+}
+
+/// Move all commit stacks on top of the public branches they're based on.
 pub fn sync(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     pull: bool,
     move_options: &MoveOptions,
+    dry_run: bool,
+    clean: bool,
     revsets: Vec<Revset>,
     resolve_revset_options: &ResolveRevsetOptions,
 ) -> eyre::Result<ExitCode> {
@@ -69,6 +142,7 @@ pub fn sync(
         force_on_disk,
         detect_duplicate_commits_via_patch_id,
         resolve_merge_conflicts,
+        create_conflict_commits,
         dump_rebase_constraints,
         dump_rebase_plan,
     } = *move_options;
@@ -87,6 +161,7 @@ pub fn sync(
         force_in_memory,
         force_on_disk,
         resolve_merge_conflicts,
+        create_conflict_commits,
         check_out_commit_options: CheckOutCommitOptions {
             additional_args: Default::default(),
             reset: false,
@@ -114,6 +189,19 @@ pub fn sync(
 
     // The main branch might have changed since we synced with `master`, so read its information again.
 
+    let landed_commits = if clean {
+        clean_landed_commits(
+            effects,
+            git_run_info,
+            &repo,
+            &event_log_db,
+            &execute_options,
+            dry_run,
+        )?
+    } else {
+        CommitSet::empty()
+    };
+
     execute_sync_plans(
         effects,
         git_run_info,
@@ -125,9 +213,198 @@ pub fn sync(
         &repo_pool,
         revsets,
         resolve_revset_options,
+        &landed_commits,
+        dry_run,
     )
 }
 
+/// Find the draft commits which have already landed on a public branch, either
+/// because a commit with the same patch ID is on a public branch, or because a
+/// public commit has the same patch ID as a range of draft commits squashed
+/// together.
+fn find_landed_commits<'repo>(
+    effects: &Effects,
+    repo: &'repo Repo,
+    dag: &Dag,
+) -> eyre::Result<Vec<(Commit<'repo>, Commit<'repo>)>> {
+    let draft_commits = dag.query_draft_commits()?;
+    let public_commits = dag
+        .query()
+        .only(dag.public_branch_commits.clone(), draft_commits.clone())?;
+    let public_commits = commit_set_to_vec(&public_commits)?;
+
+    let public_patch_ids: HashMap<PatchId, NonZeroOid> = {
+        let (effects, progress) = effects.start_operation(OperationType::GetUpstreamPatchIds);
+        progress.notify_progress(0, public_commits.len());
+        let mut result = HashMap::new();
+        for commit_oid in public_commits {
+            let commit = repo.find_commit_or_fail(commit_oid)?;
+            if let Some(patch_id) = repo.get_patch_id(&effects, &commit)? {
+                result.insert(patch_id, commit_oid);
+            }
+            progress.notify_progress_inc(1);
+        }
+        result
+    };
+    if public_patch_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut landed_oids: HashMap<NonZeroOid, NonZeroOid> = HashMap::new();
+    for stack in dag.get_connected_components(draft_commits)? {
+        for commit in sorted_commit_set(repo, dag, &stack)? {
+            if let Some(patch_id) = repo.get_patch_id(effects, &commit)? {
+                if let Some(public_commit_oid) = public_patch_ids.get(&patch_id) {
+                    landed_oids
+                        .entry(commit.get_oid())
+                        .or_insert(*public_commit_oid);
+                    continue;
+                }
+            }
+
+            // Check whether this commit and its ancestors in the stack were
+            // squashed into a single commit when they landed.
+            let range = dag
+                .query()
+                .ancestors(CommitSet::from(commit.get_oid()))?
+                .intersection(&stack);
+            let range_root = match commit_set_to_vec(&dag.query().roots(range.clone())?)?
+                .into_iter()
+                .exactly_one()
+            {
+                Ok(range_root) => repo.find_commit_or_fail(range_root)?,
+                Err(_) => continue,
+            };
+            if range_root.get_oid() == commit.get_oid() {
+                continue;
+            }
+            let base_commit = match range_root.get_only_parent() {
+                Some(parent) => parent,
+                None => continue,
+            };
+            let patch_id = repo.get_patch_id_between_trees(
+                effects,
+                Some(&base_commit.get_tree()?),
+                &commit.get_tree()?,
+            )?;
+            if let Some(public_commit_oid) = public_patch_ids.get(&patch_id) {
+                for commit_oid in commit_set_to_vec(&range)? {
+                    landed_oids.entry(commit_oid).or_insert(*public_commit_oid);
+                }
+            }
+        }
+    }
+
+    let landed_commit_set: CommitSet = landed_oids.keys().copied().collect();
+    sorted_commit_set(repo, dag, &landed_commit_set)?
+        .into_iter()
+        .map(|commit| -> eyre::Result<_> {
+            let public_commit = repo.find_commit_or_fail(landed_oids[&commit.get_oid()])?;
+            Ok((commit, public_commit))
+        })
+        .collect()
+}
+
+/// Hide the draft commits which have already landed on a public branch and
+/// delete the branches pointing to them. Returns the landed commits.
+fn clean_landed_commits(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    execute_options: &ExecuteRebasePlanOptions,
+    dry_run: bool,
+) -> eyre::Result<CommitSet> {
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let dag = Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let landed_commits = find_landed_commits(effects, repo, &dag)?;
+    if landed_commits.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "No landed commits to clean up."
+        )?;
+        return Ok(CommitSet::empty());
+    }
+
+    let glyphs = effects.get_glyphs();
+    for (commit, public_commit) in &landed_commits {
+        writeln!(
+            effects.get_output_stream(),
+            "{} {} (landed as {})",
+            if dry_run {
+                "Would hide landed commit:"
+            } else {
+                "Hid landed commit:"
+            },
+            glyphs.render(commit.friendly_describe(glyphs)?)?,
+            glyphs.render(public_commit.friendly_describe_oid(glyphs)?)?,
+        )?;
+    }
+
+    let mut landed_branches: Vec<String> = landed_commits
+        .iter()
+        .filter_map(|(commit, _)| {
+            references_snapshot
+                .branch_oid_to_names
+                .get(&commit.get_oid())
+        })
+        .flatten()
+        .map(|branch_name| CategorizedReferenceName::new(branch_name).render_suffix())
+        .collect();
+    if !landed_branches.is_empty() {
+        landed_branches.sort_unstable();
+        writeln!(
+            effects.get_output_stream(),
+            "{} {}: {}",
+            if dry_run { "Would delete" } else { "Deleted" },
+            Pluralize {
+                determiner: None,
+                amount: landed_branches.len(),
+                unit: ("branch", "branches"),
+            },
+            landed_branches.join(", ")
+        )?;
+    }
+
+    let landed_commit_set: CommitSet = landed_commits
+        .iter()
+        .map(|(commit, _)| commit.get_oid())
+        .collect();
+    if dry_run {
+        return Ok(landed_commit_set);
+    }
+
+    let ExecuteRebasePlanOptions {
+        now, event_tx_id, ..
+    } = *execute_options;
+    let landed_commits: Vec<Commit> = landed_commits
+        .into_iter()
+        .map(|(commit, _)| commit)
+        .collect();
+    hide_commits(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        now,
+        event_tx_id,
+        &landed_commits,
+        true,
+    )?;
+    write_unhide_hint(effects, landed_commits.len(), Some(landed_branches.len()))?;
+
+    Ok(landed_commit_set)
+}
+
 fn execute_main_branch_sync_plan(
     effects: &Effects,
     git_run_info: &GitRunInfo,
@@ -268,6 +545,7 @@ fn execute_main_branch_sync_plan(
         event_log_db,
         execute_options,
         vec![(root_commit_oid, Some(rebase_plan))],
+        false,
     )
 }
 
@@ -282,6 +560,8 @@ fn execute_sync_plans(
     repo_pool: &ResourcePool<RepoResource>,
     revsets: Vec<Revset>,
     resolve_revset_options: &ResolveRevsetOptions,
+    landed_commits: &CommitSet,
+    dry_run: bool,
 ) -> eyre::Result<ExitCode> {
     let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
     let event_cursor = event_replayer.make_default_cursor();
@@ -303,9 +583,10 @@ fn execute_sync_plans(
         };
     let main_branch_oid = repo.get_main_branch_oid()?;
     let root_commit_oids = if commit_sets.is_empty() {
-        get_stack_roots(&dag)?
+        get_stack_roots(&dag, landed_commits)?
     } else {
-        dag.query().roots(union_all(&commit_sets))?
+        dag.query()
+            .roots(union_all(&commit_sets).difference(landed_commits))?
     };
 
     let root_commits = sorted_commit_set(repo, &dag, &root_commit_oids)?;
@@ -323,27 +604,36 @@ fn execute_sync_plans(
         .into_iter()
         .map(|commit| commit.get_oid())
         .collect_vec();
+    let root_commit_groups = group_stack_roots(&dag, root_commit_oids)?;
     let root_commit_and_plans = thread_pool.install(|| -> eyre::Result<_> {
-        let result = root_commit_oids
+        let result = root_commit_groups
             // Don't parallelize for now, since the status updates don't render well.
             .into_iter()
             .map(
-                |root_commit_oid| -> eyre::Result<
+                |(root_commit_oid, group_root_oids)| -> eyre::Result<
                     Result<(NonZeroOid, Option<RebasePlan>), BuildRebasePlanError>,
                 > {
                     // Keep access to the same underlying caches by cloning the same instance of the builder.
                     let mut builder = builder.clone();
 
                     let repo = repo_pool.try_create()?;
-                    let root_commit = repo.find_commit_or_fail(root_commit_oid)?;
-
-                    let only_parent_id =
-                        root_commit.get_only_parent().map(|parent| parent.get_oid());
-                    if only_parent_id == Some(main_branch_oid) {
+                    let mut is_up_to_date = true;
+                    for group_root_oid in group_root_oids {
+                        let root_commit = repo.find_commit_or_fail(group_root_oid)?;
+                        let dest_oid = get_stack_destination(&dag, &root_commit, main_branch_oid)?;
+                        let only_parent_id =
+                            root_commit.get_only_parent().map(|parent| parent.get_oid());
+                        if only_parent_id == Some(dest_oid) {
+                            continue;
+                        }
+
+                        is_up_to_date = false;
+                        builder.move_subtree(root_commit.get_oid(), vec![dest_oid])?;
+                    }
+                    if is_up_to_date {
                         return Ok(Ok((root_commit_oid, None)));
                     }
 
-                    builder.move_subtree(root_commit.get_oid(), vec![main_branch_oid])?;
                     let rebase_plan = builder.build(effects, thread_pool, repo_pool)?;
                     Ok(rebase_plan.map(|rebase_plan| (root_commit_oid, rebase_plan)))
                 },
@@ -368,6 +658,7 @@ fn execute_sync_plans(
         event_log_db,
         execute_options,
         root_commit_and_plans,
+        dry_run,
     )
 }
 
@@ -378,7 +669,9 @@ fn execute_plans(
     event_log_db: &EventLogDb,
     execute_options: &ExecuteRebasePlanOptions,
     root_commit_and_plans: Vec<(NonZeroOid, Option<RebasePlan>)>,
+    dry_run: bool,
 ) -> Result<ExitCode, Report> {
+    let mut preview = RebasePlanPreview::default();
     let (success_commits, failed_merge_commits, skipped_commits) = {
         let mut success_commits: Vec<Commit> = Vec::new();
         let mut failed_merge_commits: Vec<(Commit, FailedMergeInfo)> = Vec::new();
@@ -387,25 +680,58 @@ fn execute_plans(
         let (effects, progress) = effects.start_operation(OperationType::SyncCommits);
         progress.notify_progress(0, root_commit_and_plans.len());
 
+        let mut root_commits: Vec<Commit> = Vec::new();
+        let mut rebase_plans: Vec<RebasePlan> = Vec::new();
         for (root_commit_oid, rebase_plan) in root_commit_and_plans {
             let root_commit = repo.find_commit_or_fail(root_commit_oid)?;
-            let rebase_plan = match rebase_plan {
-                Some(rebase_plan) => rebase_plan,
+            match rebase_plan {
+                Some(rebase_plan) => {
+                    root_commits.push(root_commit);
+                    rebase_plans.push(rebase_plan);
+                }
                 None => {
                     skipped_commits.push(root_commit);
-                    continue;
                 }
-            };
+            }
+        }
 
-            let result = execute_rebase_plan(
+        let results = if dry_run {
+            let mut results = Vec::new();
+            for rebase_plan in rebase_plans.iter() {
+                let result = match preview_rebase_plan(
+                    &effects,
+                    repo,
+                    rebase_plan,
+                    execute_options,
+                    &mut preview,
+                )? {
+                    Ok(()) => ExecuteRebasePlanResult::Succeeded {
+                        rewritten_oids: None,
+                    },
+                    Err(failed_merge_info) => {
+                        ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info }
+                    }
+                };
+                progress.notify_progress_inc(1);
+                results.push(result);
+            }
+            results
+        } else {
+            // The stacks are independent of each other, so they can be rebased
+            // in parallel.
+            let results = execute_rebase_plans(
                 &effects,
                 git_run_info,
                 repo,
                 event_log_db,
-                &rebase_plan,
+                &rebase_plans.iter().collect_vec(),
                 execute_options,
             )?;
-            progress.notify_progress_inc(1);
+            progress.notify_progress_inc(results.len());
+            results
+        };
+
+        for (root_commit, result) in root_commits.into_iter().zip(results) {
             match result {
                 ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => {
                     success_commits.push(root_commit);
@@ -428,7 +754,7 @@ fn execute_plans(
             "{}",
             effects.get_glyphs().render(
                 StyledStringBuilder::new()
-                    .append_plain("Synced ")
+                    .append_plain(if dry_run { "Would sync " } else { "Synced " })
                     .append(success_commit.friendly_describe(effects.get_glyphs())?)
                     .build()
             )?
@@ -480,5 +806,15 @@ fn execute_plans(
         )?;
     }
 
+    if dry_run {
+        smartlog_preview(
+            effects,
+            repo,
+            event_log_db,
+            execute_options.event_tx_id,
+            &preview,
+        )?;
+    }
+
     Ok(ExitCode(0))
 }
@@ -1112,3 +1112,44 @@ fn test_amend_merge() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_amend_signs_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    if !git.set_up_ssh_signing()? {
+        return Ok(());
+    }
+
+    git.write_file_txt("test2", "updated contents")?;
+    git.branchless("amend", &[])?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%G? %s", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        G create test2.txt
+        N create test1.txt
+        N create initial.txt
+        "###);
+    }
+
+    git.run(&["config", "user.signingKey", "/nonexistent/signing_key"])?;
+    git.write_file_txt("test2", "updated contents again")?;
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "amend",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Failed to sign amended commit: signing program "ssh-keygen" failed (exit status: 255): Couldn't load public key /nonexistent/signing_key: No such file or directory
+        "###);
+    }
+
+    Ok(())
+}
@@ -130,3 +130,123 @@ fn test_sparse_checkout() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_operation_lock() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    let lock_dir = git.repo_path.join(".git").join("branchless");
+    let mut lock_file = fslock::LockFile::open(&lock_dir.join("operation.lock"))?;
+    assert!(lock_file.try_lock_with_pid()?);
+    std::fs::write(lock_dir.join("operation.lock.holder"), "12345\ngit sync\n")?;
+
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "hide",
+            &["HEAD"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Another git-branchless operation is in progress: `git sync` (PID 12345).
+        Wait for it to finish, and then try again.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "unhide",
+            &["HEAD"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Another git-branchless operation is in progress: `git sync` (PID 12345).
+        Wait for it to finish, and then try again.
+        "###);
+    }
+    for (command, args) in [("prev", &[] as &[&str]), ("gc", &[])] {
+        let (_stdout, stderr) = git.branchless_with_options(
+            command,
+            args,
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.starts_with("Another git-branchless operation is in progress"),
+            "{}: {}",
+            command,
+            stderr
+        );
+    }
+
+    // Commands which don't rewrite anything don't need the lock.
+    {
+        let (_stdout, stderr) =
+            git.branchless("move", &["--dry-run", "-x", "HEAD", "-d", "master"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 62fc20d create test1.txt
+        "###);
+    }
+
+    lock_file.unlock()?;
+    std::fs::remove_file(lock_dir.join("operation.lock.holder"))?;
+    {
+        let (stdout, stderr) = git.branchless("hide", &["HEAD"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        To unhide this 1 commit, run: git undo
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_operation_lock_stale() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    // Simulate a process which exited without releasing the lock.
+    let lock_dir = git.repo_path.join(".git").join("branchless");
+    std::fs::write(lock_dir.join("operation.lock"), "12345\n")?;
+    std::fs::write(lock_dir.join("operation.lock.holder"), "12345\ngit move\n")?;
+
+    {
+        let (stdout, stderr) = git.branchless("hide", &["HEAD"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: removed stale lock held by `git move` (PID 12345), which exited without releasing it.
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        To unhide this 1 commit, run: git undo
+        "###);
+    }
+
+    // The stale lock should have been cleaned up.
+    assert!(!lock_dir.join("operation.lock.holder").exists());
+    {
+        let (_stdout, stderr) = git.branchless("unhide", &["HEAD"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+
+    Ok(())
+}
@@ -1,8 +1,20 @@
+use lazy_static::lazy_static;
 use lib::core::effects::Effects;
 use lib::core::eventlog::testing::{get_event_replayer_events, redact_event_timestamp};
 use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
 use lib::core::formatting::Glyphs;
 use lib::testing::make_git;
+use regex::Regex;
+
+lazy_static! {
+    static ref TIMESTAMP_RE: Regex = Regex::new(r#""timestamp": ([0-9.]+)"#).unwrap();
+}
+
+fn redact_timestamp(str: String) -> String {
+    TIMESTAMP_RE
+        .replace_all(&str, r#""timestamp": "<redacted for test>""#)
+        .to_string()
+}
 
 #[test]
 fn test_git_v2_31_events() -> eyre::Result<()> {
@@ -154,3 +166,205 @@ fn test_git_v2_31_events() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_eventlog_export_import() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.branchless("hide", &[&test2_oid.to_string()])?;
+    git.run(&["checkout", "master"])?;
+
+    let smartlog_before = git.smartlog()?;
+    insta::assert_snapshot!(smartlog_before, @r###"
+    @ f777ecc (> master) create initial.txt
+    |
+    o 62fc20d create test1.txt
+    "###);
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("eventlog", &["export", "--output", "../eventlog.json"])?;
+        insta::assert_snapshot!(stdout, @"Exported 8 transactions to ../eventlog.json");
+    }
+
+    // Simulate moving the repository to a machine without its event log.
+    std::fs::remove_file(
+        git.repo_path
+            .join(".git")
+            .join("branchless")
+            .join("db.sqlite3"),
+    )?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @"@ f777ecc (> master) create initial.txt");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("eventlog", &["import", "../eventlog.json"])?;
+        insta::assert_snapshot!(stdout, @"Imported 8 transactions from ../eventlog.json (skipped 0 already present)");
+    }
+    {
+        let stdout = git.smartlog()?;
+        assert_eq!(stdout, smartlog_before);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("eventlog", &["import", "../eventlog.json"])?;
+        insta::assert_snapshot!(stdout, @"Imported 0 transactions from ../eventlog.json (skipped 8 already present)");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_eventlog_import_older_events() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.branchless("hide", &[&test1_oid.to_string()])?;
+    git.branchless("eventlog", &["export", "--output", "../eventlog.json"])?;
+
+    // Record newer activity in a fresh event log, which has to be replayed
+    // after the imported events.
+    std::fs::remove_file(
+        git.repo_path
+            .join(".git")
+            .join("branchless")
+            .join("db.sqlite3"),
+    )?;
+    git.branchless("unhide", &[&test1_oid.to_string()])?;
+    let smartlog_before = git.smartlog()?;
+    insta::assert_snapshot!(smartlog_before, @r###"
+    @ f777ecc (> master) create initial.txt
+    |
+    o 62fc20d create test1.txt
+    "###);
+
+    {
+        let (stdout, _stderr) = git.branchless("eventlog", &["import", "../eventlog.json"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Imported 6 transactions from ../eventlog.json (skipped 0 already present)
+        "###);
+    }
+    {
+        let stdout = git.smartlog()?;
+        assert_eq!(stdout, smartlog_before);
+    }
+
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let events = event_log_db.get_events()?;
+        let obsolete_index = events.iter().position(|event| {
+            matches!(event, Event::ObsoleteEvent { commit_oid, .. } if *commit_oid == test1_oid)
+        });
+        let unobsolete_index = events.iter().position(|event| {
+            matches!(event, Event::UnobsoleteEvent { commit_oid, .. } if *commit_oid == test1_oid)
+        });
+        assert!(obsolete_index.is_some());
+        assert!(obsolete_index < unobsolete_index);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_eventlog_export_redact() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.run(&["checkout", "-b", "secret-branch"])?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("eventlog", &["export", "--redact"])?;
+        let stdout = redact_timestamp(stdout);
+        assert!(!stdout.contains("secret-branch"));
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "version": 1,
+          "transactions": [
+            {
+              "message": "reference-transaction",
+              "events": [
+                {
+                  "type": "ref_update",
+                  "timestamp": "<redacted for test>",
+                  "ref_name": "refs/heads/redacted-ref-0",
+                  "old_oid": "0000000000000000000000000000000000000000",
+                  "new_oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+                  "message": null
+                }
+              ]
+            },
+            {
+              "message": "hook-post-checkout",
+              "events": [
+                {
+                  "type": "ref_update",
+                  "timestamp": "<redacted for test>",
+                  "ref_name": "HEAD",
+                  "old_oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+                  "new_oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+                  "message": null
+                }
+              ]
+            },
+            {
+              "message": "reference-transaction",
+              "events": [
+                {
+                  "type": "ref_update",
+                  "timestamp": "<redacted for test>",
+                  "ref_name": "HEAD",
+                  "old_oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+                  "new_oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+                  "message": null
+                },
+                {
+                  "type": "ref_update",
+                  "timestamp": "<redacted for test>",
+                  "ref_name": "refs/heads/redacted-ref-0",
+                  "old_oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+                  "new_oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+                  "message": null
+                }
+              ]
+            },
+            {
+              "message": "post-commit",
+              "events": [
+                {
+                  "type": "commit",
+                  "timestamp": "<redacted for test>",
+                  "commit_oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e"
+                }
+              ]
+            }
+          ]
+        }
+        "###);
+    }
+
+    Ok(())
+}
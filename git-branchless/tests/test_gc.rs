@@ -289,3 +289,132 @@ fn test_gc_no_init() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_gc_compact_event_log() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.branchless("hide", &["HEAD"])?;
+    git.run(&["checkout", "HEAD^^"])?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+
+    let smartlog_before = git.smartlog()?;
+    insta::assert_snapshot!(smartlog_before, @r###"
+    O f777ecc (master) create initial.txt
+    |\
+    | @ ae94dc2 amended test1
+    |
+    x 62fc20d (rewritten as ae94dc2a) create test1.txt
+    |
+    o 96d1c37 create test2.txt
+    hint: there is 1 abandoned commit in your commit graph
+    hint: to fix this, run: git restack
+    hint: disable this hint by running: git config --global branchless.hint.smartlogFixAbandoned false
+    "###);
+
+    git.run(&["config", "branchless.eventLog.maxEvents", "2"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 2 dangling references deleted
+        branchless: compacted 12 events into 4 checkpoint events
+        branchless: reclaimed 0 bytes from the event log
+        "###);
+    }
+
+    let smartlog_after = git.smartlog()?;
+    assert_eq!(smartlog_before, smartlog_after);
+
+    {
+        let conn = git.get_repo()?.get_db_conn()?;
+        let event_log = EventLogDb::new(&conn)?;
+        let events = event_log
+            .get_events()?
+            .into_iter()
+            .map(redact_event_timestamp)
+            .collect_vec();
+        insta::assert_debug_snapshot!(events, @r###"
+        [
+            RefUpdateEvent {
+                timestamp: 0.0,
+                event_tx_id: EventTransactionId(
+                    15,
+                ),
+                ref_name: ReferenceName(
+                    "HEAD",
+                ),
+                old_oid: 0000000000000000000000000000000000000000,
+                new_oid: ae94dc2a748bc0965c88fcf3edac2e30074ff7e2,
+                message: None,
+            },
+            CommitEvent {
+                timestamp: 0.0,
+                event_tx_id: EventTransactionId(
+                    15,
+                ),
+                commit_oid: NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e),
+            },
+            CommitEvent {
+                timestamp: 0.0,
+                event_tx_id: EventTransactionId(
+                    15,
+                ),
+                commit_oid: NonZeroOid(96d1c37a3d4363611c49f7e52186e189a04c531f),
+            },
+            ObsoleteEvent {
+                timestamp: 0.0,
+                event_tx_id: EventTransactionId(
+                    15,
+                ),
+                commit_oid: NonZeroOid(70deb1e28791d8e7dd5a1f0c871a51b91282562f),
+            },
+            CommitEvent {
+                timestamp: 0.0,
+                event_tx_id: EventTransactionId(
+                    13,
+                ),
+                commit_oid: NonZeroOid(ae94dc2a748bc0965c88fcf3edac2e30074ff7e2),
+            },
+            RewriteEvent {
+                timestamp: 0.0,
+                event_tx_id: EventTransactionId(
+                    14,
+                ),
+                old_commit_oid: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e,
+                new_commit_oid: ae94dc2a748bc0965c88fcf3edac2e30074ff7e2,
+            },
+        ]
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling reference deleted
+        "###);
+    }
+
+    git.branchless("restack", &[])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ ae94dc2 amended test1
+        |
+        o 51452b5 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
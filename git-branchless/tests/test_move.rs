@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use lib::testing::{
     extract_hint_command, make_git, make_git_with_remote_repo, remove_rebase_lines, GitInitOptions,
     GitRunOptions, GitWrapperWithRemoteRepo,
@@ -2119,26 +2121,19 @@ fn test_move_exact_range_one_side_of_merged_stack_including_base_and_merge_commi
     O 62fc20d (master) create test1.txt
     |\
     | o 96d1c37 create test2.txt
-    | |\
-    | | o 70deb1e create test3.txt
-    | | |
-    | | o 355e173 create test4.txt
-    | | & (merge) 178e00f Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
-    | |
-    | x d2e18e3 (rewritten as ea7aa064) create test5.txt
     | |
-    | x d43fec8 (rewritten as da42aeb4) create test6.txt
+    | o 70deb1e create test3.txt
     | |
-    | | & (merge) 355e173 create test4.txt
-    | |/
-    | @ 178e00f Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
+    | o 355e173 create test4.txt
+    | & (merge) 3774d8f Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
     |
     o ea7aa06 create test5.txt
     |
     o da42aeb create test6.txt
-    hint: there is 1 abandoned commit in your commit graph
-    hint: to fix this, run: git restack
-    hint: disable this hint by running: git config --global branchless.hint.smartlogFixAbandoned false
+    |
+    | & (merge) 355e173 create test4.txt
+    |/
+    @ 3774d8f Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
     "###);
 
     Ok(())
@@ -2211,26 +2206,17 @@ fn test_move_exact_range_two_partial_components_of_merged_stack() -> eyre::Resul
     | o 96d1c37 create test2.txt
     | |\
     | | o 70deb1e create test3.txt
-    | | |
-    | | x 355e173 (rewritten as bf0d52a6) create test4.txt
-    | | & (merge) 178e00f Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
-    | |\
-    | | x d2e18e3 (rewritten as ea7aa064) create test5.txt
-    | | |
-    | | x d43fec8 (rewritten as d071649c) create test6.txt
-    | | |
-    | | | & (merge) 355e173 (rewritten as bf0d52a6) create test4.txt
-    | | |/
-    | | @ 178e00f Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
     | |
     | o d071649 create test6.txt
+    | |
+    | | & (merge) bf0d52a create test4.txt
+    | |/
+    | @ d15eb08 Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
     |\
     | o bf0d52a create test4.txt
+    | & (merge) d15eb08 Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
     |
     o ea7aa06 create test5.txt
-    hint: there is 1 abandoned commit in your commit graph
-    hint: to fix this, run: git restack
-    hint: disable this hint by running: git config --global branchless.hint.smartlogFixAbandoned false
     "###);
 
     Ok(())
@@ -3639,7 +3625,11 @@ fn test_move_merge_commit() -> eyre::Result<()> {
     let test2_oid = git.commit_file("test2", 2)?;
     git.run(&["checkout", "HEAD^"])?;
     let test3_oid = git.commit_file("test3", 3)?;
-    git.run(&["merge", &test2_oid.to_string()])?;
+    git.run(&["checkout", "HEAD^"])?;
+    let test4_oid = git.commit_file("test4", 4)?;
+    git.run(&["checkout", &test3_oid.to_string()])?;
+    // Merge commits with more than two parents can't be rebased in-memory.
+    git.run(&["merge", &test2_oid.to_string(), &test4_oid.to_string()])?;
 
     git.run(&["checkout", &test3_oid.to_string()])?;
     {
@@ -3648,13 +3638,17 @@ fn test_move_merge_commit() -> eyre::Result<()> {
         O f777ecc create initial.txt
         |\
         | o fe65c1f create test2.txt
-        | & (merge) 28790c7 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        | & (merge) ff9ac9e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
         |\
         | @ 98b9119 create test3.txt
         | |
         | | & (merge) fe65c1f create test2.txt
+        | | & (merge) 8f7aef5 create test4.txt
         | |/
-        | o 28790c7 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        | o ff9ac9e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
+        |\
+        | o 8f7aef5 create test4.txt
+        | & (merge) ff9ac9e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
         |
         O 62fc20d (master) create test1.txt
         "###);
@@ -3697,7 +3691,7 @@ fn test_move_merge_commit() -> eyre::Result<()> {
                 (
                     NonZeroOid(fe65c1fe15584744e649b2c79d4cf9b0d878f92e),
                     [
-                        NonZeroOid(28790c73f13f38ce0d3beb6cfeb2d818b32bcd09),
+                        NonZeroOid(ff9ac9e15dac94d7e399ff2c5d10dfe2ace6000a),
                     ],
                 ),
             ]
@@ -3726,11 +3720,14 @@ fn test_move_merge_commit() -> eyre::Result<()> {
                             ),
                         },
                         Merge {
-                            commit_oid: NonZeroOid(28790c73f13f38ce0d3beb6cfeb2d818b32bcd09),
+                            commit_oid: NonZeroOid(ff9ac9e15dac94d7e399ff2c5d10dfe2ace6000a),
                             commits_to_merge: [
                                 Label(
                                     "parent-3",
                                 ),
+                                Oid(
+                                    NonZeroOid(8f7aef57d66466a6e0737ae10f67cd98ddecdc66),
+                                ),
                             ],
                         },
                         RegisterExtraPostRewriteHook,
@@ -3740,6 +3737,9 @@ fn test_move_merge_commit() -> eyre::Result<()> {
             branchless: running command: <git-executable> diff --quiet
             Calling Git for on-disk rebase...
             branchless: running command: <git-executable> rebase --continue
+            Trying simple merge with 96d1c37a3d4363611c49f7e52186e189a04c531f
+            Trying simple merge with 8f7aef57d66466a6e0737ae10f67cd98ddecdc66
+            Merge made by the 'octopus' strategy.
             "###);
             insta::assert_snapshot!(stderr, @r###"
             branchless: processing 1 update: ref HEAD
@@ -3748,11 +3748,13 @@ fn test_move_merge_commit() -> eyre::Result<()> {
             Executing: git branchless hook-detect-empty-commit fe65c1fe15584744e649b2c79d4cf9b0d878f92e
             branchless: processing 1 update: ref refs/rewritten/parent-3
             branchless: processing 1 update: ref HEAD
+            branchless: processing 1 update: ref HEAD
+            branchless: processed commit: 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
             Executing: git branchless hook-register-extra-post-rewrite-hook
             branchless: processing 2 rewritten commits
             branchless: creating working copy snapshot
             branchless: running command: <git-executable> checkout 98b9119d16974f372e76cb64a3b77c528fc0b18b
-            Previous HEAD position was 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            Previous HEAD position was 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
             branchless: processing 1 update: ref HEAD
             HEAD is now at 98b9119 create test3.txt
             branchless: processing checkout
@@ -3761,13 +3763,17 @@ fn test_move_merge_commit() -> eyre::Result<()> {
             | @ 98b9119 create test3.txt
             | |
             | | & (merge) 96d1c37 create test2.txt
+            | | & (merge) 8f7aef5 create test4.txt
             | |/
-            | o 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            | o 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
+            |\
+            | o 8f7aef5 create test4.txt
+            | & (merge) 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
             |
             O 62fc20d (master) create test1.txt
             |
             o 96d1c37 create test2.txt
-            & (merge) 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            & (merge) 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
             Successfully rebased and updated detached HEAD.
             branchless: processing 1 update: ref refs/rewritten/parent-3
             "###);
@@ -3781,13 +3787,17 @@ fn test_move_merge_commit() -> eyre::Result<()> {
             | @ 98b9119 create test3.txt
             | |
             | | & (merge) 96d1c37 create test2.txt
+            | | & (merge) 8f7aef5 create test4.txt
             | |/
-            | o 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            | o 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
+            |\
+            | o 8f7aef5 create test4.txt
+            | & (merge) 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
             |
             O 62fc20d (master) create test1.txt
             |
             o 96d1c37 create test2.txt
-            & (merge) 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            & (merge) 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
             "###);
         }
     }
@@ -3836,8 +3846,8 @@ fn test_move_merge_commit() -> eyre::Result<()> {
             )?;
             insta::assert_snapshot!(stdout, @r###"
             Attempting rebase in-memory...
-            Merge commits currently can't be rebased in-memory.
-            The merge commit was: 28790c7 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            Merge commits with more than two parents currently can't be rebased in-memory.
+            The merge commit was: ff9ac9e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
             To resolve merge conflicts, retry this operation with the --merge option.
             "###);
             insta::assert_snapshot!(stderr, @"");
@@ -3849,13 +3859,17 @@ fn test_move_merge_commit() -> eyre::Result<()> {
             O f777ecc create initial.txt
             |\
             | o fe65c1f create test2.txt
-            | & (merge) 28790c7 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            | & (merge) ff9ac9e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
             |\
             | @ 98b9119 create test3.txt
             | |
             | | & (merge) fe65c1f create test2.txt
+            | | & (merge) 8f7aef5 create test4.txt
             | |/
-            | o 28790c7 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            | o ff9ac9e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
+            |\
+            | o 8f7aef5 create test4.txt
+            | & (merge) ff9ac9e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
             |
             O 62fc20d (master) create test1.txt
             "###);
@@ -3874,6 +3888,9 @@ fn test_move_merge_commit() -> eyre::Result<()> {
                 branchless: running command: <git-executable> diff --quiet
                 Calling Git for on-disk rebase...
                 branchless: running command: <git-executable> rebase --continue
+                Trying simple merge with 96d1c37a3d4363611c49f7e52186e189a04c531f
+                Trying simple merge with 8f7aef57d66466a6e0737ae10f67cd98ddecdc66
+                Merge made by the 'octopus' strategy.
                 "###);
                 insta::assert_snapshot!(stderr, @r###"
                 branchless: processing 1 update: ref HEAD
@@ -3882,11 +3899,13 @@ fn test_move_merge_commit() -> eyre::Result<()> {
                 Executing: git branchless hook-detect-empty-commit fe65c1fe15584744e649b2c79d4cf9b0d878f92e
                 branchless: processing 1 update: ref refs/rewritten/parent-3
                 branchless: processing 1 update: ref HEAD
+                branchless: processing 1 update: ref HEAD
+                branchless: processed commit: 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
                 Executing: git branchless hook-register-extra-post-rewrite-hook
                 branchless: processing 2 rewritten commits
                 branchless: creating working copy snapshot
                 branchless: running command: <git-executable> checkout 98b9119d16974f372e76cb64a3b77c528fc0b18b
-                Previous HEAD position was 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+                Previous HEAD position was 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
                 branchless: processing 1 update: ref HEAD
                 HEAD is now at 98b9119 create test3.txt
                 branchless: processing checkout
@@ -3895,13 +3914,17 @@ fn test_move_merge_commit() -> eyre::Result<()> {
                 | @ 98b9119 create test3.txt
                 | |
                 | | & (merge) 96d1c37 create test2.txt
+                | | & (merge) 8f7aef5 create test4.txt
                 | |/
-                | o 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+                | o 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
+                |\
+                | o 8f7aef5 create test4.txt
+                | & (merge) 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
                 |
                 O 62fc20d (master) create test1.txt
                 |
                 o 96d1c37 create test2.txt
-                & (merge) 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+                & (merge) 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
                 Successfully rebased and updated detached HEAD.
                 branchless: processing 1 update: ref refs/rewritten/parent-3
                 "###);
@@ -3915,13 +3938,17 @@ fn test_move_merge_commit() -> eyre::Result<()> {
                 | @ 98b9119 create test3.txt
                 | |
                 | | & (merge) 96d1c37 create test2.txt
+                | | & (merge) 8f7aef5 create test4.txt
                 | |/
-                | o 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+                | o 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
+                |\
+                | o 8f7aef5 create test4.txt
+                | & (merge) 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
                 |
                 O 62fc20d (master) create test1.txt
                 |
                 o 96d1c37 create test2.txt
-                & (merge) 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+                & (merge) 3ed5507 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e'; commit '8f7aef57d66466a6e0737ae10f67cd98ddecdc66' into HEAD
                 "###);
             }
         }
@@ -3930,6 +3957,171 @@ fn test_move_merge_commit() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_move_merge_commit_in_memory() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["merge", &test2_oid.to_string()])?;
+
+    git.run(&["checkout", &test3_oid.to_string()])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o fe65c1f create test2.txt
+        | & (merge) 28790c7 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        |\
+        | @ 98b9119 create test3.txt
+        | |
+        | | & (merge) fe65c1f create test2.txt
+        | |/
+        | o 28790c7 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        |
+        O 62fc20d (master) create test1.txt
+        "###);
+    }
+
+    {
+        {
+            let (stdout, _stderr) =
+                git.branchless("move", &["-s", &test2_oid.to_string(), "-d", "master"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Attempting rebase in-memory...
+            [1/2] Committed as: 96d1c37 create test2.txt
+            [2/2] Committed as: 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            branchless: processing 2 rewritten commits
+            In-memory rebase succeeded.
+            "###);
+        }
+
+        {
+            let stdout = git.smartlog()?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc create initial.txt
+            |\
+            | @ 98b9119 create test3.txt
+            | |
+            | | & (merge) 96d1c37 create test2.txt
+            | |/
+            | o 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            |
+            O 62fc20d (master) create test1.txt
+            |
+            o 96d1c37 create test2.txt
+            & (merge) 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+            "###);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_merge_commit_with_conflict_resolution() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    let test2_oid = git.commit_file_with_contents("test2", 2, "left\n")?;
+    git.run(&["checkout", "HEAD^"])?;
+    let test3_oid = git.commit_file_with_contents("test2", 3, "right\n")?;
+    git.run_with_options(
+        &["merge", &test2_oid.to_string()],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+    git.resolve_file("test2", "resolved\n")?;
+    git.run(&["commit", "--no-edit"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o 200d339 create test2.txt
+        | & (merge) 059a89d Merge commit '200d339d23f8b1ebf07b459a2ba4328cd97ee289' into HEAD
+        |\
+        | o eda0035 create test2.txt
+        | |
+        | | & (merge) 200d339 create test2.txt
+        | |/
+        | @ 059a89d Merge commit '200d339d23f8b1ebf07b459a2ba4328cd97ee289' into HEAD
+        |
+        O 62fc20d (master) create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("move", &["-s", &test3_oid.to_string(), "-d", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 1f78d16 create test2.txt
+        [2/2] Committed as: d99ca6b Merge commit '200d339d23f8b1ebf07b459a2ba4328cd97ee289' into HEAD
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout d99ca6bf782310dcee5920a694e93245610afaef
+        O f777ecc create initial.txt
+        |\
+        | o 200d339 create test2.txt
+        | & (merge) d99ca6b Merge commit '200d339d23f8b1ebf07b459a2ba4328cd97ee289' into HEAD
+        |
+        O 62fc20d (master) create test1.txt
+        |
+        o 1f78d16 create test2.txt
+        |
+        | & (merge) 200d339 create test2.txt
+        |/
+        @ d99ca6b Merge commit '200d339d23f8b1ebf07b459a2ba4328cd97ee289' into HEAD
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o 200d339 create test2.txt
+        | & (merge) d99ca6b Merge commit '200d339d23f8b1ebf07b459a2ba4328cd97ee289' into HEAD
+        |
+        O 62fc20d (master) create test1.txt
+        |
+        o 1f78d16 create test2.txt
+        |
+        | & (merge) 200d339 create test2.txt
+        |/
+        @ d99ca6b Merge commit '200d339d23f8b1ebf07b459a2ba4328cd97ee289' into HEAD
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "HEAD:test2.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        resolved
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_move_merge_commit_both_parents() -> eyre::Result<()> {
     let git = make_git()?;
@@ -3975,21 +4167,30 @@ fn test_move_merge_commit_both_parents() -> eyre::Result<()> {
             "move",
             &["-s", &test3_oid.to_string(), "-d", &test1_oid.to_string()],
         )?;
-        // FIXME: this operation should successfully move the merge commit.
         insta::assert_snapshot!(stdout, @r###"
         Attempting rebase in-memory...
-        [1/3] Committed as: 4838e49 create test3.txt
-        [2/3] Committed as: a248207 create test4.txt
-        [3/3] Committed as: b1f9efa create test5.txt
-        branchless: processing 3 rewritten commits
-        branchless: This operation abandoned 1 commit!
-        branchless: Consider running one of the following:
-        branchless:   - git restack: re-apply the abandoned commits/branches
-        branchless:     (this is most likely what you want to do)
-        branchless:   - git smartlog: assess the situation
-        branchless:   - git hide [<commit>...]: hide the commits from the smartlog
-        branchless:   - git undo: undo the operation
-        hint: disable this hint by running: git config --global branchless.hint.restackWarnAbandoned false
+        [1/4] Committed as: 4838e49 create test3.txt
+        [2/4] Committed as: a248207 create test4.txt
+        [3/4] Committed as: b1f9efa create test5.txt
+        [4/4] Committed as: d9a0a04 Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
+        branchless: processing 4 rewritten commits
+        branchless: running command: <git-executable> checkout d9a0a04d262240c52487c571636d9499d54a70ee
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |\
+        | o 96d1c37 create test2.txt
+        |
+        o 4838e49 create test3.txt
+        |\
+        | o a248207 create test4.txt
+        | & (merge) d9a0a04 Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
+        |
+        o b1f9efa create test5.txt
+        |
+        | & (merge) a248207 create test4.txt
+        |/
+        @ d9a0a04 Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
         In-memory rebase succeeded.
         "###);
     }
@@ -4002,26 +4203,17 @@ fn test_move_merge_commit_both_parents() -> eyre::Result<()> {
         o 62fc20d create test1.txt
         |\
         | o 96d1c37 create test2.txt
-        | |
-        | x 70deb1e (rewritten as 4838e49b) create test3.txt
-        | |\
-        | | x 355e173 (rewritten as a2482074) create test4.txt
-        | | & (merge) 8fb706a Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
-        | |
-        | x 9ea1b36 (rewritten as b1f9efa0) create test5.txt
-        | |
-        | | & (merge) 355e173 (rewritten as a2482074) create test4.txt
-        | |/
-        | @ 8fb706a Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
         |
         o 4838e49 create test3.txt
         |\
         | o a248207 create test4.txt
+        | & (merge) d9a0a04 Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
         |
         o b1f9efa create test5.txt
-        hint: there is 1 abandoned commit in your commit graph
-        hint: to fix this, run: git restack
-        hint: disable this hint by running: git config --global branchless.hint.smartlogFixAbandoned false
+        |
+        | & (merge) a248207 create test4.txt
+        |/
+        @ d9a0a04 Merge commit '355e173bf9c5d2efac2e451da0cdad3fb82b869a' into HEAD
         "###);
     }
 
@@ -4702,3 +4894,800 @@ fn test_move_delete_branch_config_entry() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_move_dry_run() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", "-b", "foo", &test1_oid.to_string()])?;
+    let test3_oid = git.commit_file_with_contents("conflict", 3, "conflict 2\n")?;
+    git.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &["--dry-run", "-s", &test3_oid.to_string(), "-d", "master"],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        [1/2] Committed with conflicts as: ceb50a0 create conflict.txt
+        [2/2] Committed as: 20a9f3d create test4.txt
+        :
+        O e85d25c (master) create conflict.txt
+        |
+        o ceb50a0 (conflicted) create conflict.txt
+        |
+        @ 20a9f3d (> foo) create test4.txt
+        "###);
+    }
+
+    // Nothing should have actually been rewritten.
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d create test1.txt
+        |\
+        | o 60f7e99 create conflict.txt
+        | |
+        | @ 2bd6e4a (> foo) create test4.txt
+        |
+        O e85d25c (master) create conflict.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_edit_plan() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.detach_head()?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.commit_file("test4", 4)?;
+    git.commit_file("test5", 5)?;
+
+    let run_with_editor = |editor: &str, expected_exit_code: i32| {
+        git.branchless_with_options(
+            "move",
+            &[
+                "--edit-plan",
+                "-s",
+                &test3_oid.to_string(),
+                "-d",
+                &test1_oid.to_string(),
+            ],
+            &GitRunOptions {
+                expected_exit_code,
+                env: HashMap::from([("GIT_EDITOR".to_string(), editor.to_string())]),
+                ..Default::default()
+            },
+        )
+    };
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &[
+                "--edit-plan",
+                "--dry-run",
+                "-s",
+                &test3_oid.to_string(),
+                "-d",
+                &test1_oid.to_string(),
+            ],
+            &GitRunOptions {
+                env: HashMap::from([("GIT_EDITOR".to_string(), "cat".to_string())]),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        reset 62fc20d
+        pick 70deb1e # create test3.txt
+        pick 355e173 # create test4.txt
+        pick f81d55c # create test5.txt
+        #
+        # Commands:
+        # reset <commit | label> = move the rebase head to the given commit or label
+        # pick <commit> = apply the commit on top of the rebase head
+        # squash <commit> = fold the commit into the rebase head, combining their
+        #     messages
+        # fixup [-C] <commit> = like squash, but keep only the rebase head's message
+        #     (or with -C, only the commit's message)
+        # label <label> = name the rebase head, so that it can be referred to later
+        # merge <commit> <parent>... = re-create the merge commit on top of the rebase
+        #     head, merging in the given parents
+        # replace <commit> <replacement> <parent>... = use the replacement commit's
+        #     contents for the commit, with the given parents
+        # skip <commit> = skip the commit, as it was already applied upstream
+        # drop <commit> = remove the commit
+        # break = stop the rebase (on-disk rebases only)
+        #
+        # Lines can be re-ordered. If you remove a line for a commit, that commit
+        # will be dropped. If you remove all the commands, the rebase will be aborted.
+        # Everything after a `#` is ignored.
+        [1/3] Committed as: 4838e49 create test3.txt
+        [2/3] Committed as: a248207 create test4.txt
+        [3/3] Committed as: 566e434 create test5.txt
+        :
+        O 62fc20d create test1.txt
+        |\
+        | o 4838e49 create test3.txt
+        | |
+        | o a248207 create test4.txt
+        | |
+        | @ 566e434 create test5.txt
+        |
+        O 96d1c37 (master) create test2.txt
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = run_with_editor("sed -i -e 's/^pick/frobnicate/'", 1)?;
+        insta::assert_snapshot!(stderr, @r###"
+        Invalid rebase plan: line 2: unknown command: frobnicate
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = run_with_editor("sed -i -e '/^[^#]/d'", 1)?;
+        insta::assert_snapshot!(stdout, @r###"
+        The rebase plan was empty, aborting.
+        "###);
+    }
+
+    {
+        let git = git.duplicate_repo()?;
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &[
+                "--edit-plan",
+                "--on-disk",
+                "-s",
+                &test3_oid.to_string(),
+                "-d",
+                &test1_oid.to_string(),
+            ],
+            &GitRunOptions {
+                env: HashMap::from([(
+                    "GIT_EDITOR".to_string(),
+                    "sed -i -e '/test4/d'".to_string(),
+                )]),
+                ..Default::default()
+            },
+        )?;
+        let stdout = remove_rebase_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> diff --quiet
+        Calling Git for on-disk rebase...
+        branchless: running command: <git-executable> rebase --continue
+        Dropping commit: 355e173 create test4.txt
+        "###);
+
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d create test1.txt
+        |\
+        | o 4838e49 create test3.txt
+        | |
+        | @ b1f9efa create test5.txt
+        |
+        O 96d1c37 (master) create test2.txt
+        "###);
+    }
+
+    // Swap `test3` and `test4`, and drop `test5`.
+    {
+        let (stdout, _stderr) =
+            run_with_editor("sed -i -e '/test3/{h;d;}' -e '/test4/G' -e '/test5/d'", 0)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/3] Committed as: bf0d52a create test4.txt
+        [2/3] Committed as: 0a4a701 create test3.txt
+        [3/3] Dropped commit: f81d55c create test5.txt
+        branchless: processing 3 rewritten commits
+        branchless: running command: <git-executable> checkout 0a4a701eca126593fb79c95e65bb94568a64c6ee
+        :
+        O 62fc20d create test1.txt
+        |\
+        | o bf0d52a create test4.txt
+        | |
+        | @ 0a4a701 create test3.txt
+        |
+        O 96d1c37 (master) create test2.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d create test1.txt
+        |\
+        | o bf0d52a create test4.txt
+        | |
+        | @ 0a4a701 create test3.txt
+        |
+        O 96d1c37 (master) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_signs_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    if !git.set_up_ssh_signing()? {
+        return Ok(());
+    }
+
+    git.branchless(
+        "move",
+        &[
+            "--in-memory",
+            "-s",
+            &test3_oid.to_string(),
+            "-d",
+            &test1_oid.to_string(),
+        ],
+    )?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%G? %s", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        G create test3.txt
+        N create test1.txt
+        N create initial.txt
+        "###);
+    }
+
+    git.run(&["config", "user.signingKey", "/nonexistent/signing_key"])?;
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "move",
+            &["--in-memory", "-x", "HEAD", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Failed to sign rebased commit: signing program "ssh-keygen" failed (exit status: 255): Couldn't load public key /nonexistent/signing_key: No such file or directory
+        No commits were rewritten. Check your commit.gpgSign, gpg.format, and user.signingKey configuration and try again.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_move_run_commit_hooks() -> eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    git.run(&["config", "branchless.restack.runCommitHooks", "true"])?;
+    let hooks_dir = git.repo_path.join(".git").join("hooks");
+    let write_hook = |name: &str, contents: &str| -> eyre::Result<()> {
+        let path = hooks_dir.join(name);
+        std::fs::write(&path, contents)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    };
+    write_hook(
+        "pre-commit",
+        "#!/bin/sh
+echo \"pre-commit: $(git ls-files | xargs)\"
+",
+    )?;
+    write_hook(
+        "commit-msg",
+        "#!/bin/sh
+printf '\\nReviewed-by: commit-msg hook\\n' >> \"$1\"
+",
+    )?;
+    write_hook(
+        "post-commit",
+        "#!/bin/sh
+echo \"post-commit: $BRANCHLESS_IN_MEMORY_COMMIT\"
+",
+    )?;
+    // Re-install the `git-branchless` section of the `post-commit` hook, which
+    // should be skipped for in-memory rebases.
+    git.branchless("init", &[])?;
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &[
+                "--in-memory",
+                "-s",
+                &test3_oid.to_string(),
+                "-d",
+                &test1_oid.to_string(),
+            ],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        pre-commit: initial.txt test1.txt test3.txt
+        [1/1] Committed as: 8e57225 create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 8e572257a6bff01f95950f286a1266c2b1bb956e
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |\
+        | o 96d1c37 create test2.txt
+        |
+        @ 8e57225 create test3.txt
+        post-commit: 8e572257a6bff01f95950f286a1266c2b1bb956e
+        In-memory rebase succeeded.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%B"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test3.txt
+
+        Reviewed-by: commit-msg hook
+
+        "###);
+    }
+
+    write_hook(
+        "pre-commit",
+        "#!/bin/sh
+echo 'pre-commit: rejected'
+exit 1
+",
+    )?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["--in-memory", "-x", "HEAD", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        pre-commit: rejected
+        The pre-commit hook failed for commit: 8e57225 create test3.txt
+        No commits were rewritten.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_move_post_commit_hook_not_run_for_rejected_rebase() -> eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    git.run(&["config", "branchless.restack.runCommitHooks", "true"])?;
+    let hooks_dir = git.repo_path.join(".git").join("hooks");
+    let post_commit_log_path = git.repo_path.join("post-commit.log");
+    let write_hook = |name: &str, contents: &str| -> eyre::Result<()> {
+        let path = hooks_dir.join(name);
+        std::fs::write(&path, contents)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    };
+    // Reject the second commit being rebased.
+    write_hook(
+        "pre-commit",
+        "#!/bin/sh
+if git ls-files | grep -q test3.txt; then
+    echo 'pre-commit: rejected'
+    exit 1
+fi
+",
+    )?;
+    write_hook(
+        "post-commit",
+        &format!(
+            "#!/bin/sh
+echo \"$BRANCHLESS_IN_MEMORY_COMMIT\" >> {:?}
+",
+            post_commit_log_path
+        ),
+    )?;
+    git.branchless("init", &[])?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["--in-memory", "-s", &test2_oid.to_string(), "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        pre-commit: rejected
+        The pre-commit hook failed for commit: 70deb1e create test3.txt
+        No commits were rewritten.
+        "###);
+    }
+    assert!(!post_commit_log_path.exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_move_commit_hooks_disabled_by_default() -> eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    let hook_path = git.repo_path.join(".git").join("hooks").join("commit-msg");
+    std::fs::write(
+        &hook_path,
+        "#!/bin/sh
+echo 'commit-msg hook should not run'
+exit 1
+",
+    )?;
+    std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755))?;
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &[
+                "--in-memory",
+                "-s",
+                &test3_oid.to_string(),
+                "-d",
+                &test1_oid.to_string(),
+            ],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 4838e49 create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 4838e49b08954becdd17c0900c1179c2c654c627
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |\
+        | o 96d1c37 create test2.txt
+        |
+        @ 4838e49 create test3.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_in_memory_upstream_rename() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.commit_file_with_contents("foo", 1, "line 1\nline 2\nline 3\nline 4\nline 5\n")?;
+    git.run(&["checkout", "-b", "feature"])?;
+    git.commit_file_with_contents("foo", 2, "line 1\nline 2\nline 3 updated\nline 4\nline 5\n")?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["mv", "foo.txt", "bar.txt"])?;
+    git.run(&["commit", "-m", "rename foo.txt to bar.txt"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("move", &["--in-memory", "-x", "feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 084cf0e create foo.txt
+        branchless: processing 1 update: branch feature
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        :
+        @ f409c73 (> master) rename foo.txt to bar.txt
+        |
+        o 084cf0e (feature) create foo.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["ls-tree", "--name-only", "feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        bar.txt
+        initial.txt
+        "###);
+        let (stdout, _stderr) = git.run(&["show", "feature:bar.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        line 1
+        line 2
+        line 3 updated
+        line 4
+        line 5
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_in_memory_merge_union() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.write_file(".gitattributes", "*.txt merge=union\n")?;
+    git.commit_file_with_contents("foo", 1, "line 1\n")?;
+    git.run(&["checkout", "-b", "feature"])?;
+    git.commit_file_with_contents("foo", 2, "line 1\nfeature line\n")?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file_with_contents("foo", 3, "line 1\nmaster line\n")?;
+
+    {
+        let (stdout, _stderr) = git.branchless("move", &["--in-memory", "-x", "feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: cb48514 create foo.txt
+        branchless: processing 1 update: branch feature
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        :
+        @ 1df6e29 (> master) create foo.txt
+        |
+        o cb48514 (feature) create foo.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["show", "feature:foo.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        line 1
+        master line
+        feature line
+        "###);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_move_in_memory_custom_merge_driver() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.write_file(".gitattributes", "*.txt merge=sorted\n")?;
+    git.run(&["config", "merge.sorted.driver", "sort -u %A %B -o %A"])?;
+    git.commit_file_with_contents("foo", 1, "b\n")?;
+    git.run(&["checkout", "-b", "feature"])?;
+    git.commit_file_with_contents("foo", 2, "c\n")?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file_with_contents("foo", 3, "a\n")?;
+
+    {
+        let (stdout, _stderr) = git.branchless("move", &["--in-memory", "-x", "feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: b32487b create foo.txt
+        branchless: processing 1 update: branch feature
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        :
+        @ be90e51 (> master) create foo.txt
+        |
+        o b32487b (feature) create foo.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["show", "feature:foo.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        a
+        c
+        "###);
+    }
+
+    // If the driver fails, then the merge is considered to have conflicts.
+    git.run(&["config", "merge.sorted.driver", "false"])?;
+    git.run(&["checkout", "-b", "feature2", "master~"])?;
+    git.commit_file_with_contents("foo", 4, "d\n")?;
+    git.run(&["checkout", "master"])?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["--in-memory", "-x", "feature2"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        This operation would cause a merge conflict:
+        - (1 conflicting file) 482555a create foo.txt
+        To resolve merge conflicts, retry this operation with the --merge option.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_in_memory_renormalize() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.commit_file_with_contents("foo", 1, "line 1\r\nline 2\r\nline 3\r\n")?;
+    git.run(&["checkout", "-b", "feature"])?;
+    git.commit_file_with_contents("foo", 2, "line 1\r\nline 2 updated\r\nline 3\r\n")?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file_with_contents("foo", 3, "line 1\nline 2\nline 3\n")?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["--in-memory", "-x", "feature"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        This operation would cause a merge conflict:
+        - (1 conflicting file) 26c29ab create foo.txt
+        To resolve merge conflicts, retry this operation with the --merge option.
+        "###);
+    }
+
+    git.run(&["config", "merge.renormalize", "true"])?;
+    {
+        let (stdout, _stderr) = git.branchless("move", &["--in-memory", "-x", "feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 7eb26db create foo.txt
+        branchless: processing 1 update: branch feature
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        :
+        @ eac4e59 (> master) create foo.txt
+        |
+        o 7eb26db (feature) create foo.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["show", "feature:foo.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        line 1
+        line 2 updated
+        line 3
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_in_memory_rerere() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "rerere.enabled", "true"])?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    let dest_oid = git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+    // Record the resolution during an on-disk rebase.
+    git.branchless_with_options(
+        "move",
+        &["--merge", "--source", &other_oid.to_string()],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+    git.resolve_file("conflict", "resolved")?;
+    git.run(&["rebase", "--continue"])?;
+
+    git.run(&["checkout", "-b", "feature", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 3, "conflict 1\n")?;
+    {
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &["--in-memory", "-x", "feature", "-d", &dest_oid.to_string()],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Resolved conflict in conflict.txt using previous resolution
+        [1/1] Committed as: b8366d1 create conflict.txt
+        branchless: processing 1 update: branch feature
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout feature
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 202143f create conflict.txt
+        |\
+        | o 42951b5 create conflict.txt
+        |
+        @ b8366d1 (> feature) create conflict.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["show", "feature:conflict.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        resolved
+        "###);
+    }
+
+    // Without `rerere`, the same conflict can't be resolved in memory.
+    git.run(&["config", "rerere.enabled", "false"])?;
+    git.run(&["checkout", "-b", "feature2", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 4, "conflict 1\n")?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["--in-memory", "-x", "feature2", "-d", &dest_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        This operation would cause a merge conflict:
+        - (1 conflicting file) 8bbdcaa create conflict.txt
+        To resolve merge conflicts, retry this operation with the --merge option.
+        "###);
+    }
+
+    Ok(())
+}
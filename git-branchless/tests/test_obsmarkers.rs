@@ -0,0 +1,157 @@
+use itertools::Itertools;
+use lib::testing::{
+    make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
+};
+
+#[test]
+fn test_obsmarkers_export_import() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["checkout", "-b", "foo"])?;
+    original_repo.commit_file("test2", 2)?;
+    let test3_oid = original_repo.commit_file("test3", 3)?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["checkout", "foo"])?;
+    cloned_repo.run(&[
+        "config",
+        "--add",
+        "remote.origin.fetch",
+        "+refs/branchless/obsmarkers:refs/branchless/remotes/origin/obsmarkers",
+    ])?;
+
+    original_repo.branchless("move", &["-x", &test3_oid.to_string(), "-d", "master"])?;
+    {
+        let (stdout, _stderr) = original_repo.branchless("obsmarkers", &["export"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Exported 1 new obsolescence marker to refs/branchless/obsmarkers (1 total)
+        "###);
+    }
+    {
+        let (stdout, _stderr) = original_repo.branchless("obsmarkers", &["export"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Exported 0 new obsolescence markers to refs/branchless/obsmarkers (1 total)
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = cloned_repo.run(&["fetch", "origin"])?;
+        let stderr = stderr
+            .lines()
+            .filter(|line| line.starts_with("branchless:"))
+            .join("\n");
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: remote branch origin/foo
+        branchless: imported 1 obsolescence marker from refs/branchless/remotes/origin/obsmarkers
+        "###);
+    }
+    {
+        let stdout = cloned_repo.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |\
+        | o 96d1c37 create test2.txt
+        | |
+        | % 70deb1e (rewritten as 4838e49b) (> foo) create test3.txt
+        |
+        o 4838e49 create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("obsmarkers", &["import"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Imported 0 obsolescence markers from refs/branchless/remotes/origin/obsmarkers
+        "###);
+    }
+
+    cloned_repo.branchless("restack", &[])?;
+    {
+        let stdout = cloned_repo.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 4838e49 (> foo) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_obsmarkers_stale_and_duplicate_markers() -> eyre::Result<()> {
+    let git = make_git()?;
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.branchless("hide", &[&test1_oid.to_string()])?;
+    git.branchless("unhide", &[&test1_oid.to_string()])?;
+
+    // Write a marker which predates the local unhide, duplicated as if the
+    // blob had been edited by hand.
+    let make_obsmarkers_commit = |contents: &str| -> eyre::Result<String> {
+        let blob_path = git.repo_path.join("obsmarkers.txt");
+        std::fs::write(&blob_path, contents)?;
+        let (blob_oid, _stderr) = git.run(&["hash-object", "-w", blob_path.to_str().unwrap()])?;
+        std::fs::remove_file(&blob_path)?;
+        let (tree_oid, _stderr) = git.run_with_options(
+            &["mktree"],
+            &GitRunOptions {
+                input: Some(format!("100644 blob {}\tobsmarkers\n", blob_oid.trim())),
+                ..Default::default()
+            },
+        )?;
+        let (commit_oid, _stderr) = git.run(&["commit-tree", "-m", "markers", tree_oid.trim()])?;
+        Ok(commit_oid.trim().to_owned())
+    };
+    let stale_obsmarker = format!("obsolete 1 {test1_oid}\n");
+    let commit_oid = make_obsmarkers_commit(&stale_obsmarker.repeat(4))?;
+    git.run(&[
+        "update-ref",
+        "refs/branchless/remotes/origin/obsmarkers",
+        &commit_oid,
+    ])?;
+    git.run(&["update-ref", "refs/branchless/obsmarkers", &commit_oid])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("obsmarkers", &["import"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Imported 0 obsolescence markers from refs/branchless/remotes/origin/obsmarkers
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless("obsmarkers", &["export"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Exported 2 new obsolescence markers to refs/branchless/obsmarkers (3 total)
+        "###);
+    }
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 62fc20d create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
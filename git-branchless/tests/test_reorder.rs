@@ -0,0 +1,320 @@
+use std::sync::{Arc, Mutex};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use git_branchless_opts::Revset;
+use git_branchless_reorder::testing::reorder;
+use lib::core::effects::Effects;
+use lib::core::formatting::Glyphs;
+use lib::git::GitRunInfo;
+use lib::testing::{make_git, trim_lines, Git};
+use lib::util::ExitCode;
+
+fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+fn run_reorder(
+    git: &Git,
+    revset: &str,
+    create_conflict_commits: bool,
+    events: Vec<Event>,
+) -> eyre::Result<(isize, String, Option<String>)> {
+    let glyphs = Glyphs::text();
+    let repo = git.get_repo()?;
+    let output: Arc<Mutex<Vec<u8>>> = Default::default();
+    let effects = Effects::new_from_buffer_for_test(glyphs, &output, &output);
+    let git_run_info = GitRunInfo {
+        path_to_git: git.path_to_git.clone(),
+        working_directory: repo.get_working_copy_path().unwrap().to_path_buf(),
+        env: git.get_base_env(0).into_iter().collect(),
+    };
+
+    let (ExitCode(exit_code), screen) = reorder(
+        &effects,
+        &git_run_info,
+        &repo,
+        Revset(revset.to_string()),
+        create_conflict_commits,
+        events,
+    )?;
+
+    let output = String::from_utf8(output.lock().unwrap().clone())?;
+    let output = trim_lines(git.preprocess_output(output)?);
+    Ok((exit_code, output, screen))
+}
+
+#[test]
+fn test_reorder_move_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    let test4_oid = git.commit_file("test4", 4)?;
+    git.commit_file("test5", 5)?;
+
+    {
+        let (exit_code, output, screen) = run_reorder(
+            &git,
+            &format!("{test2_oid}::{test4_oid}"),
+            false,
+            vec![
+                key(KeyCode::Down),
+                key(KeyCode::Down),
+                key(KeyCode::Char('K')),
+                key(KeyCode::Char('K')),
+                key(KeyCode::Enter),
+            ],
+        )?;
+        insta::assert_snapshot!(screen.unwrap(), @r###"
+        up/down, k/j: select   shift-up/shift-down, K/J: move up/down
+        p: pick   s: squash into previous   d: drop   enter, c: confirm   q: cancel
+
+        pick   8e62740 create test4.txt
+        pick   fe65c1f create test2.txt
+        pick   0206717 create test3.txt
+
+
+
+
+        "###);
+        insta::assert_snapshot!(output, @r###"
+        Attempting rebase in-memory...
+        [1/4] Committed as: 8f7aef5 create test4.txt
+        [2/4] Committed as: fa82175 create test2.txt
+        [3/4] Committed as: 127dd23 create test3.txt
+        [4/4] Committed as: 1e262ed create test5.txt
+        branchless: processing 4 rewritten commits
+        branchless: creating working copy snapshot
+        branchless: running command: <git-executable> checkout 1e262edd2b584a1a6d69fb2361644a8bb07ee993
+        Previous HEAD position was 447139a create test5.txt
+        branchless: processing 1 update: ref HEAD
+        HEAD is now at 1e262ed create test5.txt
+        branchless: processing checkout
+        In-memory rebase succeeded.
+        "###);
+        assert_eq!(exit_code, 0);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 8f7aef5 create test4.txt
+        |
+        o fa82175 create test2.txt
+        |
+        o 127dd23 create test3.txt
+        |
+        @ 1e262ed create test5.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reorder_squash_and_drop() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.commit_file("test4", 4)?;
+    git.commit_file("test5", 5)?;
+
+    {
+        let (exit_code, output, screen) = run_reorder(
+            &git,
+            "stack()",
+            false,
+            vec![
+                key(KeyCode::Down),
+                key(KeyCode::Char('s')),
+                key(KeyCode::Down),
+                key(KeyCode::Char('d')),
+                key(KeyCode::Enter),
+            ],
+        )?;
+        insta::assert_snapshot!(screen.unwrap(), @r###"
+        up/down, k/j: select   shift-up/shift-down, K/J: move up/down
+        p: pick   s: squash into previous   d: drop   enter, c: confirm   q: cancel
+
+        pick   fe65c1f create test2.txt
+        squash 0206717 create test3.txt
+        drop   8e62740 create test4.txt
+        pick   447139a create test5.txt
+
+
+
+        "###);
+        insta::assert_snapshot!(output, @r###"
+        Attempting rebase in-memory...
+        [1/4] Committed as: fe65c1f create test2.txt
+        [2/4] Squashed into: 47d3533 create test2.txt
+        [3/4] Dropped commit: 8e62740 create test4.txt
+        [4/4] Committed as: 37d0b0c create test5.txt
+        branchless: processing 4 rewritten commits
+        branchless: creating working copy snapshot
+        branchless: running command: <git-executable> checkout 37d0b0c905e4f49f08f66380287b99ee85d9c185
+        Previous HEAD position was 447139a create test5.txt
+        branchless: processing 1 update: ref HEAD
+        HEAD is now at 37d0b0c create test5.txt
+        branchless: processing checkout
+        In-memory rebase succeeded.
+        "###);
+        assert_eq!(exit_code, 0);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 47d3533 create test2.txt
+        |
+        @ 37d0b0c create test5.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "--stat", "HEAD^"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        commit 47d35339c8342819cf924780719f2361f2ccd6e2
+        Author: Testy McTestface <test@example.com>
+        Date:   Thu Oct 29 12:34:56 2020 -0200
+
+            create test2.txt
+            
+            create test3.txt
+
+         test2.txt | 1 +
+         test3.txt | 1 +
+         2 files changed, 2 insertions(+)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reorder_invalid() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (exit_code, output, _screen) =
+            run_reorder(&git, "stack()", false, vec![key(KeyCode::Char('q'))])?;
+        insta::assert_snapshot!(output, @r###"
+        Aborted.
+        "###);
+        assert_eq!(exit_code, 1);
+    }
+
+    {
+        let (exit_code, output, _screen) =
+            run_reorder(&git, "stack()", false, vec![key(KeyCode::Enter)])?;
+        insta::assert_snapshot!(output, @r###"
+        The commits were not changed; nothing to do.
+        "###);
+        assert_eq!(exit_code, 0);
+    }
+
+    {
+        let (exit_code, output, _screen) = run_reorder(
+            &git,
+            "stack()",
+            false,
+            vec![key(KeyCode::Char('s')), key(KeyCode::Enter)],
+        )?;
+        insta::assert_snapshot!(output, @r###"
+        Cannot squash this commit, as there is no earlier commit to squash it into: fe65c1f create test2.txt
+        "###);
+        assert_eq!(exit_code, 1);
+    }
+
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file("test4", 4)?;
+    {
+        let (exit_code, output, screen) = run_reorder(&git, "stack()", false, vec![])?;
+        insta::assert_snapshot!(output, @r###"
+        The commits to reorder must form a single linear stack, but this commit does not: 4d4b1fe create test4.txt
+        "###);
+        assert_eq!(screen, None);
+        assert_eq!(exit_code, 1);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reorder_merge_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+
+    git.detach_head()?;
+    git.commit_file_with_contents("test2", 2, "foo\n")?;
+    git.commit_file_with_contents("test2", 3, "bar\n")?;
+
+    let events = || {
+        vec![
+            key(KeyCode::Down),
+            key(KeyCode::Char('K')),
+            key(KeyCode::Enter),
+        ]
+    };
+
+    {
+        let (exit_code, output, _screen) = run_reorder(&git, "stack()", false, events())?;
+        insta::assert_snapshot!(output, @r###"
+        Attempting rebase in-memory...
+        This operation would cause a merge conflict:
+        - (1 conflicting file) 7d6071a create test2.txt
+        To commit the conflicts and resolve them later, retry this operation with the --commit-conflicts option.
+        "###);
+        assert_eq!(exit_code, 1);
+    }
+
+    {
+        let (exit_code, output, _screen) = run_reorder(&git, "stack()", true, events())?;
+        insta::assert_snapshot!(output, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed with conflicts as: 0bc5735 create test2.txt
+        [2/2] Committed as: af85760 create test2.txt
+        branchless: processing 2 rewritten commits
+        branchless: creating working copy snapshot
+        branchless: running command: <git-executable> checkout 0bc57354c95739e60e8b61a33a0604a1e8514e0e
+        Previous HEAD position was 7d6071a create test2.txt
+        branchless: processing 1 update: ref HEAD
+        HEAD is now at 0bc5735 create test2.txt
+        branchless: processing checkout
+        In-memory rebase succeeded.
+        1 commit was committed with merge conflicts. To find them, run: git query 'conflicted()'
+        To resolve the conflicts in a commit, run: git branchless resolve <commit>
+        "###);
+        assert_eq!(exit_code, 0);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 0bc5735 (conflicted) create test2.txt
+        |
+        o af85760 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
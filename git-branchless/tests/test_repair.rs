@@ -1,6 +1,12 @@
+use std::time::SystemTime;
+
 use lib::{
-    git::{BranchType, ReferenceName},
-    testing::make_git,
+    core::{
+        eventlog::EventLogDb,
+        rewrite::{BranchMove, RewriteJournal},
+    },
+    git::{BranchType, MaybeZeroOid, NonZeroOid, ReferenceName},
+    testing::{make_git, Git, GitRunOptions},
 };
 
 #[test]
@@ -90,3 +96,256 @@ fn test_repair_broken_branch() -> eyre::Result<()> {
 
     Ok(())
 }
+
+/// Simulate a rewrite operation which was interrupted after moving branch
+/// `foo` but before moving branch `bar`, which is checked out.
+fn make_interrupted_rewrite(git: &Git) -> eyre::Result<()> {
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["branch", "bar"])?;
+    git.run(&["checkout", "bar"])?;
+
+    // Create the rewritten commits without notifying git-branchless.
+    let (new_test1_oid, _stderr) = git.run(&[
+        "commit-tree",
+        "-p",
+        "master",
+        "-m",
+        "create test1.txt v2",
+        &format!("{test1_oid}^{{tree}}"),
+    ])?;
+    let new_test1_oid: NonZeroOid = new_test1_oid.trim().parse()?;
+    let (new_test2_oid, _stderr) = git.run(&[
+        "commit-tree",
+        "-p",
+        &new_test1_oid.to_string(),
+        "-m",
+        "create test2.txt v2",
+        &format!("{test2_oid}^{{tree}}"),
+    ])?;
+    let new_test2_oid: NonZeroOid = new_test2_oid.trim().parse()?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let journal = RewriteJournal {
+        event_tx_id: event_log_db.make_transaction_id(SystemTime::now(), "move")?,
+        head_info: repo.get_head_info()?,
+        rewritten_oids: vec![
+            (test1_oid, MaybeZeroOid::NonZero(new_test1_oid)),
+            (test2_oid, MaybeZeroOid::NonZero(new_test2_oid)),
+        ],
+        skipped_head_updated_oid: None,
+        branch_moves: vec![
+            BranchMove {
+                reference_name: ReferenceName::from("refs/heads/foo"),
+                old_oid: test1_oid,
+                new_oid: MaybeZeroOid::NonZero(new_test1_oid),
+            },
+            BranchMove {
+                reference_name: ReferenceName::from("refs/heads/bar"),
+                old_oid: test2_oid,
+                new_oid: MaybeZeroOid::NonZero(new_test2_oid),
+            },
+        ],
+        rewrites_recorded: false,
+    };
+    journal.write(&repo)?;
+    git.run(&["checkout", "--detach"])?;
+    git.run(&["branch", "-f", "foo", &new_test1_oid.to_string()])?;
+    Ok(())
+}
+
+#[test]
+fn test_repair_interrupted_rewrite_finish() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    make_interrupted_rewrite(&git)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("repair", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found interrupted rewrite operation: 1 of 2 branches updated.
+        The operation will be finished when run with --no-dry-run.
+        (This was a dry-run; run with --no-dry-run to apply changes.)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("repair", &["--no-dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found interrupted rewrite operation: 1 of 2 branches updated.
+        branchless: processing 1 update: branch bar
+        Finished the interrupted rewrite operation.
+        branchless: running command: <git-executable> checkout bar
+        O f777ecc (master) create initial.txt
+        |
+        o 911982d (foo) create test1.txt v2
+        |
+        @ bd5b83a (> bar) create test2.txt v2
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 911982d (foo) create test1.txt v2
+        |
+        @ bd5b83a (> bar) create test2.txt v2
+        "###);
+    }
+
+    // The journal should have been removed.
+    {
+        let (stdout, _stderr) = git.branchless("repair", &["--no-dry-run"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_interrupted_rewrite_rollback() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    make_interrupted_rewrite(&git)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("repair", &["--no-dry-run", "--rollback"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found interrupted rewrite operation: 1 of 2 branches updated.
+        Rolled back the interrupted rewrite operation.
+        branchless: running command: <git-executable> checkout bar
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d (foo) create test1.txt
+        |
+        @ 96d1c37 (> bar) create test2.txt
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d (foo) create test1.txt
+        |
+        @ 96d1c37 (> bar) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_interrupted_rewrite_skipped_head() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    // Simulate a move of `foo` which dropped the commit at `HEAD`, since it
+    // became empty, and was interrupted before moving `foo`.
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    let (new_test1_oid, _stderr) = git.run(&[
+        "commit-tree",
+        "-p",
+        "master",
+        "-m",
+        "create test1.txt v2",
+        &format!("{test1_oid}^{{tree}}"),
+    ])?;
+    let new_test1_oid: NonZeroOid = new_test1_oid.trim().parse()?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let journal = RewriteJournal {
+        event_tx_id: event_log_db.make_transaction_id(SystemTime::now(), "move")?,
+        head_info: repo.get_head_info()?,
+        rewritten_oids: vec![
+            (test1_oid, MaybeZeroOid::NonZero(new_test1_oid)),
+            (test2_oid, MaybeZeroOid::Zero),
+        ],
+        skipped_head_updated_oid: Some(new_test1_oid),
+        branch_moves: vec![BranchMove {
+            reference_name: ReferenceName::from("refs/heads/foo"),
+            old_oid: test1_oid,
+            new_oid: MaybeZeroOid::NonZero(new_test1_oid),
+        }],
+        rewrites_recorded: false,
+    };
+    journal.write(&repo)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("repair", &["--no-dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found interrupted rewrite operation: 0 of 1 branch updated.
+        branchless: processing 1 update: branch foo
+        Finished the interrupted rewrite operation.
+        branchless: running command: <git-executable> checkout foo
+        O f777ecc (master) create initial.txt
+        |
+        @ 911982d (> foo) create test1.txt v2
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 911982d (> foo) create test1.txt v2
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_interrupted_rewrite_blocks_new_rewrite() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    make_interrupted_rewrite(&git)?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["-x", "bar", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        A previous rewrite operation was interrupted before it finished updating branches.
+        Run git branchless repair --no-dry-run to finish it (or add --rollback to roll it back), then try again.
+        "###);
+    }
+
+    {
+        git.branchless("repair", &["--no-dry-run"])?;
+        let (stdout, _stderr) = git.branchless("move", &["-x", "bar", "-d", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 0506bfa create test2.txt v2
+        branchless: processing 1 update: branch bar
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout bar
+        O f777ecc (master) create initial.txt
+        |\
+        | @ 0506bfa (> bar) create test2.txt v2
+        |
+        o 911982d (foo) create test1.txt v2
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_resolve_conflict_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &["--commit-conflicts", "--source", &other_oid.to_string()],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed with conflicts as: e970b56 create conflict.txt
+        [2/2] Committed as: 54a76c6 create test3.txt
+        branchless: processing 2 rewritten commits
+        In-memory rebase succeeded.
+        1 commit was committed with merge conflicts. To find them, run: git query 'conflicted()'
+        To resolve the conflicts in a commit, run: git branchless resolve <commit>
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("query", &["conflicted()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        e970b56 create conflict.txt
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "resolve",
+            &["."],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Not a conflict commit: 202143f create conflict.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("resolve", &["conflicted()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> diff --quiet
+        branchless: running command: <git-executable> checkout e970b561daea517afeb2de3740c18571a3a40d3e
+        branchless: running command: <git-executable> checkout --merge -- conflict.txt
+        Wrote merge conflicts for 1 path to the working copy:
+        - conflict.txt
+        To finish resolving the conflicts, fix the files, stage them with `git add`, and then run: git amend
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        AA conflict.txt
+        "###);
+        let contents = std::fs::read_to_string(git.repo_path.join("conflict.txt"))?;
+        insta::assert_snapshot!(contents, @r###"
+        <<<<<<< ours
+        conflict 2
+        =======
+        conflict 1
+        >>>>>>> theirs
+        "###);
+    }
+
+    git.write_file_txt("conflict", "resolved\n")?;
+    git.run(&["add", "conflict.txt"])?;
+    {
+        let (stdout, _stderr) = git.branchless("amend", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> reset a136512575b9188e77b8da0ddf7a07a4d0cda8de
+        branchless: running command: <git-executable> checkout a136512575b9188e77b8da0ddf7a07a4d0cda8de
+        Attempting rebase in-memory...
+        [1/1] Committed as: f2df6a0 create test3.txt
+        branchless: processing 1 rewritten commit
+        In-memory rebase succeeded.
+        Restacked 1 commit.
+        Amended with 1 staged change.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("query", &["conflicted()"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 202143f create conflict.txt
+        |
+        @ a136512 create conflict.txt
+        |
+        o f2df6a0 create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_conflict_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+    git.branchless(
+        "move",
+        &["--commit-conflicts", "--source", &other_oid.to_string()],
+    )?;
+
+    // Moving the conflict commit back onto its original base re-does the
+    // merge, which no longer conflicts.
+    {
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &["--source", "conflicted()", "--dest", &base_oid.to_string()],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: e85d25c create conflict.txt
+        branchless: processing 1 rewritten commit
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("query", &["conflicted()"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |\
+        | @ 202143f create conflict.txt
+        |
+        o e85d25c create conflict.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_resolve_conflict_commit_rerere() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "rerere.enabled", "true"])?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    let dest_oid = git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+    git.branchless(
+        "move",
+        &["--commit-conflicts", "--source", &other_oid.to_string()],
+    )?;
+    {
+        let (stdout, stderr) = git.branchless("resolve", &["conflicted()"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: creating working copy snapshot
+        Previous HEAD position was 202143f create conflict.txt
+        branchless: processing 1 update: ref HEAD
+        HEAD is now at e970b56 create conflict.txt
+        branchless: processing checkout
+        Recorded preimage for 'conflict.txt'
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> diff --quiet
+        branchless: running command: <git-executable> checkout e970b561daea517afeb2de3740c18571a3a40d3e
+        branchless: running command: <git-executable> checkout --merge -- conflict.txt
+        branchless: running command: <git-executable> rerere
+        Wrote merge conflicts for 1 path to the working copy:
+        - conflict.txt
+        To finish resolving the conflicts, fix the files, stage them with `git add`, and then run: git amend
+        "###);
+    }
+
+    // Recording the resolution when amending lets later in-memory rebases
+    // reuse it.
+    git.write_file_txt("conflict", "resolved\n")?;
+    git.run(&["add", "conflict.txt"])?;
+    {
+        let (stdout, stderr) = git.branchless("amend", &[])?;
+        insta::assert_snapshot!(stderr, @r###"
+        Recorded resolution for 'conflict.txt'.
+        branchless: creating working copy snapshot
+        branchless: processing 1 update: ref HEAD
+        branchless: processing 1 update: ref HEAD
+        HEAD is now at a136512 create conflict.txt
+        branchless: processing checkout
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> rerere
+        branchless: running command: <git-executable> reset a136512575b9188e77b8da0ddf7a07a4d0cda8de
+        branchless: running command: <git-executable> checkout a136512575b9188e77b8da0ddf7a07a4d0cda8de
+        Amended with 1 staged change.
+        "###);
+    }
+
+    git.run(&["checkout", &base_oid.to_string()])?;
+    let other2_oid = git.commit_file_with_contents("conflict", 3, "conflict 1\n")?;
+    {
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &[
+                "--in-memory",
+                "-x",
+                &other2_oid.to_string(),
+                "-d",
+                &dest_oid.to_string(),
+            ],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Resolved conflict in conflict.txt using previous resolution
+        [1/1] Committed as: d81bb8b create conflict.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout d81bb8bd9de4ba4e1a38b38f80220b20d9e008ab
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 202143f create conflict.txt
+        |\
+        | o a136512 create conflict.txt
+        |
+        @ d81bb8b create conflict.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    Ok(())
+}
@@ -562,3 +562,218 @@ fn test_restack_non_observed_branch_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_restack_dry_run() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.run(&["commit", "--amend", "-m", "amend test1"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("restack", &["--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [1/1] Committed as: 7ffa962 create test2.txt
+        O f777ecc (master) create initial.txt
+        |
+        @ 9e8dbe9 amend test1
+        |
+        o 7ffa962 (foo) create test2.txt
+        "###);
+    }
+
+    // Nothing should have actually been rewritten.
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | @ 9e8dbe9 amend test1
+        |
+        x 62fc20d (rewritten as 9e8dbe91) create test1.txt
+        |
+        o 96d1c37 (foo) create test2.txt
+        hint: there is 1 abandoned commit in your commit graph
+        hint: to fix this, run: git restack
+        hint: disable this hint by running: git config --global branchless.hint.smartlogFixAbandoned false
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_restack_autosquash() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.write_file_txt("test1", "test1 fixed\n")?;
+    git.run(&["commit", "-am", "fixup! create test1.txt"])?;
+    git.write_file_txt("test2", "test2 squashed\n")?;
+    git.run(&[
+        "commit",
+        "-am",
+        "squash! create test2.txt",
+        "-m",
+        "squash message",
+    ])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("restack", &["--autosquash"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/5] Committed as: 62fc20d create test1.txt
+        [2/5] Squashed into: d00a224 create test1.txt
+        [3/5] Committed as: d6b7081 create test2.txt
+        [4/5] Squashed into: dc52fcb create test2.txt
+        [5/5] Committed as: ad8e153 create test3.txt
+        branchless: processing 5 rewritten commits
+        branchless: running command: <git-executable> checkout ad8e153dd6edb5d759d38a928183f00633156596
+        In-memory rebase succeeded.
+        Finished autosquashing commits.
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o d00a224 create test1.txt
+        |
+        o dc52fcb create test2.txt
+        |
+        @ ad8e153 create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s%n%b---", "master..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test3.txt
+        ---
+        create test2.txt
+        squash message
+        ---
+        create test1.txt
+        ---
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "HEAD~2:test1.txt", "HEAD~1:test2.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        test1 fixed
+        test2 squashed
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_restack_autosquash_amend() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.write_file_txt("test1", "test1 amended\n")?;
+    git.run(&[
+        "commit",
+        "-am",
+        &format!("amend! {test1_oid}"),
+        "-m",
+        "reworded test1.txt",
+    ])?;
+    git.write_file_txt("test1", "test1 amended twice\n")?;
+    git.run(&["commit", "-a", "--fixup", "HEAD"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("restack", &["--autosquash", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [1/4] Committed as: 62fc20d create test1.txt
+        [2/4] Squashed into: dd8de53 reworded test1.txt
+        [3/4] Squashed into: ec59587 reworded test1.txt
+        [4/4] Committed as: dd4ca68 create test2.txt
+        O f777ecc (master) create initial.txt
+        |
+        @ ec59587 reworded test1.txt
+        |
+        o dd4ca68 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("restack", &["--autosquash"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/4] Committed as: 62fc20d create test1.txt
+        [2/4] Squashed into: dd8de53 reworded test1.txt
+        [3/4] Squashed into: ec59587 reworded test1.txt
+        [4/4] Committed as: dd4ca68 create test2.txt
+        branchless: processing 4 rewritten commits
+        branchless: running command: <git-executable> checkout ec595874c245e3158946c4863bf2603389628c46
+        In-memory rebase succeeded.
+        Finished autosquashing commits.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s%n%b---", "master..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        reworded test1.txt
+        ---
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "HEAD:test1.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        test1 amended twice
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_restack_autosquash_no_fixups() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["commit", "--allow-empty", "-m", "fixup! nonexistent"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("restack", &["--autosquash"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No fixup commits to autosquash.
+        "###);
+    }
+
+    Ok(())
+}
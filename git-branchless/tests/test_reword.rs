@@ -660,3 +660,104 @@ fn test_reword_merge_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_reword_signs_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    if !git.set_up_ssh_signing()? {
+        return Ok(());
+    }
+
+    git.branchless("reword", &["HEAD^", "--message", "new message"])?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%G? %s", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        G create test2.txt
+        G new message
+        N create initial.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_reword_run_commit_hooks() -> eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.run(&["config", "branchless.restack.runCommitHooks", "true"])?;
+    let hook_path = git.repo_path.join(".git").join("hooks").join("commit-msg");
+    std::fs::write(
+        &hook_path,
+        "#!/bin/sh
+grep -q '^Reviewed-by:' \"$1\" || printf '\\nReviewed-by: commit-msg hook\\n' >> \"$1\"
+",
+    )?;
+    std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755))?;
+
+    git.branchless("reword", &["HEAD^", "--message", "new message"])?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s%n%(trailers)", "HEAD~2..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+        Reviewed-by: commit-msg hook
+
+        new message
+        Reviewed-by: commit-msg hook
+
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_change_ids() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.run(&["config", "branchless.changeIds", "true"])?;
+    git.branchless("reword", &["HEAD^", "--message", "new message"])?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s%n%(trailers)", "HEAD~2..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+        Change-Id: Ic31323efd25099c2e582e1bbf6ed5974380f7a4f
+
+        new message
+        Change-Id: I423e73c42f227ddfc6981f193ea2e0719157ba19
+
+        "###);
+    }
+
+    // The new message doesn't have a `Change-Id`, so the existing one should
+    // be kept.
+    git.branchless("reword", &["HEAD^", "--message", "newer message"])?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s%n%(trailers)", "HEAD~2..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+        Change-Id: Ic31323efd25099c2e582e1bbf6ed5974380f7a4f
+
+        newer message
+        Change-Id: I423e73c42f227ddfc6981f193ea2e0719157ba19
+
+        "###);
+    }
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use itertools::Itertools;
 use lib::testing::{make_git, make_git_with_remote_repo, GitInitOptions, GitWrapperWithRemoteRepo};
 
 fn remove_nondeterministic_lines(output: String) -> String {
@@ -442,9 +443,357 @@ fn test_sync_merge_commit() -> eyre::Result<()> {
         let (stdout, _stderr) = git.branchless("sync", &[])?;
         insta::assert_snapshot!(stdout, @r###"
         Attempting rebase in-memory...
+        [1/4] Committed as: 6c398da create test1.txt
+        [2/4] Committed as: d166405 create test2.txt
+        [3/4] Committed as: 9799dd9 create test3.txt
+        [4/4] Committed as: 4ccacbb Merge branch 'foo' into HEAD
+        branchless: processing 1 update: branch foo
+        branchless: processing 4 rewritten commits
+        branchless: running command: <git-executable> checkout master
+        In-memory rebase succeeded.
+        Synced 62fc20d create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_dry_run() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("sync", &["--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [1/1] Committed as: 0770943 create test1.txt
+        Would sync 62fc20d create test1.txt
+        :
+        @ fe65c1f (> master) create test2.txt
+        |
+        o 0770943 create test1.txt
+        "###);
+    }
+
+    // Nothing should have actually been rewritten.
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        |
+        @ fe65c1f (> master) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_many_stacks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file_with_contents("test4", 4, "stack contents\n")?;
+
+    git.run(&["checkout", "master"])?;
+    git.commit_file_with_contents("test4", 5, "master contents\n")?;
+    git.run(&["checkout", &test3_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["sync"])?;
+        insta::assert_snapshot!(stdout, @r###"
         Attempting rebase in-memory...
-        Can't rebase merge commit in-memory: 62fc20d create test1.txt
-        Can't rebase merge commit in-memory: 98b9119 create test3.txt
+        [1/2] Committed as: 21ff6e0 create test1.txt
+        [2/2] Committed as: fb0fbe0 create test2.txt
+        branchless: processing 2 rewritten commits
+        In-memory rebase succeeded.
+        Attempting rebase in-memory...
+        [1/1] Committed as: dac5f77 create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout dac5f77f0f755beae7883f4d3483d7efd33e6e42
+        In-memory rebase succeeded.
+        Attempting rebase in-memory...
+        Synced 62fc20d create test1.txt
+        Synced 98b9119 create test3.txt
+        Merge conflict (1 file) for 5fa474f create test4.txt
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o 5fa474f create test4.txt
+        |
+        O 2c67bf4 (master) create test4.txt
+        |\
+        | o 21ff6e0 create test1.txt
+        | |
+        | o fb0fbe0 create test2.txt
+        |
+        @ dac5f77 create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_clean() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["branch", "landed"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "not-landed"])?;
+
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    let test4_oid = git.commit_file("test4", 4)?;
+    git.run(&["branch", "squashed"])?;
+
+    git.run(&["checkout", "master"])?;
+    git.run(&["cherry-pick", &test1_oid.to_string()])?;
+    git.run(&[
+        "cherry-pick",
+        "--no-commit",
+        &test3_oid.to_string(),
+        &test4_oid.to_string(),
+    ])?;
+    git.run(&["commit", "-m", "squash test3 and test4"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d (landed) create test1.txt
+        | |
+        | o 96d1c37 (not-landed) create test2.txt
+        |\
+        : o 98b9119 create test3.txt
+        : |
+        : o 2b633ed (squashed) create test4.txt
+        :
+        @ bc61362 (> master) squash test3 and test4
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("sync", &["--clean", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would hide landed commit: 62fc20d create test1.txt (landed as 047b7ad)
+        Would hide landed commit: 98b9119 create test3.txt (landed as bc61362)
+        Would hide landed commit: 2b633ed create test4.txt (landed as bc61362)
+        Would delete 2 branches: landed, squashed
+        [1/1] Committed as: 17e3939 create test2.txt
+        Would sync 96d1c37 create test2.txt
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d (landed) create test1.txt
+        |\
+        : o 98b9119 create test3.txt
+        : |
+        : o 2b633ed (squashed) create test4.txt
+        :
+        @ bc61362 (> master) squash test3 and test4
+        |
+        o 17e3939 (not-landed) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("sync", &["--clean"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid landed commit: 62fc20d create test1.txt (landed as 047b7ad)
+        Hid landed commit: 98b9119 create test3.txt (landed as bc61362)
+        Hid landed commit: 2b633ed create test4.txt (landed as bc61362)
+        Deleted 2 branches: landed, squashed
+        branchless: processing 2 updates: branch landed, branch squashed
+        To unhide these 3 commits and restore 2 branches, run: git undo
+        Attempting rebase in-memory...
+        [1/1] Committed as: 17e3939 create test2.txt
+        branchless: processing 1 update: branch not-landed
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        In-memory rebase succeeded.
+        Synced 96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ bc61362 (> master) squash test3 and test4
+        |
+        o 17e3939 (not-landed) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_public_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "branchless.core.publicBranch", "release/*"])?;
+
+    git.run(&["checkout", "-b", "release/1.0"])?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "release/1.0"])?;
+    git.commit_file("test3", 3)?;
+
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test4", 4)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test5", 5)?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | O 62fc20d create test1.txt
+        | |\
+        | | o 96d1c37 create test2.txt
+        | |
+        | O 4838e49 (release/1.0) create test3.txt
+        |\
+        | o 8f7aef5 create test4.txt
+        |
+        @ aff9c67 (> master) create test5.txt
+        "###);
+    }
+
+    {
+        // The order of the commits isn't deterministic, so sort them.
+        let (stdout, _stderr) = git.branchless("query", &["draft()"])?;
+        let stdout: String = stdout
+            .lines()
+            .sorted()
+            .map(|line| format!("{line}\n"))
+            .collect();
+        insta::assert_snapshot!(stdout, @r###"
+        8f7aef5 create test4.txt
+        96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("sync", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: d742fb9 create test2.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        In-memory rebase succeeded.
+        Attempting rebase in-memory...
+        [1/1] Committed as: a83fa4b create test4.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        In-memory rebase succeeded.
+        Synced 96d1c37 create test2.txt
+        Synced 8f7aef5 create test4.txt
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | O 62fc20d create test1.txt
+        | |
+        | O 4838e49 (release/1.0) create test3.txt
+        | |
+        | o d742fb9 create test2.txt
+        |
+        @ aff9c67 (> master) create test5.txt
+        |
+        o a83fa4b create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_clean_public_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "branchless.core.publicBranch", "release/*"])?;
+    git.run(&["branch", "release/1.0"])?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["branch", "landed"])?;
+
+    git.run(&["checkout", "release/1.0"])?;
+    git.run(&["cherry-pick", &test1_oid.to_string()])?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("sync", &["--clean"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid landed commit: 62fc20d create test1.txt (landed as 047b7ad)
+        Deleted 1 branch: landed
+        branchless: processing 1 update: branch landed
+        To unhide this 1 commit and restore 1 branch, run: git undo
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        O 047b7ad (release/1.0) create test1.txt
         "###);
     }
 
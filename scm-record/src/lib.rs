@@ -10,10 +10,12 @@
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
 mod render;
+mod reorder;
 mod types;
 mod ui;
 mod util;
 
+pub use reorder::{ReorderAction, ReorderItem, ReorderState, Reorderer};
 pub use types::{
     ChangeType, File, FileMode, RecordError, RecordState, Section, SectionChangedLine,
 };
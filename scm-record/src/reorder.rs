@@ -0,0 +1,324 @@
+//! UI to reorder a list of items, such as the commits in a stack, and to mark
+//! items to be squashed or dropped.
+
+use std::borrow::Cow;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use tui::backend::{Backend, TestBackend};
+use tui::style::{Color, Modifier, Style};
+use tui::text::Span;
+use tui::Terminal;
+
+use crate::render::{Component, Viewport};
+use crate::types::RecordError;
+use crate::ui::{highlight_line, run_in_terminal};
+use crate::util::UsizeExt;
+
+/// What to do with an item once the user has confirmed the new order.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ReorderAction {
+    /// Keep the item.
+    Pick,
+
+    /// Fold the item into the item displayed above it.
+    Squash,
+
+    /// Remove the item.
+    Drop,
+}
+
+/// An item to be reordered.
+#[derive(Clone, Debug)]
+pub struct ReorderItem<'a> {
+    /// An identifier for the item. This is not displayed; it lets the caller
+    /// map the item back to its own data once the items have been reordered.
+    pub id: usize,
+
+    /// The text to display for the item.
+    pub label: Cow<'a, str>,
+
+    /// The action to take for the item.
+    pub action: ReorderAction,
+}
+
+/// The state used to render the items. This is passed into [`Reorderer::run`]
+/// and then updated and returned.
+#[derive(Clone, Debug, Default)]
+pub struct ReorderState<'a> {
+    /// The items, in the order that they're displayed (from top to bottom).
+    pub items: Vec<ReorderItem<'a>>,
+}
+
+#[derive(Clone, Debug)]
+enum Event {
+    None,
+    Cancel,
+    Confirm,
+    SelectPrev,
+    SelectNext,
+    MoveUp,
+    MoveDown,
+    SetAction(ReorderAction),
+}
+
+impl From<crossterm::event::Event> for Event {
+    fn from(event: crossterm::event::Event) -> Self {
+        let (code, modifiers) = match event {
+            crossterm::event::Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: KeyEventKind::Press,
+                state: _,
+            }) => (code, modifiers),
+            _ => return Self::None,
+        };
+
+        match (code, modifiers) {
+            (KeyCode::Char('q') | KeyCode::Esc, KeyModifiers::NONE)
+            | (KeyCode::Char('c'), KeyModifiers::CONTROL) => Self::Cancel,
+            (KeyCode::Char('c') | KeyCode::Enter, KeyModifiers::NONE) => Self::Confirm,
+
+            (KeyCode::Up | KeyCode::Char('k'), KeyModifiers::NONE) => Self::SelectPrev,
+            (KeyCode::Down | KeyCode::Char('j'), KeyModifiers::NONE) => Self::SelectNext,
+
+            // Depending on the terminal, uppercase letters may or may not be
+            // reported with the shift modifier.
+            (KeyCode::Up, KeyModifiers::SHIFT) | (KeyCode::Char('K'), _) => Self::MoveUp,
+            (KeyCode::Down, KeyModifiers::SHIFT) | (KeyCode::Char('J'), _) => Self::MoveDown,
+
+            (KeyCode::Char('p'), KeyModifiers::NONE) => Self::SetAction(ReorderAction::Pick),
+            (KeyCode::Char('s'), KeyModifiers::NONE) => Self::SetAction(ReorderAction::Squash),
+            (KeyCode::Char('d'), KeyModifiers::NONE) => Self::SetAction(ReorderAction::Drop),
+
+            _ => Self::None,
+        }
+    }
+}
+
+/// UI component to reorder items.
+pub struct Reorderer<'a> {
+    state: ReorderState<'a>,
+    selection_idx: usize,
+    scroll_offset_y: isize,
+}
+
+impl<'a> Reorderer<'a> {
+    fn new(state: ReorderState<'a>) -> Self {
+        Self {
+            state,
+            selection_idx: 0,
+            scroll_offset_y: 0,
+        }
+    }
+
+    /// Run the terminal user interface and have the user interactively
+    /// reorder the items. Returns [`RecordError::Cancelled`] if the user
+    /// quit without confirming the new order.
+    pub fn run(state: ReorderState<'a>) -> Result<ReorderState<'a>, RecordError> {
+        let reorderer = Self::new(state);
+        run_in_terminal(|term| {
+            reorderer.run_inner(term, || {
+                crossterm::event::read().map_err(RecordError::ReadInput)
+            })
+        })
+    }
+
+    /// Run the user interface on a virtual terminal of the given size, using
+    /// the provided events as the user input. Returns the resulting state and
+    /// the contents of the screen as it was last rendered. For testing.
+    pub fn run_with_events(
+        state: ReorderState<'a>,
+        width: u16,
+        height: u16,
+        events: impl IntoIterator<Item = crossterm::event::Event>,
+    ) -> Result<(ReorderState<'a>, String), RecordError> {
+        let reorderer = Self::new(state);
+        let backend = TestBackend::new(width, height);
+        let mut term = Terminal::new(backend).map_err(RecordError::SetUpTerminal)?;
+        let mut events = events.into_iter();
+        let state = reorderer.run_inner(&mut term, || {
+            events
+                .next()
+                .ok_or_else(|| RecordError::Bug("ran out of testing events".to_string()))
+        })?;
+
+        let buffer = term.backend().buffer();
+        let screen = (0..buffer.area.height)
+            .map(|y| {
+                let line: String = (0..buffer.area.width)
+                    .map(|x| buffer.get(x, y).symbol.as_str())
+                    .collect();
+                format!("{}\n", line.trim_end())
+            })
+            .collect();
+        Ok((state, screen))
+    }
+
+    fn run_inner<B: Backend>(
+        mut self,
+        term: &mut Terminal<B>,
+        mut read_event: impl FnMut() -> Result<crossterm::event::Event, RecordError>,
+    ) -> Result<ReorderState<'a>, RecordError> {
+        loop {
+            let term_height = usize::from(term.get_frame().size().height);
+            self.ensure_selection_in_viewport(term_height);
+
+            let app = self.make_app();
+            term.draw(|frame| {
+                Viewport::<ComponentId>::render_top_level(frame, 0, self.scroll_offset_y, &app);
+            })
+            .map_err(RecordError::RenderFrame)?;
+
+            let event = Event::from(read_event()?);
+            match event {
+                Event::None => {}
+                Event::Cancel => return Err(RecordError::Cancelled),
+                Event::Confirm => break,
+                Event::SelectPrev => {
+                    self.selection_idx = self.selection_idx.saturating_sub(1);
+                }
+                Event::SelectNext => {
+                    if self.selection_idx + 1 < self.state.items.len() {
+                        self.selection_idx += 1;
+                    }
+                }
+                Event::MoveUp => {
+                    if self.selection_idx > 0 {
+                        self.state
+                            .items
+                            .swap(self.selection_idx - 1, self.selection_idx);
+                        self.selection_idx -= 1;
+                    }
+                }
+                Event::MoveDown => {
+                    if self.selection_idx + 1 < self.state.items.len() {
+                        self.state
+                            .items
+                            .swap(self.selection_idx, self.selection_idx + 1);
+                        self.selection_idx += 1;
+                    }
+                }
+                Event::SetAction(action) => {
+                    if let Some(item) = self.state.items.get_mut(self.selection_idx) {
+                        item.action = action;
+                    }
+                }
+            }
+        }
+
+        Ok(self.state)
+    }
+
+    fn ensure_selection_in_viewport(&mut self, term_height: usize) {
+        let selection_y = (HELP_LINES.len() + self.selection_idx).unwrap_isize();
+        let term_height = term_height.unwrap_isize();
+        if selection_y < self.scroll_offset_y {
+            self.scroll_offset_y = selection_y;
+        } else if selection_y >= self.scroll_offset_y + term_height {
+            self.scroll_offset_y = selection_y - term_height + 1;
+        }
+    }
+
+    fn make_app(&self) -> App<'_> {
+        App {
+            item_views: self
+                .state
+                .items
+                .iter()
+                .enumerate()
+                .map(|(item_idx, item)| ItemView {
+                    item_idx,
+                    is_selected: item_idx == self.selection_idx,
+                    item,
+                })
+                .collect(),
+        }
+    }
+}
+
+const HELP_LINES: &[&str] = &[
+    "up/down, k/j: select   shift-up/shift-down, K/J: move up/down",
+    "p: pick   s: squash into previous   d: drop   enter, c: confirm   q: cancel",
+    "",
+];
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+enum ComponentId {
+    App,
+    Item(usize),
+}
+
+#[derive(Clone, Debug)]
+struct App<'a> {
+    item_views: Vec<ItemView<'a>>,
+}
+
+impl Component for App<'_> {
+    type Id = ComponentId;
+
+    fn id(&self) -> Self::Id {
+        ComponentId::App
+    }
+
+    fn draw(&self, viewport: &mut Viewport<Self::Id>, x: isize, y: isize) {
+        let Self { item_views } = self;
+
+        let mut y = y;
+        for help_line in HELP_LINES {
+            let span = Span::styled(*help_line, Style::default().add_modifier(Modifier::DIM));
+            viewport.draw_span(x, y, &span);
+            y += 1;
+        }
+        for item_view in item_views {
+            let item_view_rect = viewport.draw_component(x, y, item_view);
+            y += item_view_rect.height.unwrap_isize();
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ItemView<'a> {
+    item_idx: usize,
+    is_selected: bool,
+    item: &'a ReorderItem<'a>,
+}
+
+impl Component for ItemView<'_> {
+    type Id = ComponentId;
+
+    fn id(&self) -> Self::Id {
+        ComponentId::Item(self.item_idx)
+    }
+
+    fn draw(&self, viewport: &mut Viewport<Self::Id>, x: isize, y: isize) {
+        let Self {
+            item_idx: _,
+            is_selected,
+            item,
+        } = self;
+        let ReorderItem {
+            id: _,
+            label,
+            action,
+        } = item;
+
+        if *is_selected {
+            highlight_line(viewport, y);
+        }
+
+        let (action_text, style) = match action {
+            ReorderAction::Pick => ("pick  ", Style::default()),
+            ReorderAction::Squash => ("squash", Style::default().fg(Color::Yellow)),
+            ReorderAction::Drop => (
+                "drop  ",
+                Style::default()
+                    .fg(Color::Red)
+                    .add_modifier(Modifier::CROSSED_OUT),
+            ),
+        };
+        let action_span = Span::styled(action_text, style.add_modifier(Modifier::BOLD));
+        let action_rect = viewport.draw_span(x, y, &action_span);
+        let x = x + action_rect.width.unwrap_isize() + 1;
+        viewport.draw_span(x, y, &Span::styled(label.as_ref(), style));
+    }
+}
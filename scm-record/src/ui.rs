@@ -26,7 +26,7 @@ use crate::util::UsizeExt;
 use crate::{File, Section, SectionChangedLine};
 
 type Backend = CrosstermBackend<io::Stdout>;
-type CrosstermTerminal = Terminal<Backend>;
+pub(crate) type CrosstermTerminal = Terminal<Backend>;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
 struct FileKey {
@@ -190,6 +190,70 @@ enum StateUpdate {
     ToggleItem(SelectionKey),
 }
 
+/// Set up the terminal, call `f` to run the user interface, and then restore
+/// the terminal, even if `f` panicked.
+pub(crate) fn run_in_terminal<T>(
+    f: impl FnOnce(&mut CrosstermTerminal) -> Result<T, RecordError>,
+) -> Result<T, RecordError> {
+    let mut stdout = io::stdout();
+    if !is_raw_mode_enabled().map_err(RecordError::SetUpTerminal)? {
+        enable_raw_mode().map_err(RecordError::SetUpTerminal)?;
+        crossterm::execute!(stdout, EnterAlternateScreen, EnableMouseCapture)
+            .map_err(RecordError::SetUpTerminal)?;
+    }
+    let backend = CrosstermBackend::new(stdout);
+    let mut term = Terminal::new(backend).map_err(RecordError::SetUpTerminal)?;
+
+    let dump_panics = std::env::var_os("SCM_RECORD_DUMP_PANICS").is_some();
+    if dump_panics {
+        f(&mut term)
+    } else {
+        // Catch any panics and restore terminal state, since otherwise the
+        // terminal will be mostly unusable.
+        let result = panic::catch_unwind(
+            // HACK: I don't actually know if the terminal is unwind-safe 🙃.
+            AssertUnwindSafe(|| f(&mut term)),
+        );
+        if let Err(err) = clean_up(&mut term) {
+            warn!(?err, "Failed to clean up terminal");
+        }
+        match result {
+            Ok(result) => result,
+            Err(panic) => {
+                // HACK: it should be possible to just call
+                //
+                //     panic::resume_unwind(panic)
+                //
+                // but, for some reason, when I do that, the panic information
+                // is not printed. Then it generally looks like the program
+                // exited successfully but did nothing. This at least ensures
+                // that *something* is printed to indicate that there was a
+                // panic, even if it doesn't include all of the panic details.
+                if let Some(payload) = panic.downcast_ref::<String>() {
+                    panic!("panic occurred: {payload}");
+                } else if let Some(payload) = panic.downcast_ref::<&str>() {
+                    panic!("panic occurred: {payload}");
+                } else {
+                    panic!("panic occurred (message not available)");
+                }
+            }
+        }
+    }
+}
+
+fn clean_up(term: &mut CrosstermTerminal) -> io::Result<()> {
+    term.show_cursor()?;
+    if is_raw_mode_enabled()? {
+        disable_raw_mode()?;
+        crossterm::execute!(
+            term.backend_mut(),
+            LeaveAlternateScreen,
+            DisableMouseCapture
+        )?;
+    }
+    Ok(())
+}
+
 /// UI component to record the user's changes.
 pub struct Recorder<'a> {
     state: RecordState<'a>,
@@ -202,70 +266,13 @@ impl<'a> Recorder<'a> {
     /// Run the terminal user interface and have the user interactively select
     /// changes.
     pub fn run(state: RecordState<'a>) -> Result<RecordState<'a>, RecordError> {
-        let mut stdout = io::stdout();
-        if !is_raw_mode_enabled().map_err(RecordError::SetUpTerminal)? {
-            enable_raw_mode().map_err(RecordError::SetUpTerminal)?;
-            crossterm::execute!(stdout, EnterAlternateScreen, EnableMouseCapture)
-                .map_err(RecordError::SetUpTerminal)?;
-        }
-        let backend = CrosstermBackend::new(stdout);
-        let mut term = Terminal::new(backend).map_err(RecordError::SetUpTerminal)?;
-
-        let dump_panics = std::env::var_os("SCM_RECORD_DUMP_PANICS").is_some();
         let recorder = Self {
             state,
             use_unicode: true,
             selection_key: SelectionKey::None,
             scroll_offset_y: 0,
         };
-        let state = if dump_panics {
-            recorder.run_inner(&mut term)
-        } else {
-            // Catch any panics and restore terminal state, since otherwise the
-            // terminal will be mostly unusable.
-            let state = panic::catch_unwind(
-                // HACK: I don't actually know if the terminal is unwind-safe 🙃.
-                AssertUnwindSafe(|| recorder.run_inner(&mut term)),
-            );
-            if let Err(err) = Self::clean_up(&mut term) {
-                warn!(?err, "Failed to clean up terminal");
-            }
-            match state {
-                Ok(state) => state,
-                Err(panic) => {
-                    // HACK: it should be possible to just call
-                    //
-                    //     panic::resume_unwind(panic)
-                    //
-                    // but, for some reason, when I do that, the panic information
-                    // is not printed. Then it generally looks like the program
-                    // exited successfully but did nothing. This at least ensures
-                    // that *something* is printed to indicate that there was a
-                    // panic, even if it doesn't include all of the panic details.
-                    if let Some(payload) = panic.downcast_ref::<String>() {
-                        panic!("panic occurred: {payload}");
-                    } else if let Some(payload) = panic.downcast_ref::<&str>() {
-                        panic!("panic occurred: {payload}");
-                    } else {
-                        panic!("panic occurred (message not available)");
-                    }
-                }
-            }
-        };
-        state
-    }
-
-    fn clean_up(term: &mut CrosstermTerminal) -> io::Result<()> {
-        term.show_cursor()?;
-        if is_raw_mode_enabled()? {
-            disable_raw_mode()?;
-            crossterm::execute!(
-                term.backend_mut(),
-                LeaveAlternateScreen,
-                DisableMouseCapture
-            )?;
-        }
-        Ok(())
+        run_in_terminal(|term| recorder.run_inner(term))
     }
 
     fn run_inner(mut self, term: &mut CrosstermTerminal) -> Result<RecordState<'a>, RecordError> {
@@ -1262,7 +1269,7 @@ impl Component for SectionLineView<'_> {
     }
 }
 
-fn highlight_line<Id: Clone + Debug + Eq + Hash>(viewport: &mut Viewport<Id>, y: isize) {
+pub(crate) fn highlight_line<Id: Clone + Debug + Eq + Hash>(viewport: &mut Viewport<Id>, y: isize) {
     viewport.set_style(
         Rect {
             x: 0,